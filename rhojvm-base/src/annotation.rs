@@ -0,0 +1,207 @@
+//! The model of annotations as they are stored in the `RuntimeVisibleAnnotations` family of
+//! attributes.
+//! The constant pool indices are kept as-is rather than resolved, since most users only care
+//! about a few of the annotations. Use [`ClassFileData::get_text_t`] to resolve them.
+
+use std::borrow::Cow;
+
+use classfile_parser::{
+    constant_info::{ConstantInfo, Utf8Constant},
+    constant_pool::ConstantPoolIndexRaw,
+};
+
+use crate::{class::ClassFileData, class_reader::Reader};
+
+/// Annotations nested deeper than this are treated as malformed, so that bad data can't overflow
+/// the stack
+const MAX_NESTING_DEPTH: usize = 64;
+
+/// The smallest an annotation can be, which is its type index and a count of zero elements
+const MIN_ANNOTATION_SIZE: usize = 4;
+/// The smallest an element value can be, which is a tag and a single index
+const MIN_ELEMENT_VALUE_SIZE: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    /// The field descriptor of the annotation interface, like `Ljava/lang/Deprecated;`
+    pub type_index: ConstantPoolIndexRaw<Utf8Constant>,
+    /// The element-value pairs, in the order they were written
+    /// Elements which were left as their default value are not included.
+    pub elements: Vec<(ConstantPoolIndexRaw<Utf8Constant>, ElementValue)>,
+}
+impl Annotation {
+    #[must_use]
+    /// Get the descriptor of the annotation's type, like `Ljava/lang/Deprecated;`
+    pub fn type_descriptor<'a>(&self, class_file: &'a ClassFileData) -> Option<Cow<'a, str>> {
+        class_file.get_text_t(self.type_index)
+    }
+
+    #[must_use]
+    /// Find the value of the element with the given name
+    pub fn element(&self, class_file: &ClassFileData, name: &str) -> Option<&ElementValue> {
+        self.elements
+            .iter()
+            .find(|(name_index, _)| class_file.get_text_b(*name_index) == Some(name.as_bytes()))
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ElementValue {
+    /// A primitive or string constant
+    /// The `tag` is the descriptor character of the type (`B`, `C`, `D`, `F`, `I`, `J`, `S`,
+    /// `Z`), or `s` for a string. Note that strings refer directly to a utf8 constant, rather
+    /// than a `CONSTANT_String`.
+    Const {
+        tag: u8,
+        value: ConstantPoolIndexRaw<ConstantInfo>,
+    },
+    /// A constant of an enum
+    EnumConst {
+        /// The field descriptor of the enum type
+        type_name: ConstantPoolIndexRaw<Utf8Constant>,
+        /// The simple name of the enum constant
+        const_name: ConstantPoolIndexRaw<Utf8Constant>,
+    },
+    /// A class literal, like `String.class`, as its return descriptor
+    /// Ex: `Ljava/lang/String;`, `I`, or `V` for `void.class`
+    Class(ConstantPoolIndexRaw<Utf8Constant>),
+    Annotation(Annotation),
+    Array(Vec<ElementValue>),
+}
+
+/// Parse a `u16` count followed by that many annotations, as in `RuntimeVisibleAnnotations`
+pub(crate) fn parse_annotations(reader: &mut Reader<'_>) -> Option<Vec<Annotation>> {
+    let count = reader.u16()?;
+    let mut annotations = Vec::with_capacity(capacity(reader, count, MIN_ANNOTATION_SIZE));
+    for _ in 0..count {
+        annotations.push(parse_annotation(reader, 0)?);
+    }
+
+    Some(annotations)
+}
+
+fn parse_annotation(reader: &mut Reader<'_>, depth: usize) -> Option<Annotation> {
+    let type_index = ConstantPoolIndexRaw::new(reader.u16()?);
+
+    let count = reader.u16()?;
+    // Each element also has a name index before its value
+    let mut elements = Vec::with_capacity(capacity(reader, count, 2 + MIN_ELEMENT_VALUE_SIZE));
+    for _ in 0..count {
+        let name_index = ConstantPoolIndexRaw::new(reader.u16()?);
+        let value = parse_element_value(reader, depth)?;
+        elements.push((name_index, value));
+    }
+
+    Some(Annotation {
+        type_index,
+        elements,
+    })
+}
+
+/// The capacity to reserve for `count` items that are each at least `min_size` bytes
+/// The count is untrusted, so it is limited to how many items the rest of the data could hold,
+/// rather than letting a small attribute reserve a large amount of memory at each nesting level.
+fn capacity(reader: &Reader<'_>, count: u16, min_size: usize) -> usize {
+    usize::from(count).min(reader.remaining() / min_size)
+}
+
+pub(crate) fn parse_element_value(reader: &mut Reader<'_>, depth: usize) -> Option<ElementValue> {
+    if depth > MAX_NESTING_DEPTH {
+        return None;
+    }
+
+    let tag = reader.u8()?;
+    Some(match tag {
+        b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b's' => ElementValue::Const {
+            tag,
+            value: ConstantPoolIndexRaw::new(reader.u16()?),
+        },
+        b'e' => ElementValue::EnumConst {
+            type_name: ConstantPoolIndexRaw::new(reader.u16()?),
+            const_name: ConstantPoolIndexRaw::new(reader.u16()?),
+        },
+        b'c' => ElementValue::Class(ConstantPoolIndexRaw::new(reader.u16()?)),
+        b'@' => ElementValue::Annotation(parse_annotation(reader, depth + 1)?),
+        b'[' => {
+            let count = reader.u16()?;
+            let mut values = Vec::with_capacity(capacity(reader, count, MIN_ELEMENT_VALUE_SIZE));
+            for _ in 0..count {
+                values.push(parse_element_value(reader, depth + 1)?);
+            }
+            ElementValue::Array(values)
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::class_reader::Reader;
+
+    use super::{parse_annotations, parse_element_value, ElementValue};
+
+    #[test]
+    fn test_parse_annotations() {
+        #[rustfmt::skip]
+        let data = [
+            // One annotation of type #1, with two elements
+            0, 1, 0, 1, 0, 2,
+            // #2 = the int constant at #3
+            0, 2, b'I', 0, 3,
+            // #4 = { enum constant #5.#6, @#7 with no elements }
+            0, 4, b'[', 0, 2, b'e', 0, 5, 0, 6, b'@', 0, 7, 0, 0,
+        ];
+
+        let annotations = parse_annotations(&mut Reader::new(&data)).unwrap();
+        assert_eq!(annotations.len(), 1);
+        let annotation = &annotations[0];
+        assert_eq!(annotation.type_index.0, 1);
+        assert_eq!(annotation.elements.len(), 2);
+        assert!(matches!(
+            &annotation.elements[0].1,
+            ElementValue::Const { tag: b'I', value } if value.0 == 3
+        ));
+
+        let ElementValue::Array(values) = &annotation.elements[1].1 else {
+            panic!("Expected an array element value");
+        };
+        assert!(matches!(
+            &values[0],
+            ElementValue::EnumConst { type_name, const_name }
+                if type_name.0 == 5 && const_name.0 == 6
+        ));
+        assert!(matches!(
+            &values[1],
+            ElementValue::Annotation(nested) if nested.type_index.0 == 7 && nested.elements.is_empty()
+        ));
+
+        // Truncated or with an unknown tag
+        assert!(parse_annotations(&mut Reader::new(&data[..data.len() - 1])).is_none());
+        assert!(parse_annotations(&mut Reader::new(&[0, 1, 0, 1, 0, 1, 0, 2, b'?'])).is_none());
+    }
+
+    #[test]
+    fn test_untrusted_counts() {
+        // Arrays which claim to have the maximum number of values, nested as deep as is allowed,
+        // but with no data for the values
+        let mut data = Vec::new();
+        for _ in 0..64 {
+            data.extend([b'[', 0xFF, 0xFF]);
+        }
+        // The innermost value, which is the only one that exists
+        data.extend([b'[', 0, 0]);
+        assert!(parse_element_value(&mut Reader::new(&data), 0).is_none());
+
+        // An array of the same size which does have its values
+        let mut data = vec![b'[', 0xFF, 0xFF];
+        for _ in 0..u16::MAX {
+            data.extend([b'c', 0, 1]);
+        }
+        let Some(ElementValue::Array(values)) = parse_element_value(&mut Reader::new(&data), 0)
+        else {
+            panic!("Expected an array element value");
+        };
+        assert_eq!(values.len(), usize::from(u16::MAX));
+    }
+}
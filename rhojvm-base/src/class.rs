@@ -1,35 +1,425 @@
-use std::{borrow::Cow, ops::Range, rc::Rc};
+use std::{borrow::Cow, cell::OnceCell, ops::Range, path::PathBuf, rc::Rc};
 
 use classfile_parser::{
-    constant_info::{ClassConstant, ConstantInfo, Utf8Constant},
+    attribute_info::stack_map_table_attribute_parser,
+    constant_info::{
+        ClassConstant, ConstantInfo, DoubleConstant, FloatConstant, IntegerConstant, LongConstant,
+        MethodHandleConstant, MethodTypeConstant, NameAndTypeConstant, StringConstant,
+        Utf8Constant,
+    },
     constant_pool::{ConstantPoolIndex, ConstantPoolIndexRaw},
     field_info::FieldInfoOpt,
-    method_info::{MethodInfo, MethodInfoOpt},
+    method_info::{MethodAccessFlags, MethodInfo, MethodInfoOpt},
     parser::ParseData,
-    ClassFileOpt, ClassFileVersion, LoadError,
+    class_parser_opt, ClassFileOpt, ClassFileVersion, LoadError,
 };
+use either::Either;
+use indexmap::{Equivalent, IndexMap};
 
 pub use classfile_parser::ClassAccessFlags;
 
+/// Serializes the access flags as their bits, since the parser's type does not support serde
+#[cfg(feature = "serde")]
+mod access_flags_serde {
+    use classfile_parser::ClassAccessFlags;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        flags: &ClassAccessFlags,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        flags.bits().serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ClassAccessFlags, D::Error> {
+        u16::deserialize(deserializer).map(ClassAccessFlags::from_bits_truncate)
+    }
+}
+
 use crate::{
-    code::types::PrimitiveType,
-    data::class_names::ClassNames,
-    id::{ClassId, ExactMethodId, MethodIndex, PackageId},
-    util::format_class_as_object_desc,
+    annotation::{self, Annotation, ElementValue},
+    class_reader::{self, CodeAttribute, LocalVariableEntry},
+    code::{
+        method::{DescriptorParseError, DescriptorType, DescriptorTypeBasic},
+        stack_map::{RawStackMapFrame, StackMapError},
+        types::PrimitiveType,
+    },
+    data::{class_file_loader::LoadClassFileError, class_names::ClassNames},
+    id::{ClassId, ExactMethodId, FieldId, FieldIndex, MethodId, MethodIndex, PackageId},
+    signature::{self, ClassSignature, SignatureParseError},
+    symbol::{Symbol, Symbols},
+    util::decode_modified_utf8,
     BadIdError,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum ClassFileIndexError {
-    InvalidThisClassIndex,
-    InvalidThisClassNameIndex,
-    InvalidSuperClassIndex,
-    InvalidSuperClassNameIndex,
+    #[error("invalid constant pool index for this class: {}", .0.0)]
+    InvalidThisClassIndex(ConstantPoolIndexRaw<ClassConstant>),
+    #[error("invalid constant pool index for the name of this class: {}", .0.0)]
+    InvalidThisClassNameIndex(ConstantPoolIndexRaw<Utf8Constant>),
+    #[error("invalid constant pool index for the super class: {}", .0.0)]
+    InvalidSuperClassIndex(ConstantPoolIndexRaw<ClassConstant>),
+    #[error("invalid constant pool index for the name of the super class: {}", .0.0)]
+    InvalidSuperClassNameIndex(ConstantPoolIndexRaw<Utf8Constant>),
+    #[error("invalid constant pool index for an interface: {}", .0.0)]
+    InvalidInterfaceIndex(ConstantPoolIndexRaw<ClassConstant>),
+    #[error("invalid constant pool index for the name of an interface: {}", .0.0)]
+    InvalidInterfaceNameIndex(ConstantPoolIndexRaw<Utf8Constant>),
+    #[error("invalid constant pool index for a class: {}", .0.0)]
+    InvalidClassIndex(ConstantPoolIndexRaw<ClassConstant>),
+    #[error("invalid constant pool index for the name of a class: {}", .0.0)]
+    InvalidClassNameIndex(ConstantPoolIndexRaw<Utf8Constant>),
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum MethodHandleError {
+    #[error("invalid constant pool index for a method handle: {}", .0.0)]
+    InvalidIndex(ConstantPoolIndexRaw<MethodHandleConstant>),
+    #[error("invalid method handle reference kind: {0}")]
+    InvalidReferenceKind(u8),
+    /// The constant that the method handle references is not a member reference, or does not
+    /// resolve
+    #[error("invalid method handle reference index: {0}")]
+    InvalidReferenceIndex(u16),
+    /// The referenced member is of the wrong type for the kind, like a `GetField` referencing a
+    /// method
+    #[error("method handle of kind {kind:?} references the wrong type of member")]
+    WrongMemberType { kind: MethodHandleKind },
+    /// The referenced method has the wrong name for the kind, like an `InvokeVirtual` referencing
+    /// `<init>`, or a `NewInvokeSpecial` referencing anything *but* `<init>`
+    #[error("method handle of kind {kind:?} references a method with an invalid name")]
+    InvalidMethodName { kind: MethodHandleKind },
+}
+
+/// Features of the class file format which require a minimum class file version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ClassFileFeature {
+    /// The `StackMapTable` attribute, and type checking verification
+    StackMapTable,
+    /// The `invokedynamic` instruction and `CONSTANT_MethodHandle`/`CONSTANT_MethodType`
+    InvokeDynamic,
+    /// Non-abstract methods on interfaces
+    DefaultMethods,
+    /// The `Module` attribute and `CONSTANT_Module`/`CONSTANT_Package`
+    Modules,
+    /// The `NestHost` and `NestMembers` attributes
+    NestMates,
+    /// `CONSTANT_Dynamic`
+    Dynamic,
+    /// The `Record` attribute
+    Records,
+    /// The `PermittedSubclasses` attribute
+    SealedClasses,
+}
+impl ClassFileFeature {
+    #[must_use]
+    /// The first major version of the class file format that has the feature
+    pub fn min_major_version(self) -> u16 {
+        match self {
+            ClassFileFeature::StackMapTable => 50,
+            ClassFileFeature::InvokeDynamic => 51,
+            ClassFileFeature::DefaultMethods => 52,
+            ClassFileFeature::Modules => 53,
+            ClassFileFeature::NestMates | ClassFileFeature::Dynamic => 55,
+            ClassFileFeature::Records => 60,
+            ClassFileFeature::SealedClasses => 61,
+        }
+    }
+}
+
+/// A field or method reference from the constant pool, with its names resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberRef<'a> {
+    /// The class that the member is looked up on, which is not necessarily the class that
+    /// declares it
+    pub class_name: Cow<'a, str>,
+    pub name: Cow<'a, str>,
+    pub descriptor: Cow<'a, str>,
+    /// Whether this was a `CONSTANT_InterfaceMethodref`
+    pub is_interface: bool,
+}
+
+/// The initial value of a `static final` field, from its `ConstantValue` attribute
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantValueKind<'a> {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(Cow<'a, str>),
+}
+impl<'a> ConstantValueKind<'a> {
+    #[must_use]
+    /// Copy the string, if there is one, so that it does not borrow from the class file
+    pub fn into_owned(self) -> ConstantValueKind<'static> {
+        match self {
+            ConstantValueKind::Int(v) => ConstantValueKind::Int(v),
+            ConstantValueKind::Long(v) => ConstantValueKind::Long(v),
+            ConstantValueKind::Float(v) => ConstantValueKind::Float(v),
+            ConstantValueKind::Double(v) => ConstantValueKind::Double(v),
+            ConstantValueKind::String(v) => ConstantValueKind::String(Cow::Owned(v.into_owned())),
+        }
+    }
+}
+
+/// An entry of the `InnerClasses` attribute, which describes a nested class that is either
+/// declared in this class or referred to by it
+#[derive(Debug, Clone, Copy)]
+pub struct InnerClassInfo {
+    pub inner_class: ConstantPoolIndexRaw<ClassConstant>,
+    /// The class that the inner class is a member of
+    /// This is `None` for local and anonymous classes.
+    pub outer_class: Option<ConstantPoolIndexRaw<ClassConstant>>,
+    /// The simple name of the inner class, as it was written in the source
+    /// This is `None` for anonymous classes.
+    pub inner_name: Option<ConstantPoolIndexRaw<Utf8Constant>>,
+    /// The access flags that the inner class was declared with in the source, which can include
+    /// `private`, `protected` and `static` unlike the flags of the class file itself.
+    pub access_flags: InnerClassAccessFlags,
+}
+
+bitflags::bitflags! {
+    /// The access flags of a nested class, from its entry in the `InnerClasses` attribute
+    pub struct InnerClassAccessFlags: u16 {
+        const PUBLIC = 0x0001;
+        const PRIVATE = 0x0002;
+        const PROTECTED = 0x0004;
+        const STATIC = 0x0008;
+        const FINAL = 0x0010;
+        const INTERFACE = 0x0200;
+        const ABSTRACT = 0x0400;
+        const SYNTHETIC = 0x1000;
+        const ANNOTATION = 0x2000;
+        const ENUM = 0x4000;
+    }
+}
+
+/// The method or class that a local or anonymous class was declared in, from its
+/// `EnclosingMethod` attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnclosingMethodInfo<'a> {
+    /// The innermost class that encloses the declaration
+    pub class_id: ClassId,
+    /// The name of the enclosing method or constructor
+    /// This is `None` if it was not declared in a method, such as in a field initializer.
+    pub method_name: Option<Cow<'a, str>>,
+    /// The descriptor of the enclosing method, present when `method_name` is
+    pub method_descriptor: Option<Cow<'a, str>>,
+}
+
+/// A parameter of a method, from its `MethodParameters` attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodParameterInfo<'a> {
+    /// The name of the parameter, which is `None` if it was not recorded
+    pub name: Option<Cow<'a, str>>,
+    pub access_flags: MethodParameterAccessFlags,
+}
+
+bitflags::bitflags! {
+    /// The access flags of a method parameter, from its entry in the `MethodParameters` attribute
+    pub struct MethodParameterAccessFlags: u16 {
+        const FINAL = 0x0010;
+        /// The parameter was added by the compiler and does not appear in the source
+        const SYNTHETIC = 0x1000;
+        /// The parameter is implicitly declared by the language, such as the outer instance for
+        /// the constructor of an inner class
+        const MANDATED = 0x8000;
+    }
+}
+
+/// A component of a record class, from its `Record` attribute
+#[derive(Debug, Clone)]
+pub struct RecordComponentInfo<'a> {
+    pub name: Cow<'a, str>,
+    pub descriptor: Cow<'a, str>,
+    /// The attributes of the component, with their names and raw data
+    /// These can include `Signature` and the annotation attributes.
+    pub attributes: Vec<(Cow<'a, str>, &'a [u8])>,
+}
+impl<'a> RecordComponentInfo<'a> {
+    #[must_use]
+    /// Get the data of the attribute with the given name
+    pub fn attribute(&self, name: &str) -> Option<&'a [u8]> {
+        self.attributes
+            .iter()
+            .find(|(attr_name, _)| attr_name == name)
+            .map(|(_, data)| *data)
+    }
+
+    #[must_use]
+    /// Get the generic signature of the component's type, from its `Signature` attribute
+    pub fn generic_signature(&self, class_file: &'a ClassFileData) -> Option<Cow<'a, str>> {
+        let signature_index = class_reader::Reader::new(self.attribute("Signature")?).u16()?;
+        class_file.get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(signature_index))
+    }
+
+    #[must_use]
+    /// Parse the annotations on the component from its `RuntimeVisibleAnnotations` attribute
+    pub fn runtime_visible_annotations(&self) -> Option<Vec<Annotation>> {
+        let data = self.attribute("RuntimeVisibleAnnotations")?;
+        annotation::parse_annotations(&mut class_reader::Reader::new(data))
+    }
+}
+
+/// An entry in the `BootstrapMethods` attribute, which `CONSTANT_InvokeDynamic` and
+/// `CONSTANT_Dynamic` refer to by index
+#[derive(Debug, Clone)]
+pub struct BootstrapMethod {
+    /// The method handle which is invoked to produce the call site or constant
+    pub method_ref: ConstantPoolIndexRaw<MethodHandleConstant>,
+    /// The static arguments which are passed to the bootstrap method, which must each be a
+    /// loadable constant
+    pub arguments: Vec<ConstantPoolIndexRaw<ConstantInfo>>,
+}
+
+/// The kind of a `CONSTANT_MethodHandle`, which determines the bytecode behavior that the
+/// handle has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MethodHandleKind {
+    GetField,
+    GetStatic,
+    PutField,
+    PutStatic,
+    InvokeVirtual,
+    InvokeStatic,
+    InvokeSpecial,
+    NewInvokeSpecial,
+    InvokeInterface,
+}
+impl MethodHandleKind {
+    #[must_use]
+    /// Get the kind from its `reference_kind` value in the class file
+    pub fn from_reference_kind(kind: u8) -> Option<MethodHandleKind> {
+        Some(match kind {
+            1 => MethodHandleKind::GetField,
+            2 => MethodHandleKind::GetStatic,
+            3 => MethodHandleKind::PutField,
+            4 => MethodHandleKind::PutStatic,
+            5 => MethodHandleKind::InvokeVirtual,
+            6 => MethodHandleKind::InvokeStatic,
+            7 => MethodHandleKind::InvokeSpecial,
+            8 => MethodHandleKind::NewInvokeSpecial,
+            9 => MethodHandleKind::InvokeInterface,
+            _ => return None,
+        })
+    }
+
+    #[must_use]
+    /// Get the `reference_kind` value that the kind is stored as in the class file
+    pub fn reference_kind(self) -> u8 {
+        match self {
+            MethodHandleKind::GetField => 1,
+            MethodHandleKind::GetStatic => 2,
+            MethodHandleKind::PutField => 3,
+            MethodHandleKind::PutStatic => 4,
+            MethodHandleKind::InvokeVirtual => 5,
+            MethodHandleKind::InvokeStatic => 6,
+            MethodHandleKind::InvokeSpecial => 7,
+            MethodHandleKind::NewInvokeSpecial => 8,
+            MethodHandleKind::InvokeInterface => 9,
+        }
+    }
+
+    #[must_use]
+    /// Whether the handle references a field rather than a method
+    pub fn is_field(self) -> bool {
+        matches!(
+            self,
+            MethodHandleKind::GetField
+                | MethodHandleKind::GetStatic
+                | MethodHandleKind::PutField
+                | MethodHandleKind::PutStatic
+        )
+    }
+}
+
+/// A decoded `CONSTANT_MethodHandle`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodHandleInfo<'a> {
+    pub kind: MethodHandleKind,
+    /// The field or method that the handle references
+    pub member: MemberRef<'a>,
+}
+
+type MethodMap = IndexMap<(Box<[u8]>, Box<[u8]>), MethodIndex>;
+
+/// The borrowed form of the key of a [`MethodMap`], for looking up without allocating
+/// This hashes the same as the owned tuple.
+#[derive(Hash)]
+struct MethodKey<'a>(&'a [u8], &'a [u8]);
+impl<'a> Equivalent<(Box<[u8]>, Box<[u8]>)> for MethodKey<'a> {
+    fn equivalent(&self, key: &(Box<[u8]>, Box<[u8]>)) -> bool {
+        self.0 == &*key.0 && self.1 == &*key.1
+    }
+}
+
+/// Where the bytes of a class file came from
+/// This is purely informational, and is primarily for debugging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassFileSource {
+    /// The bytes were given directly, such as if they were generated at runtime
+    Memory,
+    /// The class file was read from a file at this path
+    File(PathBuf),
+    /// The class file was read from an entry within a jar
+    Jar { jar_path: PathBuf, entry: String },
+}
+
+fn parse_class_file(class_file_data: &[u8]) -> Result<ClassFileOpt, LoadClassFileError> {
+    trace_span!("parse_class_file", bytes = class_file_data.len());
+    #[cfg(feature = "tracing-spans")]
+    let start = std::time::Instant::now();
+
+    // Check the magic before handing it off to the parser, since its errors for data that
+    // isn't a class file at all are not very helpful
+    if class_reader::Reader::new(class_file_data).u32() != Some(class_reader::CLASS_FILE_MAGIC) {
+        return Err(LoadClassFileError::NotAClassFile);
+    }
+
+    // TODO: Better errors
+    let (rem_data, class_file) = class_parser_opt(ParseData::new(class_file_data))
+        .map_err(|x| format!("{:?}", x))
+        .map_err(LoadClassFileError::ClassFileParseError)?;
+    // TODO: Don't assert
+    debug_assert!(rem_data.is_empty());
+
+    trace_event!(duration = ?start.elapsed(), "parsed class file");
+
+    Ok(class_file)
+}
+
+/// A class file that has been parsed, but not yet made into a [`ClassFileData`]
+/// Unlike [`ClassFileData`], this owns its data rather than sharing it through an `Rc`, and so it
+/// can be sent between threads. This allows parsing a class file on another thread.
+#[derive(Debug, Clone)]
+pub struct ParsedClassFile {
+    class_file_data: Vec<u8>,
+    class_file: ClassFileOpt,
+}
+impl ParsedClassFile {
+    /// Parse the class file from the given data
+    pub fn parse(class_file_data: Vec<u8>) -> Result<ParsedClassFile, LoadClassFileError> {
+        let class_file = parse_class_file(&class_file_data)?;
+        Ok(ParsedClassFile {
+            class_file_data,
+            class_file,
+        })
+    }
+
+    #[must_use]
+    pub fn into_class_file_data(self, id: ClassId, source: ClassFileSource) -> ClassFileData {
+        ClassFileData::new(id, source, Rc::from(self.class_file_data), self.class_file)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ClassFileData {
     pub(crate) id: ClassId,
+    pub(crate) source: ClassFileSource,
     /// The raw bytes of the class file
     /// We keep this around because the majority of class files are relatively small
     /// This could switch to holding a File, or just opening the file as needed, to read the bytes
@@ -38,17 +428,43 @@ pub struct ClassFileData {
     /// complicates the implementation, and so has not yet been done.
     pub(crate) class_file_data: Rc<[u8]>,
     pub(crate) class_file: ClassFileOpt,
+    /// Map from the name and descriptor of each method to its index, built the first time that
+    /// [`ClassFileData::find_method_b`] is called. The names and descriptors of the methods are
+    /// never modified after parsing, so this never has to be invalidated.
+    method_map: OnceCell<MethodMap>,
 }
 impl ClassFileData {
     #[must_use]
-    pub fn new(id: ClassId, class_file_data: Rc<[u8]>, class_file: ClassFileOpt) -> ClassFileData {
+    pub fn new(
+        id: ClassId,
+        source: ClassFileSource,
+        class_file_data: Rc<[u8]>,
+        class_file: ClassFileOpt,
+    ) -> ClassFileData {
         ClassFileData {
             id,
+            source,
             class_file_data,
             class_file,
+            method_map: OnceCell::new(),
         }
     }
 
+    /// Parse the class file from bytes that were not loaded from any path
+    pub fn from_bytes(id: ClassId, bytes: &[u8]) -> Result<ClassFileData, LoadClassFileError> {
+        ClassFileData::parse(id, ClassFileSource::Memory, Rc::from(bytes))
+    }
+
+    /// Parse the class file from the given data
+    pub fn parse(
+        id: ClassId,
+        source: ClassFileSource,
+        class_file_data: Rc<[u8]>,
+    ) -> Result<ClassFileData, LoadClassFileError> {
+        let class_file = parse_class_file(&class_file_data)?;
+        Ok(ClassFileData::new(id, source, class_file_data, class_file))
+    }
+
     #[must_use]
     /// Gets the classfile directly.
     /// There is _no_ guarantee that this is stable, and it may be removed without a major version
@@ -69,11 +485,77 @@ impl ClassFileData {
         self.id
     }
 
+    /// Drop the attributes which are only needed to verify or debug the class, along with the
+    /// constant pool text that only they referred to, to save memory once the class has been
+    /// verified.
+    /// The dropped attributes are `StackMapTable`, `LocalVariableTable`, `LocalVariableTypeTable`,
+    /// `SourceDebugExtension`, and the `RuntimeInvisible*Annotations` attributes, wherever they
+    /// appear, including within `Code`. Each `Utf8` entry that only they referred to (their names,
+    /// and the names and descriptors of the local variables) is replaced with empty text, but not
+    /// removed, so that every constant pool index stays the same.
+    /// Everything else is retained:
+    /// - Every other attribute, including `Code`, `LineNumberTable`, `Signature`, the visible
+    /// annotations, and attributes that we don't know about.
+    /// - Every other constant pool entry, since the interpreter resolves constants (`ldc`, field
+    /// and method refs) lazily as they are executed. A `Utf8` entry that appears anywhere in the
+    /// data of a retained attribute is kept, even if it is not actually referred to by it.
+    /// This reparses the class file, so the lookup caches are rebuilt as they are needed, and
+    /// anything which holds ranges into the old bytes (such as the `Code` of a method that was
+    /// already loaded) should be reloaded.
+    pub fn release_unneeded_constants(&mut self) -> Result<(), LoadClassFileError> {
+        let data = class_reader::release_attributes(&self.class_file_data).ok_or_else(|| {
+            LoadClassFileError::ClassFileParseError("Malformed class file structure".to_owned())
+        })?;
+        *self = ClassFileData::parse(self.id, self.source.clone(), Rc::from(data))?;
+
+        Ok(())
+    }
+
+    #[must_use]
+    /// Where the class file was loaded from
+    pub fn source(&self) -> &ClassFileSource {
+        &self.source
+    }
+
     #[must_use]
     pub fn version(&self) -> Option<ClassFileVersion> {
         Some(self.class_file.version)
     }
 
+    #[must_use]
+    pub fn major_version(&self) -> u16 {
+        self.class_file.version.major
+    }
+
+    #[must_use]
+    pub fn minor_version(&self) -> u16 {
+        self.class_file.version.minor
+    }
+
+    #[must_use]
+    /// Whether the class file depends on the preview features of its Java SE version
+    pub fn is_preview(&self) -> bool {
+        self.minor_version() == 0xFFFF
+    }
+
+    #[must_use]
+    /// Get the Java SE version that the class file's major version corresponds to
+    /// Ex: 52 -> 8, 55 -> 11, 61 -> 17
+    /// The releases before Java 5 (major versions 45 through 48) are all reported as `1`.
+    pub fn java_se_version(&self) -> Option<u32> {
+        match self.major_version() {
+            0..=44 => None,
+            45..=48 => Some(1),
+            major => Some(u32::from(major) - 44),
+        }
+    }
+
+    #[must_use]
+    /// Whether the class file's version is high enough for it to use the feature
+    pub fn supports(&self, feature: ClassFileFeature) -> bool {
+        self.major_version() >= feature.min_major_version()
+    }
+
     pub fn get_t<'a, T>(&'a self, i: impl TryInto<ConstantPoolIndex<T>>) -> Option<&'a T>
     where
         &'a T: TryFrom<&'a ConstantInfo>,
@@ -86,10 +568,236 @@ impl ClassFileData {
         self.get_t(i).map(|x| x.as_text(&self.class_file_data))
     }
 
+    /// Get the text of the utf8 constant as an interned symbol, interning it if needed
+    pub fn get_symbol(
+        &self,
+        i: impl TryInto<ConstantPoolIndex<Utf8Constant>>,
+        symbols: &mut Symbols,
+    ) -> Option<Symbol> {
+        self.get_text_t(i).map(|text| symbols.intern(&text))
+    }
+
+    /// Get the text of the utf8 constant, decoded from the modified UTF-8 that class files use.
+    /// Returns `None` if the index is invalid or if the text is not valid modified UTF-8, unlike
+    /// [`ClassFileData::get_text_t`] which may replace invalid characters.
+    pub fn get_modified_utf8(
+        &self,
+        i: impl TryInto<ConstantPoolIndex<Utf8Constant>>,
+    ) -> Option<Cow<str>> {
+        self.get_text_b(i).and_then(decode_modified_utf8)
+    }
+
     pub fn get_text_b(&self, i: impl TryInto<ConstantPoolIndex<Utf8Constant>>) -> Option<&[u8]> {
         self.get_t(i).map(|x| x.as_bytes(&self.class_file_data))
     }
 
+    #[must_use]
+    /// The `constant_pool_count` of the class file, which is one more than the number of entries
+    /// since the constant pool is indexed from 1
+    pub fn constant_pool_count(&self) -> u16 {
+        class_reader::constant_pool_count(&self.class_file_data).unwrap_or(0)
+    }
+
+    /// Iterate over the entries of the constant pool, with their index
+    /// The unusable entry after a long/double is skipped, as are indices that fail to resolve.
+    pub fn constants(
+        &self,
+    ) -> impl Iterator<Item = (ConstantPoolIndexRaw<ConstantInfo>, &ConstantInfo)> + '_ {
+        (1..self.constant_pool_count())
+            .map(ConstantPoolIndexRaw::<ConstantInfo>::new)
+            .filter_map(|index| Some((index, self.get_t(index)?)))
+            .filter(|(_, info)| !matches!(info, ConstantInfo::Unusable))
+    }
+
+    /// Iterate over the names of every `CONSTANT_Class` in the constant pool, in the order that
+    /// they appear. This may contain duplicates, and array classes will have their descriptor as
+    /// their name, like `[Ljava/lang/String;`.
+    /// Classes whose name does not resolve are skipped.
+    pub fn referenced_class_names(&self) -> impl Iterator<Item = Cow<str>> + '_ {
+        self.constants().filter_map(|(_, info)| match info {
+            ConstantInfo::Class(class) => self.get_text_t(class.name_index),
+            _ => None,
+        })
+    }
+
+    /// Resolve the class name, name, and descriptor of a field or method reference
+    fn resolve_member_ref(
+        &self,
+        class_index: ConstantPoolIndexRaw<ClassConstant>,
+        name_and_type_index: ConstantPoolIndexRaw<NameAndTypeConstant>,
+        is_interface: bool,
+    ) -> Option<MemberRef<'_>> {
+        let class = self.get_t(class_index)?;
+        let (name, descriptor) = self.get_name_and_type(name_and_type_index)?;
+        Some(MemberRef {
+            class_name: self.get_text_t(class.name_index)?,
+            name,
+            descriptor,
+            is_interface,
+        })
+    }
+
+    /// Resolve the name and descriptor of the `CONSTANT_NameAndType` at the given index
+    pub fn get_name_and_type(
+        &self,
+        index: ConstantPoolIndexRaw<NameAndTypeConstant>,
+    ) -> Option<(Cow<str>, Cow<str>)> {
+        let nat = self.get_t(index)?;
+        Some((
+            self.get_text_t(nat.name_index)?,
+            self.get_text_t(nat.descriptor_index)?,
+        ))
+    }
+
+    /// Decode the `CONSTANT_MethodHandle` at the given index, resolving the member it references
+    /// This checks the rules on the reference kind from the JVM specification:
+    /// - field kinds must reference a `CONSTANT_Fieldref`
+    /// - `InvokeVirtual` and `NewInvokeSpecial` must reference a `CONSTANT_Methodref`
+    /// - `InvokeStatic` and `InvokeSpecial` must reference a `CONSTANT_Methodref`, or, since
+    ///   version 52, a `CONSTANT_InterfaceMethodref`
+    /// - `InvokeInterface` must reference a `CONSTANT_InterfaceMethodref`
+    /// - `NewInvokeSpecial` must reference `<init>`, and the other method kinds must not reference
+    ///   `<init>` or `<clinit>`
+    pub fn method_handle(
+        &self,
+        index: ConstantPoolIndexRaw<MethodHandleConstant>,
+    ) -> Result<MethodHandleInfo<'_>, MethodHandleError> {
+        let handle = self
+            .get_t(index)
+            .ok_or(MethodHandleError::InvalidIndex(index))?;
+        let kind = MethodHandleKind::from_reference_kind(handle.reference_kind).ok_or(
+            MethodHandleError::InvalidReferenceKind(handle.reference_kind),
+        )?;
+
+        let reference_index = handle.reference_index.0;
+        let reference = self
+            .get_t(ConstantPoolIndexRaw::<ConstantInfo>::new(reference_index))
+            .ok_or(MethodHandleError::InvalidReferenceIndex(reference_index))?;
+        let (member, is_field) = match reference {
+            ConstantInfo::FieldRef(field) => (
+                self.resolve_member_ref(field.class_index, field.name_and_type_index, false),
+                true,
+            ),
+            ConstantInfo::MethodRef(method) => (
+                self.resolve_member_ref(method.class_index, method.name_and_type_index, false),
+                false,
+            ),
+            ConstantInfo::InterfaceMethodRef(method) => (
+                self.resolve_member_ref(method.class_index, method.name_and_type_index, true),
+                false,
+            ),
+            _ => return Err(MethodHandleError::InvalidReferenceIndex(reference_index)),
+        };
+        let member = member.ok_or(MethodHandleError::InvalidReferenceIndex(reference_index))?;
+
+        let is_valid_type = match kind {
+            MethodHandleKind::GetField
+            | MethodHandleKind::GetStatic
+            | MethodHandleKind::PutField
+            | MethodHandleKind::PutStatic => is_field,
+            MethodHandleKind::InvokeVirtual | MethodHandleKind::NewInvokeSpecial => {
+                !is_field && !member.is_interface
+            }
+            MethodHandleKind::InvokeStatic | MethodHandleKind::InvokeSpecial => {
+                !is_field
+                    && (!member.is_interface || self.supports(ClassFileFeature::DefaultMethods))
+            }
+            MethodHandleKind::InvokeInterface => member.is_interface,
+        };
+        if !is_valid_type {
+            return Err(MethodHandleError::WrongMemberType { kind });
+        }
+
+        if !is_field {
+            let is_init = member.name == "<init>";
+            let is_valid_name = if kind == MethodHandleKind::NewInvokeSpecial {
+                is_init
+            } else {
+                !is_init && member.name != "<clinit>"
+            };
+            if !is_valid_name {
+                return Err(MethodHandleError::InvalidMethodName { kind });
+            }
+        }
+
+        Ok(MethodHandleInfo { kind, member })
+    }
+
+    #[must_use]
+    /// Get the method descriptor of the `CONSTANT_MethodType` at the given index
+    pub fn method_type(&self, index: ConstantPoolIndexRaw<MethodTypeConstant>) -> Option<Cow<str>> {
+        let method_type = self.get_t(index)?;
+        self.get_text_t(method_type.descriptor_index)
+    }
+
+    #[must_use]
+    /// Get the value of the `CONSTANT_Integer` at the given index
+    pub fn get_integer(
+        &self,
+        index: impl TryInto<ConstantPoolIndex<IntegerConstant>>,
+    ) -> Option<i32> {
+        self.get_t(index).map(|v| v.value)
+    }
+
+    #[must_use]
+    /// Get the value of the `CONSTANT_Long` at the given index
+    pub fn get_long(&self, index: impl TryInto<ConstantPoolIndex<LongConstant>>) -> Option<i64> {
+        self.get_t(index).map(|v| v.value)
+    }
+
+    #[must_use]
+    /// Get the value of the `CONSTANT_Float` at the given index
+    pub fn get_float(&self, index: impl TryInto<ConstantPoolIndex<FloatConstant>>) -> Option<f32> {
+        self.get_t(index).map(|v| v.value)
+    }
+
+    #[must_use]
+    /// Get the value of the `CONSTANT_Double` at the given index
+    pub fn get_double(
+        &self,
+        index: impl TryInto<ConstantPoolIndex<DoubleConstant>>,
+    ) -> Option<f64> {
+        self.get_t(index).map(|v| v.value)
+    }
+
+    #[must_use]
+    /// Get the text of the `CONSTANT_String` at the given index
+    /// This is a `Cow` rather than a `&str` since the class file stores the text as modified
+    /// UTF-8, which has to be converted if it has any null or supplementary characters.
+    pub fn get_string(
+        &self,
+        index: impl TryInto<ConstantPoolIndex<StringConstant>>,
+    ) -> Option<Cow<str>> {
+        let string = self.get_t(index)?;
+        self.get_text_t(string.string_index)
+    }
+
+    /// Iterate over every `CONSTANT_Methodref` and `CONSTANT_InterfaceMethodref` in the constant
+    /// pool, in the order that they appear. See [`MemberRef::is_interface`].
+    /// References that do not resolve are skipped.
+    pub fn referenced_methodrefs(&self) -> impl Iterator<Item = MemberRef<'_>> + '_ {
+        self.constants().filter_map(|(_, info)| match info {
+            ConstantInfo::MethodRef(method) => {
+                self.resolve_member_ref(method.class_index, method.name_and_type_index, false)
+            }
+            ConstantInfo::InterfaceMethodRef(method) => {
+                self.resolve_member_ref(method.class_index, method.name_and_type_index, true)
+            }
+            _ => None,
+        })
+    }
+
+    /// Iterate over every `CONSTANT_Fieldref` in the constant pool, in the order that they appear
+    /// References that do not resolve are skipped.
+    pub fn referenced_fieldrefs(&self) -> impl Iterator<Item = MemberRef<'_>> + '_ {
+        self.constants().filter_map(|(_, info)| match info {
+            ConstantInfo::FieldRef(field) => {
+                self.resolve_member_ref(field.class_index, field.name_and_type_index, false)
+            }
+            _ => None,
+        })
+    }
+
     #[must_use]
     pub fn load_attribute_range_with_name(&self, name: &str) -> Option<Range<usize>> {
         self.class_file
@@ -98,6 +806,221 @@ impl ClassFileData {
             .flatten()
     }
 
+    #[must_use]
+    /// Get the data of the class-level attribute with the given name, if it exists
+    /// This is the raw data of the attribute, not including its name index or length.
+    pub fn attribute(&self, name: &str) -> Option<&[u8]> {
+        self.load_attribute_range_with_name(name)
+            .and_then(|range| self.class_file_data.get(range))
+    }
+
+    #[must_use]
+    /// Get the name of the source file that this class was compiled from, such as `Main.java`
+    /// This is an optional attribute, so it is fine for it to not exist.
+    pub fn source_file(&self) -> Option<Cow<str>> {
+        let data = self.attribute("SourceFile")?;
+        let sourcefile_index = class_reader::Reader::new(data).u16()?;
+        self.get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(sourcefile_index))
+    }
+
+    #[must_use]
+    /// Get the raw generic signature of the class from its `Signature` attribute
+    /// Ex: `<T:Ljava/lang/Object;>Ljava/lang/Object;Ljava/lang/Comparable<TT;>;`
+    /// This only exists if the class uses generics in its declaration.
+    pub fn generic_signature(&self) -> Option<Cow<str>> {
+        let data = self.attribute("Signature")?;
+        let signature_index = class_reader::Reader::new(data).u16()?;
+        self.get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(signature_index))
+    }
+
+    /// Parse the generic signature of the class, see [`ClassFileData::generic_signature`]
+    /// Returns `None` if the class does not have one.
+    pub fn parse_class_signature(&self) -> Option<Result<ClassSignature, SignatureParseError>> {
+        self.generic_signature()
+            .map(|signature| signature::parse_class_signature(&signature))
+    }
+
+    #[must_use]
+    /// Parse the annotations on the class from its `RuntimeVisibleAnnotations` attribute
+    /// Returns `None` if there is no such attribute or if it is malformed.
+    pub fn runtime_visible_annotations(&self) -> Option<Vec<Annotation>> {
+        let mut reader = class_reader::Reader::new(self.attribute("RuntimeVisibleAnnotations")?);
+        annotation::parse_annotations(&mut reader)
+    }
+
+    #[must_use]
+    /// Whether this is a record class
+    /// There is no access flag for records, so this checks that it extends `java/lang/Record` and
+    /// has a `Record` attribute, in a class file version which supports records.
+    pub fn is_record(&self) -> bool {
+        if !self.supports(ClassFileFeature::Records) {
+            return false;
+        }
+
+        let super_name = self
+            .get_t(self.class_file.super_class)
+            .and_then(|class| self.get_text_b(class.name_index));
+        super_name == Some(b"java/lang/Record".as_slice()) && self.attribute("Record").is_some()
+    }
+
+    #[must_use]
+    /// Parse the components of the record from its `Record` attribute
+    /// Returns `None` if the class file version does not support records, if there is no `Record`
+    /// attribute, or if it is malformed.
+    pub fn record_components(&self) -> Option<Vec<RecordComponentInfo>> {
+        if !self.supports(ClassFileFeature::Records) {
+            return None;
+        }
+
+        let data = self.attribute("Record")?;
+        let mut reader = class_reader::Reader::new(data);
+
+        let count = reader.u16()?;
+        let mut components = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let name = self.get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(reader.u16()?))?;
+            let descriptor =
+                self.get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(reader.u16()?))?;
+
+            let attributes = class_reader::read_attributes(&mut reader)?
+                .into_iter()
+                .map(|attr| {
+                    let name = self
+                        .get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(attr.name_index))?;
+                    Some((name, data.get(attr.range)?))
+                })
+                .collect::<Option<Vec<_>>>()?;
+
+            components.push(RecordComponentInfo {
+                name,
+                descriptor,
+                attributes,
+            });
+        }
+
+        Some(components)
+    }
+
+    /// Get the host of the nest that this class claims to be a member of, from its `NestHost`
+    /// attribute
+    /// Returns `None` if there is no such attribute, which means that it is either the host of its
+    /// own nest or in no nest at all, or if it is malformed.
+    /// This is only the claim, see [`crate::data::class_files::ClassFiles::nest_host_of`] for
+    /// the validated host.
+    pub fn nest_host(&self, class_names: &mut ClassNames) -> Option<ClassId> {
+        let host_index = class_reader::Reader::new(self.attribute("NestHost")?).u16()?;
+        self.class_id_from_constant(ConstantPoolIndexRaw::new(host_index), class_names)
+            .ok()
+    }
+
+    /// Get the members of the nest that this class hosts, from its `NestMembers` attribute
+    /// Returns `None` if there is no such attribute or if it is malformed.
+    pub fn nest_members(&self, class_names: &mut ClassNames) -> Option<Vec<ClassId>> {
+        self.class_ids_attribute("NestMembers", class_names)
+    }
+
+    #[must_use]
+    /// Whether this class is sealed, which restricts which classes may directly extend or
+    /// implement it
+    pub fn is_sealed(&self) -> bool {
+        self.supports(ClassFileFeature::SealedClasses)
+            && self.attribute("PermittedSubclasses").is_some()
+    }
+
+    /// Get the classes which are permitted to directly extend or implement this class, from its
+    /// `PermittedSubclasses` attribute
+    /// Returns `None` if the class is not sealed or if the attribute is malformed.
+    pub fn permitted_subclasses(&self, class_names: &mut ClassNames) -> Option<Vec<ClassId>> {
+        if !self.supports(ClassFileFeature::SealedClasses) {
+            return None;
+        }
+
+        self.class_ids_attribute("PermittedSubclasses", class_names)
+    }
+
+    #[must_use]
+    /// Parse the entries of the `BootstrapMethods` attribute
+    /// Returns `None` if there is no such attribute or if it is malformed.
+    pub fn bootstrap_methods(&self) -> Option<Vec<BootstrapMethod>> {
+        let mut reader = class_reader::Reader::new(self.attribute("BootstrapMethods")?);
+
+        let count = reader.u16()?;
+        let mut methods = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            methods.push(Self::read_bootstrap_method(&mut reader)?);
+        }
+
+        Some(methods)
+    }
+
+    #[must_use]
+    /// Get the entry of the `BootstrapMethods` attribute at the given index, as used by the
+    /// `bootstrap_method_attr_index` of `CONSTANT_InvokeDynamic` and `CONSTANT_Dynamic`
+    /// This only parses the entries up to the one requested.
+    pub fn bootstrap_method(&self, index: u16) -> Option<BootstrapMethod> {
+        let mut reader = class_reader::Reader::new(self.attribute("BootstrapMethods")?);
+
+        let count = reader.u16()?;
+        if index >= count {
+            return None;
+        }
+
+        for _ in 0..index {
+            reader.skip(2)?;
+            let argument_count = reader.u16()?;
+            reader.skip(usize::from(argument_count) * 2)?;
+        }
+
+        Self::read_bootstrap_method(&mut reader)
+    }
+
+    fn read_bootstrap_method(reader: &mut class_reader::Reader<'_>) -> Option<BootstrapMethod> {
+        let method_ref = ConstantPoolIndexRaw::new(reader.u16()?);
+
+        let argument_count = reader.u16()?;
+        let mut arguments = Vec::with_capacity(usize::from(argument_count));
+        for _ in 0..argument_count {
+            arguments.push(ConstantPoolIndexRaw::new(reader.u16()?));
+        }
+
+        Some(BootstrapMethod {
+            method_ref,
+            arguments,
+        })
+    }
+
+    /// Read an attribute which is a `u16` count followed by that many `CONSTANT_Class` indices
+    fn class_ids_attribute(
+        &self,
+        name: &str,
+        class_names: &mut ClassNames,
+    ) -> Option<Vec<ClassId>> {
+        let mut reader = class_reader::Reader::new(self.attribute(name)?);
+
+        let count = reader.u16()?;
+        let mut ids = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let index = ConstantPoolIndexRaw::new(reader.u16()?);
+            ids.push(self.class_id_from_constant(index, class_names).ok()?);
+        }
+
+        Some(ids)
+    }
+
+    /// Iterate over the class-level attributes, with their names and raw data
+    /// Attributes with an invalid name index are skipped.
+    // TODO: Cache the offset of the attributes so we don't have to walk the class file each time
+    pub fn attributes(&self) -> impl Iterator<Item = (Cow<str>, &[u8])> + '_ {
+        class_reader::class_attributes(&self.class_file_data)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|attr| {
+                let name =
+                    self.get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(attr.name_index))?;
+                Some((name, &self.class_file_data[attr.range]))
+            })
+    }
+
     pub fn load_method_info_by_index(
         &self,
         index: MethodIndex,
@@ -128,6 +1051,124 @@ impl ClassFileData {
         self.class_file.load_method_opt_iter(&self.class_file_data)
     }
 
+    #[must_use]
+    /// Get the name of the method at the given index
+    pub fn method_name(&self, index: MethodIndex) -> Option<Cow<str>> {
+        let method = self.load_method_info_opt_by_index(index).ok()?;
+        self.get_text_t(method.name_index)
+    }
+
+    #[must_use]
+    /// Get the unparsed descriptor of the method at the given index
+    pub fn method_descriptor(&self, index: MethodIndex) -> Option<Cow<str>> {
+        let method = self.load_method_info_opt_by_index(index).ok()?;
+        self.get_text_t(method.descriptor_index)
+    }
+
+    #[must_use]
+    /// Find the index of the method with exactly the given name and descriptor
+    /// Ex: `("equals", "(Ljava/lang/Object;)Z")`
+    /// This compares the descriptor textually, see
+    /// [`crate::data::methods::Methods::load_method_from_desc`] for a parsed comparison.
+    pub fn find_method(&self, name: &str, descriptor: &str) -> Option<MethodIndex> {
+        self.find_method_b(
+            &cesu8::to_java_cesu8(name),
+            &cesu8::to_java_cesu8(descriptor),
+        )
+    }
+
+    #[must_use]
+    /// [`ClassFileData::find_method`] but for the raw cesu8 bytes of the name and descriptor
+    pub fn find_method_b(&self, name: &[u8], descriptor: &[u8]) -> Option<MethodIndex> {
+        self.method_map
+            .get_or_init(|| self.build_method_map())
+            .get(&MethodKey(name, descriptor))
+            .copied()
+    }
+
+    fn build_method_map(&self) -> MethodMap {
+        let mut map = MethodMap::with_capacity(usize::from(self.methods_len()));
+        for (index, method) in self.load_method_info_opt_iter_with_index() {
+            let name = self.get_text_b(method.name_index);
+            let descriptor = self.get_text_b(method.descriptor_index);
+            if let (Some(name), Some(descriptor)) = (name, descriptor) {
+                // If there are duplicates, which is invalid, then the first one wins like it
+                // would with a linear search
+                map.entry((Box::from(name), Box::from(descriptor)))
+                    .or_insert(index);
+            }
+        }
+
+        map
+    }
+
+    #[must_use]
+    /// Get the access flags of the method at the given index
+    /// Returns `None` if there is no method at that index.
+    pub fn method_flags(&self, index: MethodIndex) -> Option<MethodAccessFlags> {
+        if index >= self.methods_len() {
+            return None;
+        }
+
+        self.load_method_info_opt_by_index(index)
+            .ok()
+            .map(|method| method.access_flags)
+    }
+
+    #[must_use]
+    /// Check whether the method at the given index is signature polymorphic (JVMS 2.9.3), like
+    /// `MethodHandle.invokeExact`
+    /// These are the native varargs methods of `MethodHandle` and `VarHandle` which take a single
+    /// `Object[]`. Calls to them are not required to match their declared descriptor.
+    pub fn is_signature_polymorphic(&self, index: MethodIndex) -> bool {
+        let Ok(this_name) = self.get_this_class_name() else {
+            return false;
+        };
+        if !SIGNATURE_POLYMORPHIC_CLASSES.contains(&this_name) {
+            return false;
+        }
+
+        let Ok(method) = self.load_method_info_opt_by_index(index) else {
+            return false;
+        };
+        method
+            .access_flags
+            .contains(MethodAccessFlags::VARARGS | MethodAccessFlags::NATIVE)
+            && self
+                .get_text_b(method.descriptor_index)
+                .is_some_and(|descriptor| descriptor.starts_with(b"([Ljava/lang/Object;)"))
+    }
+
+    /// Iterate over the indices of the instance initialization methods, those named `<init>`
+    pub fn constructors(&self) -> impl Iterator<Item = MethodIndex> + '_ {
+        self.load_method_info_opt_iter_with_index()
+            .filter(|(_, method)| self.get_text_b(method.name_index) == Some(b"<init>".as_slice()))
+            .map(|(index, _)| index)
+    }
+
+    #[must_use]
+    /// Find the index of the constructor that takes no arguments, `<init>` with the descriptor
+    /// `()V`. This does not check whether it is accessible, see
+    /// [`ClassFileData::method_flags`] for that.
+    pub fn default_constructor(&self) -> Option<MethodIndex> {
+        self.find_method_b(b"<init>", b"()V")
+    }
+
+    #[must_use]
+    /// Find the index of the class initialization method, `<clinit>`
+    /// Only a `<clinit>` with the descriptor `()V` counts, and since version 51 it must also be
+    /// static. Other methods with that name are of no consequence, as per the JVM spec.
+    pub fn class_initializer(&self) -> Option<MethodIndex> {
+        let requires_static = self.major_version() >= 51;
+        self.load_method_info_opt_iter_with_index()
+            .find(|(_, method)| {
+                self.get_text_b(method.name_index) == Some(b"<clinit>".as_slice())
+                    && self.get_text_b(method.descriptor_index) == Some(b"()V".as_slice())
+                    && (!requires_static || method.access_flags.contains(MethodAccessFlags::STATIC))
+            })
+            .map(|(index, _)| index)
+    }
+
     /// Load all the methods from the class file into memory
     /// This should be used if you're going to be iterating over all/most methods
     /// Since the individual seeking methods would be slower if they were not laoded at all
@@ -148,6 +1189,113 @@ impl ClassFileData {
             .flatten()
     }
 
+    #[must_use]
+    /// Read the `Code` attribute of the method at the given index
+    /// Native and abstract methods do not have code, so `None` is a normal result.
+    pub fn method_code(&self, index: MethodIndex) -> Option<CodeAttribute<'_>> {
+        let range = self.load_method_attribute_info_range_by_name(index, "Code")?;
+        class_reader::read_code_attribute(&self.class_file_data, range)
+    }
+
+    /// Get the range of the attribute with the given name on a method's `Code` attribute
+    fn method_code_attribute_range(&self, index: MethodIndex, name: &str) -> Option<Range<usize>> {
+        let code = self.method_code(index)?;
+        code.attributes
+            .into_iter()
+            .find(|attr| {
+                self.get_text_b(ConstantPoolIndexRaw::<Utf8Constant>::new(attr.name_index))
+                    == Some(name.as_bytes())
+            })
+            .map(|attr| attr.range)
+    }
+
+    /// Get the data of every attribute with the given name on a method's `Code` attribute, in
+    /// the order they appear
+    /// Returns `None` if there are none.
+    fn method_code_attributes(&self, index: MethodIndex, name: &str) -> Option<Vec<&[u8]>> {
+        let code = self.method_code(index)?;
+        let attributes = code
+            .attributes
+            .into_iter()
+            .filter(|attr| {
+                self.get_text_b(ConstantPoolIndexRaw::<Utf8Constant>::new(attr.name_index))
+                    == Some(name.as_bytes())
+            })
+            .map(|attr| self.class_file_data.get(attr.range))
+            .collect::<Option<Vec<_>>>()?;
+
+        if attributes.is_empty() {
+            None
+        } else {
+            Some(attributes)
+        }
+    }
+
+    #[must_use]
+    /// Get the `(start_pc, line_number)` pairs for the method at the given index
+    /// A compiler may split the table across several `LineNumberTable` attributes, in which case
+    /// their entries are concatenated.
+    /// See [`class_reader::line_for_pc`] for finding the line of some instruction
+    pub fn line_number_table(&self, index: MethodIndex) -> Option<Vec<(u16, u16)>> {
+        let mut lines = Vec::new();
+        for data in self.method_code_attributes(index, "LineNumberTable")? {
+            let mut reader = class_reader::Reader::new(data);
+            let length = reader.u16()?;
+            for _ in 0..length {
+                lines.push((reader.u16()?, reader.u16()?));
+            }
+        }
+
+        Some(lines)
+    }
+
+    #[must_use]
+    /// Get the local variables for the method at the given index, for debugging
+    /// This is an optional attribute, so it is fine for it to not exist. Like the line numbers,
+    /// the entries of every `LocalVariableTable` attribute are concatenated.
+    pub fn local_variable_table(&self, index: MethodIndex) -> Option<Vec<LocalVariableEntry<'_>>> {
+        let mut variables = Vec::new();
+        for data in self.method_code_attributes(index, "LocalVariableTable")? {
+            let mut reader = class_reader::Reader::new(data);
+            let length = reader.u16()?;
+            for _ in 0..length {
+                let start_pc = reader.u16()?;
+                let length = reader.u16()?;
+                let name_index = reader.u16()?;
+                let descriptor_index = reader.u16()?;
+                let slot = reader.u16()?;
+                variables.push(LocalVariableEntry {
+                    start_pc,
+                    length,
+                    name: self.get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(name_index))?,
+                    descriptor: self
+                        .get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(descriptor_index))?,
+                    slot,
+                });
+            }
+        }
+
+        Some(variables)
+    }
+
+    #[must_use]
+    /// Parse the frames of the `StackMapTable` for the method at the given index
+    /// These are the frames as they are written in the class file, where each is relative to the
+    /// previous. See [`crate::code::stack_map::StackMapFramesProcessor`] for getting the full
+    /// frame at each point.
+    /// Returns `None` if there is no `StackMapTable`.
+    pub fn stack_map_frames(
+        &self,
+        index: MethodIndex,
+    ) -> Option<Result<Vec<RawStackMapFrame>, StackMapError>> {
+        let range = self.method_code_attribute_range(index, "StackMapTable")?;
+        Some(
+            stack_map_table_attribute_parser(self.parse_data_for(range))
+                .map(|(_, table)| table.entries)
+                .map_err(|_| StackMapError::ParseError),
+        )
+    }
+
     pub fn load_field_values_iter(
         &self,
     ) -> impl Iterator<
@@ -157,11 +1305,51 @@ impl ClassFileData {
             .load_fields_values_iter(&self.class_file_data)
     }
 
+    /// Iterate over the fields of the class file, in order.
+    /// Fields are parsed lazily from the backing data, hence the `Result`.
+    pub fn fields(&self) -> impl Iterator<Item = Result<FieldInfoOpt, LoadError>> + '_ {
+        self.load_field_values_iter()
+            .map(|x| x.map(|(field_info, _)| field_info))
+    }
+
+    /// Get the field at the given index
+    /// Returns `Ok(None)` if there is no field at that index
+    pub fn get_field(&self, index: FieldIndex) -> Result<Option<FieldInfoOpt>, LoadError> {
+        self.fields().nth(usize::from(index)).transpose()
+    }
+
+    #[must_use]
+    /// Get the value from the `ConstantValue` attribute of the field at the given index
+    /// This is what `static final` fields are initialized to before `<clinit>` is run.
+    /// Returns `None` if the field does not have the attribute, or if it refers to a constant
+    /// that is not a valid field value.
+    /// Note: `boolean`, `byte`, `char` and `short` fields store their value as an `Int`.
+    pub fn field_constant_value(&self, index: FieldIndex) -> Option<ConstantValueKind<'_>> {
+        let (_, value_index) = self
+            .load_field_values_iter()
+            .nth(usize::from(index))?
+            .ok()?;
+
+        Some(match self.get_t(value_index?)? {
+            ConstantInfo::Integer(v) => ConstantValueKind::Int(v.value),
+            ConstantInfo::Long(v) => ConstantValueKind::Long(v.value),
+            ConstantInfo::Float(v) => ConstantValueKind::Float(v.value),
+            ConstantInfo::Double(v) => ConstantValueKind::Double(v.value),
+            ConstantInfo::String(v) => ConstantValueKind::String(self.get_text_t(v.string_index)?),
+            _ => return None,
+        })
+    }
+
     #[must_use]
     pub fn methods_len(&self) -> u16 {
         self.class_file.methods.len() as u16
     }
 
+    #[must_use]
+    pub fn fields_len(&self) -> FieldIndex {
+        self.class_file.fields.len() as FieldIndex
+    }
+
     // #[must_use]
     // pub fn get_method(&self, index: usize) -> Option<&MethodInfo> {
     //     self.class_file.methods.get(index)
@@ -178,45 +1366,266 @@ impl ClassFileData {
     }
 
     pub(crate) fn get_this_class_name(&self) -> Result<&[u8], ClassFileIndexError> {
-        let this_class = self
-            .get_t(self.class_file.this_class)
-            .ok_or(ClassFileIndexError::InvalidThisClassIndex)?;
-        self.get_text_b(this_class.name_index)
-            .ok_or(ClassFileIndexError::InvalidThisClassNameIndex)
+        let this_class = self.get_t(self.class_file.this_class).ok_or(
+            ClassFileIndexError::InvalidThisClassIndex(self.class_file.this_class),
+        )?;
+        self.get_text_b(this_class.name_index).ok_or(
+            ClassFileIndexError::InvalidThisClassNameIndex(this_class.name_index),
+        )
+    }
+
+    /// Get the id of the class referred to by the `CONSTANT_Class` at the given index
+    pub fn class_id_from_constant(
+        &self,
+        index: ConstantPoolIndexRaw<ClassConstant>,
+        class_names: &mut ClassNames,
+    ) -> Result<ClassId, ClassFileIndexError> {
+        let class = self
+            .get_t(index)
+            .ok_or(ClassFileIndexError::InvalidClassIndex(index))?;
+        let class_name = self
+            .get_text_b(class.name_index)
+            .ok_or(ClassFileIndexError::InvalidClassNameIndex(class.name_index))?;
+        Ok(class_names.gcid_from_bytes(class_name))
     }
 
-    pub(crate) fn get_super_class_name(&self) -> Result<Option<&[u8]>, ClassFileIndexError> {
-        // There is no base class
-        // Only java/lang/Object should have no base class, but we don't do that verification here
-        if self.class_file.super_class.is_zero() {
-            return Ok(None);
+    pub(crate) fn get_super_class_id(
+        &self,
+        class_names: &mut ClassNames,
+    ) -> Result<Option<ClassId>, ClassFileIndexError> {
+        // There is no base class
+        // Only java/lang/Object should have no base class, but we don't do that verification here
+        if self.class_file.super_class.is_zero() {
+            return Ok(None);
+        }
+
+        self.class_id_from_constant(self.class_file.super_class, class_names)
+            .map(Some)
+            .map_err(|err| match err {
+                ClassFileIndexError::InvalidClassIndex(index) => {
+                    ClassFileIndexError::InvalidSuperClassIndex(index)
+                }
+                ClassFileIndexError::InvalidClassNameIndex(index) => {
+                    ClassFileIndexError::InvalidSuperClassNameIndex(index)
+                }
+                err => err,
+            })
+    }
+
+    pub fn interfaces_indices_iter(
+        &self,
+    ) -> impl Iterator<Item = ConstantPoolIndexRaw<ClassConstant>> + '_ {
+        self.class_file.interfaces.iter().copied()
+    }
+
+    /// Resolve the ids of the interfaces that this class directly implements, in order
+    pub fn interface_ids(
+        &self,
+        class_names: &mut ClassNames,
+    ) -> Result<Vec<ClassId>, ClassFileIndexError> {
+        self.interfaces_indices_iter()
+            .map(|index| {
+                self.class_id_from_constant(index, class_names)
+                    .map_err(|err| match err {
+                        ClassFileIndexError::InvalidClassIndex(index) => {
+                            ClassFileIndexError::InvalidInterfaceIndex(index)
+                        }
+                        ClassFileIndexError::InvalidClassNameIndex(index) => {
+                            ClassFileIndexError::InvalidInterfaceNameIndex(index)
+                        }
+                        err => err,
+                    })
+            })
+            .collect()
+    }
+
+    #[must_use]
+    /// Parse the entries of the `InnerClasses` attribute
+    /// Returns `None` if the attribute does not exist or is truncated.
+    pub fn inner_classes(&self) -> Option<Vec<InnerClassInfo>> {
+        let mut reader = class_reader::Reader::new(self.attribute("InnerClasses")?);
+
+        let count = reader.u16()?;
+        let mut inner_classes = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let inner_class = ConstantPoolIndexRaw::new(reader.u16()?);
+            let outer_class = reader.u16()?;
+            let inner_name = reader.u16()?;
+            // Flags which aren't defined are ignored, as they are for the class itself
+            let access_flags = InnerClassAccessFlags::from_bits_truncate(reader.u16()?);
+
+            // Zero means that there is no value
+            inner_classes.push(InnerClassInfo {
+                inner_class,
+                outer_class: (outer_class != 0).then(|| ConstantPoolIndexRaw::new(outer_class)),
+                inner_name: (inner_name != 0).then(|| ConstantPoolIndexRaw::new(inner_name)),
+                access_flags,
+            });
+        }
+
+        Some(inner_classes)
+    }
+
+    /// Find the `InnerClasses` entry which describes this class itself, if it is a nested class
+    fn own_inner_class_info(&self) -> Option<InnerClassInfo> {
+        let this_name = self.get_this_class_name().ok()?;
+        self.inner_classes()?.into_iter().find(|info| {
+            self.get_t(info.inner_class)
+                .and_then(|class| self.get_text_b(class.name_index))
+                == Some(this_name)
+        })
+    }
+
+    #[must_use]
+    /// Whether this is an anonymous class, such as `new Runnable() { ... }`
+    pub fn is_anonymous(&self) -> bool {
+        self.own_inner_class_info()
+            .map_or(false, |info| info.inner_name.is_none())
+    }
+
+    #[must_use]
+    /// Whether this is a local class, one which is declared with a name inside of a method
+    pub fn is_local_class(&self) -> bool {
+        self.own_inner_class_info().map_or(false, |info| {
+            info.outer_class.is_none() && info.inner_name.is_some()
+        })
+    }
+
+    #[must_use]
+    /// Whether this is a member class, one which is declared directly inside of another class
+    pub fn is_member_class(&self) -> bool {
+        self.own_inner_class_info()
+            .map_or(false, |info| info.outer_class.is_some())
+    }
+
+    /// Get where this class was declared, if it is a local or anonymous class
+    /// Returns `None` if there is no `EnclosingMethod` attribute, such as for top-level and member
+    /// classes, or if the attribute is malformed.
+    pub fn enclosing_method(&self, class_names: &mut ClassNames) -> Option<EnclosingMethodInfo> {
+        let mut reader = class_reader::Reader::new(self.attribute("EnclosingMethod")?);
+        let class_index = ConstantPoolIndexRaw::<ClassConstant>::new(reader.u16()?);
+        let method_index = reader.u16()?;
+
+        let class_id = self.class_id_from_constant(class_index, class_names).ok()?;
+
+        // Zero means that it was not enclosed by a method
+        let (method_name, method_descriptor) = if method_index == 0 {
+            (None, None)
+        } else {
+            let nat = self.get_t(ConstantPoolIndexRaw::<NameAndTypeConstant>::new(
+                method_index,
+            ))?;
+            (
+                Some(self.get_text_t(nat.name_index)?),
+                Some(self.get_text_t(nat.descriptor_index)?),
+            )
+        };
+
+        Some(EnclosingMethodInfo {
+            class_id,
+            method_name,
+            method_descriptor,
+        })
+    }
+
+    /// Get the data of the attribute with the given name on the method at the given index
+    fn method_attribute(&self, index: MethodIndex, name: &str) -> Option<&[u8]> {
+        let range = self.load_method_attribute_info_range_by_name(index, name)?;
+        self.class_file_data.get(range)
+    }
+
+    #[must_use]
+    /// Parse the annotations on the method at the given index, from its
+    /// `RuntimeVisibleAnnotations` attribute
+    /// Returns `None` if there is no such attribute or if it is malformed.
+    pub fn method_annotations(&self, index: MethodIndex) -> Option<Vec<Annotation>> {
+        let data = self.method_attribute(index, "RuntimeVisibleAnnotations")?;
+        annotation::parse_annotations(&mut class_reader::Reader::new(data))
+    }
+
+    #[must_use]
+    /// Parse the annotations on each parameter of the method at the given index, from its
+    /// `RuntimeVisibleParameterAnnotations` attribute
+    /// NOTE: This may have fewer entries than the descriptor has parameters, since compilers can
+    /// leave out synthetic parameters, such as the outer instance of an inner class's constructor.
+    /// Returns `None` if there is no such attribute or if it is malformed.
+    pub fn parameter_annotations(&self, index: MethodIndex) -> Option<Vec<Vec<Annotation>>> {
+        let data = self.method_attribute(index, "RuntimeVisibleParameterAnnotations")?;
+        let mut reader = class_reader::Reader::new(data);
+
+        let count = reader.u8()?;
+        let mut parameters = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            parameters.push(annotation::parse_annotations(&mut reader)?);
+        }
+
+        Some(parameters)
+    }
+
+    #[must_use]
+    /// Parse the `MethodParameters` attribute of the method at the given index
+    /// This is only emitted when compiling with `-parameters`, so it is normal for it to not
+    /// exist, in which case this returns `None`. This also returns `None` if it is malformed.
+    pub fn method_parameters(&self, index: MethodIndex) -> Option<Vec<MethodParameterInfo>> {
+        let mut reader =
+            class_reader::Reader::new(self.method_attribute(index, "MethodParameters")?);
+
+        let count = reader.u8()?;
+        let mut parameters = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let name_index = reader.u16()?;
+            let access_flags = MethodParameterAccessFlags::from_bits_truncate(reader.u16()?);
+
+            // Zero means that there is no name
+            let name = if name_index == 0 {
+                None
+            } else {
+                Some(self.get_text_t(ConstantPoolIndexRaw::<Utf8Constant>::new(name_index))?)
+            };
+
+            parameters.push(MethodParameterInfo { name, access_flags });
         }
 
-        let super_class = self
-            .get_t(self.class_file.super_class)
-            .ok_or(ClassFileIndexError::InvalidSuperClassIndex)?;
-        self.get_text_b(super_class.name_index)
-            .map(Some)
-            .ok_or(ClassFileIndexError::InvalidSuperClassNameIndex)
+        Some(parameters)
     }
 
-    pub(crate) fn get_super_class_id(
-        &self,
-        class_names: &mut ClassNames,
-    ) -> Result<Option<ClassId>, ClassFileIndexError> {
-        Ok(self
-            .get_super_class_name()?
-            .map(|x| class_names.gcid_from_bytes(x)))
+    #[must_use]
+    /// Parse the default value of the annotation interface method at the given index, from its
+    /// `AnnotationDefault` attribute
+    /// Returns `None` if there is no default or if it is malformed.
+    pub fn annotation_default(&self, index: MethodIndex) -> Option<ElementValue> {
+        let data = self.method_attribute(index, "AnnotationDefault")?;
+        annotation::parse_element_value(&mut class_reader::Reader::new(data), 0)
     }
 
-    pub fn interfaces_indices_iter(
+    /// Get the checked exceptions that the method at the given index declares it throws, from its
+    /// `Exceptions` attribute
+    /// Returns `None` if it has no such attribute, which means that it declares no checked
+    /// exceptions, or if the attribute is truncated.
+    pub fn method_exceptions(
         &self,
-    ) -> impl Iterator<Item = ConstantPoolIndexRaw<ClassConstant>> + '_ {
-        self.class_file.interfaces.iter().copied()
+        index: MethodIndex,
+        class_names: &mut ClassNames,
+    ) -> Option<Result<Vec<ClassId>, ClassFileIndexError>> {
+        let mut reader = class_reader::Reader::new(self.method_attribute(index, "Exceptions")?);
+
+        let count = reader.u16()?;
+        let mut indices = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            indices.push(ConstantPoolIndexRaw::<ClassConstant>::new(reader.u16()?));
+        }
+
+        Some(
+            indices
+                .into_iter()
+                .map(|index| self.class_id_from_constant(index, class_names))
+                .collect(),
+        )
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ClassVariant {
     Class(Class),
     Array(ArrayClass),
@@ -284,17 +1693,72 @@ impl ClassVariant {
             ClassVariant::Array(_) => false,
         }
     }
+
+    #[must_use]
+    pub fn is_public(&self) -> bool {
+        self.access_flags().contains(ClassAccessFlags::PUBLIC)
+    }
+
+    #[must_use]
+    pub fn is_final(&self) -> bool {
+        self.access_flags().contains(ClassAccessFlags::FINAL)
+    }
+
+    #[must_use]
+    pub fn is_abstract(&self) -> bool {
+        self.access_flags().contains(ClassAccessFlags::ABSTRACT)
+    }
+
+    #[must_use]
+    pub fn is_synthetic(&self) -> bool {
+        self.access_flags().contains(ClassAccessFlags::SYNTHETIC)
+    }
+
+    #[must_use]
+    pub fn is_annotation(&self) -> bool {
+        self.access_flags().contains(ClassAccessFlags::ANNOTATION)
+    }
+
+    #[must_use]
+    pub fn is_enum(&self) -> bool {
+        self.access_flags().contains(ClassAccessFlags::ENUM)
+    }
+
+    #[must_use]
+    /// The number of methods that this class has directly
+    /// Array classes have the single synthetic `clone` method.
+    pub fn methods_len(&self) -> MethodIndex {
+        match self {
+            ClassVariant::Class(x) => x.method_count(),
+            ClassVariant::Array(_) => 1,
+        }
+    }
+
+    /// Iterate over the ids of all methods that this class has directly
+    /// Array classes yield [`MethodId::ArrayClone`] as their only method.
+    pub fn iter_method_ids(&self) -> impl Iterator<Item = MethodId> {
+        match self {
+            ClassVariant::Class(x) => Either::Left(x.iter_method_ids().map(MethodId::Exact)),
+            ClassVariant::Array(_) => Either::Right(std::iter::once(MethodId::ArrayClone)),
+        }
+    }
 }
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Class {
     pub(crate) id: ClassId,
     pub(crate) super_class: Option<ClassId>,
     pub(crate) package: Option<PackageId>,
+    #[cfg_attr(feature = "serde", serde(with = "access_flags_serde"))]
     pub(crate) access_flags: ClassAccessFlags,
     /// This is just the length of methods
     /// Not all methods are guaranteed to be initialized
     /// 0..last_method_id
     pub(crate) len_method_idx: MethodIndex,
+    /// This is just the length of fields
+    /// Not all fields are guaranteed to be initialized
+    /// 0..last_field_id
+    pub(crate) len_field_idx: FieldIndex,
 }
 impl Class {
     pub(crate) fn new(
@@ -303,6 +1767,7 @@ impl Class {
         package: Option<PackageId>,
         access_flags: ClassAccessFlags,
         len_method_idx: MethodIndex,
+        len_field_idx: FieldIndex,
     ) -> Self {
         Self {
             id,
@@ -310,6 +1775,7 @@ impl Class {
             package,
             access_flags,
             len_method_idx,
+            len_field_idx,
         }
     }
 
@@ -333,28 +1799,152 @@ impl Class {
         self.access_flags.contains(ClassAccessFlags::INTERFACE)
     }
 
+    #[must_use]
+    pub fn is_public(&self) -> bool {
+        self.access_flags.contains(ClassAccessFlags::PUBLIC)
+    }
+
+    #[must_use]
+    pub fn is_final(&self) -> bool {
+        self.access_flags.contains(ClassAccessFlags::FINAL)
+    }
+
+    #[must_use]
+    pub fn is_abstract(&self) -> bool {
+        self.access_flags.contains(ClassAccessFlags::ABSTRACT)
+    }
+
+    #[must_use]
+    pub fn is_synthetic(&self) -> bool {
+        self.access_flags.contains(ClassAccessFlags::SYNTHETIC)
+    }
+
+    #[must_use]
+    pub fn is_annotation(&self) -> bool {
+        self.access_flags.contains(ClassAccessFlags::ANNOTATION)
+    }
+
+    #[must_use]
+    pub fn is_enum(&self) -> bool {
+        self.access_flags.contains(ClassAccessFlags::ENUM)
+    }
+
     /// Iterate over all method ids that this method has.
     /// Note that this is just the ids, they are not guaranteed to be loaded.
     pub fn iter_method_ids(&self) -> impl Iterator<Item = ExactMethodId> {
         let class_id = self.id;
         (0..self.len_method_idx).map(move |idx| ExactMethodId::unchecked_compose(class_id, idx))
     }
+
+    #[must_use]
+    /// The number of methods declared directly on this class
+    pub fn method_count(&self) -> MethodIndex {
+        self.len_method_idx
+    }
+
+    #[must_use]
+    /// Get the id of the method at the given index, if this class has a method at that index
+    /// Note that the method is not guaranteed to be loaded.
+    pub fn method_id(&self, index: MethodIndex) -> Option<ExactMethodId> {
+        if index < self.len_method_idx {
+            Some(ExactMethodId::unchecked_compose(self.id, index))
+        } else {
+            None
+        }
+    }
+
+    #[must_use]
+    /// The number of fields declared directly on this class
+    pub fn field_count(&self) -> FieldIndex {
+        self.len_field_idx
+    }
+
+    /// Iterate over all field ids that this class declares.
+    /// Note that this is just the ids, they are not guaranteed to be loaded.
+    pub fn iter_field_ids(&self) -> impl Iterator<Item = FieldId> {
+        let class_id = self.id;
+        (0..self.len_field_idx).map(move |idx| FieldId::unchecked_compose(class_id, idx))
+    }
+}
+
+/// The name of the synthetic `clone` method that every array class has
+pub const ARRAY_CLONE_NAME: &str = "clone";
+
+/// The classes which can declare signature polymorphic methods
+pub const SIGNATURE_POLYMORPHIC_CLASSES: [&[u8]; 2] = [
+    b"java/lang/invoke/MethodHandle",
+    b"java/lang/invoke/VarHandle",
+];
+
+#[must_use]
+/// The descriptor of the synthetic `clone` method that every array class has.
+/// Arrays override `Object.clone` with a covariant return type in the Java language, so
+/// `int[].clone()` gives an `int[]`, but in the class file it keeps the descriptor of
+/// `Object.clone`, which is what `invokevirtual` on an array references.
+pub fn array_clone_descriptor() -> &'static str {
+    "()Ljava/lang/Object;"
+}
+
+/// The metadata of a method that does not have a class file backing it, such as the `clone`
+/// method of arrays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyntheticMethod {
+    pub id: MethodId,
+    pub name: &'static str,
+    pub descriptor: &'static str,
+    pub access_flags: MethodAccessFlags,
 }
 
-// TODO: Are arrays in the same package as their defining type?
+/// An array class, which is not backed by a class file
+/// Arrays are in the same package as their innermost element type, so `[[Ljava/lang/String;` is
+/// in `java/lang`. Arrays of primitives have no package, the same as the primitive types
+/// themselves.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ArrayClass {
     pub(crate) id: ClassId,
     pub(crate) component_type: ArrayComponentType,
     /// Always "java/lang/Object"
     pub(crate) super_class: ClassId,
+    #[cfg_attr(feature = "serde", serde(with = "access_flags_serde"))]
     pub(crate) access_flags: ClassAccessFlags,
     /// The package id of the innermost component type, if it has one
+    /// This is `None` for arrays of primitives.
     pub(crate) package: Option<PackageId>,
 }
 impl ArrayClass {
+    #[must_use]
+    /// Create the array class with the access flags that the JVM gives arrays
+    /// Arrays are always `final` and `abstract`, and they are `public` only if their element type
+    /// is. Arrays of primitives are always `public`.
+    pub fn new(
+        id: ClassId,
+        component_type: ArrayComponentType,
+        super_class: ClassId,
+        element_access_flags: ClassAccessFlags,
+        package: Option<PackageId>,
+    ) -> Self {
+        ArrayClass {
+            id,
+            component_type,
+            super_class,
+            access_flags: ArrayClass::access_flags_for_element(element_access_flags),
+            package,
+        }
+    }
+
+    #[must_use]
+    /// Get the access flags for an array with an element type that has the given flags
+    pub fn access_flags_for_element(element_access_flags: ClassAccessFlags) -> ClassAccessFlags {
+        ClassAccessFlags::FINAL
+            | ClassAccessFlags::ABSTRACT
+            | (element_access_flags & ClassAccessFlags::PUBLIC)
+    }
+
     // TODO: provide more libsound ways of creating this
     #[must_use]
+    /// Create the array class with exactly the given access flags
+    /// See [`ArrayClass::new`] for the flags that arrays should have.
     pub fn new_unchecked(
         id: ClassId,
         component_type: ArrayComponentType,
@@ -393,8 +1983,48 @@ impl ArrayClass {
     }
 
     #[must_use]
-    /// Returns the package id
-    /// If there is none, then it is of some class that is rootmost package
+    /// The synthetic `clone` method of the array
+    /// Unlike `Object.clone`, which is `protected`, this is `public` and never throws
+    /// `CloneNotSupportedException`.
+    pub fn clone_method(&self) -> SyntheticMethod {
+        SyntheticMethod {
+            id: MethodId::ArrayClone,
+            name: ARRAY_CLONE_NAME,
+            descriptor: array_clone_descriptor(),
+            access_flags: MethodAccessFlags::PUBLIC,
+        }
+    }
+
+    /// Get the number of dimensions of the array and the innermost non-array component type
+    /// Ex: `[[[I` -> `(3, Int)`
+    pub fn rank_and_base(
+        &self,
+        class_names: &mut ClassNames,
+    ) -> Result<(u32, ArrayComponentType), BadIdError> {
+        let mut rank = 1;
+        let mut component = self.component_type();
+        while let ArrayComponentType::Class(id) = component {
+            let (name, info) = class_names.name_from_gcid(id)?;
+            if !info.is_array() {
+                break;
+            }
+
+            // The component is itself an array, so we peel off one level of it
+            let inner = name.get()[1..].to_owned();
+            // Array names are verified when they are created, so failing to parse is a sign that
+            // the id was bad.
+            component = ArrayComponentType::parse_from_desc(inner, class_names)
+                .map_err(|_| BadIdError { id })?;
+            rank += 1;
+        }
+
+        Ok((rank, component))
+    }
+
+    #[must_use]
+    /// Returns the package id of the innermost element type
+    /// If there is none, then the element type is either a primitive or a class in the rootmost
+    /// package
     pub fn package(&self) -> Option<PackageId> {
         self.package
     }
@@ -402,7 +2032,10 @@ impl ArrayClass {
 
 /// NOTE: We could have various other types, like unsigned versions, to allow for more granular type
 /// checking, but that only makes sense if it can be determined.
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// NOTE: Like [`ClassId`], comparisons are only meaningful when the ids come from the same
+/// [`ClassNames`] instance.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ArrayComponentType {
     Boolean,
     Char,
@@ -429,27 +2062,80 @@ impl ArrayComponentType {
         }
     }
 
+    /// Parse exactly one component type from the descriptor.
+    /// Array descriptors (`[I`) are resolved to the id of the array class as a whole.
+    pub fn parse_from_desc(
+        desc: impl AsRef<[u8]>,
+        class_names: &mut ClassNames,
+    ) -> Result<ArrayComponentType, DescriptorParseError> {
+        let desc = desc.as_ref();
+        let typ = match desc.first() {
+            None => return Err(DescriptorParseError::Empty),
+            Some(b'[') => {
+                // Check that it is a single well-formed descriptor before registering the name
+                let (_, rest) = DescriptorType::parse_prefix(desc, class_names)?;
+                if !rest.is_empty() {
+                    return Err(DescriptorParseError::TrailingData);
+                }
+
+                return Ok(ArrayComponentType::Class(class_names.gcid_from_bytes(desc)));
+            }
+            Some(_) => {
+                let (basic, rest) = DescriptorTypeBasic::parse_prefix(desc, class_names)?;
+                if !rest.is_empty() {
+                    return Err(DescriptorParseError::TrailingData);
+                }
+                basic
+            }
+        };
+
+        Ok(typ.as_array_component_type())
+    }
+
     pub fn to_desc_string(&self, class_names: &mut ClassNames) -> Result<Vec<u8>, BadIdError> {
-        match self {
-            ArrayComponentType::Byte => Ok(Vec::from(b"B" as &[u8])),
-            ArrayComponentType::Char => Ok(Vec::from(b"C" as &[u8])),
-            ArrayComponentType::Double => Ok(Vec::from(b"D" as &[u8])),
-            ArrayComponentType::Float => Ok(Vec::from(b"F" as &[u8])),
-            ArrayComponentType::Int => Ok(Vec::from(b"I" as &[u8])),
-            ArrayComponentType::Long => Ok(Vec::from(b"J" as &[u8])),
+        let mut out = Vec::new();
+        self.write_desc(&mut out, class_names)?;
+        Ok(out)
+    }
+
+    /// Append the descriptor for this component type to `out`, so that a single buffer can be
+    /// reused when building many descriptors.
+    /// This writes bytes rather than a `String` because class names are not necessarily valid
+    /// UTF-8.
+    pub fn write_desc(
+        &self,
+        out: &mut Vec<u8>,
+        class_names: &ClassNames,
+    ) -> Result<(), BadIdError> {
+        let prim = match self {
+            ArrayComponentType::Byte => b'B',
+            ArrayComponentType::Char => b'C',
+            ArrayComponentType::Double => b'D',
+            ArrayComponentType::Float => b'F',
+            ArrayComponentType::Int => b'I',
+            ArrayComponentType::Long => b'J',
+            ArrayComponentType::Short => b'S',
+            ArrayComponentType::Boolean => b'Z',
             ArrayComponentType::Class(class_id) => {
                 let (class_name, class_info) = class_names.name_from_gcid(*class_id)?;
+                let class_name = class_name.get();
                 if class_info.is_array() {
-                    // If we have the id for an array then we just use the singular path it has
-                    // because writing it as an object is incorrect.
-                    Ok(class_name.get().to_owned())
+                    // An array id's name is already its full descriptor, including every
+                    // dimension (`[[I`), so it is used as-is rather than wrapped as an object.
+                    out.extend_from_slice(class_name);
                 } else {
-                    Ok(format_class_as_object_desc(class_name.get()))
+                    out.reserve(class_name.len() + 2);
+                    out.push(b'L');
+                    out.extend_from_slice(class_name);
+                    out.push(b';');
                 }
+
+                return Ok(());
             }
-            ArrayComponentType::Short => Ok(Vec::from(b"S" as &[u8])),
-            ArrayComponentType::Boolean => Ok(Vec::from(b"Z" as &[u8])),
-        }
+        };
+
+        out.push(prim);
+        Ok(())
     }
 }
 // TODO: Make From<DescriptorTypeBasic>
@@ -467,3 +2153,1033 @@ impl From<PrimitiveType> for ArrayComponentType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use classfile_parser::{
+        constant_info::{
+            DoubleConstant, FloatConstant, IntegerConstant, LongConstant, NameAndTypeConstant,
+            StringConstant,
+        },
+        constant_pool::ConstantPoolIndexRaw,
+    };
+
+    use crate::{
+        class_writer::AttributeParts,
+        constant_pool_builder::PoolEntry,
+        data::class_names::ClassNames,
+        id::ClassId,
+        test_util::{code_attribute, u16s, TestClass, ACC_FINAL, ACC_STATIC},
+    };
+
+    use super::{
+        ArrayComponentType, ClassFileData, ConstantValueKind, ElementValue, InnerClassAccessFlags,
+        MethodHandleError, MethodHandleKind, MethodParameterAccessFlags,
+    };
+
+    /// The component type, but with class names rather than ids since the ids are only meaningful
+    /// within a single [`ClassNames`]
+    #[derive(Debug, Clone)]
+    enum Component {
+        Prim(ArrayComponentType),
+        Name(String),
+    }
+
+    fn component_strategy() -> impl Strategy<Value = Component> {
+        prop_oneof![
+            prop_oneof![
+                Just(ArrayComponentType::Boolean),
+                Just(ArrayComponentType::Char),
+                Just(ArrayComponentType::Byte),
+                Just(ArrayComponentType::Short),
+                Just(ArrayComponentType::Int),
+                Just(ArrayComponentType::Long),
+                Just(ArrayComponentType::Float),
+                Just(ArrayComponentType::Double),
+            ]
+            .prop_map(Component::Prim),
+            "[a-z]{1,8}(/[A-Za-z$_]{1,8}){0,3}".prop_map(Component::Name),
+            "\\[{1,3}([BCDFIJSZ]|L[a-z]{1,8};)".prop_map(Component::Name),
+        ]
+    }
+
+    /// Wrap the element in arrays up to rank 3, checking the descriptor of the new array at
+    /// each level
+    fn check_nested_array_descs(element_desc: &str) {
+        let mut class_names = ClassNames::new();
+        let mut component =
+            ArrayComponentType::parse_from_desc(element_desc, &mut class_names).unwrap();
+        for rank in 1..=3 {
+            let array_id = class_names
+                .get_or_create_array_class(component.clone())
+                .unwrap();
+            let expected = format!("{}{}", "[".repeat(rank), element_desc);
+
+            let (name, info) = class_names.name_from_gcid(array_id).unwrap();
+            assert!(info.is_array());
+            assert_eq!(name.get(), expected.as_bytes());
+
+            // Creating it again gives back the same id
+            assert_eq!(
+                class_names.get_or_create_array_class(component).unwrap(),
+                array_id
+            );
+
+            component = ArrayComponentType::Class(array_id);
+            assert_eq!(
+                component.to_desc_string(&mut class_names).unwrap(),
+                expected.as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn test_nested_array_desc() {
+        check_nested_array_descs("I");
+        check_nested_array_descs("Z");
+        check_nested_array_descs("J");
+        check_nested_array_descs("Ljava/lang/String;");
+        check_nested_array_descs("Ljava/lang/Object;");
+    }
+
+    proptest! {
+        #[test]
+        fn test_component_desc_roundtrip(component in component_strategy()) {
+            let mut class_names = ClassNames::new();
+            let component = match component {
+                Component::Prim(prim) => prim,
+                Component::Name(name) => {
+                    ArrayComponentType::Class(class_names.gcid_from_bytes(name.as_bytes()))
+                }
+            };
+
+            let desc = component.to_desc_string(&mut class_names).unwrap();
+            let parsed = ArrayComponentType::parse_from_desc(&desc, &mut class_names);
+            prop_assert_eq!(parsed, Ok(component));
+        }
+    }
+
+    /// Build an abstract class file with no super class and the given methods, as
+    /// `(access flags, name, descriptor)`
+    fn class_with_methods(this: &str, methods: &[(u16, &str, &str)]) -> ClassFileData {
+        methods
+            .iter()
+            .fold(
+                TestClass::new(this, None).access(0x0421),
+                |class, (flags, name, desc)| class.method(*flags, name, desc),
+            )
+            .build(ClassId::new_unchecked(1))
+    }
+
+    #[test]
+    fn test_signature_polymorphic() {
+        const POLYMORPHIC: u16 = 0x0001 | 0x0010 | 0x0080 | 0x0100;
+        let methods = [
+            (
+                POLYMORPHIC,
+                "invokeExact",
+                "([Ljava/lang/Object;)Ljava/lang/Object;",
+            ),
+            (
+                POLYMORPHIC,
+                "invoke",
+                "([Ljava/lang/Object;)Ljava/lang/Object;",
+            ),
+            // Not native
+            (
+                0x0081,
+                "invokeWithArguments",
+                "([Ljava/lang/Object;)Ljava/lang/Object;",
+            ),
+            (
+                POLYMORPHIC,
+                "bindTo",
+                "(Ljava/lang/Object;)Ljava/lang/invoke/MethodHandle;",
+            ),
+        ];
+        let handle = class_with_methods("java/lang/invoke/MethodHandle", &methods);
+        assert!(handle.is_signature_polymorphic(0));
+        assert!(handle.is_signature_polymorphic(1));
+        assert!(!handle.is_signature_polymorphic(2));
+        assert!(!handle.is_signature_polymorphic(3));
+        assert!(!handle.is_signature_polymorphic(4));
+
+        // Only the methods of `MethodHandle` and `VarHandle` are signature polymorphic
+        let other = class_with_methods("a/MethodHandle", &methods);
+        assert!(!other.is_signature_polymorphic(0));
+    }
+
+    #[test]
+    fn test_find_method() {
+        // Supplementary characters and nul are encoded differently in modified utf8
+        let class_file = class_with_methods(
+            "a/A",
+            &[
+                (0, "run", "()V"),
+                (0, "\u{1D538}\0", "()V"),
+                (0, "run", "(I)V"),
+            ],
+        );
+        assert_eq!(class_file.find_method("run", "()V"), Some(0));
+        assert_eq!(class_file.find_method("\u{1D538}\0", "()V"), Some(1));
+        assert_eq!(class_file.find_method("run", "(I)V"), Some(2));
+        assert_eq!(class_file.find_method("run", "(J)V"), None);
+        // The raw utf8 bytes don't match
+        assert_eq!(
+            class_file.find_method_b("\u{1D538}\0".as_bytes(), b"()V"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_release_unneeded_constants() {
+        fn utf8(text: &str) -> Vec<u8> {
+            let mut entry = vec![1];
+            entry.extend(u16::try_from(text.len()).unwrap().to_be_bytes());
+            entry.extend(text.as_bytes());
+            entry
+        }
+
+        let pool = [
+            utf8("a/A"),
+            vec![7, 0, 1],
+            utf8("java/lang/Object"),
+            vec![7, 0, 3],
+            utf8("run"),
+            utf8("()V"),
+            utf8("Code"),
+            utf8("LocalVariableTable"),
+            // 9: Only referenced by the local variable table
+            utf8("this"),
+            // 10: Also referenced by the string constant
+            utf8("La/A;"),
+            vec![8, 0, 10],
+            utf8("LineNumberTable"),
+            utf8("SourceDebugExtension"),
+            utf8("Custom"),
+            // 15: Referenced by the data of an unknown attribute
+            utf8("kept"),
+        ];
+        #[rustfmt::skip]
+        let code = [
+            // max stack, max locals, code length, return, no exception handlers
+            0, 1, 0, 1, 0, 0, 0, 1, 0xB1, 0, 0,
+            // two attributes
+            0, 2,
+            // LineNumberTable
+            0, 12, 0, 0, 0, 6, 0, 1, 0, 0, 0, 7,
+            // LocalVariableTable
+            0, 8, 0, 0, 0, 12, 0, 1, 0, 0, 0, 1, 0, 9, 0, 10, 0, 0,
+        ];
+        #[rustfmt::skip]
+        let members = [
+            // access flags, this class, super class, no interfaces or fields
+            0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0,
+            // public void run() with a Code attribute
+            0, 1, 0, 1, 0, 5, 0, 6, 0, 1, 0, 7,
+        ];
+        #[rustfmt::skip]
+        let attributes = [
+            0, 2,
+            0, 13, 0, 0, 0, 3, b'd', b'b', b'g',
+            0, 14, 0, 0, 0, 2, 0, 15,
+        ];
+        let mut data = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 16];
+        data.extend(pool.concat());
+        data.extend(members);
+        data.extend(u32::try_from(code.len()).unwrap().to_be_bytes());
+        data.extend(code);
+        data.extend(attributes);
+
+        let mut class_file = ClassFileData::from_bytes(ClassId::new_unchecked(1), &data).unwrap();
+        assert_eq!(class_file.find_method("run", "()V"), Some(0));
+        assert!(class_file.local_variable_table(0).is_some());
+        assert!(class_file.attribute("SourceDebugExtension").is_some());
+
+        class_file.release_unneeded_constants().unwrap();
+        assert!(class_file.local_variable_table(0).is_none());
+        assert!(class_file.attribute("SourceDebugExtension").is_none());
+        assert_eq!(class_file.attribute("Custom"), Some(&[0, 15][..]));
+        assert_eq!(class_file.line_number_table(0), Some(vec![(0, 7)]));
+        assert_eq!(class_file.method_code(0).unwrap().code, &[0xB1]);
+        assert_eq!(class_file.find_method("run", "()V"), Some(0));
+
+        let text = |index| {
+            class_file.get_text_b(ConstantPoolIndexRaw::<
+                classfile_parser::constant_info::Utf8Constant,
+            >::new(index))
+        };
+        assert_eq!(text(8), Some(&b""[..]));
+        assert_eq!(text(9), Some(&b""[..]));
+        assert_eq!(text(13), Some(&b""[..]));
+        assert_eq!(text(10), Some(&b"La/A;"[..]));
+        assert_eq!(text(12), Some(&b"LineNumberTable"[..]));
+        assert_eq!(text(15), Some(&b"kept"[..]));
+    }
+
+    #[test]
+    fn test_split_debug_tables() {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let line_numbers = pool.add_utf8("LineNumberTable").unwrap();
+        let local_variables = pool.add_utf8("LocalVariableTable").unwrap();
+        let this_name = pool.add_utf8("this").unwrap();
+        let this_desc = pool.add_utf8("La/A;").unwrap();
+        let x_name = pool.add_utf8("x").unwrap();
+        let int_desc = pool.add_utf8("I").unwrap();
+
+        let table = |entries: &[&[u16]]| {
+            let mut data = u16::try_from(entries.len()).unwrap().to_be_bytes().to_vec();
+            for value in entries.iter().copied().flatten() {
+                data.extend(value.to_be_bytes());
+            }
+            data
+        };
+        let attribute = |name_index, data| AttributeParts { name_index, data };
+        let code = code_attribute(
+            1,
+            2,
+            // iconst_0, istore_1, return
+            &[0x03, 0x3C, 0xB1],
+            // Both tables are split in two, interleaved with each other
+            &[
+                attribute(line_numbers, table(&[&[0, 10]])),
+                attribute(local_variables, table(&[&[0, 3, this_name, this_desc, 0]])),
+                attribute(line_numbers, table(&[&[1, 11], &[2, 12]])),
+                attribute(local_variables, table(&[&[2, 1, x_name, int_desc, 1]])),
+            ],
+        );
+        let class_file = class
+            .method(0x0001, "run", "()V")
+            .method_attribute("Code", code)
+            .build(ClassId::new_unchecked(1));
+
+        assert_eq!(
+            class_file.line_number_table(0),
+            Some(vec![(0, 10), (1, 11), (2, 12)])
+        );
+        let variables = class_file.local_variable_table(0).unwrap();
+        let variables = variables
+            .iter()
+            .map(|var| (var.name.as_ref(), var.descriptor.as_ref(), var.slot))
+            .collect::<Vec<_>>();
+        assert_eq!(variables, [("this", "La/A;", 0), ("x", "I", 1)]);
+
+        // A method without the attributes has no tables
+        let class_file = TestClass::new("a/B", None)
+            .method(0x0400, "run", "()V")
+            .build(ClassId::new_unchecked(2));
+        assert_eq!(class_file.line_number_table(0), None);
+        assert!(class_file.local_variable_table(0).is_none());
+    }
+
+    #[test]
+    fn test_field_constant_value() {
+        const STATIC_FINAL: u16 = ACC_STATIC | ACC_FINAL;
+
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let int = pool.add_integer(-4).unwrap();
+        let long = pool.add_long(1 << 40).unwrap();
+        let string = pool.add_string("text").unwrap();
+        let not_a_value = pool.add_class("a/B").unwrap();
+        let class_file = class
+            .field(STATIC_FINAL, "a", "I")
+            .field_attribute("ConstantValue", u16s(&[int]))
+            .field(STATIC_FINAL, "b", "J")
+            .field_attribute("ConstantValue", u16s(&[long]))
+            .field(STATIC_FINAL, "c", "Ljava/lang/String;")
+            .field_attribute("ConstantValue", u16s(&[string]))
+            .field(STATIC_FINAL, "d", "I")
+            .field_attribute("ConstantValue", u16s(&[not_a_value]))
+            .field(STATIC_FINAL, "e", "I")
+            .build(ClassId::new_unchecked(1));
+
+        assert_eq!(
+            class_file.field_constant_value(0),
+            Some(ConstantValueKind::Int(-4))
+        );
+        assert_eq!(
+            class_file.field_constant_value(1),
+            Some(ConstantValueKind::Long(1 << 40))
+        );
+        assert_eq!(
+            class_file.field_constant_value(2),
+            Some(ConstantValueKind::String("text".into()))
+        );
+        // A class is not a valid value for a field
+        assert_eq!(class_file.field_constant_value(3), None);
+        // No attribute, and no such field
+        assert_eq!(class_file.field_constant_value(4), None);
+        assert_eq!(class_file.field_constant_value(5), None);
+    }
+
+    #[test]
+    fn test_generic_signature() {
+        const SIGNATURE: &str = "<T:Ljava/lang/Object;>Ljava/lang/Object;";
+
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let signature = class.parts.constant_pool.add_utf8(SIGNATURE).unwrap();
+        let class_file = class
+            .attribute("Signature", u16s(&[signature]))
+            .build(ClassId::new_unchecked(1));
+        assert_eq!(class_file.generic_signature().as_deref(), Some(SIGNATURE));
+        assert!(matches!(class_file.parse_class_signature(), Some(Ok(_))));
+
+        // Not generic
+        let class_file =
+            TestClass::new("a/B", Some("java/lang/Object")).build(ClassId::new_unchecked(2));
+        assert_eq!(class_file.generic_signature(), None);
+        assert!(class_file.parse_class_signature().is_none());
+
+        // Truncated, and referring to a class rather than its text
+        let class_file = TestClass::new("a/C", Some("java/lang/Object"))
+            .attribute("Signature", vec![0])
+            .build(ClassId::new_unchecked(3));
+        assert_eq!(class_file.generic_signature(), None);
+        let mut class = TestClass::new("a/D", Some("java/lang/Object"));
+        let object = class
+            .parts
+            .constant_pool
+            .add_class("java/lang/Object")
+            .unwrap();
+        let class_file = class
+            .attribute("Signature", u16s(&[object]))
+            .build(ClassId::new_unchecked(4));
+        assert_eq!(class_file.generic_signature(), None);
+    }
+
+    #[test]
+    fn test_method_exceptions() {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let io = pool.add_class("java/io/IOException").unwrap();
+        let interrupted = pool.add_class("java/lang/InterruptedException").unwrap();
+        let not_a_class = pool.add_utf8("java/lang/Exception").unwrap();
+        let class_file = class
+            .method(0x0400, "run", "()V")
+            .method_attribute("Exceptions", u16s(&[2, io, interrupted]))
+            .method(0x0400, "none", "()V")
+            .method(0x0400, "truncated", "()V")
+            .method_attribute("Exceptions", u16s(&[2, io]))
+            .method(0x0400, "invalid", "()V")
+            .method_attribute("Exceptions", u16s(&[1, not_a_class]))
+            .build(ClassId::new_unchecked(1));
+
+        let mut class_names = ClassNames::new();
+        let expected = vec![
+            class_names.gcid_from_bytes(b"java/io/IOException"),
+            class_names.gcid_from_bytes(b"java/lang/InterruptedException"),
+        ];
+        let exceptions = class_file.method_exceptions(0, &mut class_names);
+        assert_eq!(exceptions.unwrap().unwrap(), expected);
+
+        assert!(class_file.method_exceptions(1, &mut class_names).is_none());
+        assert!(class_file.method_exceptions(2, &mut class_names).is_none());
+        assert!(matches!(
+            class_file.method_exceptions(3, &mut class_names),
+            Some(Err(_))
+        ));
+    }
+
+    #[test]
+    fn test_inner_classes() {
+        let mut class = TestClass::new("a/A$B", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let member = pool.add_class("a/A$B").unwrap();
+        let outer = pool.add_class("a/A").unwrap();
+        let name = pool.add_utf8("B").unwrap();
+        let anonymous = pool.add_class("a/A$1").unwrap();
+        #[rustfmt::skip]
+        let entries = u16s(&[
+            2,
+            // A private static member class, with a flag that isn't defined for inner classes
+            member, outer, name, 0x8000 | 0x0008 | 0x0002,
+            // A final anonymous class
+            anonymous, 0, 0, 0x0010,
+        ]);
+        let class_file = class
+            .attribute("InnerClasses", entries.clone())
+            .build(ClassId::new_unchecked(1));
+
+        let inner_classes = class_file.inner_classes().unwrap();
+        assert_eq!(inner_classes.len(), 2);
+        let info = &inner_classes[0];
+        assert_eq!(info.inner_class.0, member);
+        assert_eq!(info.outer_class.map(|index| index.0), Some(outer));
+        assert_eq!(info.inner_name.map(|index| index.0), Some(name));
+        // The undefined flag is dropped
+        assert_eq!(
+            info.access_flags,
+            InnerClassAccessFlags::PRIVATE | InnerClassAccessFlags::STATIC
+        );
+        let info = &inner_classes[1];
+        assert_eq!(info.inner_class.0, anonymous);
+        assert!(info.outer_class.is_none());
+        assert!(info.inner_name.is_none());
+        assert_eq!(info.access_flags, InnerClassAccessFlags::FINAL);
+
+        // The entry for the class itself is the member class
+        assert!(class_file.is_member_class());
+        assert!(!class_file.is_anonymous());
+        assert!(!class_file.is_local_class());
+
+        // The same entries, but from the anonymous class
+        let class_file = TestClass::new("a/A$1", Some("java/lang/Object"))
+            .attribute("InnerClasses", entries)
+            .build(ClassId::new_unchecked(2));
+        assert!(class_file.is_anonymous());
+        assert!(!class_file.is_member_class());
+
+        // The count claims more entries than there are
+        let class_file = TestClass::new("a/C", Some("java/lang/Object"))
+            .attribute("InnerClasses", u16s(&[2, member, outer, name, 0]))
+            .build(ClassId::new_unchecked(3));
+        assert!(class_file.inner_classes().is_none());
+        assert!(!class_file.is_member_class());
+    }
+
+    #[test]
+    fn test_enclosing_method() {
+        let mut class = TestClass::new("a/A$1", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let outer = pool.add_class("a/A").unwrap();
+        let method = pool.add_name_and_type("run", "()V").unwrap();
+        let not_a_method = pool.add_utf8("run").unwrap();
+        let attribute = |data| {
+            class
+                .clone()
+                .attribute("EnclosingMethod", data)
+                .build(ClassId::new_unchecked(1))
+        };
+
+        let mut class_names = ClassNames::new();
+        let outer_id = class_names.gcid_from_bytes(b"a/A");
+
+        let info = attribute(u16s(&[outer, method]))
+            .enclosing_method(&mut class_names)
+            .unwrap();
+        assert_eq!(info.class_id, outer_id);
+        assert_eq!(info.method_name.as_deref(), Some("run"));
+        assert_eq!(info.method_descriptor.as_deref(), Some("()V"));
+
+        // Declared in an initializer rather than a method
+        let info = attribute(u16s(&[outer, 0]))
+            .enclosing_method(&mut class_names)
+            .unwrap();
+        assert_eq!(info.class_id, outer_id);
+        assert!(info.method_name.is_none());
+        assert!(info.method_descriptor.is_none());
+
+        // Truncated, or referring to the wrong kinds of constants
+        for data in [
+            u16s(&[outer]),
+            u16s(&[outer, not_a_method]),
+            u16s(&[not_a_method, method]),
+        ] {
+            assert!(attribute(data).enclosing_method(&mut class_names).is_none());
+        }
+
+        // A top-level class has no attribute
+        let class_file = TestClass::new("a/A", None).build(ClassId::new_unchecked(2));
+        assert!(class_file.enclosing_method(&mut class_names).is_none());
+    }
+
+    #[test]
+    fn test_class_annotations() {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let deprecated = pool.add_utf8("Ljava/lang/Deprecated;").unwrap();
+        let since = pool.add_utf8("since").unwrap();
+        let version = pool.add_utf8("9").unwrap();
+        let mut data = u16s(&[1, deprecated, 1, since]);
+        data.push(b's');
+        data.extend(u16s(&[version]));
+        let class_file = class
+            .attribute("RuntimeVisibleAnnotations", data)
+            .build(ClassId::new_unchecked(1));
+
+        let annotations = class_file.runtime_visible_annotations().unwrap();
+        assert_eq!(annotations.len(), 1);
+        let annotation = &annotations[0];
+        assert_eq!(
+            annotation.type_descriptor(&class_file).as_deref(),
+            Some("Ljava/lang/Deprecated;")
+        );
+        assert!(matches!(
+            annotation.element(&class_file, "since"),
+            Some(ElementValue::Const { tag: b's', value }) if value.0 == version
+        ));
+        assert!(annotation.element(&class_file, "forRemoval").is_none());
+
+        // The count claims more annotations than there are
+        let class_file = TestClass::new("a/B", Some("java/lang/Object"))
+            .attribute("RuntimeVisibleAnnotations", u16s(&[2, deprecated, 0]))
+            .build(ClassId::new_unchecked(2));
+        assert!(class_file.runtime_visible_annotations().is_none());
+
+        let class_file = TestClass::new("a/C", None).build(ClassId::new_unchecked(3));
+        assert!(class_file.runtime_visible_annotations().is_none());
+    }
+
+    #[test]
+    fn test_method_annotations() {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let deprecated = pool.add_utf8("Ljava/lang/Deprecated;").unwrap();
+        let nullable = pool.add_utf8("La/Nullable;").unwrap();
+
+        // The first parameter has no annotations, and the second has one
+        let mut parameters = vec![2];
+        parameters.extend(u16s(&[0, 1, nullable, 0]));
+        // The count claims one more parameter than there are
+        let mut truncated = vec![3];
+        truncated.extend(u16s(&[0, 1, nullable, 0]));
+        let class_file = class
+            .method(0x0400, "run", "(II)V")
+            .method_attribute("RuntimeVisibleAnnotations", u16s(&[1, deprecated, 0]))
+            .method_attribute("RuntimeVisibleParameterAnnotations", parameters)
+            .method(0x0400, "none", "(II)V")
+            .method(0x0400, "truncated", "(II)V")
+            .method_attribute("RuntimeVisibleAnnotations", u16s(&[1, deprecated]))
+            .method_attribute("RuntimeVisibleParameterAnnotations", truncated)
+            .build(ClassId::new_unchecked(1));
+
+        let annotations = class_file.method_annotations(0).unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].type_index.0, deprecated);
+        let parameters = class_file.parameter_annotations(0).unwrap();
+        assert_eq!(parameters.len(), 2);
+        assert!(parameters[0].is_empty());
+        assert_eq!(parameters[1].len(), 1);
+        assert_eq!(
+            parameters[1][0].type_descriptor(&class_file).as_deref(),
+            Some("La/Nullable;")
+        );
+
+        for index in [1, 2] {
+            assert!(class_file.method_annotations(index).is_none());
+            assert!(class_file.parameter_annotations(index).is_none());
+        }
+    }
+
+    #[test]
+    fn test_method_parameters() {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let name = pool.add_utf8("count").unwrap();
+        let not_a_name = pool.add_integer(1).unwrap();
+
+        let mut parameters = vec![2];
+        parameters.extend(u16s(&[name, 0x0010, 0, 0x8000]));
+        let mut invalid_name = vec![1];
+        invalid_name.extend(u16s(&[not_a_name, 0]));
+        let class_file = class
+            .method(0x0400, "<init>", "(La/B;I)V")
+            .method_attribute("MethodParameters", parameters)
+            .method(0x0400, "none", "(I)V")
+            .method(0x0400, "truncated", "(I)V")
+            .method_attribute("MethodParameters", vec![1, 0, 0])
+            .method(0x0400, "invalid", "(I)V")
+            .method_attribute("MethodParameters", invalid_name)
+            .build(ClassId::new_unchecked(1));
+
+        let parameters = class_file.method_parameters(0).unwrap();
+        assert_eq!(parameters.len(), 2);
+        assert_eq!(parameters[0].name.as_deref(), Some("count"));
+        assert_eq!(
+            parameters[0].access_flags,
+            MethodParameterAccessFlags::FINAL
+        );
+        // Such as the outer instance of an inner class, which has no name
+        assert!(parameters[1].name.is_none());
+        assert_eq!(
+            parameters[1].access_flags,
+            MethodParameterAccessFlags::MANDATED
+        );
+
+        for index in 1..=3 {
+            assert!(class_file.method_parameters(index).is_none());
+        }
+    }
+
+    #[test]
+    fn test_record_components() {
+        let mut class = TestClass::new("a/Point", Some("java/lang/Record"));
+        class.parts.major_version = 60;
+        let pool = &mut class.parts.constant_pool;
+        let x = pool.add_utf8("x").unwrap();
+        let int = pool.add_utf8("I").unwrap();
+        let items = pool.add_utf8("items").unwrap();
+        let list = pool.add_utf8("Ljava/util/List;").unwrap();
+        let signature_name = pool.add_utf8("Signature").unwrap();
+        let signature = pool.add_utf8("Ljava/util/List<La/Point;>;").unwrap();
+
+        // `int x`, and `List<Point> items` with a `Signature` attribute
+        #[rustfmt::skip]
+        let mut data = u16s(&[
+            2,
+            x, int, 0,
+            items, list, 1, signature_name,
+        ]);
+        data.extend(2u32.to_be_bytes());
+        data.extend(u16s(&[signature]));
+        let class = class.attribute("Record", data.clone());
+
+        let class_file = class.build(ClassId::new_unchecked(1));
+        assert!(class_file.is_record());
+        let components = class_file.record_components().unwrap();
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].name, "x");
+        assert_eq!(components[0].descriptor, "I");
+        assert!(components[0].attributes.is_empty());
+        assert_eq!(components[1].name, "items");
+        assert_eq!(components[1].descriptor, "Ljava/util/List;");
+        assert_eq!(
+            components[1].attribute("Signature"),
+            Some(u16s(&[signature]).as_slice())
+        );
+
+        // Records did not exist before version 60
+        let mut old = class.clone();
+        old.parts.major_version = 59;
+        let class_file = old.build(ClassId::new_unchecked(2));
+        assert!(!class_file.is_record());
+        assert!(class_file.record_components().is_none());
+
+        // The component's attribute is longer than the data
+        let mut truncated = class;
+        let attribute = truncated.parts.attributes.last_mut().unwrap();
+        attribute.data.truncate(data.len() - 1);
+        let class_file = truncated.build(ClassId::new_unchecked(3));
+        assert!(class_file.record_components().is_none());
+    }
+
+    #[test]
+    fn test_nest_attributes() {
+        let mut class_names = ClassNames::new();
+        let host_id = class_names.gcid_from_bytes(b"a/A");
+        let member_ids = [
+            class_names.gcid_from_bytes(b"a/A$B"),
+            class_names.gcid_from_bytes(b"a/A$C"),
+        ];
+
+        let mut host = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut host.parts.constant_pool;
+        let b = pool.add_class("a/A$B").unwrap();
+        let c = pool.add_class("a/A$C").unwrap();
+        let class_file = host
+            .attribute("NestMembers", u16s(&[2, b, c]))
+            .build(ClassId::new_unchecked(1));
+        assert_eq!(
+            class_file.nest_members(&mut class_names),
+            Some(member_ids.to_vec())
+        );
+        // The host does not claim a host
+        assert_eq!(class_file.nest_host(&mut class_names), None);
+
+        let mut member = TestClass::new("a/A$B", Some("java/lang/Object"));
+        let pool = &mut member.parts.constant_pool;
+        let a = pool.add_class("a/A").unwrap();
+        let not_a_class = pool.add_utf8("a/A").unwrap();
+        let class_file = member
+            .clone()
+            .attribute("NestHost", u16s(&[a]))
+            .build(ClassId::new_unchecked(2));
+        assert_eq!(class_file.nest_host(&mut class_names), Some(host_id));
+        assert_eq!(class_file.nest_members(&mut class_names), None);
+
+        // Referring to the text of the name rather than a class, and a truncated list
+        let class_file = member
+            .attribute("NestHost", u16s(&[not_a_class]))
+            .attribute("NestMembers", u16s(&[2, a]))
+            .build(ClassId::new_unchecked(3));
+        assert_eq!(class_file.nest_host(&mut class_names), None);
+        assert_eq!(class_file.nest_members(&mut class_names), None);
+    }
+
+    #[test]
+    fn test_permitted_subclasses() {
+        let mut class_names = ClassNames::new();
+        let expected = vec![
+            class_names.gcid_from_bytes(b"a/Circle"),
+            class_names.gcid_from_bytes(b"a/Square"),
+        ];
+
+        let mut class = TestClass::new("a/Shape", Some("java/lang/Object")).access(0x0421);
+        class.parts.major_version = 61;
+        let pool = &mut class.parts.constant_pool;
+        let circle = pool.add_class("a/Circle").unwrap();
+        let square = pool.add_class("a/Square").unwrap();
+        let attribute = |major_version, data| {
+            let mut class = class.clone().attribute("PermittedSubclasses", data);
+            class.parts.major_version = major_version;
+            class.build(ClassId::new_unchecked(1))
+        };
+
+        let class_file = attribute(61, u16s(&[2, circle, square]));
+        assert!(class_file.is_sealed());
+        assert_eq!(
+            class_file.permitted_subclasses(&mut class_names),
+            Some(expected)
+        );
+
+        // Sealed classes did not exist before version 61
+        let class_file = attribute(60, u16s(&[2, circle, square]));
+        assert!(!class_file.is_sealed());
+        assert_eq!(class_file.permitted_subclasses(&mut class_names), None);
+
+        // The count claims more classes than there are
+        let class_file = attribute(61, u16s(&[3, circle, square]));
+        assert_eq!(class_file.permitted_subclasses(&mut class_names), None);
+
+        let class_file = class.build(ClassId::new_unchecked(2));
+        assert!(!class_file.is_sealed());
+        assert_eq!(class_file.permitted_subclasses(&mut class_names), None);
+    }
+
+    #[test]
+    fn test_bootstrap_methods() {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let factory = pool
+            .add_methodref(
+                "a/Bootstrap",
+                "bootstrap",
+                "(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/Class;)I",
+            )
+            .unwrap();
+        let factory = pool
+            .add_method_handle(MethodHandleKind::InvokeStatic, factory)
+            .unwrap();
+        let method_type = pool.add_method_type("()V").unwrap();
+        let count = pool.add_integer(3).unwrap();
+
+        #[rustfmt::skip]
+        let data = u16s(&[
+            2,
+            factory, 2, method_type, count,
+            factory, 0,
+        ]);
+        let class_file = class
+            .clone()
+            .attribute("BootstrapMethods", data)
+            .build(ClassId::new_unchecked(1));
+
+        let methods = class_file.bootstrap_methods().unwrap();
+        assert_eq!(methods.len(), 2);
+        assert_eq!(methods[0].method_ref.0, factory);
+        let arguments = methods[0]
+            .arguments
+            .iter()
+            .map(|argument| argument.0)
+            .collect::<Vec<_>>();
+        assert_eq!(arguments, [method_type, count]);
+        assert_eq!(methods[1].method_ref.0, factory);
+        assert!(methods[1].arguments.is_empty());
+
+        // Looking up a single entry skips over the ones before it
+        let method = class_file.bootstrap_method(1).unwrap();
+        assert_eq!(method.method_ref.0, factory);
+        assert!(method.arguments.is_empty());
+        assert!(class_file.bootstrap_method(2).is_none());
+
+        // The first entry claims more arguments than there are
+        let class_file = class
+            .attribute("BootstrapMethods", u16s(&[1, factory, 2, method_type]))
+            .build(ClassId::new_unchecked(2));
+        assert!(class_file.bootstrap_methods().is_none());
+        assert!(class_file.bootstrap_method(0).is_none());
+    }
+
+    #[test]
+    fn test_method_handles() {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let method = pool.add_methodref("a/B", "run", "(I)V").unwrap();
+        let init = pool.add_methodref("a/B", "<init>", "()V").unwrap();
+        let interface_method = pool.add_interface_methodref("a/I", "get", "()I").unwrap();
+        let field = pool.add_fieldref("a/B", "x", "I").unwrap();
+        let name = pool.add_utf8("run").unwrap();
+
+        let invoke_static = pool
+            .add_method_handle(MethodHandleKind::InvokeStatic, method)
+            .unwrap();
+        let get_field = pool
+            .add_method_handle(MethodHandleKind::GetField, field)
+            .unwrap();
+        let static_interface = pool
+            .add_method_handle(MethodHandleKind::InvokeStatic, interface_method)
+            .unwrap();
+        let get_method = pool
+            .add_method_handle(MethodHandleKind::GetField, method)
+            .unwrap();
+        let virtual_init = pool
+            .add_method_handle(MethodHandleKind::InvokeVirtual, init)
+            .unwrap();
+        let new_run = pool
+            .add_method_handle(MethodHandleKind::NewInvokeSpecial, method)
+            .unwrap();
+        let invalid_kind = pool
+            .add(PoolEntry::MethodHandle {
+                reference_kind: 10,
+                reference_index: method,
+            })
+            .unwrap();
+        let invalid_reference = pool
+            .add_method_handle(MethodHandleKind::InvokeStatic, name)
+            .unwrap();
+        let method_type = pool.add_method_type("(I)V").unwrap();
+        let class_file = class.clone().build(ClassId::new_unchecked(1));
+
+        let handle = class_file
+            .method_handle(ConstantPoolIndexRaw::new(invoke_static))
+            .unwrap();
+        assert_eq!(handle.kind, MethodHandleKind::InvokeStatic);
+        assert_eq!(handle.member.class_name, "a/B");
+        assert_eq!(handle.member.name, "run");
+        assert_eq!(handle.member.descriptor, "(I)V");
+        assert!(!handle.member.is_interface);
+        let handle = class_file
+            .method_handle(ConstantPoolIndexRaw::new(get_field))
+            .unwrap();
+        assert_eq!(handle.kind, MethodHandleKind::GetField);
+        assert_eq!(handle.member.name, "x");
+        let handle = class_file
+            .method_handle(ConstantPoolIndexRaw::new(static_interface))
+            .unwrap();
+        assert!(handle.member.is_interface);
+
+        assert!(matches!(
+            class_file.method_handle(ConstantPoolIndexRaw::new(name)),
+            Err(MethodHandleError::InvalidIndex(_))
+        ));
+        assert!(matches!(
+            class_file.method_handle(ConstantPoolIndexRaw::new(invalid_kind)),
+            Err(MethodHandleError::InvalidReferenceKind(10))
+        ));
+        assert!(matches!(
+            class_file.method_handle(ConstantPoolIndexRaw::new(invalid_reference)),
+            Err(MethodHandleError::InvalidReferenceIndex(_))
+        ));
+        assert!(matches!(
+            class_file.method_handle(ConstantPoolIndexRaw::new(get_method)),
+            Err(MethodHandleError::WrongMemberType {
+                kind: MethodHandleKind::GetField
+            })
+        ));
+        assert!(matches!(
+            class_file.method_handle(ConstantPoolIndexRaw::new(virtual_init)),
+            Err(MethodHandleError::InvalidMethodName { .. })
+        ));
+        assert!(matches!(
+            class_file.method_handle(ConstantPoolIndexRaw::new(new_run)),
+            Err(MethodHandleError::InvalidMethodName { .. })
+        ));
+
+        // Static interface methods could not be referenced before version 52
+        let mut old = class;
+        old.parts.major_version = 51;
+        let old_file = old.build(ClassId::new_unchecked(2));
+        assert!(matches!(
+            old_file.method_handle(ConstantPoolIndexRaw::new(static_interface)),
+            Err(MethodHandleError::WrongMemberType { .. })
+        ));
+
+        assert_eq!(
+            class_file
+                .method_type(ConstantPoolIndexRaw::new(method_type))
+                .as_deref(),
+            Some("(I)V")
+        );
+        assert_eq!(
+            class_file.method_type(ConstantPoolIndexRaw::new(name)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_numeric_and_string_constants() {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let int = pool.add_integer(i32::MIN).unwrap();
+        let long = pool.add_long(-1).unwrap();
+        let float = pool.add_float(1.5).unwrap();
+        let double = pool.add_double(f64::MAX).unwrap();
+        // Nul is encoded differently in modified utf8
+        let string = pool.add_string("a\0b").unwrap();
+        let text = pool.add_utf8("text").unwrap();
+        let class_file = class.build(ClassId::new_unchecked(1));
+
+        assert_eq!(
+            class_file.get_integer(ConstantPoolIndexRaw::<IntegerConstant>::new(int)),
+            Some(i32::MIN)
+        );
+        assert_eq!(
+            class_file.get_long(ConstantPoolIndexRaw::<LongConstant>::new(long)),
+            Some(-1)
+        );
+        assert_eq!(
+            class_file.get_float(ConstantPoolIndexRaw::<FloatConstant>::new(float)),
+            Some(1.5)
+        );
+        assert_eq!(
+            class_file.get_double(ConstantPoolIndexRaw::<DoubleConstant>::new(double)),
+            Some(f64::MAX)
+        );
+        assert_eq!(
+            class_file
+                .get_string(ConstantPoolIndexRaw::<StringConstant>::new(string))
+                .as_deref(),
+            Some("a\0b")
+        );
+
+        // Constants of a different type, the unusable slot after a long, and out of range
+        assert_eq!(
+            class_file.get_integer(ConstantPoolIndexRaw::<IntegerConstant>::new(long)),
+            None
+        );
+        assert_eq!(
+            class_file.get_long(ConstantPoolIndexRaw::<LongConstant>::new(long + 1)),
+            None
+        );
+        assert_eq!(
+            class_file.get_float(ConstantPoolIndexRaw::<FloatConstant>::new(int)),
+            None
+        );
+        assert_eq!(
+            class_file.get_double(ConstantPoolIndexRaw::<DoubleConstant>::new(u16::MAX)),
+            None
+        );
+        // The text itself rather than a `CONSTANT_String`
+        assert_eq!(
+            class_file.get_string(ConstantPoolIndexRaw::<StringConstant>::new(text)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_name_and_type() {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        let nat = pool.add_name_and_type("run", "(I)V").unwrap();
+        let method = pool.add_methodref("a/B", "run", "(I)V").unwrap();
+        let class_file = class.build(ClassId::new_unchecked(1));
+
+        let (name, descriptor) = class_file
+            .get_name_and_type(ConstantPoolIndexRaw::<NameAndTypeConstant>::new(nat))
+            .unwrap();
+        assert_eq!(name, "run");
+        assert_eq!(descriptor, "(I)V");
+
+        // A method reference is not itself a name and type
+        assert!(class_file
+            .get_name_and_type(ConstantPoolIndexRaw::<NameAndTypeConstant>::new(method))
+            .is_none());
+        assert!(class_file
+            .get_name_and_type(ConstantPoolIndexRaw::<NameAndTypeConstant>::new(0))
+            .is_none());
+    }
+}
@@ -0,0 +1,647 @@
+//! Minimal reading of the raw layout of a class file.
+//! This is for the pieces of the class file which the parser does not expose in a way that we can
+//! use directly, such as iterating over all of the attributes. It does not validate anything
+//! beyond what is needed to walk the structure.
+//! It can also rewrite a class file without the attributes that are not needed after verification.
+
+use std::{borrow::Cow, ops::Range};
+
+use classfile_parser::{
+    constant_info::ClassConstant, constant_pool::ConstantPoolIndexRaw, ClassAccessFlags,
+};
+
+use crate::{data::class_file_loader::LoadClassFileError, util::convert_classfile_text};
+
+/// The magic number at the start of every class file
+pub const CLASS_FILE_MAGIC: u32 = 0xCAFE_BABE;
+
+/// A simple big-endian reader over a class file's bytes.
+#[derive(Debug, Clone)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    pub(crate) fn new_at(data: &'a [u8], pos: usize) -> Reader<'a> {
+        Reader { data, pos }
+    }
+
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// The number of bytes left to read
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    pub(crate) fn range(&mut self, len: usize) -> Option<Range<usize>> {
+        let start = self.pos;
+        self.bytes(len)?;
+        Some(start..self.pos)
+    }
+
+    pub(crate) fn skip(&mut self, len: usize) -> Option<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|x| x[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|x| u16::from_be_bytes([x[0], x[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]]))
+    }
+}
+
+/// An attribute whose name has not been resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawAttribute {
+    pub name_index: u16,
+    /// The range of the attribute's data within the data it was read from, excluding the name
+    /// index and length
+    pub range: Range<usize>,
+}
+
+/// Read an attribute table (the count followed by the attributes) from the reader
+pub(crate) fn read_attributes(reader: &mut Reader<'_>) -> Option<Vec<RawAttribute>> {
+    let count = reader.u16()?;
+    let mut attributes = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let name_index = reader.u16()?;
+        let length = reader.u32()?;
+        let range = reader.range(usize::try_from(length).ok()?)?;
+        attributes.push(RawAttribute { name_index, range });
+    }
+
+    Some(attributes)
+}
+
+/// Skip over an attribute table
+pub(crate) fn skip_attributes(reader: &mut Reader<'_>) -> Option<()> {
+    let count = reader.u16()?;
+    for _ in 0..count {
+        reader.skip(2)?;
+        let length = reader.u32()?;
+        reader.skip(usize::try_from(length).ok()?)?;
+    }
+
+    Some(())
+}
+
+/// Read the `constant_pool_count` of the class file
+pub(crate) fn constant_pool_count(data: &[u8]) -> Option<u16> {
+    let mut reader = Reader::new(data);
+    // magic, minor, major
+    reader.skip(8)?;
+    reader.u16()
+}
+
+/// Skip over the constant pool, with the reader positioned at the constant pool count
+pub(crate) fn skip_constant_pool(reader: &mut Reader<'_>) -> Option<()> {
+    let count = reader.u16()?;
+    // The constant pool is indexed from 1
+    let mut i = 1;
+    while i < count {
+        let tag = reader.u8()?;
+        match tag {
+            // Utf8
+            1 => {
+                let length = reader.u16()?;
+                reader.skip(usize::from(length))?;
+            }
+            // Integer, Float
+            3 | 4 => reader.skip(4)?,
+            // Long, Double. These take up two entries in the constant pool
+            5 | 6 => {
+                reader.skip(8)?;
+                i += 1;
+            }
+            // Class, String, MethodType, Module, Package
+            7 | 8 | 16 | 19 | 20 => reader.skip(2)?,
+            // Fieldref, Methodref, InterfaceMethodref, NameAndType, Dynamic, InvokeDynamic
+            9 | 10 | 11 | 12 | 17 | 18 => reader.skip(4)?,
+            // MethodHandle
+            15 => reader.skip(3)?,
+            _ => return None,
+        }
+        i += 1;
+    }
+
+    Some(())
+}
+
+/// The entries of the constant pool that are needed to resolve class names
+#[derive(Debug, Clone)]
+enum HeaderConstant {
+    Utf8(Range<usize>),
+    Class { name_index: u16 },
+    Other,
+}
+
+/// Read the constant pool, only keeping the entries needed to resolve class names
+/// The returned vector is indexed by the constant pool index, with index 0 being unused.
+fn read_header_constant_pool(reader: &mut Reader<'_>) -> Option<Vec<HeaderConstant>> {
+    let count = reader.u16()?;
+    let mut entries = Vec::with_capacity(usize::from(count));
+    // The constant pool is indexed from 1
+    entries.push(HeaderConstant::Other);
+    while entries.len() < usize::from(count) {
+        let tag = reader.u8()?;
+        let entry = match tag {
+            // Utf8
+            1 => {
+                let length = reader.u16()?;
+                HeaderConstant::Utf8(reader.range(usize::from(length))?)
+            }
+            // Class
+            7 => HeaderConstant::Class {
+                name_index: reader.u16()?,
+            },
+            // Integer, Float
+            3 | 4 => {
+                reader.skip(4)?;
+                HeaderConstant::Other
+            }
+            // Long, Double. These take up two entries in the constant pool
+            5 | 6 => {
+                reader.skip(8)?;
+                entries.push(HeaderConstant::Other);
+                HeaderConstant::Other
+            }
+            // String, MethodType, Module, Package
+            8 | 16 | 19 | 20 => {
+                reader.skip(2)?;
+                HeaderConstant::Other
+            }
+            // Fieldref, Methodref, InterfaceMethodref, NameAndType, Dynamic, InvokeDynamic
+            9 | 10 | 11 | 12 | 17 | 18 => {
+                reader.skip(4)?;
+                HeaderConstant::Other
+            }
+            // MethodHandle
+            15 => {
+                reader.skip(3)?;
+                HeaderConstant::Other
+            }
+            _ => return None,
+        };
+        entries.push(entry);
+    }
+
+    Some(entries)
+}
+
+/// The information at the start of a class file, before the fields and methods
+#[derive(Debug, Clone)]
+pub struct ClassHeader {
+    pub minor_version: u16,
+    pub major_version: u16,
+    pub access_flags: ClassAccessFlags,
+    pub this_class: String,
+    /// `None` if there is no super class, which should only be the case for `java/lang/Object`
+    pub super_class: Option<String>,
+    pub interfaces: Vec<String>,
+}
+
+/// Parse only the header of a class file: its version, access flags, name, super class, and
+/// interfaces. This skips over the fields, methods, and attributes entirely, and only keeps the
+/// parts of the constant pool needed for the names, which makes it much cheaper than a full parse
+/// when indexing many classes.
+pub fn parse_header(data: &[u8]) -> Result<ClassHeader, LoadClassFileError> {
+    let mut reader = Reader::new(data);
+    if reader.u32() != Some(CLASS_FILE_MAGIC) {
+        return Err(LoadClassFileError::NotAClassFile);
+    }
+
+    parse_header_after_magic(data, &mut reader).ok_or_else(|| {
+        LoadClassFileError::ClassFileParseError("Malformed class file header".to_owned())
+    })
+}
+
+fn parse_header_after_magic(data: &[u8], reader: &mut Reader<'_>) -> Option<ClassHeader> {
+    let minor_version = reader.u16()?;
+    let major_version = reader.u16()?;
+    let constants = read_header_constant_pool(reader)?;
+    let access_flags = ClassAccessFlags::from_bits_truncate(reader.u16()?);
+
+    let class_name = |index: u16| -> Option<String> {
+        let name_index = match constants.get(usize::from(index))? {
+            HeaderConstant::Class { name_index } => *name_index,
+            _ => return None,
+        };
+        match constants.get(usize::from(name_index))? {
+            HeaderConstant::Utf8(range) => {
+                Some(convert_classfile_text(data.get(range.clone())?).into_owned())
+            }
+            _ => None,
+        }
+    };
+
+    let this_class = class_name(reader.u16()?)?;
+    let super_class = match reader.u16()? {
+        0 => None,
+        index => Some(class_name(index)?),
+    };
+
+    let interfaces_count = reader.u16()?;
+    let interfaces = (0..interfaces_count)
+        .map(|_| class_name(reader.u16()?))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(ClassHeader {
+        minor_version,
+        major_version,
+        access_flags,
+        this_class,
+        super_class,
+        interfaces,
+    })
+}
+
+/// Skip over the fields or methods table, which share the same layout
+pub(crate) fn skip_members(reader: &mut Reader<'_>) -> Option<()> {
+    let count = reader.u16()?;
+    for _ in 0..count {
+        // access flags, name index, descriptor index
+        reader.skip(6)?;
+        skip_attributes(reader)?;
+    }
+
+    Some(())
+}
+
+/// Get the offset of the class-level attribute table within the class file
+pub(crate) fn class_attributes_offset(data: &[u8]) -> Option<usize> {
+    let mut reader = Reader::new(data);
+    // magic, minor, major
+    reader.skip(8)?;
+    skip_constant_pool(&mut reader)?;
+    // access flags, this class, super class
+    reader.skip(6)?;
+    let interfaces_count = reader.u16()?;
+    reader.skip(usize::from(interfaces_count) * 2)?;
+    // fields
+    skip_members(&mut reader)?;
+    // methods
+    skip_members(&mut reader)?;
+
+    Some(reader.pos())
+}
+
+/// Read the class-level attributes of the class file
+pub(crate) fn class_attributes(data: &[u8]) -> Option<Vec<RawAttribute>> {
+    let offset = class_attributes_offset(data)?;
+    read_attributes(&mut Reader::new_at(data, offset))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExceptionTableEntry {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    /// The type of exception that is caught, `None` if it catches everything
+    pub catch_type: Option<ConstantPoolIndexRaw<ClassConstant>>,
+}
+
+/// The `Code` attribute of a method
+#[derive(Debug, Clone)]
+pub struct CodeAttribute<'a> {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    /// The bytecode of the method
+    pub code: &'a [u8],
+    pub exception_table: Vec<ExceptionTableEntry>,
+    /// The attributes of the code, such as the `LineNumberTable`
+    /// Their ranges are into the class file data.
+    pub attributes: Vec<RawAttribute>,
+}
+
+/// Read the `Code` attribute whose data is at `range` within the class file data
+pub(crate) fn read_code_attribute(data: &[u8], range: Range<usize>) -> Option<CodeAttribute<'_>> {
+    let end = range.end;
+    let mut reader = Reader::new_at(data.get(..end)?, range.start);
+    let max_stack = reader.u16()?;
+    let max_locals = reader.u16()?;
+    let code_length = reader.u32()?;
+    let code = reader.bytes(usize::try_from(code_length).ok()?)?;
+
+    let exception_table_length = reader.u16()?;
+    let mut exception_table = Vec::with_capacity(usize::from(exception_table_length));
+    for _ in 0..exception_table_length {
+        let start_pc = reader.u16()?;
+        let end_pc = reader.u16()?;
+        let handler_pc = reader.u16()?;
+        let catch_type = reader.u16()?;
+        let catch_type = if catch_type == 0 {
+            None
+        } else {
+            Some(ConstantPoolIndexRaw::new(catch_type))
+        };
+        exception_table.push(ExceptionTableEntry {
+            start_pc,
+            end_pc,
+            handler_pc,
+            catch_type,
+        });
+    }
+
+    let attributes = read_attributes(&mut reader)?;
+
+    Some(CodeAttribute {
+        max_stack,
+        max_locals,
+        code,
+        exception_table,
+        attributes,
+    })
+}
+
+/// An entry in a method's `LocalVariableTable`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalVariableEntry<'a> {
+    /// The variable has a value in the range `start_pc..start_pc + length`
+    pub start_pc: u16,
+    pub length: u16,
+    pub name: Cow<'a, str>,
+    pub descriptor: Cow<'a, str>,
+    /// The index of the variable in the local variables
+    pub slot: u16,
+}
+impl<'a> LocalVariableEntry<'a> {
+    #[must_use]
+    /// Whether the variable is live at the given instruction
+    pub fn contains_pc(&self, pc: u16) -> bool {
+        pc >= self.start_pc && u32::from(pc) < u32::from(self.start_pc) + u32::from(self.length)
+    }
+}
+
+#[must_use]
+/// Find the line number for the instruction at `pc` from a `(start_pc, line_number)` table
+/// This picks the entry with the greatest `start_pc` that is at or before `pc`.
+pub fn line_for_pc(table: &[(u16, u16)], pc: u16) -> Option<u16> {
+    table
+        .iter()
+        .filter(|(start_pc, _)| *start_pc <= pc)
+        .max_by_key(|(start_pc, _)| *start_pc)
+        .map(|(_, line)| *line)
+}
+
+/// Attributes that are only used to verify the class, or by debuggers and other tools which read
+/// the class file, and so are not needed once the class has been verified
+const RELEASABLE_ATTRIBUTES: [&[u8]; 7] = [
+    b"StackMapTable",
+    b"LocalVariableTable",
+    b"LocalVariableTypeTable",
+    b"SourceDebugExtension",
+    b"RuntimeInvisibleAnnotations",
+    b"RuntimeInvisibleParameterAnnotations",
+    b"RuntimeInvisibleTypeAnnotations",
+];
+
+/// The state of [`release_attributes`] as it walks the class file
+struct Release<'a> {
+    data: &'a [u8],
+    /// The range of each constant pool entry, including its tag, indexed by its constant pool
+    /// index. Index 0, and the second index taken up by a long or double, have an empty range.
+    entries: Vec<Range<usize>>,
+    /// Whether each constant pool entry is referenced by anything that is kept
+    retained: Vec<bool>,
+    /// The constant pool entries referenced by the attributes that are dropped
+    released: Vec<u16>,
+}
+impl<'a> Release<'a> {
+    /// Read the constant pool, with the reader positioned at the constant pool count
+    fn new(data: &'a [u8], reader: &mut Reader<'a>) -> Option<Release<'a>> {
+        let count = reader.u16()?;
+        let mut release = Release {
+            data,
+            entries: Vec::with_capacity(usize::from(count)),
+            retained: vec![false; usize::from(count)],
+            released: Vec::new(),
+        };
+        // The constant pool is indexed from 1
+        release.entries.push(0..0);
+        while release.entries.len() < usize::from(count) {
+            let start = reader.pos();
+            let tag = reader.u8()?;
+            match tag {
+                // Utf8
+                1 => {
+                    let length = reader.u16()?;
+                    reader.skip(usize::from(length))?;
+                }
+                // Integer, Float, Fieldref, Methodref, InterfaceMethodref, Dynamic, InvokeDynamic
+                // These don't refer to any Utf8 entries directly
+                3 | 4 | 9 | 10 | 11 | 17 | 18 => reader.skip(4)?,
+                // Long, Double. These take up two entries in the constant pool
+                5 | 6 => {
+                    reader.skip(8)?;
+                    release.entries.push(start..reader.pos());
+                    release.entries.push(0..0);
+                    continue;
+                }
+                // Class, String, MethodType, Module, Package
+                7 | 8 | 16 | 19 | 20 => {
+                    let index = reader.u16()?;
+                    release.retain(index);
+                }
+                // NameAndType
+                12 => {
+                    let name_index = reader.u16()?;
+                    let descriptor_index = reader.u16()?;
+                    release.retain(name_index);
+                    release.retain(descriptor_index);
+                }
+                // MethodHandle
+                15 => reader.skip(3)?,
+                _ => return None,
+            }
+            release.entries.push(start..reader.pos());
+        }
+
+        Some(release)
+    }
+
+    /// Get the text of the `Utf8` entry at the index
+    fn utf8(&self, index: u16) -> Option<&'a [u8]> {
+        let entry = self.entries.get(usize::from(index))?;
+        if self.data.get(entry.start) != Some(&1) {
+            return None;
+        }
+
+        self.data.get(entry.start + 3..entry.end)
+    }
+
+    fn retain(&mut self, index: u16) {
+        if let Some(retained) = self.retained.get_mut(usize::from(index)) {
+            *retained = true;
+        }
+    }
+
+    /// Retain every entry whose index appears anywhere in the data.
+    /// This is used for the attributes that are kept, since we don't know the layout of all of
+    /// them, and being conservative only means keeping some text that isn't needed.
+    fn retain_data(&mut self, data: &[u8]) {
+        for window in data.windows(2) {
+            self.retain(u16::from_be_bytes([window[0], window[1]]));
+        }
+    }
+
+    /// Copy the attribute table at the reader to `out`, without the releasable attributes
+    fn attributes(&mut self, reader: &mut Reader<'a>, out: &mut Vec<u8>) -> Option<()> {
+        let count = reader.u16()?;
+        let mut kept_count: u16 = 0;
+        let mut kept = Vec::new();
+        for _ in 0..count {
+            let name_index = reader.u16()?;
+            let length = reader.u32()?;
+            let range = reader.range(usize::try_from(length).ok()?)?;
+            let name = self.utf8(name_index);
+            let attribute = self.data.get(range.clone())?;
+
+            if name.is_some_and(|name| RELEASABLE_ATTRIBUTES.contains(&name)) {
+                self.released.push(name_index);
+                if matches!(
+                    name,
+                    Some(b"LocalVariableTable" | b"LocalVariableTypeTable")
+                ) {
+                    self.release_local_variables(attribute)?;
+                }
+                continue;
+            }
+
+            self.retain(name_index);
+            kept_count += 1;
+            kept.extend_from_slice(&name_index.to_be_bytes());
+            if matches!(name, Some(b"Code")) {
+                let code = self.code(range)?;
+                kept.extend_from_slice(&u32::try_from(code.len()).ok()?.to_be_bytes());
+                kept.extend_from_slice(&code);
+            } else {
+                self.retain_data(attribute);
+                kept.extend_from_slice(&length.to_be_bytes());
+                kept.extend_from_slice(attribute);
+            }
+        }
+
+        out.extend_from_slice(&kept_count.to_be_bytes());
+        out.extend_from_slice(&kept);
+        Some(())
+    }
+
+    /// Rewrite the data of a `Code` attribute without its releasable attributes
+    /// The bytecode never refers to a `Utf8` entry directly, so it does not retain any.
+    fn code(&mut self, range: Range<usize>) -> Option<Vec<u8>> {
+        let end = range.end;
+        let mut reader = Reader::new_at(self.data.get(..end)?, range.start);
+        // max stack, max locals
+        reader.skip(4)?;
+        let code_length = reader.u32()?;
+        reader.skip(usize::try_from(code_length).ok()?)?;
+        let exception_table_length = reader.u16()?;
+        reader.skip(usize::from(exception_table_length) * 8)?;
+
+        let mut code = self.data.get(range.start..reader.pos())?.to_vec();
+        self.attributes(&mut reader, &mut code)?;
+        if reader.pos() != end {
+            return None;
+        }
+
+        Some(code)
+    }
+
+    /// Release the names and descriptors (or signatures) of a `LocalVariableTable` or
+    /// `LocalVariableTypeTable`
+    fn release_local_variables(&mut self, attribute: &[u8]) -> Option<()> {
+        let mut reader = Reader::new(attribute);
+        let count = reader.u16()?;
+        for _ in 0..count {
+            // start pc, length
+            reader.skip(4)?;
+            self.released.push(reader.u16()?);
+            self.released.push(reader.u16()?);
+            // index
+            reader.skip(2)?;
+        }
+
+        Some(())
+    }
+}
+
+/// Rewrite the class file without the [`RELEASABLE_ATTRIBUTES`], wherever they appear, including
+/// within `Code`.
+/// Each `Utf8` entry that was only referenced by the dropped attributes is replaced with empty
+/// text. The entries are not removed, so that every index into the constant pool stays the same.
+/// Returns `None` if the class file is malformed.
+pub(crate) fn release_attributes(data: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = Reader::new(data);
+    // magic, minor, major
+    reader.skip(8)?;
+    let mut release = Release::new(data, &mut reader)?;
+
+    let mut rest = Vec::with_capacity(reader.remaining());
+    // access flags, this class, super class
+    rest.extend_from_slice(reader.bytes(6)?);
+    let interfaces_count = reader.u16()?;
+    rest.extend_from_slice(&interfaces_count.to_be_bytes());
+    rest.extend_from_slice(reader.bytes(usize::from(interfaces_count) * 2)?);
+    // The fields and then the methods
+    for _ in 0..2 {
+        let count = reader.u16()?;
+        rest.extend_from_slice(&count.to_be_bytes());
+        for _ in 0..count {
+            let access_flags = reader.u16()?;
+            let name_index = reader.u16()?;
+            let descriptor_index = reader.u16()?;
+            release.retain(name_index);
+            release.retain(descriptor_index);
+            for value in [access_flags, name_index, descriptor_index] {
+                rest.extend_from_slice(&value.to_be_bytes());
+            }
+            release.attributes(&mut reader, &mut rest)?;
+        }
+    }
+    release.attributes(&mut reader, &mut rest)?;
+    if reader.remaining() != 0 {
+        return None;
+    }
+
+    let mut blank = vec![false; release.entries.len()];
+    for &index in &release.released {
+        let index = usize::from(index);
+        if release.retained.get(index) == Some(&false) {
+            blank[index] = true;
+        }
+    }
+
+    // magic, minor, major, constant pool count
+    let mut out = data.get(..10)?.to_vec();
+    for (entry, blank) in release.entries.iter().zip(blank) {
+        if blank && data.get(entry.start) == Some(&1) {
+            // An empty Utf8 entry
+            out.extend_from_slice(&[1, 0, 0]);
+        } else {
+            out.extend_from_slice(data.get(entry.clone())?);
+        }
+    }
+    out.extend_from_slice(&rest);
+
+    Some(out)
+}
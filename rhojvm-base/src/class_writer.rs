@@ -0,0 +1,594 @@
+//! Writing class files back out as bytes
+//! A class file is split into its [`ClassFileParts`], which can be modified and then written.
+//! Attributes are kept as their raw bytes, so that ones which we don't understand are written
+//! back out unchanged.
+
+use std::rc::Rc;
+
+use classfile_parser::{
+    field_info::FieldAccessFlags, method_info::MethodAccessFlags, ClassAccessFlags,
+};
+
+use crate::{
+    class::ClassFileData,
+    class_reader::{self, ExceptionTableEntry, Reader, CLASS_FILE_MAGIC},
+    constant_pool_builder::{ConstantPoolBuilder, ConstantPoolBuilderError},
+    data::{class_file_loader::LoadClassFileError, class_names::ClassNames},
+    id::{ClassId, FieldIndex, MethodIndex},
+};
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ClassWriteError {
+    /// The class file that was being read was malformed
+    #[error("malformed class file")]
+    Malformed,
+    /// There were more of something than can be counted in the class file
+    #[error("too many {0} to write")]
+    TooMany(&'static str),
+    /// An attribute's data was longer than can be written
+    #[error("attribute of {0} bytes is too long")]
+    AttributeTooLong(usize),
+    #[error(transparent)]
+    ConstantPool(ConstantPoolBuilderError),
+}
+impl From<ConstantPoolBuilderError> for ClassWriteError {
+    fn from(err: ConstantPoolBuilderError) -> Self {
+        Self::ConstantPool(err)
+    }
+}
+
+/// An error from modifying a class file
+#[derive(Debug, thiserror::Error)]
+pub enum ClassEditError {
+    #[error("failed to write the modified class file")]
+    Write(#[source] ClassWriteError),
+    /// The modified class file could not be parsed, which is likely a bug in the modification
+    #[error("failed to parse the modified class file")]
+    Parse(#[source] LoadClassFileError),
+    /// The class file to modify was not loaded
+    #[error("class file {0:?} is not loaded")]
+    NotLoaded(ClassId),
+    /// There is already a class file loaded with the name that a class was being renamed to
+    #[error("there is already a class file loaded for {0:?}")]
+    AlreadyLoaded(ClassId),
+    /// The class being renamed was already derived from its class file
+    #[error("class {0:?} has already been derived")]
+    AlreadyDerived(ClassId),
+}
+impl From<ClassWriteError> for ClassEditError {
+    fn from(err: ClassWriteError) -> Self {
+        Self::Write(err)
+    }
+}
+impl From<ConstantPoolBuilderError> for ClassEditError {
+    fn from(err: ConstantPoolBuilderError) -> Self {
+        Self::Write(ClassWriteError::ConstantPool(err))
+    }
+}
+
+/// An attribute, with its data excluding the name index and length
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeParts {
+    pub name_index: u16,
+    pub data: Vec<u8>,
+}
+
+/// A field or a method, since they share the same layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberParts {
+    pub access_flags: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<AttributeParts>,
+}
+
+/// The contents of a `Code` attribute
+/// Unlike [`class_reader::CodeAttribute`], this owns its data, so that it can be built up and
+/// then added to a class file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeParts {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: Vec<u8>,
+    pub exception_table: Vec<ExceptionTableEntry>,
+    /// The attributes of the code, such as the `LineNumberTable`
+    /// Their name indices must be valid in the class file that the code is added to.
+    pub attributes: Vec<AttributeParts>,
+}
+impl CodeParts {
+    /// Write the data of the `Code` attribute, excluding its name index and length
+    pub fn write(&self) -> Result<Vec<u8>, ClassWriteError> {
+        let code_length =
+            u32::try_from(self.code.len()).map_err(|_| ClassWriteError::TooMany("code bytes"))?;
+
+        let mut out = Vec::new();
+        out.extend(self.max_stack.to_be_bytes());
+        out.extend(self.max_locals.to_be_bytes());
+        out.extend(code_length.to_be_bytes());
+        out.extend(&self.code);
+
+        write_count(&mut out, self.exception_table.len(), "exception handlers")?;
+        for entry in &self.exception_table {
+            out.extend(entry.start_pc.to_be_bytes());
+            out.extend(entry.end_pc.to_be_bytes());
+            out.extend(entry.handler_pc.to_be_bytes());
+            let catch_type = entry.catch_type.map_or(0, |index| index.0);
+            out.extend(catch_type.to_be_bytes());
+        }
+
+        write_attributes(&mut out, &self.attributes)?;
+        Ok(out)
+    }
+}
+
+/// The pieces of a class file, in the order that they are written
+/// Access flags are kept as their bits, so that flags which we don't know about are kept.
+#[derive(Debug, Clone)]
+pub struct ClassFileParts {
+    pub minor_version: u16,
+    pub major_version: u16,
+    pub constant_pool: ConstantPoolBuilder,
+    pub access_flags: u16,
+    pub this_class: u16,
+    /// 0 if there is no super class
+    pub super_class: u16,
+    pub interfaces: Vec<u16>,
+    pub fields: Vec<MemberParts>,
+    pub methods: Vec<MemberParts>,
+    pub attributes: Vec<AttributeParts>,
+}
+impl ClassFileParts {
+    /// An empty public class named `this`, like `a/b/Generated`, which extends `super_name` if
+    /// there is one
+    /// It uses the Java 8 class file version, and has no members or attributes.
+    pub fn new(
+        this: &str,
+        super_name: Option<&str>,
+    ) -> Result<ClassFileParts, ConstantPoolBuilderError> {
+        let mut constant_pool = ConstantPoolBuilder::new();
+        let this_class = constant_pool.add_class(this)?;
+        let super_class = match super_name {
+            Some(super_name) => constant_pool.add_class(super_name)?,
+            None => 0,
+        };
+
+        Ok(ClassFileParts {
+            minor_version: 0,
+            major_version: 52,
+            constant_pool,
+            access_flags: (ClassAccessFlags::PUBLIC | ClassAccessFlags::SUPER).bits(),
+            this_class,
+            super_class,
+            interfaces: Vec::new(),
+            fields: Vec::new(),
+            methods: Vec::new(),
+            attributes: Vec::new(),
+        })
+    }
+
+    /// Split the class file into its parts
+    pub fn from_class_file(class_file: &ClassFileData) -> Result<ClassFileParts, ClassWriteError> {
+        ClassFileParts::read(&class_file.class_file_data)
+    }
+
+    /// Read the parts from the bytes of a class file
+    pub fn read(data: &[u8]) -> Result<ClassFileParts, ClassWriteError> {
+        let mut reader = Reader::new(data);
+        if reader.u32() != Some(CLASS_FILE_MAGIC) {
+            return Err(ClassWriteError::Malformed);
+        }
+
+        read_after_magic(data, &mut reader).ok_or(ClassWriteError::Malformed)
+    }
+
+    /// Add a field to the end of the fields, returning its index
+    pub fn add_field(
+        &mut self,
+        name: &str,
+        descriptor: &str,
+        access: FieldAccessFlags,
+    ) -> Result<FieldIndex, ClassWriteError> {
+        let index = FieldIndex::try_from(self.fields.len())
+            .map_err(|_| ClassWriteError::TooMany("fields"))?;
+
+        self.fields.push(MemberParts {
+            access_flags: access.bits(),
+            name_index: self.constant_pool.add_utf8(name)?,
+            descriptor_index: self.constant_pool.add_utf8(descriptor)?,
+            attributes: Vec::new(),
+        });
+
+        Ok(index)
+    }
+
+    /// Add a method to the end of the methods, returning its index
+    /// The code should refer to the entries of [`ClassFileParts::constant_pool`], such as by
+    /// building it with [`crate::code::builder::CodeBuilder`].
+    pub fn add_method(
+        &mut self,
+        name: &str,
+        descriptor: &str,
+        access: MethodAccessFlags,
+        code: Option<&CodeParts>,
+    ) -> Result<MethodIndex, ClassWriteError> {
+        let index = MethodIndex::try_from(self.methods.len())
+            .map_err(|_| ClassWriteError::TooMany("methods"))?;
+
+        let mut attributes = Vec::new();
+        if let Some(code) = code {
+            attributes.push(AttributeParts {
+                name_index: self.constant_pool.add_utf8("Code")?,
+                data: code.write()?,
+            });
+        }
+        self.methods.push(MemberParts {
+            access_flags: access.bits(),
+            name_index: self.constant_pool.add_utf8(name)?,
+            descriptor_index: self.constant_pool.add_utf8(descriptor)?,
+            attributes,
+        });
+
+        Ok(index)
+    }
+
+    /// Write the class file
+    pub fn write(&self) -> Result<Vec<u8>, ClassWriteError> {
+        let mut out = Vec::new();
+        out.extend(CLASS_FILE_MAGIC.to_be_bytes());
+        out.extend(self.minor_version.to_be_bytes());
+        out.extend(self.major_version.to_be_bytes());
+        self.constant_pool.write(&mut out);
+        out.extend(self.access_flags.to_be_bytes());
+        out.extend(self.this_class.to_be_bytes());
+        out.extend(self.super_class.to_be_bytes());
+
+        write_count(&mut out, self.interfaces.len(), "interfaces")?;
+        for interface in &self.interfaces {
+            out.extend(interface.to_be_bytes());
+        }
+
+        write_members(&mut out, &self.fields, "fields")?;
+        write_members(&mut out, &self.methods, "methods")?;
+        write_attributes(&mut out, &self.attributes)?;
+
+        Ok(out)
+    }
+}
+
+fn read_after_magic(data: &[u8], reader: &mut Reader<'_>) -> Option<ClassFileParts> {
+    let minor_version = reader.u16()?;
+    let major_version = reader.u16()?;
+    let constant_pool = ConstantPoolBuilder::read(reader)?;
+    let access_flags = reader.u16()?;
+    let this_class = reader.u16()?;
+    let super_class = reader.u16()?;
+
+    let interfaces_count = reader.u16()?;
+    let interfaces = (0..interfaces_count)
+        .map(|_| reader.u16())
+        .collect::<Option<Vec<_>>>()?;
+
+    let fields = read_members(data, reader)?;
+    let methods = read_members(data, reader)?;
+    let attributes = read_attributes(data, reader)?;
+
+    // There should be nothing after the attributes
+    if reader.pos() != data.len() {
+        return None;
+    }
+
+    Some(ClassFileParts {
+        minor_version,
+        major_version,
+        constant_pool,
+        access_flags,
+        this_class,
+        super_class,
+        interfaces,
+        fields,
+        methods,
+        attributes,
+    })
+}
+
+fn read_members(data: &[u8], reader: &mut Reader<'_>) -> Option<Vec<MemberParts>> {
+    let count = reader.u16()?;
+    let mut members = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        members.push(MemberParts {
+            access_flags: reader.u16()?,
+            name_index: reader.u16()?,
+            descriptor_index: reader.u16()?,
+            attributes: read_attributes(data, reader)?,
+        });
+    }
+
+    Some(members)
+}
+
+fn read_attributes(data: &[u8], reader: &mut Reader<'_>) -> Option<Vec<AttributeParts>> {
+    class_reader::read_attributes(reader)?
+        .into_iter()
+        .map(|attr| {
+            Some(AttributeParts {
+                name_index: attr.name_index,
+                data: data.get(attr.range)?.to_vec(),
+            })
+        })
+        .collect()
+}
+
+fn write_count(out: &mut Vec<u8>, count: usize, what: &'static str) -> Result<(), ClassWriteError> {
+    let count = u16::try_from(count).map_err(|_| ClassWriteError::TooMany(what))?;
+    out.extend(count.to_be_bytes());
+    Ok(())
+}
+
+fn write_members(
+    out: &mut Vec<u8>,
+    members: &[MemberParts],
+    what: &'static str,
+) -> Result<(), ClassWriteError> {
+    write_count(out, members.len(), what)?;
+    for member in members {
+        out.extend(member.access_flags.to_be_bytes());
+        out.extend(member.name_index.to_be_bytes());
+        out.extend(member.descriptor_index.to_be_bytes());
+        write_attributes(out, &member.attributes)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_attributes(
+    out: &mut Vec<u8>,
+    attributes: &[AttributeParts],
+) -> Result<(), ClassWriteError> {
+    write_count(out, attributes.len(), "attributes")?;
+    for attribute in attributes {
+        let length = u32::try_from(attribute.data.len())
+            .map_err(|_| ClassWriteError::AttributeTooLong(attribute.data.len()))?;
+        out.extend(attribute.name_index.to_be_bytes());
+        out.extend(length.to_be_bytes());
+        out.extend(&attribute.data);
+    }
+
+    Ok(())
+}
+
+impl ClassFileData {
+    /// Serialize the class file back into bytes
+    /// The constant pool is rewritten entry by entry, and everything else is written from its
+    /// structure, with attributes copied as-is.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ClassWriteError> {
+        ClassFileParts::from_class_file(self)?.write()
+    }
+
+    /// Replace the class file with the written parts, keeping its id and source
+    /// The parts should have been split from this class file, so that the name still matches
+    /// its id.
+    pub fn replace_with_parts(&mut self, parts: &ClassFileParts) -> Result<(), ClassEditError> {
+        let data = parts.write()?;
+        *self = ClassFileData::parse(self.id, self.source.clone(), Rc::from(data))
+            .map_err(ClassEditError::Parse)?;
+        Ok(())
+    }
+
+    /// Add a method to the end of the class's methods, returning its index
+    /// The method has the given `Code` attribute, if there is one. Abstract and native methods
+    /// should not have code.
+    /// If a [`crate::class::Class`] was already derived from the class file then its method count
+    /// will be out of date, see [`crate::data::classes::Classes::add_method`] to keep it in sync.
+    pub fn add_method(
+        &mut self,
+        name: &str,
+        descriptor: &str,
+        access: MethodAccessFlags,
+        code: Option<&CodeParts>,
+    ) -> Result<MethodIndex, ClassEditError> {
+        let mut parts = ClassFileParts::from_class_file(self)?;
+        let index = parts.add_method(name, descriptor, access, code)?;
+        self.replace_with_parts(&parts)?;
+        Ok(index)
+    }
+
+    /// Rename the class, so that `this_class` refers to `name`, like `a/b/Generated`
+    /// This updates the id of the class file to the id of the new name, which is returned.
+    /// References to the old name elsewhere in the class, such as in the descriptors of its own
+    /// methods, are left as they are.
+    /// See [`crate::data::class_files::ClassFiles::rename`] to rename a loaded class file.
+    pub fn set_this_class_name(
+        &mut self,
+        class_names: &mut ClassNames,
+        name: &str,
+    ) -> Result<ClassId, ClassEditError> {
+        let mut parts = ClassFileParts::from_class_file(self)?;
+        parts.this_class = parts.constant_pool.add_class(name)?;
+        self.replace_with_parts(&parts)?;
+
+        self.id = class_names.gcid_from_bytes(&cesu8::to_java_cesu8(name));
+        Ok(self.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use classfile_parser::{
+        constant_info::LongConstant, constant_pool::ConstantPoolIndexRaw,
+        method_info::MethodAccessFlags,
+    };
+
+    use crate::{
+        class::ClassFileData,
+        code::builder::CodeBuilder,
+        data::class_names::ClassNames,
+        id::ClassId,
+        test_util::{TestClass, ACC_PRIVATE},
+    };
+
+    use super::{AttributeParts, ClassFileParts, CodeParts};
+
+    /// A class with a field, a method with code and an attribute on the code, and a class
+    /// attribute
+    fn class_bytes() -> Vec<u8> {
+        let mut class = TestClass::new("a/A", Some("java/lang/Object"));
+        let pool = &mut class.parts.constant_pool;
+        // #5 = Long 7, which also takes up #6
+        pool.add_long(7).unwrap();
+        // #7 = Utf8 x, #8 = Utf8 ()V, #9 = Utf8 Code, #10 = Utf8 J
+        for text in ["x", "()V", "Code", "J"] {
+            pool.add_utf8(text).unwrap();
+        }
+
+        let code = CodeParts {
+            max_stack: 0,
+            max_locals: 1,
+            // return
+            code: vec![0xB1],
+            exception_table: Vec::new(),
+            // An attribute which we don't know about
+            attributes: vec![AttributeParts {
+                name_index: 7,
+                data: vec![0xAB, 0xCD],
+            }],
+        };
+        class
+            .parts
+            .add_method("x", "()V", MethodAccessFlags::PUBLIC, Some(&code))
+            .unwrap();
+        class
+            .field(ACC_PRIVATE, "x", "J")
+            .attribute("x", vec![0x42])
+            .bytes()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = class_bytes();
+        let id = ClassId::new_unchecked(1);
+        let class_file = ClassFileData::from_bytes(id, &data).unwrap();
+        let written = class_file.to_bytes().unwrap();
+        assert_eq!(written, data);
+
+        let reparsed = ClassFileData::from_bytes(id, &written).unwrap();
+        assert_eq!(reparsed.methods_len(), 1);
+        assert_eq!(reparsed.method_name(0).as_deref(), Some("x"));
+        assert_eq!(
+            reparsed.get_long(ConstantPoolIndexRaw::<LongConstant>::new(5)),
+            Some(7)
+        );
+
+        let parts = ClassFileParts::read(&written).unwrap();
+        assert_eq!(parts.fields.len(), 1);
+        assert_eq!(parts.methods[0].attributes[0].name_index, 9);
+        assert_eq!(parts.attributes[0].data, [0x42]);
+
+        // Trailing data is rejected
+        let mut trailing = data;
+        trailing.push(0);
+        assert!(ClassFileParts::read(&trailing).is_err());
+    }
+
+    #[test]
+    fn test_set_this_class_name() {
+        let mut class_names = ClassNames::new();
+        let id = class_names.gcid_from_bytes(b"a/A");
+        let mut class_file = ClassFileData::from_bytes(id, &class_bytes()).unwrap();
+
+        let new_id = class_file
+            .set_this_class_name(&mut class_names, "b/Renamed")
+            .unwrap();
+        assert_ne!(new_id, id);
+        assert_eq!(class_file.id(), new_id);
+        assert_eq!(new_id, class_names.gcid_from_bytes(b"b/Renamed"));
+        assert_eq!(
+            class_file.get_this_class_name().unwrap(),
+            b"b/Renamed".as_slice()
+        );
+        // The rest of the class is unchanged
+        assert_eq!(class_file.method_name(0).as_deref(), Some("x"));
+
+        // Writing it out and loading it again keeps the name
+        let reloaded = ClassFileData::from_bytes(new_id, &class_file.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            reloaded.get_this_class_name().unwrap(),
+            b"b/Renamed".as_slice()
+        );
+
+        // Renaming to a name that is already in the pool reuses its entries
+        let count = class_file.constant_pool_count();
+        class_file
+            .set_this_class_name(&mut class_names, "java/lang/Object")
+            .unwrap();
+        assert_eq!(class_file.constant_pool_count(), count);
+    }
+
+    #[test]
+    fn test_add_method() {
+        let id = ClassId::new_unchecked(1);
+        let mut class_file = ClassFileData::from_bytes(id, &class_bytes()).unwrap();
+
+        let code = CodeParts {
+            max_stack: 1,
+            max_locals: 0,
+            // iconst_1, ireturn
+            code: vec![0x04, 0xAC],
+            ..CodeParts::default()
+        };
+        let index = class_file
+            .add_method(
+                "one",
+                "()I",
+                MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+                Some(&code),
+            )
+            .unwrap();
+        assert_eq!(index, 1);
+        let abstract_index = class_file
+            .add_method("two", "()V", MethodAccessFlags::ABSTRACT, None)
+            .unwrap();
+        assert_eq!(abstract_index, 2);
+
+        assert_eq!(class_file.methods_len(), 3);
+        assert_eq!(class_file.method_name(index).as_deref(), Some("one"));
+        assert_eq!(class_file.method_descriptor(index).as_deref(), Some("()I"));
+        assert!(class_file
+            .method_flags(index)
+            .unwrap()
+            .contains(MethodAccessFlags::STATIC));
+        assert_eq!(class_file.find_method("two", "()V"), Some(abstract_index));
+        // The existing method is unchanged
+        assert_eq!(class_file.method_name(0).as_deref(), Some("x"));
+
+        let parts = ClassFileParts::from_class_file(&class_file).unwrap();
+        assert!(parts.methods[2].attributes.is_empty());
+        let code_attribute = &parts.methods[1].attributes[0];
+        // `Code` was already in the constant pool
+        assert_eq!(code_attribute.name_index, 9);
+        assert_eq!(code_attribute.data, code.write().unwrap());
+    }
+
+    #[test]
+    fn test_add_built_method() {
+        let id = ClassId::new_unchecked(1);
+        let mut class_file = ClassFileData::from_bytes(id, &class_bytes()).unwrap();
+
+        let mut parts = ClassFileParts::from_class_file(&class_file).unwrap();
+        let mut code = CodeBuilder::new(&mut parts.constant_pool, "()J", true).unwrap();
+        code.lconst(7).unwrap();
+        code.lreturn().unwrap();
+        let code = code.build().unwrap();
+        let index = parts
+            .add_method("seven", "()J", MethodAccessFlags::STATIC, Some(&code))
+            .unwrap();
+        class_file.replace_with_parts(&parts).unwrap();
+
+        assert_eq!(class_file.method_name(index).as_deref(), Some("seven"));
+        // The long was already in the constant pool as #5, so `ldc2_w` refers to it
+        assert_eq!(code.code, [0x14, 0, 5, 0xAD]);
+        assert_eq!(
+            class_file.constant_pool_count(),
+            parts.constant_pool.count()
+        );
+    }
+}
@@ -0,0 +1,1178 @@
+//! Building the code of a method instruction by instruction
+//! The [`CodeBuilder`] picks the shortest encoding of each instruction, tracks the depth of the
+//! operand stack and the local variables that are used to compute `max_stack` and `max_locals`,
+//! and patches branches to labels once the code is finished, so that labels can be branched to
+//! before they are placed.
+
+use classfile_parser::constant_pool::ConstantPoolIndexRaw;
+
+use crate::{
+    class_reader::ExceptionTableEntry,
+    class_writer::CodeParts,
+    constant_pool_builder::{ConstantPoolBuilder, ConstantPoolBuilderError, PoolEntry},
+};
+
+use super::op::{
+    AConstNull, ALoad, ALoad0, ANewArray, AReturn, AStore, AStore0, AThrow, ArrayLength, CheckCast,
+    DoubleConst0, DoubleConst1, DoubleLoad, DoubleLoad0, DoubleReturn, DoubleStore, DoubleStore0,
+    Dup, Dup2, DupX1, FloatConst0, FloatConst1, FloatConst2, FloatLoad, FloatLoad0, FloatReturn,
+    FloatStore, FloatStore0, GetField, GetStatic, Goto, IConstNeg1, IfACmpEq, IfACmpNe, IfEqZero,
+    IfGeZero, IfGtZero, IfIntCmpEq, IfIntCmpGe, IfIntCmpGt, IfIntCmpLe, IfIntCmpLt, IfIntCmpNe,
+    IfLeZero, IfLtZero, IfNeZero, IfNonNull, IfNull, InstanceOf, IntAdd, IntDivide, IntIncrement,
+    IntLoad, IntLoad0, IntMultiply, IntNegate, IntRemainder, IntReturn, IntStore, IntStore0,
+    IntSubtract, InvokeInterface, InvokeSpecial, InvokeStatic, InvokeVirtual, LoadConstant,
+    LoadConstant2Wide, LoadConstantWide, LongConst0, LongConst1, LongLoad, LongLoad0, LongReturn,
+    LongStore, LongStore0, New, Pop, Pop2, PushByte, PushShort, PutField, PutStaticField,
+    RawOpcode, Return, Wide,
+};
+
+/// A position in the code which can be branched to
+/// Created by [`CodeBuilder::new_label`] and given a position by [`CodeBuilder::place_label`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(usize);
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum CodeBuilderError {
+    #[error(transparent)]
+    ConstantPool(ConstantPoolBuilderError),
+    /// The descriptor of a method or field was malformed
+    #[error("invalid descriptor")]
+    InvalidDescriptor,
+    /// The constant pool entry was not the kind that the instruction needs
+    #[error("constant pool entry {0} can not be used by the instruction")]
+    BadConstant(u16),
+    #[error("instruction at {pc} pops more than is on the stack")]
+    StackUnderflow { pc: usize },
+    #[error("instruction at {pc} pushes more than the stack can hold")]
+    StackOverflow { pc: usize },
+    /// The local variable, including its second slot for `long`s and `double`s, is beyond the
+    /// highest index that a method can have
+    #[error("local variable {0} is out of range")]
+    LocalOutOfRange(u16),
+    /// A label was reached with different stack depths, such as by falling through to it and by
+    /// branching to it
+    #[error("{label:?} is reached with a stack depth of {found}, but expected {expected}")]
+    StackMismatch {
+        label: Label,
+        expected: u16,
+        found: u16,
+    },
+    #[error("{0:?} was placed more than once")]
+    LabelPlacedTwice(Label),
+    #[error("{0:?} was used but never placed")]
+    UnplacedLabel(Label),
+    /// The branch was too far from its target to fit in the offset
+    #[error("branch at {pc} is too far from its target")]
+    BranchTooFar { pc: usize },
+    /// The code is longer than the 65535 bytes that a method can have
+    #[error("the code is {0} bytes long, which is too long")]
+    CodeTooLong(usize),
+}
+impl From<ConstantPoolBuilderError> for CodeBuilderError {
+    fn from(err: ConstantPoolBuilderError) -> Self {
+        Self::ConstantPool(err)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct LabelState {
+    /// The offset in the code that the label was placed at
+    offset: Option<usize>,
+    /// The stack depth whenever the label is reached
+    stack: Option<u16>,
+}
+
+/// A branch instruction whose offset is filled in when the code is built
+#[derive(Debug, Clone)]
+struct Branch {
+    /// The position of the branch instruction, which the offset is relative to
+    pc: usize,
+    label: Label,
+}
+
+#[derive(Debug, Clone)]
+struct Handler {
+    start: Label,
+    end: Label,
+    handler: Label,
+    catch_type: Option<u16>,
+}
+
+/// The kinds of values that can be loaded from and stored into local variables
+#[derive(Debug, Clone, Copy)]
+enum LocalKind {
+    Int,
+    Long,
+    Float,
+    Double,
+    Reference,
+}
+impl LocalKind {
+    /// The opcodes of the load and store instructions which take an index, and of the ones
+    /// for local variable 0, which are followed by the ones for 1, 2, and 3.
+    fn opcodes(self) -> [RawOpcode; 4] {
+        match self {
+            LocalKind::Int => [
+                IntLoad::OPCODE,
+                IntLoad0::OPCODE,
+                IntStore::OPCODE,
+                IntStore0::OPCODE,
+            ],
+            LocalKind::Long => [
+                LongLoad::OPCODE,
+                LongLoad0::OPCODE,
+                LongStore::OPCODE,
+                LongStore0::OPCODE,
+            ],
+            LocalKind::Float => [
+                FloatLoad::OPCODE,
+                FloatLoad0::OPCODE,
+                FloatStore::OPCODE,
+                FloatStore0::OPCODE,
+            ],
+            LocalKind::Double => [
+                DoubleLoad::OPCODE,
+                DoubleLoad0::OPCODE,
+                DoubleStore::OPCODE,
+                DoubleStore0::OPCODE,
+            ],
+            LocalKind::Reference => [
+                ALoad::OPCODE,
+                ALoad0::OPCODE,
+                AStore::OPCODE,
+                AStore0::OPCODE,
+            ],
+        }
+    }
+
+    fn slot_size(self) -> u16 {
+        match self {
+            LocalKind::Long | LocalKind::Double => 2,
+            LocalKind::Int | LocalKind::Float | LocalKind::Reference => 1,
+        }
+    }
+}
+
+/// Builds the code of a method, adding the constants that it uses to a constant pool
+/// The constant pool should be the one of the class that the code will be added to, such as
+/// the [`crate::class_writer::ClassFileParts::constant_pool`].
+///
+/// The stack depth is tracked as instructions are added, and so each label must be reached with
+/// the same stack depth. Code which follows an unconditional jump, a return, or a throw is only
+/// reachable through a label, and so starts with the stack depth that the label was branched to
+/// with, or an empty stack if nothing has branched to it yet.
+///
+/// Switches are not supported.
+#[derive(Debug)]
+pub struct CodeBuilder<'a> {
+    pool: &'a mut ConstantPoolBuilder,
+    code: Vec<u8>,
+    stack: u16,
+    max_stack: u16,
+    max_locals: u16,
+    /// Whether the current position can be reached by the previous instruction
+    reachable: bool,
+    labels: Vec<LabelState>,
+    branches: Vec<Branch>,
+    handlers: Vec<Handler>,
+}
+impl<'a> CodeBuilder<'a> {
+    /// Create a builder for the code of a method with the given descriptor, like `(IJ)V`
+    /// The parameters, and `this` for instance methods, are counted towards `max_locals`.
+    pub fn new(
+        pool: &'a mut ConstantPoolBuilder,
+        descriptor: &str,
+        is_static: bool,
+    ) -> Result<CodeBuilder<'a>, CodeBuilderError> {
+        let (parameters, _) = method_descriptor_slots(descriptor.as_bytes())
+            .ok_or(CodeBuilderError::InvalidDescriptor)?;
+        let max_locals = parameters
+            .checked_add(u16::from(!is_static))
+            .ok_or(CodeBuilderError::InvalidDescriptor)?;
+
+        Ok(CodeBuilder {
+            pool,
+            code: Vec::new(),
+            stack: 0,
+            max_stack: 0,
+            max_locals,
+            reachable: true,
+            labels: Vec::new(),
+            branches: Vec::new(),
+            handlers: Vec::new(),
+        })
+    }
+
+    /// The constant pool that the code refers to, for adding the constants that instructions
+    /// take, like with [`ConstantPoolBuilder::add_methodref`]
+    pub fn pool(&mut self) -> &mut ConstantPoolBuilder {
+        self.pool
+    }
+
+    #[must_use]
+    /// The offset that the next instruction will be at
+    pub fn offset(&self) -> usize {
+        self.code.len()
+    }
+
+    #[must_use]
+    /// The current depth of the operand stack, in slots
+    pub fn stack_depth(&self) -> u16 {
+        self.stack
+    }
+
+    /// Start an instruction, adjusting the stack by what it pops and pushes
+    fn start(&mut self, opcode: RawOpcode, pop: u16, push: u16) -> Result<(), CodeBuilderError> {
+        let pc = self.code.len();
+        self.stack = self
+            .stack
+            .checked_sub(pop)
+            .ok_or(CodeBuilderError::StackUnderflow { pc })?
+            .checked_add(push)
+            .ok_or(CodeBuilderError::StackOverflow { pc })?;
+        self.max_stack = self.max_stack.max(self.stack);
+        self.reachable = true;
+        self.code.push(opcode);
+        Ok(())
+    }
+
+    /// Mark the code after the previous instruction as only reachable through a label
+    fn end_reachable(&mut self) {
+        self.reachable = false;
+        self.stack = 0;
+    }
+
+    fn use_local(&mut self, index: u16, size: u16) -> Result<(), CodeBuilderError> {
+        let end = index
+            .checked_add(size)
+            .ok_or(CodeBuilderError::LocalOutOfRange(index))?;
+        self.max_locals = self.max_locals.max(end);
+        Ok(())
+    }
+
+    /// Add an instruction which has no operands, and which pops and pushes the given number of
+    /// stack slots
+    /// This is for the instructions which do not have their own method, like `lmul`.
+    pub fn op(&mut self, opcode: RawOpcode, pop: u16, push: u16) -> Result<(), CodeBuilderError> {
+        self.start(opcode, pop, push)
+    }
+
+    fn load(&mut self, kind: LocalKind, index: u16) -> Result<(), CodeBuilderError> {
+        let [load, load0, _, _] = kind.opcodes();
+        self.use_local(index, kind.slot_size())?;
+        self.local_op(load, load0, index, 0, kind.slot_size())
+    }
+
+    fn store(&mut self, kind: LocalKind, index: u16) -> Result<(), CodeBuilderError> {
+        let [_, _, store, store0] = kind.opcodes();
+        self.use_local(index, kind.slot_size())?;
+        self.local_op(store, store0, index, kind.slot_size(), 0)
+    }
+
+    /// Add a load or store, picking between the short form, the form with a byte index, and the
+    /// wide form
+    fn local_op(
+        &mut self,
+        opcode: RawOpcode,
+        opcode0: RawOpcode,
+        index: u16,
+        pop: u16,
+        push: u16,
+    ) -> Result<(), CodeBuilderError> {
+        if index <= 3 {
+            // The short forms are one opcode for each of the four locals
+            #[allow(clippy::cast_possible_truncation)]
+            let index = index as u8;
+            self.start(opcode0 + index, pop, push)
+        } else if let Ok(index) = u8::try_from(index) {
+            self.start(opcode, pop, push)?;
+            self.code.push(index);
+            Ok(())
+        } else {
+            self.start(Wide::OPCODE, pop, push)?;
+            self.code.push(opcode);
+            self.code.extend(index.to_be_bytes());
+            Ok(())
+        }
+    }
+
+    pub fn iload(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.load(LocalKind::Int, index)
+    }
+
+    pub fn lload(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.load(LocalKind::Long, index)
+    }
+
+    pub fn fload(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.load(LocalKind::Float, index)
+    }
+
+    pub fn dload(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.load(LocalKind::Double, index)
+    }
+
+    pub fn aload(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.load(LocalKind::Reference, index)
+    }
+
+    pub fn istore(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.store(LocalKind::Int, index)
+    }
+
+    pub fn lstore(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.store(LocalKind::Long, index)
+    }
+
+    pub fn fstore(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.store(LocalKind::Float, index)
+    }
+
+    pub fn dstore(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.store(LocalKind::Double, index)
+    }
+
+    pub fn astore(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        self.store(LocalKind::Reference, index)
+    }
+
+    /// Add `delta` to the int in the local variable
+    pub fn iinc(&mut self, index: u16, delta: i16) -> Result<(), CodeBuilderError> {
+        self.use_local(index, 1)?;
+        match (u8::try_from(index), i8::try_from(delta)) {
+            (Ok(index), Ok(delta)) => {
+                self.start(IntIncrement::OPCODE, 0, 0)?;
+                self.code.push(index);
+                self.code.extend(delta.to_be_bytes());
+            }
+            _ => {
+                self.start(Wide::OPCODE, 0, 0)?;
+                self.code.push(IntIncrement::OPCODE);
+                self.code.extend(index.to_be_bytes());
+                self.code.extend(delta.to_be_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn aconst_null(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(AConstNull::OPCODE, 0, 1)
+    }
+
+    /// Push an int, using the shortest instruction that can hold it
+    pub fn iconst(&mut self, value: i32) -> Result<(), CodeBuilderError> {
+        if (-1..=5).contains(&value) {
+            // iconst_m1 through iconst_5 are consecutive
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let offset = (value + 1) as u8;
+            self.start(IConstNeg1::OPCODE + offset, 0, 1)
+        } else if let Ok(value) = i8::try_from(value) {
+            self.start(PushByte::OPCODE, 0, 1)?;
+            self.code.extend(value.to_be_bytes());
+            Ok(())
+        } else if let Ok(value) = i16::try_from(value) {
+            self.start(PushShort::OPCODE, 0, 1)?;
+            self.code.extend(value.to_be_bytes());
+            Ok(())
+        } else {
+            let index = self.pool.add_integer(value)?;
+            self.ldc(index)
+        }
+    }
+
+    /// Push a long, from the constant pool if it isn't `0` or `1`
+    pub fn lconst(&mut self, value: i64) -> Result<(), CodeBuilderError> {
+        match value {
+            0 => self.start(LongConst0::OPCODE, 0, 2),
+            1 => self.start(LongConst1::OPCODE, 0, 2),
+            _ => {
+                let index = self.pool.add_long(value)?;
+                self.ldc2_w(index)
+            }
+        }
+    }
+
+    /// Push a float, from the constant pool if it isn't `0.0`, `1.0`, or `2.0`
+    pub fn fconst(&mut self, value: f32) -> Result<(), CodeBuilderError> {
+        // Compared by bits, since `-0.0` has to come from the constant pool
+        let bits = value.to_bits();
+        if bits == 0.0f32.to_bits() {
+            self.start(FloatConst0::OPCODE, 0, 1)
+        } else if bits == 1.0f32.to_bits() {
+            self.start(FloatConst1::OPCODE, 0, 1)
+        } else if bits == 2.0f32.to_bits() {
+            self.start(FloatConst2::OPCODE, 0, 1)
+        } else {
+            let index = self.pool.add_float(value)?;
+            self.ldc(index)
+        }
+    }
+
+    /// Push a double, from the constant pool if it isn't `0.0` or `1.0`
+    pub fn dconst(&mut self, value: f64) -> Result<(), CodeBuilderError> {
+        let bits = value.to_bits();
+        if bits == 0.0f64.to_bits() {
+            self.start(DoubleConst0::OPCODE, 0, 2)
+        } else if bits == 1.0f64.to_bits() {
+            self.start(DoubleConst1::OPCODE, 0, 2)
+        } else {
+            let index = self.pool.add_double(value)?;
+            self.ldc2_w(index)
+        }
+    }
+
+    /// Push a `java/lang/String` with the text
+    pub fn ldc_string(&mut self, text: &str) -> Result<(), CodeBuilderError> {
+        let index = self.pool.add_string(text)?;
+        self.ldc(index)
+    }
+
+    /// Push the single-slot constant at the index, using `ldc_w` if the index doesn't fit in a
+    /// byte
+    pub fn ldc(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        match self.pool.get(index) {
+            Some(PoolEntry::Long(_) | PoolEntry::Double(_)) | None => {
+                return Err(CodeBuilderError::BadConstant(index))
+            }
+            Some(_) => {}
+        }
+
+        if let Ok(index) = u8::try_from(index) {
+            self.start(LoadConstant::OPCODE, 0, 1)?;
+            self.code.push(index);
+        } else {
+            self.start(LoadConstantWide::OPCODE, 0, 1)?;
+            self.code.extend(index.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    /// Push the long or double at the index
+    pub fn ldc2_w(&mut self, index: u16) -> Result<(), CodeBuilderError> {
+        if !matches!(
+            self.pool.get(index),
+            Some(PoolEntry::Long(_) | PoolEntry::Double(_))
+        ) {
+            return Err(CodeBuilderError::BadConstant(index));
+        }
+
+        self.start(LoadConstant2Wide::OPCODE, 0, 2)?;
+        self.code.extend(index.to_be_bytes());
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(Pop::OPCODE, 1, 0)
+    }
+
+    pub fn pop2(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(Pop2::OPCODE, 2, 0)
+    }
+
+    pub fn dup(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(Dup::OPCODE, 1, 2)
+    }
+
+    pub fn dup_x1(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(DupX1::OPCODE, 2, 3)
+    }
+
+    pub fn dup2(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(Dup2::OPCODE, 2, 4)
+    }
+
+    pub fn iadd(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(IntAdd::OPCODE, 2, 1)
+    }
+
+    pub fn isub(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(IntSubtract::OPCODE, 2, 1)
+    }
+
+    pub fn imul(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(IntMultiply::OPCODE, 2, 1)
+    }
+
+    pub fn idiv(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(IntDivide::OPCODE, 2, 1)
+    }
+
+    pub fn irem(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(IntRemainder::OPCODE, 2, 1)
+    }
+
+    pub fn ineg(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(IntNegate::OPCODE, 1, 1)
+    }
+
+    pub fn arraylength(&mut self) -> Result<(), CodeBuilderError> {
+        self.start(ArrayLength::OPCODE, 1, 1)
+    }
+
+    fn end_op(&mut self, opcode: RawOpcode, pop: u16) -> Result<(), CodeBuilderError> {
+        self.start(opcode, pop, 0)?;
+        self.end_reachable();
+        Ok(())
+    }
+
+    /// Return void, named with an underscore since `return` is a keyword
+    pub fn return_(&mut self) -> Result<(), CodeBuilderError> {
+        self.end_op(Return::OPCODE, 0)
+    }
+
+    pub fn ireturn(&mut self) -> Result<(), CodeBuilderError> {
+        self.end_op(IntReturn::OPCODE, 1)
+    }
+
+    pub fn lreturn(&mut self) -> Result<(), CodeBuilderError> {
+        self.end_op(LongReturn::OPCODE, 2)
+    }
+
+    pub fn freturn(&mut self) -> Result<(), CodeBuilderError> {
+        self.end_op(FloatReturn::OPCODE, 1)
+    }
+
+    pub fn dreturn(&mut self) -> Result<(), CodeBuilderError> {
+        self.end_op(DoubleReturn::OPCODE, 2)
+    }
+
+    pub fn areturn(&mut self) -> Result<(), CodeBuilderError> {
+        self.end_op(AReturn::OPCODE, 1)
+    }
+
+    pub fn athrow(&mut self) -> Result<(), CodeBuilderError> {
+        self.end_op(AThrow::OPCODE, 1)
+    }
+
+    /// Get the tag of the field or method reference at the index, and the descriptor that it
+    /// refers to
+    fn member_ref(&self, index: u16) -> Option<(u8, &[u8])> {
+        let entry = self.pool.get(index)?;
+        let (PoolEntry::FieldRef {
+            name_and_type_index,
+            ..
+        }
+        | PoolEntry::MethodRef {
+            name_and_type_index,
+            ..
+        }
+        | PoolEntry::InterfaceMethodRef {
+            name_and_type_index,
+            ..
+        }) = entry
+        else {
+            return None;
+        };
+        let Some(PoolEntry::NameAndType {
+            descriptor_index, ..
+        }) = self.pool.get(*name_and_type_index)
+        else {
+            return None;
+        };
+        let Some(PoolEntry::Utf8(descriptor)) = self.pool.get(*descriptor_index) else {
+            return None;
+        };
+
+        Some((entry.tag(), descriptor))
+    }
+
+    /// Get the number of slots taken up by the type of the field reference at the index
+    fn field_slots(&self, index: u16) -> Result<u16, CodeBuilderError> {
+        match self.member_ref(index) {
+            Some((tag, descriptor)) if tag == FIELD_REF_TAG => {
+                match field_descriptor_prefix_slots(descriptor) {
+                    Some((slots, [])) => Ok(slots),
+                    _ => Err(CodeBuilderError::InvalidDescriptor),
+                }
+            }
+            _ => Err(CodeBuilderError::BadConstant(index)),
+        }
+    }
+
+    fn field_op(
+        &mut self,
+        opcode: RawOpcode,
+        fieldref: u16,
+        pop: u16,
+        push: u16,
+    ) -> Result<(), CodeBuilderError> {
+        self.start(opcode, pop, push)?;
+        self.code.extend(fieldref.to_be_bytes());
+        Ok(())
+    }
+
+    /// Push the value of the static field at the index of a `CONSTANT_Fieldref`
+    pub fn getstatic(&mut self, fieldref: u16) -> Result<(), CodeBuilderError> {
+        let slots = self.field_slots(fieldref)?;
+        self.field_op(GetStatic::OPCODE, fieldref, 0, slots)
+    }
+
+    pub fn putstatic(&mut self, fieldref: u16) -> Result<(), CodeBuilderError> {
+        let slots = self.field_slots(fieldref)?;
+        self.field_op(PutStaticField::OPCODE, fieldref, slots, 0)
+    }
+
+    pub fn getfield(&mut self, fieldref: u16) -> Result<(), CodeBuilderError> {
+        let slots = self.field_slots(fieldref)?;
+        self.field_op(GetField::OPCODE, fieldref, 1, slots)
+    }
+
+    pub fn putfield(&mut self, fieldref: u16) -> Result<(), CodeBuilderError> {
+        let slots = self.field_slots(fieldref)?;
+        self.field_op(PutField::OPCODE, fieldref, slots + 1, 0)
+    }
+
+    /// Add an invoke instruction for the method reference, which pops the arguments and the
+    /// receiver if there is one, returning the number of argument slots
+    fn invoke(
+        &mut self,
+        opcode: RawOpcode,
+        methodref: u16,
+        allow_interface: bool,
+        has_receiver: bool,
+    ) -> Result<u16, CodeBuilderError> {
+        let (parameters, ret) = match self.member_ref(methodref) {
+            Some((tag, descriptor))
+                if tag == METHOD_REF_TAG
+                    || (allow_interface && tag == INTERFACE_METHOD_REF_TAG) =>
+            {
+                method_descriptor_slots(descriptor).ok_or(CodeBuilderError::InvalidDescriptor)?
+            }
+            _ => return Err(CodeBuilderError::BadConstant(methodref)),
+        };
+
+        let pop = parameters + u16::from(has_receiver);
+        self.start(opcode, pop, ret)?;
+        self.code.extend(methodref.to_be_bytes());
+        Ok(pop)
+    }
+
+    /// Invoke the method at the index of a `CONSTANT_Methodref`
+    pub fn invokevirtual(&mut self, methodref: u16) -> Result<(), CodeBuilderError> {
+        self.invoke(InvokeVirtual::OPCODE, methodref, false, true)
+            .map(|_| ())
+    }
+
+    /// Invoke the method at the index of a `CONSTANT_Methodref` or
+    /// `CONSTANT_InterfaceMethodref`, without virtual dispatch
+    pub fn invokespecial(&mut self, methodref: u16) -> Result<(), CodeBuilderError> {
+        self.invoke(InvokeSpecial::OPCODE, methodref, true, true)
+            .map(|_| ())
+    }
+
+    pub fn invokestatic(&mut self, methodref: u16) -> Result<(), CodeBuilderError> {
+        self.invoke(InvokeStatic::OPCODE, methodref, true, false)
+            .map(|_| ())
+    }
+
+    /// Invoke the method at the index of a `CONSTANT_InterfaceMethodref`
+    pub fn invokeinterface(&mut self, methodref: u16) -> Result<(), CodeBuilderError> {
+        if !matches!(
+            self.member_ref(methodref),
+            Some((INTERFACE_METHOD_REF_TAG, _))
+        ) {
+            return Err(CodeBuilderError::BadConstant(methodref));
+        }
+
+        let count = self.invoke(InvokeInterface::OPCODE, methodref, true, true)?;
+        // The count includes the receiver, and has to fit in a byte
+        let count = u8::try_from(count).map_err(|_| CodeBuilderError::InvalidDescriptor)?;
+        self.code.extend([count, 0]);
+        Ok(())
+    }
+
+    fn class_op(
+        &mut self,
+        opcode: RawOpcode,
+        class: u16,
+        pop: u16,
+        push: u16,
+    ) -> Result<(), CodeBuilderError> {
+        if !matches!(self.pool.get(class), Some(PoolEntry::Class { .. })) {
+            return Err(CodeBuilderError::BadConstant(class));
+        }
+
+        self.start(opcode, pop, push)?;
+        self.code.extend(class.to_be_bytes());
+        Ok(())
+    }
+
+    /// Create an uninitialized instance of the class at the index of a `CONSTANT_Class`
+    /// This is `new`, but that is taken by [`CodeBuilder::new`].
+    pub fn new_object(&mut self, class: u16) -> Result<(), CodeBuilderError> {
+        self.class_op(New::OPCODE, class, 0, 1)
+    }
+
+    /// Create an array with the length on the stack, whose elements are of the class at the index
+    /// of a `CONSTANT_Class`
+    pub fn anewarray(&mut self, class: u16) -> Result<(), CodeBuilderError> {
+        self.class_op(ANewArray::OPCODE, class, 1, 1)
+    }
+
+    pub fn checkcast(&mut self, class: u16) -> Result<(), CodeBuilderError> {
+        self.class_op(CheckCast::OPCODE, class, 1, 1)
+    }
+
+    pub fn instanceof(&mut self, class: u16) -> Result<(), CodeBuilderError> {
+        self.class_op(InstanceOf::OPCODE, class, 1, 1)
+    }
+
+    #[must_use]
+    /// Create a label, which can be branched to before or after it is placed
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(LabelState::default());
+        Label(self.labels.len() - 1)
+    }
+
+    /// Record that the label is reached with the stack depth, checking that it is the same as
+    /// any other way of reaching it
+    fn reach_label(&mut self, label: Label, stack: u16) -> Result<(), CodeBuilderError> {
+        let state = &mut self.labels[label.0];
+        match state.stack {
+            Some(expected) if expected != stack => Err(CodeBuilderError::StackMismatch {
+                label,
+                expected,
+                found: stack,
+            }),
+            _ => {
+                state.stack = Some(stack);
+                Ok(())
+            }
+        }
+    }
+
+    /// Place the label at the current offset, so that branches to it go to the next instruction
+    pub fn place_label(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        if self.labels[label.0].offset.is_some() {
+            return Err(CodeBuilderError::LabelPlacedTwice(label));
+        }
+
+        if self.reachable {
+            self.reach_label(label, self.stack)?;
+        } else {
+            let stack = self.labels[label.0].stack.unwrap_or(0);
+            self.reach_label(label, stack)?;
+            self.stack = stack;
+            self.reachable = true;
+        }
+        self.labels[label.0].offset = Some(self.code.len());
+        Ok(())
+    }
+
+    fn branch(
+        &mut self,
+        opcode: RawOpcode,
+        pop: u16,
+        label: Label,
+    ) -> Result<(), CodeBuilderError> {
+        let pc = self.code.len();
+        self.start(opcode, pop, 0)?;
+        self.reach_label(label, self.stack)?;
+        self.branches.push(Branch { pc, label });
+        // Filled in when the code is built
+        self.code.extend([0, 0]);
+        Ok(())
+    }
+
+    pub fn goto(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(Goto::OPCODE, 0, label)?;
+        self.end_reachable();
+        Ok(())
+    }
+
+    /// Branch if the int is zero
+    pub fn ifeq(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfEqZero::OPCODE, 1, label)
+    }
+
+    pub fn ifne(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfNeZero::OPCODE, 1, label)
+    }
+
+    pub fn iflt(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfLtZero::OPCODE, 1, label)
+    }
+
+    pub fn ifge(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfGeZero::OPCODE, 1, label)
+    }
+
+    pub fn ifgt(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfGtZero::OPCODE, 1, label)
+    }
+
+    pub fn ifle(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfLeZero::OPCODE, 1, label)
+    }
+
+    pub fn if_icmpeq(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfIntCmpEq::OPCODE, 2, label)
+    }
+
+    pub fn if_icmpne(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfIntCmpNe::OPCODE, 2, label)
+    }
+
+    pub fn if_icmplt(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfIntCmpLt::OPCODE, 2, label)
+    }
+
+    pub fn if_icmpge(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfIntCmpGe::OPCODE, 2, label)
+    }
+
+    pub fn if_icmpgt(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfIntCmpGt::OPCODE, 2, label)
+    }
+
+    pub fn if_icmple(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfIntCmpLe::OPCODE, 2, label)
+    }
+
+    pub fn if_acmpeq(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfACmpEq::OPCODE, 2, label)
+    }
+
+    pub fn if_acmpne(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfACmpNe::OPCODE, 2, label)
+    }
+
+    pub fn ifnull(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfNull::OPCODE, 1, label)
+    }
+
+    pub fn ifnonnull(&mut self, label: Label) -> Result<(), CodeBuilderError> {
+        self.branch(IfNonNull::OPCODE, 1, label)
+    }
+
+    /// Add an exception handler for the code from `start` up to, but not including, `end`
+    /// The handler is reached with just the exception on the stack. `catch_type` is the index of
+    /// the `CONSTANT_Class` of the exceptions that it catches, or `None` to catch everything.
+    /// Handlers are checked in the order that they are added.
+    pub fn try_catch(
+        &mut self,
+        start: Label,
+        end: Label,
+        handler: Label,
+        catch_type: Option<u16>,
+    ) -> Result<(), CodeBuilderError> {
+        if let Some(catch_type) = catch_type {
+            if !matches!(self.pool.get(catch_type), Some(PoolEntry::Class { .. })) {
+                return Err(CodeBuilderError::BadConstant(catch_type));
+            }
+        }
+
+        self.reach_label(handler, 1)?;
+        self.max_stack = self.max_stack.max(1);
+        self.handlers.push(Handler {
+            start,
+            end,
+            handler,
+            catch_type,
+        });
+        Ok(())
+    }
+
+    fn label_offset(&self, label: Label) -> Result<usize, CodeBuilderError> {
+        self.labels[label.0]
+            .offset
+            .ok_or(CodeBuilderError::UnplacedLabel(label))
+    }
+
+    /// Patch the branches and produce the code
+    /// The [`CodeParts`] has no attributes, so there is no `StackMapTable`, which class files of
+    /// version 51 and above need if they have branches. Version 50 class files may go without, in
+    /// which case the verifier falls back to inferring the types.
+    pub fn build(mut self) -> Result<CodeParts, CodeBuilderError> {
+        let code_len = self.code.len();
+        if code_len > usize::from(u16::MAX) {
+            return Err(CodeBuilderError::CodeTooLong(code_len));
+        }
+        // Every offset is now known to fit in a u16
+        #[allow(clippy::cast_possible_truncation)]
+        let to_u16 = |offset: usize| offset as u16;
+
+        for branch in std::mem::take(&mut self.branches) {
+            let target = self.label_offset(branch.label)?;
+            let offset = i16::try_from(i32::from(to_u16(target)) - i32::from(to_u16(branch.pc)))
+                .map_err(|_| CodeBuilderError::BranchTooFar { pc: branch.pc })?;
+            self.code[branch.pc + 1..branch.pc + 3].copy_from_slice(&offset.to_be_bytes());
+        }
+
+        let mut exception_table = Vec::with_capacity(self.handlers.len());
+        for handler in &self.handlers {
+            exception_table.push(ExceptionTableEntry {
+                start_pc: to_u16(self.label_offset(handler.start)?),
+                end_pc: to_u16(self.label_offset(handler.end)?),
+                handler_pc: to_u16(self.label_offset(handler.handler)?),
+                catch_type: handler.catch_type.map(ConstantPoolIndexRaw::new),
+            });
+        }
+
+        Ok(CodeParts {
+            max_stack: self.max_stack,
+            max_locals: self.max_locals,
+            code: self.code,
+            exception_table,
+            attributes: Vec::new(),
+        })
+    }
+}
+
+const FIELD_REF_TAG: u8 = 9;
+const METHOD_REF_TAG: u8 = 10;
+const INTERFACE_METHOD_REF_TAG: u8 = 11;
+
+/// Parse the field descriptor at the start of `desc`, returning the number of slots that its
+/// type takes up and the rest of the text after it
+pub(crate) fn field_descriptor_prefix_slots(desc: &[u8]) -> Option<(u16, &[u8])> {
+    let (first, rest) = desc.split_first()?;
+    match first {
+        b'J' | b'D' => Some((2, rest)),
+        b'B' | b'C' | b'F' | b'I' | b'S' | b'Z' => Some((1, rest)),
+        b'L' => {
+            let end = rest.iter().position(|&c| c == b';')?;
+            Some((1, &rest[end + 1..]))
+        }
+        b'[' => {
+            let (_, rest) = field_descriptor_prefix_slots(rest)?;
+            Some((1, rest))
+        }
+        _ => None,
+    }
+}
+
+/// Get the number of slots taken up by the parameters of the method descriptor, and by its
+/// return value
+pub(crate) fn method_descriptor_slots(desc: &[u8]) -> Option<(u16, u16)> {
+    let mut rest = desc.strip_prefix(b"(")?;
+    let mut parameters: u16 = 0;
+    loop {
+        if let Some(after) = rest.strip_prefix(b")") {
+            rest = after;
+            break;
+        }
+
+        let (slots, after) = field_descriptor_prefix_slots(rest)?;
+        parameters = parameters.checked_add(slots)?;
+        rest = after;
+    }
+
+    let ret = if rest == b"V" {
+        0
+    } else {
+        match field_descriptor_prefix_slots(rest)? {
+            (slots, []) => slots,
+            _ => return None,
+        }
+    };
+
+    Some((parameters, ret))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        code::{
+            frame_size::compute_max_stack_locals,
+            method::MethodDescriptor,
+            op::{instructions, Inst, WideInst},
+            types::Instruction,
+        },
+        constant_pool_builder::{ConstantPoolBuilder, PoolEntry},
+        data::class_names::ClassNames,
+    };
+
+    use super::{CodeBuilder, CodeBuilderError};
+
+    #[test]
+    fn test_code_builder() {
+        let mut pool = ConstantPoolBuilder::new();
+        // static long f(int x, long y)
+        let mut b = CodeBuilder::new(&mut pool, "(IJ)J", true).unwrap();
+        let positive = b.new_label();
+        let done = b.new_label();
+        b.iload(0).unwrap();
+        // A forward branch
+        b.ifge(positive).unwrap();
+        b.lload(1).unwrap();
+        b.goto(done).unwrap();
+        b.place_label(positive).unwrap();
+        b.lconst(7).unwrap();
+        b.lload(1).unwrap();
+        b.op(0x61, 4, 2).unwrap();
+        b.place_label(done).unwrap();
+        b.lstore(5).unwrap();
+        let top = b.new_label();
+        b.place_label(top).unwrap();
+        b.iinc(0, -1).unwrap();
+        b.iload(0).unwrap();
+        // A backward branch
+        b.ifgt(top).unwrap();
+        b.lload(5).unwrap();
+        b.lreturn().unwrap();
+        let code = b.build().unwrap();
+
+        let long = pool.find(&PoolEntry::Long(7)).unwrap();
+        #[rustfmt::skip]
+        let expected = [
+            // 0: iload_0, ifge +7
+            0x1A, 0x9C, 0, 7,
+            // 4: lload_1, goto +8
+            0x1F, 0xA7, 0, 8,
+            // 8: ldc2_w 7, lload_1, ladd
+            0x14, 0, u8::try_from(long).unwrap(), 0x1F, 0x61,
+            // 13: lstore 5
+            0x37, 5,
+            // 15: iinc 0 -1, iload_0, ifgt -4
+            0x84, 0, 0xFF, 0x1A, 0x9D, 0xFF, 0xFC,
+            // 22: lload 5, lreturn
+            0x16, 5, 0xAD,
+        ];
+        assert_eq!(code.code, expected);
+        assert_eq!(code.max_stack, 4);
+        assert_eq!(code.max_locals, 7);
+    }
+
+    #[test]
+    fn test_code_builder_invoke() {
+        let mut pool = ConstantPoolBuilder::new();
+        let length = pool
+            .add_methodref("java/lang/String", "length", "()I")
+            .unwrap();
+        let exception = pool.add_class("java/lang/Exception").unwrap();
+        let mut b = CodeBuilder::new(&mut pool, "()I", false).unwrap();
+        let start = b.new_label();
+        let end = b.new_label();
+        let handler = b.new_label();
+        b.try_catch(start, end, handler, Some(exception)).unwrap();
+        b.place_label(start).unwrap();
+        b.ldc_string("hello").unwrap();
+        b.invokevirtual(length).unwrap();
+        b.iconst(1000).unwrap();
+        b.iadd().unwrap();
+        b.place_label(end).unwrap();
+        b.ireturn().unwrap();
+        b.place_label(handler).unwrap();
+        // The handler starts with the exception on the stack
+        assert_eq!(b.stack_depth(), 1);
+        b.athrow().unwrap();
+        // A method reference can't be used as a class
+        assert!(matches!(
+            b.new_object(length),
+            Err(CodeBuilderError::BadConstant(_))
+        ));
+        let code = b.build().unwrap();
+
+        assert_eq!(code.max_stack, 2);
+        assert_eq!(code.max_locals, 1);
+        assert_eq!(code.code[2..5], [0xB6, 0, u8::try_from(length).unwrap()]);
+        // sipush 1000
+        assert_eq!(code.code[5..8], [0x11, 0x03, 0xE8]);
+        let entry = &code.exception_table[0];
+        assert_eq!((entry.start_pc, entry.end_pc, entry.handler_pc), (0, 9, 10));
+        assert_eq!(entry.catch_type.map(|x| x.0), Some(exception));
+    }
+
+    #[test]
+    fn test_code_builder_errors() {
+        let mut pool = ConstantPoolBuilder::new();
+        let mut b = CodeBuilder::new(&mut pool, "()V", true).unwrap();
+        assert!(matches!(
+            b.pop(),
+            Err(CodeBuilderError::StackUnderflow { pc: 0 })
+        ));
+        let label = b.new_label();
+        b.iconst(0).unwrap();
+        b.ifeq(label).unwrap();
+        b.iconst(0).unwrap();
+        // Falling through to the label with something on the stack doesn't match the branch
+        assert!(matches!(
+            b.place_label(label),
+            Err(CodeBuilderError::StackMismatch {
+                expected: 0,
+                found: 1,
+                ..
+            })
+        ));
+
+        let mut pool = ConstantPoolBuilder::new();
+        let mut b = CodeBuilder::new(&mut pool, "()V", true).unwrap();
+        let label = b.new_label();
+        b.goto(label).unwrap();
+        assert!(matches!(b.build(), Err(CodeBuilderError::UnplacedLabel(_))));
+    }
+
+    #[test]
+    fn test_code_builder_wide_locals() {
+        let mut class_names = ClassNames::new();
+        let mut pool = ConstantPoolBuilder::new();
+        let mut b = CodeBuilder::new(&mut pool, "()V", true).unwrap();
+        b.iconst(1).unwrap();
+        b.istore(256).unwrap();
+        b.iinc(256, -1000).unwrap();
+        b.iload(256).unwrap();
+        b.pop().unwrap();
+        b.lconst(1).unwrap();
+        b.lstore(257).unwrap();
+        b.lload(257).unwrap();
+        b.pop2().unwrap();
+        b.fconst(1.0).unwrap();
+        b.fstore(300).unwrap();
+        b.fload(300).unwrap();
+        b.pop().unwrap();
+        b.dconst(1.0).unwrap();
+        b.dstore(400).unwrap();
+        b.dload(400).unwrap();
+        b.pop2().unwrap();
+        b.aconst_null().unwrap();
+        b.astore(1000).unwrap();
+        b.aload(1000).unwrap();
+        b.pop().unwrap();
+        b.return_().unwrap();
+        let code = b.build().unwrap();
+        assert_eq!((code.max_stack, code.max_locals), (2, 1001));
+
+        // Every load and store is parsed back as its wide form
+        let insts = instructions(&code.code)
+            .map(|x| x.map(|(_, inst)| inst))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let wide = insts
+            .iter()
+            .filter_map(|inst| match inst {
+                Inst::Wide(wide) => Some(&wide.0),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let names = wide.iter().map(|inst| inst.name()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "WideIntStore",
+                "WideIntIncrement",
+                "WideIntLoad",
+                "WideLongStore",
+                "WideLongLoad",
+                "WideFloatStore",
+                "WideFloatLoad",
+                "WideDoubleStore",
+                "WideDoubleLoad",
+                "WideAStore",
+                "WideALoad",
+            ]
+        );
+        assert!(matches!(
+            wide[1],
+            WideInst::WideIntIncrement(x) if x.index == 256 && x.increment_amount == -1000
+        ));
+        assert!(matches!(wide[9], WideInst::WideAStore(x) if x.index == 1000));
+
+        // Which also gives the same frame size when computed from the instructions
+        let descriptor = MethodDescriptor::from_text(b"()V", &mut class_names).unwrap();
+        let computed =
+            compute_max_stack_locals(&pool, &code.code, &code.exception_table, &descriptor, true)
+                .unwrap();
+        assert_eq!(computed, (2, 1001));
+    }
+}
@@ -0,0 +1,641 @@
+//! Computing the `max_stack` and `max_locals` of code from its instructions, rather than trusting
+//! the values that the `Code` attribute claims.
+
+use classfile_parser::attribute_info::InstructionIndex;
+
+use crate::{
+    class_reader::ExceptionTableEntry,
+    class_writer::CodeParts,
+    constant_pool_builder::{ConstantPoolBuilder, PoolEntry},
+};
+
+use super::{
+    builder::{field_descriptor_prefix_slots, method_descriptor_slots},
+    method::MethodDescriptor,
+    op::{self, Inst, WideInst},
+    op_ex::InstructionParseError,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameSizeError {
+    #[error("failed to parse the instructions")]
+    Parse(#[source] InstructionParseError),
+    /// The constant pool entry was not the kind that the instruction needs
+    #[error("constant pool entry {0} can not be used by the instruction")]
+    BadConstant(u16),
+    /// The descriptor of a method or field was malformed
+    #[error("invalid descriptor")]
+    InvalidDescriptor,
+    #[error("instruction at {pc} pops more than is on the stack")]
+    StackUnderflow { pc: u16 },
+    #[error("instruction at {pc} pushes more than the stack can hold")]
+    StackOverflow { pc: u16 },
+    /// The instruction is reached with different stack depths along different paths
+    #[error(
+        "instruction at {pc} is reached with a stack depth of {found}, but expected {expected}"
+    )]
+    StackMismatch { pc: u16, expected: u16, found: u16 },
+    /// The instruction branches outside of the code, or into the middle of an instruction
+    #[error("instruction at {pc} has an invalid branch target")]
+    InvalidBranchOffset { pc: u16 },
+    /// The exception handler does not start at an instruction
+    #[error("exception handler at {handler} is not at an instruction")]
+    InvalidHandler { handler: u16 },
+    /// The last instruction can continue on to the next, but there is no next instruction
+    #[error("execution can continue past the end of the code")]
+    FallsOffEndOfCode,
+    /// The local variable, including its second slot for `long`s and `double`s, is beyond the
+    /// highest index that a method can have
+    #[error("local variable {0} is out of range")]
+    LocalOutOfRange(u16),
+}
+impl From<InstructionParseError> for FrameSizeError {
+    fn from(err: InstructionParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+/// Compute the `max_stack` and `max_locals` that the code needs, returned in that order.
+/// `pool` is the constant pool that the code refers to, which is used to find the types of
+/// fields and the descriptors of invoked methods.
+///
+/// The stack depth is followed along each path through the code, with exception handlers
+/// starting with just the exception on the stack, and must be the same wherever paths join.
+/// Code which can't be reached does not count towards `max_stack`.
+/// Every local variable which is loaded, stored, or incremented counts towards `max_locals`,
+/// along with the parameters and `this` for instance methods.
+pub fn compute_max_stack_locals(
+    pool: &ConstantPoolBuilder,
+    code: &[u8],
+    exception_table: &[ExceptionTableEntry],
+    descriptor: &MethodDescriptor,
+    is_static: bool,
+) -> Result<(u16, u16), FrameSizeError> {
+    let instructions = op::instructions(code).collect::<Result<Vec<_>, _>>()?;
+
+    let mut max_locals = descriptor
+        .parameter_slot_count()
+        .checked_add(u16::from(!is_static))
+        .ok_or(FrameSizeError::InvalidDescriptor)?;
+    for (_, inst) in &instructions {
+        if let Some((index, size)) = local_slots(inst) {
+            let end = index
+                .checked_add(size)
+                .ok_or(FrameSizeError::LocalOutOfRange(index))?;
+            max_locals = max_locals.max(end);
+        }
+    }
+
+    let max_stack = compute_max_stack(pool, &instructions, exception_table)?;
+
+    Ok((max_stack, max_locals))
+}
+
+impl CodeParts {
+    /// Replace the `max_stack` and `max_locals` with the values that the code needs, such as
+    /// after editing the code that a [`super::builder::CodeBuilder`] produced.
+    /// See [`compute_max_stack_locals`].
+    pub fn recompute_max_stack_locals(
+        &mut self,
+        pool: &ConstantPoolBuilder,
+        descriptor: &MethodDescriptor,
+        is_static: bool,
+    ) -> Result<(), FrameSizeError> {
+        let (max_stack, max_locals) = compute_max_stack_locals(
+            pool,
+            &self.code,
+            &self.exception_table,
+            descriptor,
+            is_static,
+        )?;
+        self.max_stack = max_stack;
+        self.max_locals = max_locals;
+        Ok(())
+    }
+}
+
+fn compute_max_stack(
+    pool: &ConstantPoolBuilder,
+    instructions: &[(InstructionIndex, Inst)],
+    exception_table: &[ExceptionTableEntry],
+) -> Result<u16, FrameSizeError> {
+    let position_of = |at: InstructionIndex| {
+        instructions
+            .binary_search_by_key(&at.0, |(idx, _)| idx.0)
+            .ok()
+    };
+
+    if instructions.is_empty() {
+        return Ok(0);
+    }
+
+    let mut depths = StackDepths {
+        depths: vec![None; instructions.len()],
+        pending: Vec::new(),
+    };
+    depths.reach(instructions, 0, 0)?;
+
+    let mut max_stack = 0;
+    while let Some((position, depth)) = depths.pending.pop() {
+        let (idx, inst) = &instructions[position];
+        let pc = idx.0;
+
+        for handler in exception_table {
+            if pc < handler.start_pc || pc >= handler.end_pc {
+                continue;
+            }
+
+            let handler_position = position_of(InstructionIndex(handler.handler_pc)).ok_or(
+                FrameSizeError::InvalidHandler {
+                    handler: handler.handler_pc,
+                },
+            )?;
+            // The handler is reached with only the exception on the stack
+            depths.reach(instructions, handler_position, 1)?;
+            max_stack = max_stack.max(1);
+        }
+
+        let (pop, push) = stack_slots(pool, inst)?;
+        let depth = depth
+            .checked_sub(pop)
+            .ok_or(FrameSizeError::StackUnderflow { pc })?
+            .checked_add(push)
+            .ok_or(FrameSizeError::StackOverflow { pc })?;
+        max_stack = max_stack.max(depth);
+
+        let targets = inst
+            .branch_targets(*idx)
+            .ok_or(FrameSizeError::InvalidBranchOffset { pc })?;
+        for target in targets {
+            let target_position =
+                position_of(target).ok_or(FrameSizeError::InvalidBranchOffset { pc })?;
+            depths.reach(instructions, target_position, depth)?;
+        }
+
+        if inst.can_fall_through() {
+            if position + 1 >= instructions.len() {
+                return Err(FrameSizeError::FallsOffEndOfCode);
+            }
+            depths.reach(instructions, position + 1, depth)?;
+        }
+    }
+
+    Ok(max_stack)
+}
+
+struct StackDepths {
+    /// The stack depth that each instruction is reached with, `None` if it hasn't been reached
+    depths: Vec<Option<u16>>,
+    /// The instructions which have been reached but not yet followed
+    pending: Vec<(usize, u16)>,
+}
+impl StackDepths {
+    /// Mark the instruction at the position as reached with the stack depth, which must be the
+    /// same as any other path that reaches it
+    fn reach(
+        &mut self,
+        instructions: &[(InstructionIndex, Inst)],
+        position: usize,
+        depth: u16,
+    ) -> Result<(), FrameSizeError> {
+        match self.depths[position] {
+            None => {
+                self.depths[position] = Some(depth);
+                self.pending.push((position, depth));
+                Ok(())
+            }
+            Some(expected) if expected == depth => Ok(()),
+            Some(expected) => Err(FrameSizeError::StackMismatch {
+                pc: instructions[position].0 .0,
+                expected,
+                found: depth,
+            }),
+        }
+    }
+}
+
+/// The local variable that the instruction uses, and the number of slots it takes up
+fn local_slots(inst: &Inst) -> Option<(u16, u16)> {
+    Some(match inst {
+        Inst::ALoad(x) => (u16::from(x.index), 1),
+        Inst::AStore(x) => (u16::from(x.index), 1),
+        Inst::IntLoad(x) => (u16::from(x.index), 1),
+        Inst::IntStore(x) => (u16::from(x.index), 1),
+        Inst::IntIncrement(x) => (u16::from(x.index), 1),
+        Inst::FloatLoad(x) => (u16::from(x.index), 1),
+        Inst::FloatStore(x) => (u16::from(x.index), 1),
+        Inst::LongLoad(x) => (u16::from(x.index), 2),
+        Inst::LongStore(x) => (u16::from(x.index), 2),
+        Inst::DoubleLoad(x) => (u16::from(x.index), 2),
+        Inst::DoubleStore(x) => (u16::from(x.index), 2),
+        Inst::Wide(wide) => match &wide.0 {
+            WideInst::WideALoad(x) => (x.index, 1),
+            WideInst::WideAStore(x) => (x.index, 1),
+            WideInst::WideIntLoad(x) => (x.index, 1),
+            WideInst::WideIntStore(x) => (x.index, 1),
+            WideInst::WideIntIncrement(x) => (x.index, 1),
+            WideInst::WideFloatLoad(x) => (x.index, 1),
+            WideInst::WideFloatStore(x) => (x.index, 1),
+            WideInst::WideLongLoad(x) => (x.index, 2),
+            WideInst::WideLongStore(x) => (x.index, 2),
+            WideInst::WideDoubleLoad(x) => (x.index, 2),
+            WideInst::WideDoubleStore(x) => (x.index, 2),
+        },
+        Inst::ALoad0(_)
+        | Inst::AStore0(_)
+        | Inst::IntLoad0(_)
+        | Inst::IntStore0(_)
+        | Inst::FloatLoad0(_)
+        | Inst::FloatStore0(_) => (0, 1),
+        Inst::ALoad1(_)
+        | Inst::AStore1(_)
+        | Inst::IntLoad1(_)
+        | Inst::IntStore1(_)
+        | Inst::FloatLoad1(_)
+        | Inst::FloatStore1(_) => (1, 1),
+        Inst::ALoad2(_)
+        | Inst::AStore2(_)
+        | Inst::IntLoad2(_)
+        | Inst::IntStore2(_)
+        | Inst::FloatLoad2(_)
+        | Inst::FloatStore2(_) => (2, 1),
+        Inst::ALoad3(_)
+        | Inst::AStore3(_)
+        | Inst::IntLoad3(_)
+        | Inst::IntStore3(_)
+        | Inst::FloatLoad3(_)
+        | Inst::FloatStore3(_) => (3, 1),
+        Inst::LongLoad0(_) | Inst::LongStore0(_) | Inst::DoubleLoad0(_) | Inst::DoubleStore0(_) => {
+            (0, 2)
+        }
+        Inst::LongLoad1(_) | Inst::LongStore1(_) | Inst::DoubleLoad1(_) | Inst::DoubleStore1(_) => {
+            (1, 2)
+        }
+        Inst::LongLoad2(_) | Inst::LongStore2(_) | Inst::DoubleLoad2(_) | Inst::DoubleStore2(_) => {
+            (2, 2)
+        }
+        Inst::LongLoad3(_) | Inst::LongStore3(_) | Inst::DoubleLoad3(_) | Inst::DoubleStore3(_) => {
+            (3, 2)
+        }
+        _ => return None,
+    })
+}
+
+/// The number of stack slots that the instruction pops and then pushes
+fn stack_slots(pool: &ConstantPoolBuilder, inst: &Inst) -> Result<(u16, u16), FrameSizeError> {
+    Ok(match inst {
+        Inst::Return(_) | Inst::Goto(_) | Inst::IntIncrement(_) => (0, 0),
+        Inst::AConstNull(_)
+        | Inst::ALoad(_)
+        | Inst::ALoad0(_)
+        | Inst::ALoad1(_)
+        | Inst::ALoad2(_)
+        | Inst::ALoad3(_)
+        | Inst::IConstNeg1(_)
+        | Inst::IntConst0(_)
+        | Inst::IntConst1(_)
+        | Inst::IntConst2(_)
+        | Inst::IntConst3(_)
+        | Inst::IntConst4(_)
+        | Inst::IntConst5(_)
+        | Inst::IntLoad(_)
+        | Inst::IntLoad0(_)
+        | Inst::IntLoad1(_)
+        | Inst::IntLoad2(_)
+        | Inst::IntLoad3(_)
+        | Inst::FloatLoad(_)
+        | Inst::FloatLoad0(_)
+        | Inst::FloatLoad1(_)
+        | Inst::FloatLoad2(_)
+        | Inst::FloatLoad3(_)
+        | Inst::FloatConst0(_)
+        | Inst::FloatConst1(_)
+        | Inst::FloatConst2(_)
+        | Inst::PushByte(_)
+        | Inst::PushShort(_)
+        | Inst::LoadConstant(_)
+        | Inst::LoadConstantWide(_)
+        | Inst::New(_) => (0, 1),
+        Inst::LongLoad(_)
+        | Inst::LongLoad0(_)
+        | Inst::LongLoad1(_)
+        | Inst::LongLoad2(_)
+        | Inst::LongLoad3(_)
+        | Inst::LongConst0(_)
+        | Inst::LongConst1(_)
+        | Inst::DoubleLoad(_)
+        | Inst::DoubleLoad0(_)
+        | Inst::DoubleLoad1(_)
+        | Inst::DoubleLoad2(_)
+        | Inst::DoubleLoad3(_)
+        | Inst::DoubleConst0(_)
+        | Inst::DoubleConst1(_)
+        | Inst::LoadConstant2Wide(_) => (0, 2),
+        Inst::AStore(_)
+        | Inst::AStore0(_)
+        | Inst::AStore1(_)
+        | Inst::AStore2(_)
+        | Inst::AStore3(_)
+        | Inst::IntStore(_)
+        | Inst::IntStore0(_)
+        | Inst::IntStore1(_)
+        | Inst::IntStore2(_)
+        | Inst::IntStore3(_)
+        | Inst::FloatStore(_)
+        | Inst::FloatStore0(_)
+        | Inst::FloatStore1(_)
+        | Inst::FloatStore2(_)
+        | Inst::FloatStore3(_)
+        | Inst::Pop(_)
+        | Inst::IfEqZero(_)
+        | Inst::IfNeZero(_)
+        | Inst::IfLtZero(_)
+        | Inst::IfGeZero(_)
+        | Inst::IfGtZero(_)
+        | Inst::IfLeZero(_)
+        | Inst::IfNull(_)
+        | Inst::IfNonNull(_)
+        | Inst::IntReturn(_)
+        | Inst::FloatReturn(_)
+        | Inst::AReturn(_)
+        | Inst::AThrow(_)
+        | Inst::MonitorEnter(_)
+        | Inst::MonitorExit(_)
+        | Inst::LookupSwitch(_)
+        | Inst::TableSwitch(_) => (1, 0),
+        Inst::LongStore(_)
+        | Inst::LongStore0(_)
+        | Inst::LongStore1(_)
+        | Inst::LongStore2(_)
+        | Inst::LongStore3(_)
+        | Inst::DoubleStore(_)
+        | Inst::DoubleStore0(_)
+        | Inst::DoubleStore1(_)
+        | Inst::DoubleStore2(_)
+        | Inst::DoubleStore3(_)
+        | Inst::Pop2(_)
+        | Inst::IfIntCmpEq(_)
+        | Inst::IfIntCmpNe(_)
+        | Inst::IfIntCmpLt(_)
+        | Inst::IfIntCmpGe(_)
+        | Inst::IfIntCmpGt(_)
+        | Inst::IfIntCmpLe(_)
+        | Inst::IfACmpEq(_)
+        | Inst::IfACmpNe(_)
+        | Inst::LongReturn(_)
+        | Inst::DoubleReturn(_) => (2, 0),
+        Inst::ANewArray(_)
+        | Inst::NewArray(_)
+        | Inst::ArrayLength(_)
+        | Inst::CheckCast(_)
+        | Inst::InstanceOf(_)
+        | Inst::IntNegate(_)
+        | Inst::FloatNegate(_)
+        | Inst::IntToFloat(_)
+        | Inst::FloatToInt(_)
+        | Inst::IntToByte(_)
+        | Inst::IntToChar(_)
+        | Inst::IntToShort(_) => (1, 1),
+        Inst::IntToLong(_)
+        | Inst::IntToDouble(_)
+        | Inst::FloatToLong(_)
+        | Inst::FloatToDouble(_) => (1, 2),
+        Inst::AALoad(_)
+        | Inst::IntALoad(_)
+        | Inst::FloatArrayLoad(_)
+        | Inst::ShortArrayLoad(_)
+        | Inst::ByteArrayLoad(_)
+        | Inst::CharArrayLoad(_)
+        | Inst::IntAdd(_)
+        | Inst::IntSubtract(_)
+        | Inst::IntMultiply(_)
+        | Inst::IntDivide(_)
+        | Inst::IntRemainder(_)
+        | Inst::IntAnd(_)
+        | Inst::IntOr(_)
+        | Inst::IntXor(_)
+        | Inst::IntShiftLeft(_)
+        | Inst::IntArithmeticShiftRight(_)
+        | Inst::IntLogicalShiftRight(_)
+        | Inst::FloatAdd(_)
+        | Inst::FloatSub(_)
+        | Inst::FloatMultiply(_)
+        | Inst::FloatDivide(_)
+        | Inst::FloatRemainder(_)
+        | Inst::FloatCmpL(_)
+        | Inst::FloatCmpG(_)
+        | Inst::LongToInt(_)
+        | Inst::LongToFloat(_)
+        | Inst::DoubleToInt(_)
+        | Inst::DoubleToFloat(_) => (2, 1),
+        Inst::LongNegate(_)
+        | Inst::DoubleNegate(_)
+        | Inst::LongToDouble(_)
+        | Inst::DoubleToLong(_)
+        | Inst::LongArrayLoad(_)
+        | Inst::DoubleArrayLoad(_) => (2, 2),
+        Inst::AAStore(_)
+        | Inst::IntArrayStore(_)
+        | Inst::FloatArrayStore(_)
+        | Inst::ShortArrayStore(_)
+        | Inst::ByteArrayStore(_)
+        | Inst::CharArrayStore(_) => (3, 0),
+        // A long and the int amount to shift it by
+        Inst::LongShiftLeft(_)
+        | Inst::LongArithmeticShiftRight(_)
+        | Inst::LongLogicalShiftRight(_) => (3, 2),
+        Inst::LongArrayStore(_) | Inst::DoubleArrayStore(_) => (4, 0),
+        Inst::LongCmp(_) | Inst::DoubleCmpL(_) | Inst::DoubleCmpG(_) => (4, 1),
+        Inst::LongAdd(_)
+        | Inst::LongSubtract(_)
+        | Inst::LongMultiply(_)
+        | Inst::LongDivide(_)
+        | Inst::LongRemainder(_)
+        | Inst::LongAnd(_)
+        | Inst::LongOr(_)
+        | Inst::LongXor(_)
+        | Inst::DoubleAdd(_)
+        | Inst::DoubleSubtract(_)
+        | Inst::DoubleMultiply(_)
+        | Inst::DoubleDivide(_)
+        | Inst::DoubleRemainder(_) => (4, 2),
+        // The dup instructions pop what they duplicate and push it back along with the copy
+        Inst::Dup(_) => (1, 2),
+        Inst::DupX1(_) => (2, 3),
+        Inst::DupX2(_) => (3, 4),
+        Inst::Dup2(_) => (2, 4),
+        Inst::Dup2X1(_) => (3, 5),
+        Inst::Dup2X2(_) => (4, 6),
+        Inst::MultiANewArray(x) => (u16::from(x.dimensions), 1),
+        Inst::GetStatic(x) => (0, field_slots(pool, x.index.0)?),
+        Inst::PutStaticField(x) => (field_slots(pool, x.index.0)?, 0),
+        Inst::GetField(x) => (1, field_slots(pool, x.index.0)?),
+        Inst::PutField(x) => (field_slots(pool, x.index.0)? + 1, 0),
+        Inst::InvokeVirtual(x) => invoke_slots(pool, x.index.0, true)?,
+        Inst::InvokeSpecial(x) => invoke_slots(pool, x.index.0, true)?,
+        Inst::InvokeInterface(x) => invoke_slots(pool, x.index.0, true)?,
+        Inst::InvokeStatic(x) => invoke_slots(pool, x.index.0, false)?,
+        Inst::InvokeDynamic(x) => invoke_slots(pool, x.index.0, false)?,
+        Inst::Wide(wide) => match &wide.0 {
+            WideInst::WideALoad(_) | WideInst::WideIntLoad(_) | WideInst::WideFloatLoad(_) => {
+                (0, 1)
+            }
+            WideInst::WideLongLoad(_) | WideInst::WideDoubleLoad(_) => (0, 2),
+            WideInst::WideAStore(_) | WideInst::WideIntStore(_) | WideInst::WideFloatStore(_) => {
+                (1, 0)
+            }
+            WideInst::WideLongStore(_) | WideInst::WideDoubleStore(_) => (2, 0),
+            WideInst::WideIntIncrement(_) => (0, 0),
+        },
+    })
+}
+
+/// Get the descriptor of the field, method, or dynamic call site at the index
+fn member_descriptor(pool: &ConstantPoolBuilder, index: u16) -> Option<&[u8]> {
+    let (PoolEntry::FieldRef {
+        name_and_type_index,
+        ..
+    }
+    | PoolEntry::MethodRef {
+        name_and_type_index,
+        ..
+    }
+    | PoolEntry::InterfaceMethodRef {
+        name_and_type_index,
+        ..
+    }
+    | PoolEntry::InvokeDynamic {
+        name_and_type_index,
+        ..
+    }) = pool.get(index)?
+    else {
+        return None;
+    };
+    let PoolEntry::NameAndType {
+        descriptor_index, ..
+    } = pool.get(*name_and_type_index)?
+    else {
+        return None;
+    };
+    let PoolEntry::Utf8(descriptor) = pool.get(*descriptor_index)? else {
+        return None;
+    };
+
+    Some(descriptor)
+}
+
+/// Get the number of slots taken up by the type of the field reference at the index
+fn field_slots(pool: &ConstantPoolBuilder, index: u16) -> Result<u16, FrameSizeError> {
+    let descriptor = member_descriptor(pool, index).ok_or(FrameSizeError::BadConstant(index))?;
+    match field_descriptor_prefix_slots(descriptor) {
+        Some((slots, [])) => Ok(slots),
+        _ => Err(FrameSizeError::InvalidDescriptor),
+    }
+}
+
+/// Get the number of slots that invoking the method at the index pops and pushes
+fn invoke_slots(
+    pool: &ConstantPoolBuilder,
+    index: u16,
+    has_receiver: bool,
+) -> Result<(u16, u16), FrameSizeError> {
+    let descriptor = member_descriptor(pool, index).ok_or(FrameSizeError::BadConstant(index))?;
+    let (parameters, ret) =
+        method_descriptor_slots(descriptor).ok_or(FrameSizeError::InvalidDescriptor)?;
+    let pop = parameters
+        .checked_add(u16::from(has_receiver))
+        .ok_or(FrameSizeError::InvalidDescriptor)?;
+    Ok((pop, ret))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        code::{builder::CodeBuilder, method::MethodDescriptor},
+        constant_pool_builder::ConstantPoolBuilder,
+        data::class_names::ClassNames,
+    };
+
+    use super::{compute_max_stack_locals, FrameSizeError};
+
+    #[test]
+    fn test_compute_max_stack_locals() {
+        let mut class_names = ClassNames::new();
+        let mut pool = ConstantPoolBuilder::new();
+        let method = pool.add_methodref("a/B", "m", "(JI)J").unwrap();
+        let exception = pool.add_class("java/lang/Exception").unwrap();
+
+        // static void f(Object o)
+        let mut b = CodeBuilder::new(&mut pool, "(Ljava/lang/Object;)V", true).unwrap();
+        let start = b.new_label();
+        let end = b.new_label();
+        let handler = b.new_label();
+        b.try_catch(start, end, handler, Some(exception)).unwrap();
+        b.place_label(start).unwrap();
+        b.lconst(1).unwrap();
+        b.iconst(2).unwrap();
+        b.invokestatic(method).unwrap();
+        b.lstore(1).unwrap();
+        b.place_label(end).unwrap();
+        b.return_().unwrap();
+        b.place_label(handler).unwrap();
+        b.astore(3).unwrap();
+        b.return_().unwrap();
+        let mut code = b.build().unwrap();
+        assert_eq!((code.max_stack, code.max_locals), (3, 4));
+
+        let descriptor =
+            MethodDescriptor::from_text(b"(Ljava/lang/Object;)V", &mut class_names).unwrap();
+        let computed =
+            compute_max_stack_locals(&pool, &code.code, &code.exception_table, &descriptor, true)
+                .unwrap();
+        assert_eq!(computed, (3, 4));
+
+        // The parameters count even if they are never used, and instance methods have `this`
+        let wide_descriptor =
+            MethodDescriptor::from_text(b"(Ljava/lang/Object;JJ)V", &mut class_names).unwrap();
+        for (is_static, max_locals) in [(true, 5), (false, 6)] {
+            let computed = compute_max_stack_locals(
+                &pool,
+                &code.code,
+                &code.exception_table,
+                &wide_descriptor,
+                is_static,
+            )
+            .unwrap();
+            assert_eq!(computed, (3, max_locals));
+        }
+
+        code.max_stack = 0;
+        code.max_locals = 0;
+        code.recompute_max_stack_locals(&pool, &descriptor, true)
+            .unwrap();
+        assert_eq!((code.max_stack, code.max_locals), (3, 4));
+    }
+
+    #[test]
+    fn test_stack_mismatch() {
+        let mut class_names = ClassNames::new();
+        let pool = ConstantPoolBuilder::new();
+        let descriptor = MethodDescriptor::from_text(b"()V", &mut class_names).unwrap();
+
+        // iconst_0; ifeq +4; iconst_1; return
+        // The return is reached with an empty stack by the branch, but with an int by falling
+        // through
+        let code = [0x03, 0x99, 0x00, 0x04, 0x04, 0xB1];
+        let err = compute_max_stack_locals(&pool, &code, &[], &descriptor, true).unwrap_err();
+        assert!(matches!(
+            err,
+            FrameSizeError::StackMismatch {
+                pc: 5,
+                expected: 0,
+                found: 1,
+            }
+        ));
+
+        // iconst_0; pop; pop; return
+        let code = [0x03, 0x57, 0x57, 0xB1];
+        let err = compute_max_stack_locals(&pool, &code, &[], &descriptor, true).unwrap_err();
+        assert!(matches!(err, FrameSizeError::StackUnderflow { pc: 2 }));
+    }
+}
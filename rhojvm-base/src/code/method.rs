@@ -20,7 +20,7 @@ use smallvec::SmallVec;
 
 use crate::{
     class::{ArrayComponentType, ClassFileData},
-    code::{self},
+    code::{self, types},
     data::{class_files::ClassFiles, class_names::ClassNames},
     id::{ClassId, ExactMethodId, MethodId},
     util::format_class_as_object_desc,
@@ -134,6 +134,41 @@ impl Method {
         self.access_flags
     }
 
+    #[must_use]
+    pub fn is_static(&self) -> bool {
+        self.access_flags.contains(MethodAccessFlags::STATIC)
+    }
+
+    #[must_use]
+    pub fn is_native(&self) -> bool {
+        self.access_flags.contains(MethodAccessFlags::NATIVE)
+    }
+
+    #[must_use]
+    pub fn is_abstract(&self) -> bool {
+        self.access_flags.contains(MethodAccessFlags::ABSTRACT)
+    }
+
+    #[must_use]
+    pub fn is_final(&self) -> bool {
+        self.access_flags.contains(MethodAccessFlags::FINAL)
+    }
+
+    #[must_use]
+    pub fn is_synchronized(&self) -> bool {
+        self.access_flags.contains(MethodAccessFlags::SYNCHRONIZED)
+    }
+
+    #[must_use]
+    pub fn is_bridge(&self) -> bool {
+        self.access_flags.contains(MethodAccessFlags::BRIDGE)
+    }
+
+    #[must_use]
+    pub fn is_varargs(&self) -> bool {
+        self.access_flags.contains(MethodAccessFlags::VARARGS)
+    }
+
     #[must_use]
     /// Some if it has been initialized
     pub fn overrides(&self) -> Option<&[MethodOverride]> {
@@ -240,6 +275,34 @@ impl Method {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DescriptorParseError {
+    /// There was no descriptor at all
+    #[error("empty descriptor")]
+    Empty,
+    /// Expected the start of a type but got this character
+    #[error("unknown descriptor type {:?}", char::from(*.0))]
+    UnknownType(u8),
+    /// A class name (`L...;`) was not terminated with a `;`
+    #[error("class name was not terminated with ';'")]
+    UnterminatedClassName,
+    /// A class name (`L;`) was empty
+    #[error("empty class name")]
+    EmptyClassName,
+    /// An array (`[`) did not have a component type
+    #[error("array was missing its component type")]
+    MissingArrayComponent,
+    /// There was data remaining after a complete descriptor
+    #[error("trailing data after descriptor")]
+    TrailingData,
+    /// A method descriptor did not start with `(`
+    #[error("method descriptor did not start with '('")]
+    MissingOpeningParen,
+    /// A method descriptor's parameters were not closed with `)`
+    #[error("method descriptor parameters were not closed with ')'")]
+    MissingClosingParen,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DescriptorTypeBasic {
     Byte,
@@ -307,6 +370,40 @@ impl DescriptorTypeBasic {
         }))
     }
 
+    /// Parse a single non-array type from the start of `desc`, returning the remaining data.
+    pub(crate) fn parse_prefix<'a>(
+        desc: &'a [u8],
+        class_names: &mut ClassNames,
+    ) -> Result<(DescriptorTypeBasic, &'a [u8]), DescriptorParseError> {
+        let (first, rest) = desc.split_first().ok_or(DescriptorParseError::Empty)?;
+        let typ = match first {
+            b'B' => DescriptorTypeBasic::Byte,
+            b'C' => DescriptorTypeBasic::Char,
+            b'D' => DescriptorTypeBasic::Double,
+            b'F' => DescriptorTypeBasic::Float,
+            b'I' => DescriptorTypeBasic::Int,
+            b'J' => DescriptorTypeBasic::Long,
+            b'S' => DescriptorTypeBasic::Short,
+            b'Z' => DescriptorTypeBasic::Boolean,
+            b'L' => {
+                let end = rest
+                    .iter()
+                    .position(|x| *x == b';')
+                    .ok_or(DescriptorParseError::UnterminatedClassName)?;
+                let class_name = &rest[..end];
+                if class_name.is_empty() {
+                    return Err(DescriptorParseError::EmptyClassName);
+                }
+
+                let class_id = class_names.gcid_from_bytes(class_name);
+                return Ok((DescriptorTypeBasic::Class(class_id), &rest[end + 1..]));
+            }
+            _ => return Err(DescriptorParseError::UnknownType(*first)),
+        };
+
+        Ok((typ, rest))
+    }
+
     pub(crate) fn from_class_file_desc(
         desc: DescriptorTypeBasicCF<'_>,
         class_names: &mut ClassNames,
@@ -359,6 +456,21 @@ impl DescriptorTypeBasic {
         }
     }
 
+    #[must_use]
+    /// Whether this is a `long` or `double`, which take up two slots
+    pub fn is_category_2(self) -> bool {
+        matches!(
+            self,
+            DescriptorTypeBasic::Long | DescriptorTypeBasic::Double
+        )
+    }
+
+    #[must_use]
+    /// The number of local variable/stack slots that this type takes up
+    pub fn slot_size(self) -> u8 {
+        types::slot_size(self.is_category_2())
+    }
+
     #[must_use]
     pub fn as_class_id(self) -> Option<ClassId> {
         if let DescriptorTypeBasic::Class(class_id) = self {
@@ -393,6 +505,40 @@ pub enum DescriptorType {
     },
 }
 impl DescriptorType {
+    /// Parse a field descriptor, such as `I`, `Ljava/lang/String;` or `[[J`
+    /// The entire input must be a single descriptor.
+    pub fn parse_field(
+        desc: impl AsRef<[u8]>,
+        class_names: &mut ClassNames,
+    ) -> Result<DescriptorType, DescriptorParseError> {
+        let (typ, rest) = DescriptorType::parse_prefix(desc.as_ref(), class_names)?;
+        if rest.is_empty() {
+            Ok(typ)
+        } else {
+            Err(DescriptorParseError::TrailingData)
+        }
+    }
+
+    /// Parse a single field descriptor from the start of `desc`, returning the remaining data.
+    pub(crate) fn parse_prefix<'a>(
+        desc: &'a [u8],
+        class_names: &mut ClassNames,
+    ) -> Result<(DescriptorType, &'a [u8]), DescriptorParseError> {
+        let level = desc.iter().take_while(|x| **x == b'[').count();
+        let rest = &desc[level..];
+        if let Some(level) = NonZeroUsize::new(level) {
+            if rest.is_empty() {
+                return Err(DescriptorParseError::MissingArrayComponent);
+            }
+
+            let (component, rest) = DescriptorTypeBasic::parse_prefix(rest, class_names)?;
+            Ok((DescriptorType::Array { level, component }, rest))
+        } else {
+            let (basic, rest) = DescriptorTypeBasic::parse_prefix(rest, class_names)?;
+            Ok((DescriptorType::Basic(basic), rest))
+        }
+    }
+
     pub fn from_class_file_desc(class_names: &mut ClassNames, desc: DescriptorTypeCF<'_>) -> Self {
         match desc {
             DescriptorTypeCF::Basic(x) => {
@@ -415,6 +561,22 @@ impl DescriptorType {
         }
     }
 
+    #[must_use]
+    /// Whether this is a `long` or `double`, which take up two slots
+    /// Arrays are references, and so are never category 2.
+    pub fn is_category_2(&self) -> bool {
+        match self {
+            DescriptorType::Basic(basic) => basic.is_category_2(),
+            DescriptorType::Array { .. } => false,
+        }
+    }
+
+    #[must_use]
+    /// The number of local variable/stack slots that this type takes up
+    pub fn slot_size(&self) -> u8 {
+        types::slot_size(self.is_category_2())
+    }
+
     #[must_use]
     pub fn is_reference(&self) -> bool {
         matches!(
@@ -519,6 +681,52 @@ impl MethodDescriptor {
         (self.parameters, self.return_type)
     }
 
+    /// Parse a method descriptor, such as `(ILjava/lang/String;)V`
+    pub fn parse(
+        desc: impl AsRef<[u8]>,
+        class_names: &mut ClassNames,
+    ) -> Result<MethodDescriptor, DescriptorParseError> {
+        let desc = desc.as_ref();
+        let mut rest = desc
+            .strip_prefix(b"(")
+            .ok_or(DescriptorParseError::MissingOpeningParen)?;
+
+        let mut parameters = ParametersContainer::new();
+        loop {
+            match rest.first() {
+                Some(b')') => {
+                    rest = &rest[1..];
+                    break;
+                }
+                None => return Err(DescriptorParseError::MissingClosingParen),
+                Some(_) => {
+                    let (parameter, next) = DescriptorType::parse_prefix(rest, class_names)?;
+                    parameters.push(parameter);
+                    rest = next;
+                }
+            }
+        }
+
+        let return_type = if rest == b"V" {
+            None
+        } else {
+            Some(DescriptorType::parse_field(rest, class_names)?)
+        };
+
+        Ok(MethodDescriptor::new(parameters, return_type))
+    }
+
+    #[must_use]
+    /// The number of local variable slots that the parameters take up.
+    /// `long` and `double` take up two slots, despite being a single parameter.
+    /// This does not include the slot for `this` on instance methods.
+    pub fn parameter_slot_count(&self) -> u16 {
+        self.parameters
+            .iter()
+            .map(|parameter| u16::from(parameter.slot_size()))
+            .sum()
+    }
+
     pub(crate) fn from_text_iter<'desc, 'names>(
         desc: &'desc [u8],
         class_names: &'names mut ClassNames,
@@ -682,3 +890,111 @@ pub(crate) fn can_method_override(flags: MethodAccessFlags) -> bool {
     // They can shadow, though, but this is not an override.
     !(flags.contains(MethodAccessFlags::PRIVATE) || flags.contains(MethodAccessFlags::STATIC))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use crate::data::class_names::ClassNames;
+
+    use super::{DescriptorParseError, DescriptorType, DescriptorTypeBasic, MethodDescriptor};
+
+    #[test]
+    fn test_parse_method_descriptor() {
+        let mut class_names = ClassNames::new();
+        let string_id = class_names.gcid_from_bytes(b"java/lang/String");
+
+        let desc = MethodDescriptor::parse("(ILjava/lang/String;JD)V", &mut class_names).unwrap();
+        assert_eq!(
+            desc.parameters(),
+            &[
+                DescriptorType::Basic(DescriptorTypeBasic::Int),
+                DescriptorType::Basic(DescriptorTypeBasic::Class(string_id)),
+                DescriptorType::Basic(DescriptorTypeBasic::Long),
+                DescriptorType::Basic(DescriptorTypeBasic::Double),
+            ]
+        );
+        assert_eq!(desc.return_type(), None);
+        assert_eq!(desc.parameter_slot_count(), 6);
+
+        let desc = MethodDescriptor::parse("()[[Z", &mut class_names).unwrap();
+        assert!(desc.parameters().is_empty());
+        assert_eq!(
+            desc.return_type(),
+            Some(&DescriptorType::Array {
+                level: NonZeroUsize::new(2).unwrap(),
+                component: DescriptorTypeBasic::Boolean,
+            })
+        );
+
+        assert_eq!(
+            MethodDescriptor::parse("(II", &mut class_names),
+            Err(DescriptorParseError::MissingClosingParen)
+        );
+        assert_eq!(
+            MethodDescriptor::parse("I)V", &mut class_names),
+            Err(DescriptorParseError::MissingOpeningParen)
+        );
+        assert_eq!(
+            MethodDescriptor::parse("()", &mut class_names),
+            Err(DescriptorParseError::Empty)
+        );
+        assert_eq!(
+            MethodDescriptor::parse("()VV", &mut class_names),
+            Err(DescriptorParseError::UnknownType(b'V'))
+        );
+    }
+
+    #[test]
+    fn test_parse_field_descriptor() {
+        let mut class_names = ClassNames::new();
+        let string_id = class_names.gcid_from_bytes(b"java/lang/String");
+
+        assert_eq!(
+            DescriptorType::parse_field("I", &mut class_names),
+            Ok(DescriptorType::Basic(DescriptorTypeBasic::Int))
+        );
+        assert_eq!(
+            DescriptorType::parse_field("Ljava/lang/String;", &mut class_names),
+            Ok(DescriptorType::Basic(DescriptorTypeBasic::Class(string_id)))
+        );
+        assert_eq!(
+            DescriptorType::parse_field("[[I", &mut class_names),
+            Ok(DescriptorType::Array {
+                level: NonZeroUsize::new(2).unwrap(),
+                component: DescriptorTypeBasic::Int,
+            })
+        );
+        assert_eq!(
+            DescriptorType::parse_field("[Ljava/lang/String;", &mut class_names),
+            Ok(DescriptorType::single_array(DescriptorTypeBasic::Class(
+                string_id
+            )))
+        );
+
+        assert_eq!(
+            DescriptorType::parse_field("", &mut class_names),
+            Err(DescriptorParseError::Empty)
+        );
+        assert_eq!(
+            DescriptorType::parse_field("II", &mut class_names),
+            Err(DescriptorParseError::TrailingData)
+        );
+        assert_eq!(
+            DescriptorType::parse_field("Ljava/lang/String", &mut class_names),
+            Err(DescriptorParseError::UnterminatedClassName)
+        );
+        assert_eq!(
+            DescriptorType::parse_field("L;", &mut class_names),
+            Err(DescriptorParseError::EmptyClassName)
+        );
+        assert_eq!(
+            DescriptorType::parse_field("[[", &mut class_names),
+            Err(DescriptorParseError::MissingArrayComponent)
+        );
+        assert_eq!(
+            DescriptorType::parse_field("V", &mut class_names),
+            Err(DescriptorParseError::UnknownType(b'V'))
+        );
+    }
+}
@@ -10,6 +10,8 @@ use self::{method::Method, op::Inst, op_ex::InstructionParseError};
 
 pub use classfile_parser::attribute_info::InstructionIndex;
 
+pub mod builder;
+pub mod frame_size;
 pub mod method;
 pub mod op;
 pub mod op_ex;
@@ -247,18 +249,7 @@ pub(crate) fn parse_code(
     let code_range = code_attr.code.clone();
     let code = class_file.parse_data_for(code_range);
     let code = code.data();
-    let mut instructions = Vec::new();
-
-    let mut idx: u16 = 0;
-    while (idx as usize) < code.len() {
-        // We don't need to give the entirety of the code to the instructions but it does not
-        // harm anything.
-        let inst = Inst::parse(code, InstructionIndex(idx))?;
-        let size: u16 = inst.memory_size_u16();
-        instructions.push((InstructionIndex(idx), inst));
-        idx += size;
-    }
-
+    let instructions = op::instructions(code).collect::<Result<Vec<_>, _>>()?;
     let instructions = Instructions::new(instructions);
 
     Ok(CodeInfo {
@@ -4,6 +4,7 @@ use classfile_parser::constant_info::{
     InvokeDynamicConstant,
 };
 use classfile_parser::constant_pool::ConstantPoolIndexRaw;
+use smallvec::SmallVec;
 
 use crate::class::ClassFileData;
 use crate::code::op_ex::InstructionParseError;
@@ -12,7 +13,7 @@ use crate::code::types::{
     LocalVariableIndex, LocalVariableIndexByteType, LocalVariableIndexType, LocalVariableType,
     LocalsIn, LocalsOutAt, Long, ParseOutput, PopComplexType, PopIndex, PopType, PopTypeAt,
     PrimitiveType, PushIndex, PushType, PushTypeAt, Short, StackInfo, StackSizes, UnsignedByte,
-    UnsignedShort, WithType,
+    WithType,
 };
 use crate::data::class_names::ClassNames;
 use crate::util::{MemorySizeU16, StaticMemorySizeU16};
@@ -2978,13 +2979,93 @@ WIDE_INSTR: [
         exceptions: [],
         locals_in: [{extern}],
     },
+    WideLongLoad: {
+        opcode: LongLoad::OPCODE,
+        args: [index: LocalVariableIndexType],
+        pop: [],
+        push: [val: Long],
+        exceptions: [],
+        locals_in: [{extern}],
+    },
+    WideFloatLoad: {
+        opcode: FloatLoad::OPCODE,
+        args: [index: LocalVariableIndexType],
+        pop: [],
+        push: [val: Float],
+        exceptions: [],
+        locals_in: [{extern}],
+    },
+    WideDoubleLoad: {
+        opcode: DoubleLoad::OPCODE,
+        args: [index: LocalVariableIndexType],
+        pop: [],
+        push: [res: Double],
+        exceptions: [],
+        locals_in: [{extern}],
+    },
+    WideALoad: {
+        opcode: ALoad::OPCODE,
+        args: [
+            /// The locvar at index must contain a reference
+            index: LocalVariableIndexType,
+        ],
+        pop: [],
+        push: [{extern}],
+        exceptions: [],
+        locals_in: [{extern}],
+        init: [inst; RequireValidLocalVariableIndex(inst.index)],
+    },
+    WideIntStore: {
+        opcode: IntStore::OPCODE,
+        args: [index: LocalVariableIndexType],
+        pop: [value: Int],
+        push: [],
+        exceptions: [],
+        locals_out: [{extern}],
+    },
+    WideLongStore: {
+        opcode: LongStore::OPCODE,
+        args: [index: LocalVariableIndexType],
+        pop: [val: Long],
+        push: [],
+        exceptions: [],
+        locals_out: [{extern}],
+    },
+    WideFloatStore: {
+        opcode: FloatStore::OPCODE,
+        args: [index: LocalVariableIndexType],
+        pop: [val: Float],
+        push: [],
+        exceptions: [],
+        locals_out: [{extern}],
+    },
+    WideDoubleStore: {
+        opcode: DoubleStore::OPCODE,
+        args: [index: LocalVariableIndexType],
+        pop: [val: Double],
+        push: [],
+        exceptions: [],
+        locals_out: [{extern}],
+    },
+    WideAStore: {
+        opcode: AStore::OPCODE,
+        args: [index: LocalVariableIndexType],
+        pop: [
+            /// Must be of type returnAddress | reference
+            objectref: PopComplexType::ReferenceAny,
+        ],
+        push: [],
+        exceptions: [],
+        locals_out: [{extern}],
+        init: [inst; RequireValidLocalVariableIndex(inst.index)],
+    },
     WideIntIncrement: {
         opcode: IntIncrement::OPCODE,
         args: [
             /// Index into local variable array
             index: LocalVariableIndexType,
             /// The amount to increment by
-            increment_amount: UnsignedShort,
+            increment_amount: Short,
         ],
         pop: [],
         push: [],
@@ -3039,12 +3120,23 @@ impl LookupSwitch {
         #[allow(clippy::cast_possible_truncation)]
         let padding = (idx.0 % 4) as u8;
         let padding = if padding == 0 { padding } else { 4 - padding };
+        // padding, default, npairs
+        ensure_switch_len(data, Self::OPCODE, usize::from(padding) + 8)?;
         let data = &data[padding as usize..];
         let default = Int::parse(data);
         let data = &data[Int::MEMORY_SIZE_U16 as usize..];
         let npairs = Int::parse(data);
         let mut data = &data[Int::MEMORY_SIZE_U16 as usize..];
-        let mut pairs = Vec::new();
+        let npairs =
+            usize::try_from(npairs).map_err(|_| InstructionParseError::InvalidSwitchCount {
+                opcode: Self::OPCODE,
+            })?;
+        ensure_switch_len(
+            data,
+            Self::OPCODE,
+            npairs * usize::from(LookupSwitchPair::MEMORY_SIZE_U16),
+        )?;
+        let mut pairs = Vec::with_capacity(npairs);
         for _ in 0..npairs {
             let val = LookupSwitchPair::parse(data)?;
             data = &data[LookupSwitchPair::MEMORY_SIZE_U16 as usize..];
@@ -3101,6 +3193,23 @@ impl MemorySizeU16 for LookupSwitch {
     }
 }
 
+/// Ensure that the variable-length data of a switch instruction is actually there
+fn ensure_switch_len(
+    data: &[u8],
+    opcode: RawOpcode,
+    needed: usize,
+) -> Result<(), InstructionParseError> {
+    if data.len() < needed {
+        Err(InstructionParseError::NotEnoughData {
+            opcode,
+            needed,
+            had: data.len(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LookupSwitchPair {
     pub match_v: i32,
@@ -3144,6 +3253,8 @@ impl TableSwitch {
         #[allow(clippy::cast_possible_truncation)]
         let padding = (idx.0 % 4) as u8;
         let padding = if padding == 0 { padding } else { 4 - padding };
+        // padding, default, low, high
+        ensure_switch_len(data, Self::OPCODE, usize::from(padding) + 12)?;
         let data = &data[padding as usize..];
         let default = Int::parse(data);
         let data = &data[Int::MEMORY_SIZE_U16 as usize..];
@@ -3151,8 +3262,17 @@ impl TableSwitch {
         let data = &data[Int::MEMORY_SIZE_U16 as usize..];
         let high = Int::parse(data);
         let mut data = &data[Int::MEMORY_SIZE_U16 as usize..];
-        let jump_table_count = high - low + 1;
-        let mut jump_offsets = Vec::new();
+        let jump_table_count = (i64::from(high) - i64::from(low) + 1)
+            .try_into()
+            .map_err(|_| InstructionParseError::InvalidSwitchCount {
+                opcode: Self::OPCODE,
+            })?;
+        ensure_switch_len(
+            data,
+            Self::OPCODE,
+            jump_table_count * usize::from(Int::MEMORY_SIZE_U16),
+        )?;
+        let mut jump_offsets = Vec::with_capacity(jump_table_count);
         for _ in 0..jump_table_count {
             jump_offsets.push(Int::parse(data));
             data = &data[Int::MEMORY_SIZE_U16 as usize..];
@@ -3247,12 +3367,176 @@ impl MemorySizeU16 for Wide {
     }
 }
 
+impl Inst {
+    #[must_use]
+    /// The instructions that this instruction, which is at `at`, can branch to
+    /// This does not include the next instruction, see [`Inst::can_fall_through`], nor any
+    /// exception handlers.
+    /// Returns `None` if a target is outside of the range that code can have.
+    pub fn branch_targets(&self, at: InstructionIndex) -> Option<SmallVec<[InstructionIndex; 2]>> {
+        let target = |offset: i32| {
+            u16::try_from(i32::from(at.0) + offset)
+                .ok()
+                .map(InstructionIndex)
+        };
+
+        let mut targets = SmallVec::new();
+        match self {
+            Inst::Goto(Goto { branch_offset })
+            | Inst::IfIntCmpEq(IfIntCmpEq { branch_offset })
+            | Inst::IfIntCmpNe(IfIntCmpNe { branch_offset })
+            | Inst::IfIntCmpLt(IfIntCmpLt { branch_offset })
+            | Inst::IfIntCmpGe(IfIntCmpGe { branch_offset })
+            | Inst::IfIntCmpGt(IfIntCmpGt { branch_offset })
+            | Inst::IfIntCmpLe(IfIntCmpLe { branch_offset })
+            | Inst::IfACmpEq(IfACmpEq { branch_offset })
+            | Inst::IfACmpNe(IfACmpNe { branch_offset })
+            | Inst::IfEqZero(IfEqZero { branch_offset })
+            | Inst::IfNeZero(IfNeZero { branch_offset })
+            | Inst::IfLtZero(IfLtZero { branch_offset })
+            | Inst::IfGeZero(IfGeZero { branch_offset })
+            | Inst::IfGtZero(IfGtZero { branch_offset })
+            | Inst::IfLeZero(IfLeZero { branch_offset })
+            | Inst::IfNonNull(IfNonNull { branch_offset })
+            | Inst::IfNull(IfNull { branch_offset }) => {
+                targets.push(target(i32::from(*branch_offset))?);
+            }
+            Inst::LookupSwitch(switch) => {
+                targets.push(target(switch.default)?);
+                for pair in &switch.pairs {
+                    targets.push(target(pair.offset)?);
+                }
+            }
+            Inst::TableSwitch(switch) => {
+                targets.push(target(switch.default)?);
+                for offset in &switch.jump_offsets {
+                    targets.push(target(*offset)?);
+                }
+            }
+            _ => {}
+        }
+
+        Some(targets)
+    }
+
+    #[must_use]
+    /// Whether execution can continue on to the next instruction after this one
+    /// This is false for unconditional branches, returns, and throws.
+    pub fn can_fall_through(&self) -> bool {
+        !matches!(
+            self,
+            Inst::Goto(_)
+                | Inst::LookupSwitch(_)
+                | Inst::TableSwitch(_)
+                | Inst::Return(_)
+                | Inst::IntReturn(_)
+                | Inst::LongReturn(_)
+                | Inst::FloatReturn(_)
+                | Inst::DoubleReturn(_)
+                | Inst::AReturn(_)
+                | Inst::AThrow(_)
+        )
+    }
+}
+
+/// Iterate over the decoded instructions of some code, along with their index
+/// See [`InstructionIter`]
+#[must_use]
+pub fn instructions(code: &[u8]) -> InstructionIter<'_> {
+    InstructionIter { code, idx: Some(0) }
+}
+
+/// An iterator over the instructions of some code
+/// This stops after the first error, since we can't know where the next instruction starts.
+#[derive(Debug, Clone)]
+pub struct InstructionIter<'a> {
+    code: &'a [u8],
+    /// `None` once we've finished or hit an error
+    idx: Option<u16>,
+}
+impl<'a> Iterator for InstructionIter<'a> {
+    type Item = Result<(InstructionIndex, Inst), InstructionParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.idx?;
+        if usize::from(idx) >= self.code.len() {
+            self.idx = None;
+            return None;
+        }
+
+        let idx = InstructionIndex(idx);
+        match Inst::parse(self.code, idx) {
+            Ok(inst) => {
+                self.idx = idx.0.checked_add(inst.memory_size_u16());
+                Some(Ok((idx, inst)))
+            }
+            Err(err) => {
+                self.idx = None;
+                Some(Err(err))
+            }
+        }
+    }
+}
+impl<'a> std::iter::FusedIterator for InstructionIter<'a> {}
+
 #[cfg(test)]
 mod tests {
-    use super::check_instruction_duplicates;
+    use classfile_parser::attribute_info::InstructionIndex;
+
+    use super::{check_instruction_duplicates, instructions, Inst};
 
     #[test]
     fn test_ops() {
         check_instruction_duplicates();
     }
+
+    #[test]
+    fn test_instructions() {
+        // bipush 4; return
+        let code = [0x10, 0x04, 0xB1];
+        let insts = instructions(&code)
+            .map(|x| x.map(|(idx, inst)| (idx.0, inst)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(insts.len(), 2);
+        assert!(matches!(insts[0], (0, Inst::PushByte(_))));
+        assert!(matches!(insts[1], (2, Inst::Return(_))));
+
+        // tableswitch that is missing its jump offsets
+        let code = [0xAA, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let mut iter = instructions(&code);
+        assert!(matches!(iter.next(), Some(Err(_))));
+
+        // bipush without its operand
+        let code = [0xB1, 0x10];
+        let mut iter = instructions(&code);
+        assert!(matches!(iter.next(), Some(Ok(_))));
+        assert!(matches!(iter.next(), Some(Err(_))));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_branch_targets() {
+        // iload_0; ifeq +6; goto -4; return
+        let code = [0x1A, 0x99, 0, 6, 0xA7, 0xFF, 0xFC, 0xB1];
+        let insts = instructions(&code).collect::<Result<Vec<_>, _>>().unwrap();
+        let targets = |i: usize| {
+            let (idx, inst): &(InstructionIndex, Inst) = &insts[i];
+            inst.branch_targets(*idx)
+                .map(|targets| targets.iter().map(|x| x.0).collect::<Vec<_>>())
+        };
+
+        assert_eq!(targets(0), Some(vec![]));
+        assert!(insts[0].1.can_fall_through());
+        assert_eq!(targets(1), Some(vec![7]));
+        assert!(insts[1].1.can_fall_through());
+        assert_eq!(targets(2), Some(vec![0]));
+        assert!(!insts[2].1.can_fall_through());
+        assert!(!insts[3].1.can_fall_through());
+
+        // goto -1, which is before the start of the code
+        let code = [0xA7, 0xFF, 0xFF];
+        let (idx, inst) = instructions(&code).next().unwrap().unwrap();
+        assert!(inst.branch_targets(idx).is_none());
+    }
 }
@@ -16,7 +16,8 @@ use super::op::{
     FloatLoad, FloatStore, GetField, GetStatic, IntIncrement, IntLoad, IntStore, InvokeDynamic,
     InvokeInterface, InvokeSpecial, InvokeStatic, InvokeVirtual, LoadConstant, LoadConstant2Wide,
     LoadConstantWide, LongLoad, LongStore, MultiANewArray, New, NewArray, Pop2, PutField,
-    PutStaticField, WideIntIncrement, WideIntLoad,
+    PutStaticField, WideALoad, WideAStore, WideDoubleLoad, WideDoubleStore, WideFloatLoad,
+    WideFloatStore, WideIntIncrement, WideIntLoad, WideIntStore, WideLongLoad, WideLongStore,
 };
 use super::types::{
     Category, ComplexType, HasStackInfo, LocalVariableInType, LocalVariableIndex,
@@ -34,22 +35,29 @@ use crate::data::class_names::ClassNames;
 use crate::id::{ClassId, ExactMethodId};
 use crate::{LoadMethodError, StepError};
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum InstructionParseError {
+    #[error("not enough data for opcode {opcode:#04X}: needed {needed} bytes but had {had}")]
     NotEnoughData {
         opcode: RawOpcode,
         needed: usize,
         had: usize,
     },
+    #[error("expected an opcode at {}", .0.0)]
     ExpectedOpCodeAt(InstructionIndex),
+    #[error("unknown opcode {opcode:#04X} at {}", idx.0)]
     UnknownOpcode {
         idx: InstructionIndex,
         opcode: RawOpcode,
     },
+    #[error("unknown wide opcode {opcode:#04X} at {}", idx.0)]
     UnknownWideOpcode {
         idx: InstructionIndex,
         opcode: RawOpcode,
     },
+    /// A `tableswitch` or `lookupswitch` had a negative number of entries
+    #[error("switch instruction {opcode:#04X} had a negative number of entries")]
+    InvalidSwitchCount { opcode: RawOpcode },
 }
 
 macro_rules! empty_push {
@@ -348,6 +356,34 @@ impl LocalsIn for WideIntIncrement {
         [(self.index, PrimitiveType::Int.into())].into_iter()
     }
 }
+impl LocalsIn for WideALoad {
+    type Iter = SingleInLocal;
+
+    fn locals_in_type_iter(&self) -> Self::Iter {
+        [(self.index, LocalVariableInType::ReferenceAny)].into_iter()
+    }
+}
+impl LocalsIn for WideLongLoad {
+    type Iter = SingleInLocal;
+
+    fn locals_in_type_iter(&self) -> Self::Iter {
+        [(self.index, PrimitiveType::Long.into())].into_iter()
+    }
+}
+impl LocalsIn for WideDoubleLoad {
+    type Iter = SingleInLocal;
+
+    fn locals_in_type_iter(&self) -> Self::Iter {
+        [(self.index, PrimitiveType::Double.into())].into_iter()
+    }
+}
+impl LocalsIn for WideFloatLoad {
+    type Iter = SingleInLocal;
+
+    fn locals_in_type_iter(&self) -> Self::Iter {
+        [(self.index, PrimitiveType::Float.into())].into_iter()
+    }
+}
 
 impl LocalsIn for LongLoad {
     type Iter = SingleInLocal;
@@ -406,6 +442,41 @@ impl LocalsOutAt for LongStore {
         [(self.index.into(), PrimitiveType::Long.into())].into_iter()
     }
 }
+impl LocalsOutAt for WideAStore {
+    type Iter = std::array::IntoIter<(LocalVariableIndex, LocalVariableType), 1>;
+
+    fn locals_out_type_iter(&self) -> Self::Iter {
+        [(self.index, WithType::Type(0).into())].into_iter()
+    }
+}
+impl LocalsOutAt for WideIntStore {
+    type Iter = std::array::IntoIter<(LocalVariableIndex, LocalVariableType), 1>;
+
+    fn locals_out_type_iter(&self) -> Self::Iter {
+        [(self.index, PrimitiveType::Int.into())].into_iter()
+    }
+}
+impl LocalsOutAt for WideDoubleStore {
+    type Iter = std::array::IntoIter<(LocalVariableIndex, LocalVariableType), 1>;
+
+    fn locals_out_type_iter(&self) -> Self::Iter {
+        [(self.index, PrimitiveType::Double.into())].into_iter()
+    }
+}
+impl LocalsOutAt for WideFloatStore {
+    type Iter = std::array::IntoIter<(LocalVariableIndex, LocalVariableType), 1>;
+
+    fn locals_out_type_iter(&self) -> Self::Iter {
+        [(self.index, PrimitiveType::Float.into())].into_iter()
+    }
+}
+impl LocalsOutAt for WideLongStore {
+    type Iter = std::array::IntoIter<(LocalVariableIndex, LocalVariableType), 1>;
+
+    fn locals_out_type_iter(&self) -> Self::Iter {
+        [(self.index, PrimitiveType::Long.into())].into_iter()
+    }
+}
 
 fn descriptor_into_parameters_ret<const N: usize>(
     class_names: &mut ClassNames,
@@ -1171,6 +1242,19 @@ impl PushTypeAt for ALoad {
         1
     }
 }
+impl PushTypeAt for WideALoad {
+    fn push_type_at(&self, i: PushIndex) -> Option<PushType> {
+        if i == 0 {
+            Some(WithType::LocalVariableRefAtIndexNoRetAddr(self.index).into())
+        } else {
+            None
+        }
+    }
+
+    fn push_count(&self) -> usize {
+        1
+    }
+}
 
 pub enum Pop2Info {
     /// Pop two category ones
@@ -3,12 +3,19 @@ use std::borrow::Cow;
 use classfile_parser::{
     attribute_info::{
         stack_map_table_attribute_parser, InstructionIndex, StackMapFrame as StackMapFrameCF,
-        StackMapTableAttribute, VerificationTypeInfo,
+        StackMapTableAttribute,
     },
     method_info::MethodAccessFlags,
 };
 use smallvec::SmallVec;
 
+/// A frame of the `StackMapTable` as it is written in the class file
+/// This is relative to the previous frame, see [`StackMapFramesProcessor`] for the computed
+/// frames.
+pub use classfile_parser::attribute_info::StackMapFrame as RawStackMapFrame;
+/// The type of a local or stack entry in a [`RawStackMapFrame`]
+pub use classfile_parser::attribute_info::VerificationTypeInfo;
+
 use crate::{class::ClassFileData, data::class_names::ClassNames, id::ClassId, BadIdError};
 
 use super::{
@@ -16,15 +23,19 @@ use super::{
     CodeInfo,
 };
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum StackMapError {
     /// There should have been a stack map
+    #[error("missing stack map table")]
     NoStackMap,
     /// Failed to parse the stack map attribute
+    #[error("failed to parse the stack map table")]
     ParseError,
     /// The id of a descriptor type was incorrect
-    BadDescriptorTypeId(BadIdError),
+    #[error("bad class id in a descriptor type")]
+    BadDescriptorTypeId(#[source] BadIdError),
     /// Failed to convert a verification type to a stack map type
+    #[error("failed to convert a verification type to a stack map type")]
     VerificationTypeToStackMapTypeFailure,
 }
 
@@ -140,6 +151,56 @@ impl StackMapFrame {
             locals,
         }
     }
+
+    /// The frame at the start of the method, which has `this` (if the method is not static) and
+    /// the parameters in its locals, and an empty stack
+    pub fn initial(
+        class_names: &mut ClassNames,
+        class_file: &ClassFileData,
+        method: &Method,
+    ) -> Result<StackMapFrame, StackMapError> {
+        let descriptor = method.descriptor();
+        // TODO: Should we skip adding the initial frame if it is empty? Some code may rely on it
+        // being nonempty
+        let this_type = if class_names.is_object(class_file.id()) && method.is_init() {
+            // Object's init has some special handling
+            Some(StackMapType::Object(class_file.id()))
+        } else if method.is_init() {
+            Some(StackMapType::UninitializedThis(class_file.id()))
+        } else if !method.access_flags().contains(MethodAccessFlags::STATIC) {
+            Some(StackMapType::Object(class_file.id()))
+        } else {
+            None
+        };
+
+        let count = descriptor.parameters().len() + if this_type.is_some() { 1 } else { 0 };
+        // Not seeing if this_type is expanded is fine since it can't be a cat-2
+        let mut locals = SmallVec::with_capacity(count);
+        if let Some(this_type) = this_type {
+            locals.push(this_type);
+        }
+
+        for parameter in descriptor.parameters().iter() {
+            let typ = StackMapType::from_desc(class_names, parameter)
+                .map_err(StackMapError::BadDescriptorTypeId)?;
+            match typ {
+                StackMapType::Integer | StackMapType::Float | StackMapType::Object(_) => {
+                    locals.push(typ)
+                }
+                StackMapType::Long | StackMapType::Double => {
+                    locals.push(typ);
+                    locals.push(StackMapType::Top);
+                }
+
+                StackMapType::Top
+                | StackMapType::Null
+                | StackMapType::UninitializedVariable(_)
+                | StackMapType::UninitializedThis(_) => unreachable!(),
+            }
+        }
+
+        Ok(StackMapFrame::new_locals(InstructionIndex(0), locals))
+    }
 }
 
 /// The start of the `SameLocals1Item` frame, used for computing the `offset_delta`
@@ -165,49 +226,7 @@ impl StackMapFramesProcessor {
         method: &'a Method,
         method_code: &'a CodeInfo,
     ) -> Result<StackMapFramesProcessor, StackMapError> {
-        let descriptor = method.descriptor();
-        // TODO: Should we skip adding the initial frame if it is empty? Some code may rely on it
-        // being nonempty
-        let initial_frame = {
-            let this_type = if class_file.id() == class_names.object_id() && method.is_init() {
-                // Object's init has some special handling
-                Some(StackMapType::Object(class_file.id()))
-            } else if method.is_init() {
-                Some(StackMapType::UninitializedThis(class_file.id()))
-            } else if !method.access_flags().contains(MethodAccessFlags::STATIC) {
-                Some(StackMapType::Object(class_file.id()))
-            } else {
-                None
-            };
-
-            let count = descriptor.parameters().len() + if this_type.is_some() { 1 } else { 0 };
-            // Not seeing if this_type is expanded is fine since it can't be a cat-2
-            let mut locals = SmallVec::with_capacity(count);
-            if let Some(this_type) = this_type {
-                locals.push(this_type);
-            }
-
-            for parameter in descriptor.parameters().iter() {
-                let typ = StackMapType::from_desc(class_names, parameter)
-                    .map_err(StackMapError::BadDescriptorTypeId)?;
-                match typ {
-                    StackMapType::Integer | StackMapType::Float | StackMapType::Object(_) => {
-                        locals.push(typ)
-                    }
-                    StackMapType::Long | StackMapType::Double => {
-                        locals.push(typ);
-                        locals.push(StackMapType::Top);
-                    }
-
-                    StackMapType::Top
-                    | StackMapType::Null
-                    | StackMapType::UninitializedVariable(_)
-                    | StackMapType::UninitializedThis(_) => unreachable!(),
-                }
-            }
-
-            StackMapFrame::new_locals(InstructionIndex(0), locals)
-        };
+        let initial_frame = StackMapFrame::initial(class_names, class_file, method)?;
 
         // Stack map table attribute
         let smt = method_code.attributes().iter().find(|x| {
@@ -226,8 +245,8 @@ impl StackMapFramesProcessor {
             debug_assert!(rem_data.is_empty());
             smt
         } else if class_file.version().map_or(false, |x| x.major <= 50) {
-            // TODO: Allow nonexistent stack map table for earlier versions of the bytecode
-            // We will have to figure out how the type inference is meant to work
+            // Earlier versions of the bytecode don't need a stack map table, and instead have
+            // their frames inferred, which the verifier does instead of using this
             tracing::warn!("Class File Version: {:?}", class_file.version());
             return Err(StackMapError::NoStackMap);
         } else {
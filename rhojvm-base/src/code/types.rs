@@ -84,7 +84,7 @@ macro_rules! create_primitive_types {
             }
         )*
 
-        #[derive(Debug, Clone, Copy)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
         pub enum PrimitiveTypeM {
             $(
                 $name,
@@ -115,12 +115,29 @@ create_primitive_types!([
     Char = 2; d -> JavaChar { JavaChar(u16::from_be_bytes([d[0], d[1]])) },
     Boolean = 1; d -> bool { d[0] != 0 },
 ]);
+
+/// The number of local variable/stack slots that a value takes up, which is two for the category 2
+/// types (`long` and `double`) and one for everything else
+pub(crate) fn slot_size(is_category_2: bool) -> u8 {
+    if is_category_2 {
+        2
+    } else {
+        1
+    }
+}
+
 impl PrimitiveTypeM {
     #[must_use]
     pub fn is_category_2(&self) -> bool {
         matches!(self, PrimitiveType::Double | PrimitiveType::Long)
     }
 
+    #[must_use]
+    /// The number of local variable/stack slots that this type takes up
+    pub fn slot_size(&self) -> u8 {
+        slot_size(self.is_category_2())
+    }
+
     #[must_use]
     pub fn as_desc_prefix(&self) -> &'static [u8] {
         match self {
@@ -436,28 +453,36 @@ impl From<WithType> for Type {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum StackInfoError {
     /// The class file id was incorrect, it really shouldn't have been.
+    #[error("invalid class id")]
     InvalidClassId,
     /// The method id was incorrect, it really shouldn't have been
+    #[error("invalid method id")]
     InvalidMethodId,
     /// Tried to index into the constant pool, but failed to get it
+    #[error("invalid constant pool index: {}", .0.0)]
     InvalidConstantPoolIndex(ConstantPoolIndexRaw<ConstantInfo>),
     // TODO: provide the expected type, and what it should have been?
     /// Got a value from the constant pool but it was the wrong kind
+    #[error("constant pool entry was of the wrong type")]
     IncorrectConstantPoolType,
     /// There was an error parsing a descriptor type
+    #[error("invalid descriptor type: {0:?}")]
     InvalidDescriptorType(classfile_parser::descriptor::DescriptorTypeError),
     /// When parsing the type of a field, it had remaining data.
     /// This indicates either a bug in the parsing or a problem with the class file
+    #[error("field type had trailing data")]
     UnparsedFieldType,
     /// It needed a stack size at the given index to make a decision about the stack infop
+    #[error("needed the stack size at {0}")]
     NeededStackSizeAt(usize),
     /// The stack sizes were bad for what it needed.
     /// Ex: dup2 only makes sense as either a category 1 then a category 1
     /// or a category 2
     /// but not as a category 1 then a category 2
+    #[error("bad stack sizes for instruction")]
     BadStackSizes,
 }
 
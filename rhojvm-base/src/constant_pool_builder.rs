@@ -0,0 +1,536 @@
+//! Building a constant pool, for generating or patching class files
+//! Entries are deduplicated, so adding a constant which is already in the pool gives back the
+//! index of the existing entry.
+
+use std::collections::HashMap;
+
+use crate::{
+    class::{ClassFileData, MethodHandleKind},
+    class_reader::Reader,
+};
+
+/// A single entry in the constant pool, as it is written in the class file
+/// Text is stored as the raw modified utf8 (cesu8) bytes, and floating point values as their
+/// bits, so that entries are only equal if they would be written identically.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PoolEntry {
+    Utf8(Box<[u8]>),
+    Integer(i32),
+    Float(u32),
+    Long(i64),
+    Double(u64),
+    Class {
+        name_index: u16,
+    },
+    String {
+        string_index: u16,
+    },
+    FieldRef {
+        class_index: u16,
+        name_and_type_index: u16,
+    },
+    MethodRef {
+        class_index: u16,
+        name_and_type_index: u16,
+    },
+    InterfaceMethodRef {
+        class_index: u16,
+        name_and_type_index: u16,
+    },
+    NameAndType {
+        name_index: u16,
+        descriptor_index: u16,
+    },
+    MethodHandle {
+        reference_kind: u8,
+        reference_index: u16,
+    },
+    MethodType {
+        descriptor_index: u16,
+    },
+    Dynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
+    InvokeDynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
+    Module {
+        name_index: u16,
+    },
+    Package {
+        name_index: u16,
+    },
+}
+impl PoolEntry {
+    #[must_use]
+    /// The tag byte that the entry is written with
+    pub fn tag(&self) -> u8 {
+        match self {
+            PoolEntry::Utf8(_) => 1,
+            PoolEntry::Integer(_) => 3,
+            PoolEntry::Float(_) => 4,
+            PoolEntry::Long(_) => 5,
+            PoolEntry::Double(_) => 6,
+            PoolEntry::Class { .. } => 7,
+            PoolEntry::String { .. } => 8,
+            PoolEntry::FieldRef { .. } => 9,
+            PoolEntry::MethodRef { .. } => 10,
+            PoolEntry::InterfaceMethodRef { .. } => 11,
+            PoolEntry::NameAndType { .. } => 12,
+            PoolEntry::MethodHandle { .. } => 15,
+            PoolEntry::MethodType { .. } => 16,
+            PoolEntry::Dynamic { .. } => 17,
+            PoolEntry::InvokeDynamic { .. } => 18,
+            PoolEntry::Module { .. } => 19,
+            PoolEntry::Package { .. } => 20,
+        }
+    }
+
+    #[must_use]
+    /// Whether the entry takes up two indices in the constant pool, which is the case for
+    /// `Long` and `Double`
+    pub fn is_wide(&self) -> bool {
+        matches!(self, PoolEntry::Long(_) | PoolEntry::Double(_))
+    }
+
+    /// Read an entry, with the reader positioned at its tag
+    pub(crate) fn read(reader: &mut Reader<'_>) -> Option<PoolEntry> {
+        let tag = reader.u8()?;
+        Some(match tag {
+            1 => {
+                let length = reader.u16()?;
+                PoolEntry::Utf8(Box::from(reader.bytes(usize::from(length))?))
+            }
+            3 => PoolEntry::Integer(i32::from_be_bytes(reader.u32()?.to_be_bytes())),
+            4 => PoolEntry::Float(reader.u32()?),
+            5 => PoolEntry::Long(i64::from_be_bytes(reader_u64(reader)?.to_be_bytes())),
+            6 => PoolEntry::Double(reader_u64(reader)?),
+            7 => PoolEntry::Class {
+                name_index: reader.u16()?,
+            },
+            8 => PoolEntry::String {
+                string_index: reader.u16()?,
+            },
+            9 => PoolEntry::FieldRef {
+                class_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            10 => PoolEntry::MethodRef {
+                class_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            11 => PoolEntry::InterfaceMethodRef {
+                class_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            12 => PoolEntry::NameAndType {
+                name_index: reader.u16()?,
+                descriptor_index: reader.u16()?,
+            },
+            15 => PoolEntry::MethodHandle {
+                reference_kind: reader.u8()?,
+                reference_index: reader.u16()?,
+            },
+            16 => PoolEntry::MethodType {
+                descriptor_index: reader.u16()?,
+            },
+            17 => PoolEntry::Dynamic {
+                bootstrap_method_attr_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            18 => PoolEntry::InvokeDynamic {
+                bootstrap_method_attr_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            19 => PoolEntry::Module {
+                name_index: reader.u16()?,
+            },
+            20 => PoolEntry::Package {
+                name_index: reader.u16()?,
+            },
+            _ => return None,
+        })
+    }
+
+    /// Write the entry, including its tag
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.push(self.tag());
+        match self {
+            PoolEntry::Utf8(text) => {
+                // The builder never holds text which is too long
+                #[allow(clippy::cast_possible_truncation)]
+                out.extend((text.len() as u16).to_be_bytes());
+                out.extend(text.iter());
+            }
+            PoolEntry::Integer(value) => out.extend(value.to_be_bytes()),
+            PoolEntry::Float(bits) => out.extend(bits.to_be_bytes()),
+            PoolEntry::Long(value) => out.extend(value.to_be_bytes()),
+            PoolEntry::Double(bits) => out.extend(bits.to_be_bytes()),
+            PoolEntry::Class { name_index: index }
+            | PoolEntry::String {
+                string_index: index,
+            }
+            | PoolEntry::MethodType {
+                descriptor_index: index,
+            }
+            | PoolEntry::Module { name_index: index }
+            | PoolEntry::Package { name_index: index } => out.extend(index.to_be_bytes()),
+            PoolEntry::FieldRef {
+                class_index: a,
+                name_and_type_index: b,
+            }
+            | PoolEntry::MethodRef {
+                class_index: a,
+                name_and_type_index: b,
+            }
+            | PoolEntry::InterfaceMethodRef {
+                class_index: a,
+                name_and_type_index: b,
+            }
+            | PoolEntry::NameAndType {
+                name_index: a,
+                descriptor_index: b,
+            }
+            | PoolEntry::Dynamic {
+                bootstrap_method_attr_index: a,
+                name_and_type_index: b,
+            }
+            | PoolEntry::InvokeDynamic {
+                bootstrap_method_attr_index: a,
+                name_and_type_index: b,
+            } => {
+                out.extend(a.to_be_bytes());
+                out.extend(b.to_be_bytes());
+            }
+            PoolEntry::MethodHandle {
+                reference_kind,
+                reference_index,
+            } => {
+                out.push(*reference_kind);
+                out.extend(reference_index.to_be_bytes());
+            }
+        }
+    }
+}
+
+fn reader_u64(reader: &mut Reader<'_>) -> Option<u64> {
+    let high = u64::from(reader.u32()?);
+    let low = u64::from(reader.u32()?);
+    Some((high << 32) | low)
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ConstantPoolBuilderError {
+    /// There are no more indices available in the constant pool
+    #[error("the constant pool is full")]
+    Full,
+    /// The text is longer than can be stored in a `CONSTANT_Utf8`
+    #[error("text of {0} bytes is too long for the constant pool")]
+    TextTooLong(usize),
+}
+
+/// A constant pool which can be added to
+/// Adding an entry which is equal to one already in the pool returns the existing index rather
+/// than adding a duplicate.
+#[derive(Debug, Clone)]
+pub struct ConstantPoolBuilder {
+    /// The entries, indexed by their constant pool index
+    /// Index 0 is unused, as is the index after each `Long` and `Double`, and so those are
+    /// `None`.
+    entries: Vec<Option<PoolEntry>>,
+    indices: HashMap<PoolEntry, u16>,
+}
+impl Default for ConstantPoolBuilder {
+    fn default() -> Self {
+        ConstantPoolBuilder {
+            entries: vec![None],
+            indices: HashMap::new(),
+        }
+    }
+}
+impl ConstantPoolBuilder {
+    #[must_use]
+    pub fn new() -> ConstantPoolBuilder {
+        ConstantPoolBuilder::default()
+    }
+
+    #[must_use]
+    /// Create a builder starting from the constant pool of the class file, so that the indices
+    /// of its existing entries are kept
+    /// Returns `None` if the constant pool is malformed.
+    pub fn from_class_file(class_file: &ClassFileData) -> Option<ConstantPoolBuilder> {
+        let mut reader = Reader::new(&class_file.class_file_data);
+        // magic, minor, major
+        reader.skip(8)?;
+        ConstantPoolBuilder::read(&mut reader)
+    }
+
+    /// Read a constant pool, with the reader positioned at the constant pool count
+    pub(crate) fn read(reader: &mut Reader<'_>) -> Option<ConstantPoolBuilder> {
+        let count = reader.u16()?;
+        let mut builder = ConstantPoolBuilder::new();
+        while builder.entries.len() < usize::from(count) {
+            let entry = PoolEntry::read(reader)?;
+            // Unlike when adding, existing duplicates are kept so that the indices don't change,
+            // and the first one is what gets reused.
+            let index = builder.next_index();
+            builder.indices.entry(entry.clone()).or_insert(index);
+            let is_wide = entry.is_wide();
+            builder.entries.push(Some(entry));
+            if is_wide {
+                builder.entries.push(None);
+            }
+        }
+
+        // A wide entry at the very end would have gone past the count
+        (builder.entries.len() == usize::from(count)).then_some(builder)
+    }
+
+    #[must_use]
+    /// The `constant_pool_count`, which is one more than the highest index
+    pub fn count(&self) -> u16 {
+        // Adding never goes beyond this
+        #[allow(clippy::cast_possible_truncation)]
+        let count = self.entries.len() as u16;
+        count
+    }
+
+    #[must_use]
+    /// Get the entry at the index
+    /// Returns `None` for unused indices, like 0 and the second index of a `Long` or `Double`.
+    pub fn get(&self, index: u16) -> Option<&PoolEntry> {
+        self.entries.get(usize::from(index))?.as_ref()
+    }
+
+    #[must_use]
+    /// Find the index of an entry which is equal to the given entry
+    pub fn find(&self, entry: &PoolEntry) -> Option<u16> {
+        self.indices.get(entry).copied()
+    }
+
+    /// Iterate over the used indices and their entries, in order
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &PoolEntry)> + '_ {
+        (0..)
+            .zip(self.entries.iter())
+            .filter_map(|(i, entry)| entry.as_ref().map(|entry| (i, entry)))
+    }
+
+    fn next_index(&self) -> u16 {
+        self.count()
+    }
+
+    /// Add the entry, or get the index of an equal entry that is already in the pool
+    pub fn add(&mut self, entry: PoolEntry) -> Result<u16, ConstantPoolBuilderError> {
+        if let Some(index) = self.find(&entry) {
+            return Ok(index);
+        }
+
+        let width = if entry.is_wide() { 2 } else { 1 };
+        // The count is a u16, so the highest index is `u16::MAX - 1`
+        if self.entries.len() + width > usize::from(u16::MAX) {
+            return Err(ConstantPoolBuilderError::Full);
+        }
+
+        let index = self.next_index();
+        self.indices.insert(entry.clone(), index);
+        self.entries.push(Some(entry));
+        if width == 2 {
+            self.entries.push(None);
+        }
+
+        Ok(index)
+    }
+
+    /// Add a `CONSTANT_Utf8` with the text, converting it to modified utf8
+    pub fn add_utf8(&mut self, text: &str) -> Result<u16, ConstantPoolBuilderError> {
+        self.add_utf8_b(&cesu8::to_java_cesu8(text))
+    }
+
+    /// Add a `CONSTANT_Utf8` with text that is already in modified utf8
+    pub fn add_utf8_b(&mut self, text: &[u8]) -> Result<u16, ConstantPoolBuilderError> {
+        if text.len() > usize::from(u16::MAX) {
+            return Err(ConstantPoolBuilderError::TextTooLong(text.len()));
+        }
+
+        self.add(PoolEntry::Utf8(Box::from(text)))
+    }
+
+    pub fn add_integer(&mut self, value: i32) -> Result<u16, ConstantPoolBuilderError> {
+        self.add(PoolEntry::Integer(value))
+    }
+
+    pub fn add_float(&mut self, value: f32) -> Result<u16, ConstantPoolBuilderError> {
+        self.add(PoolEntry::Float(value.to_bits()))
+    }
+
+    pub fn add_long(&mut self, value: i64) -> Result<u16, ConstantPoolBuilderError> {
+        self.add(PoolEntry::Long(value))
+    }
+
+    pub fn add_double(&mut self, value: f64) -> Result<u16, ConstantPoolBuilderError> {
+        self.add(PoolEntry::Double(value.to_bits()))
+    }
+
+    /// Add a `CONSTANT_Class` for the class with the given internal name, like
+    /// `java/lang/String`, or the descriptor of an array class
+    pub fn add_class(&mut self, name: &str) -> Result<u16, ConstantPoolBuilderError> {
+        let name_index = self.add_utf8(name)?;
+        self.add(PoolEntry::Class { name_index })
+    }
+
+    /// Add a `CONSTANT_String` with the text
+    pub fn add_string(&mut self, text: &str) -> Result<u16, ConstantPoolBuilderError> {
+        let string_index = self.add_utf8(text)?;
+        self.add(PoolEntry::String { string_index })
+    }
+
+    pub fn add_name_and_type(
+        &mut self,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolBuilderError> {
+        let name_index = self.add_utf8(name)?;
+        let descriptor_index = self.add_utf8(descriptor)?;
+        self.add(PoolEntry::NameAndType {
+            name_index,
+            descriptor_index,
+        })
+    }
+
+    pub fn add_fieldref(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolBuilderError> {
+        let class_index = self.add_class(class)?;
+        let name_and_type_index = self.add_name_and_type(name, descriptor)?;
+        self.add(PoolEntry::FieldRef {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn add_methodref(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolBuilderError> {
+        let class_index = self.add_class(class)?;
+        let name_and_type_index = self.add_name_and_type(name, descriptor)?;
+        self.add(PoolEntry::MethodRef {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn add_interface_methodref(
+        &mut self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolBuilderError> {
+        let class_index = self.add_class(class)?;
+        let name_and_type_index = self.add_name_and_type(name, descriptor)?;
+        self.add(PoolEntry::InterfaceMethodRef {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    /// Add a `CONSTANT_MethodType` for the method descriptor
+    pub fn add_method_type(&mut self, descriptor: &str) -> Result<u16, ConstantPoolBuilderError> {
+        let descriptor_index = self.add_utf8(descriptor)?;
+        self.add(PoolEntry::MethodType { descriptor_index })
+    }
+
+    /// Add a `CONSTANT_MethodHandle` which refers to the field or method reference at
+    /// `reference_index`
+    pub fn add_method_handle(
+        &mut self,
+        kind: MethodHandleKind,
+        reference_index: u16,
+    ) -> Result<u16, ConstantPoolBuilderError> {
+        self.add(PoolEntry::MethodHandle {
+            reference_kind: kind.reference_kind(),
+            reference_index,
+        })
+    }
+
+    /// Write the `constant_pool_count` followed by the entries
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.extend(self.count().to_be_bytes());
+        for (_, entry) in self.iter() {
+            entry.write(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::class_reader::Reader;
+
+    use super::{ConstantPoolBuilder, PoolEntry};
+
+    #[test]
+    fn test_constant_pool_builder() {
+        let mut pool = ConstantPoolBuilder::new();
+        let name = pool.add_utf8("java/lang/String").unwrap();
+        assert_eq!(name, 1);
+        let class = pool.add_class("java/lang/String").unwrap();
+        assert_eq!(class, 2);
+        // Equal entries are reused
+        assert_eq!(pool.add_class("java/lang/String").unwrap(), class);
+        assert_eq!(pool.add_utf8("java/lang/String").unwrap(), name);
+
+        // Longs and doubles take up two indices
+        let long = pool.add_long(5).unwrap();
+        assert_eq!(long, 3);
+        assert!(pool.get(4).is_none());
+        let method = pool
+            .add_methodref("java/lang/String", "length", "()I")
+            .unwrap();
+        // The name, descriptor, and name-and-type are added first
+        assert_eq!(method, 8);
+        assert_eq!(
+            pool.get(method),
+            Some(&PoolEntry::MethodRef {
+                class_index: class,
+                name_and_type_index: 7
+            })
+        );
+        assert_eq!(pool.add_long(5).unwrap(), long);
+        // Doubles are compared by their bits, so different NaNs are kept separate
+        let nan = pool.add_double(f64::NAN).unwrap();
+        assert_eq!(pool.add_double(f64::NAN).unwrap(), nan);
+        assert_ne!(
+            pool.add_double(-0.0).unwrap(),
+            pool.add_double(0.0).unwrap()
+        );
+        assert_eq!(pool.count(), 15);
+
+        // Text is stored as modified utf8
+        let null = pool.add_utf8("\0").unwrap();
+        assert_eq!(
+            pool.get(null),
+            Some(&PoolEntry::Utf8(Box::from(&b"\xC0\x80"[..])))
+        );
+
+        // Writing then reading gives back the same pool
+        let mut data = Vec::new();
+        pool.write(&mut data);
+        let read = ConstantPoolBuilder::read(&mut Reader::new(&data)).unwrap();
+        assert_eq!(read.count(), pool.count());
+        assert!(read.iter().eq(pool.iter()));
+        assert_eq!(read.find(&PoolEntry::Long(5)), Some(long));
+
+        // A long in the last index would go beyond the count
+        assert!(
+            ConstantPoolBuilder::read(&mut Reader::new(&[0, 2, 5, 0, 0, 0, 0, 0, 0, 0, 0]))
+                .is_none()
+        );
+    }
+}
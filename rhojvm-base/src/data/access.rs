@@ -0,0 +1,224 @@
+//! Access control for the members of classes, as in JVMS 5.4.4
+
+use classfile_parser::{field_info::FieldAccessFlags, method_info::MethodAccessFlags};
+
+use crate::{id::ClassId, package::Packages, StepError};
+
+use super::{class_files::ClassFiles, class_names::ClassNames, classes::Classes};
+
+/// The visibility of a field or method, from its access flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberAccess {
+    Public,
+    Protected,
+    /// No access modifier, so only visible within the same runtime package
+    Package,
+    Private,
+}
+impl From<MethodAccessFlags> for MemberAccess {
+    fn from(flags: MethodAccessFlags) -> Self {
+        if flags.contains(MethodAccessFlags::PUBLIC) {
+            MemberAccess::Public
+        } else if flags.contains(MethodAccessFlags::PROTECTED) {
+            MemberAccess::Protected
+        } else if flags.contains(MethodAccessFlags::PRIVATE) {
+            MemberAccess::Private
+        } else {
+            MemberAccess::Package
+        }
+    }
+}
+impl From<FieldAccessFlags> for MemberAccess {
+    fn from(flags: FieldAccessFlags) -> Self {
+        if flags.contains(FieldAccessFlags::PUBLIC) {
+            MemberAccess::Public
+        } else if flags.contains(FieldAccessFlags::PROTECTED) {
+            MemberAccess::Protected
+        } else if flags.contains(FieldAccessFlags::PRIVATE) {
+            MemberAccess::Private
+        } else {
+            MemberAccess::Package
+        }
+    }
+}
+
+/// A class tried to use a member of another class which it can't access
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{from:?} can not access a {access:?} member of {member_owner:?}")]
+pub struct IllegalAccessError {
+    pub from: ClassId,
+    pub member_owner: ClassId,
+    pub access: MemberAccess,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccessCheckError {
+    #[error("failed to load the classes")]
+    Step(#[source] StepError),
+    #[error(transparent)]
+    IllegalAccess(IllegalAccessError),
+}
+impl From<StepError> for AccessCheckError {
+    fn from(err: StepError) -> Self {
+        Self::Step(err)
+    }
+}
+impl From<IllegalAccessError> for AccessCheckError {
+    fn from(err: IllegalAccessError) -> Self {
+        Self::IllegalAccess(err)
+    }
+}
+
+impl Classes {
+    /// Check whether code in `from` can access a member of `member_owner` with the given access
+    /// - Public members are always accessible
+    /// - Private members are accessible from the same class or its nestmates
+    /// - Protected members are accessible from subclasses and the same runtime package
+    /// - Package-private members are accessible from the same runtime package
+    ///
+    /// This does not check that the owner class itself is accessible, nor the extra restriction
+    /// on the type of the object used to access a protected instance member, which is left to
+    /// the verifier.
+    pub fn can_access(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_files: &mut ClassFiles,
+        packages: &mut Packages,
+        from: ClassId,
+        member_owner: ClassId,
+        access: impl Into<MemberAccess>,
+    ) -> Result<bool, StepError> {
+        let access = access.into();
+        if access == MemberAccess::Public || from == member_owner {
+            return Ok(true);
+        }
+
+        if access == MemberAccess::Private {
+            return Ok(class_files.is_nestmate_of(class_names, from, member_owner)?);
+        }
+
+        self.load_class(class_names, class_files, packages, from)?;
+        self.load_class(class_names, class_files, packages, member_owner)?;
+        let from_package = self
+            .get(&from)
+            .ok_or(StepError::MissingLoadedValue("can_access: from"))?
+            .package();
+        let owner_package = self
+            .get(&member_owner)
+            .ok_or(StepError::MissingLoadedValue("can_access: member_owner"))?
+            .package();
+        // TODO: A runtime package also depends on the class loader, which we don't track yet
+        if from_package == owner_package {
+            return Ok(true);
+        }
+
+        if access == MemberAccess::Protected {
+            return self.is_super_class(class_names, class_files, packages, from, member_owner);
+        }
+
+        Ok(false)
+    }
+
+    /// Like [`Classes::can_access`], but returns an [`IllegalAccessError`] if the member is not
+    /// accessible
+    pub fn check_access(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_files: &mut ClassFiles,
+        packages: &mut Packages,
+        from: ClassId,
+        member_owner: ClassId,
+        access: impl Into<MemberAccess>,
+    ) -> Result<(), AccessCheckError> {
+        let access = access.into();
+        if self.can_access(
+            class_names,
+            class_files,
+            packages,
+            from,
+            member_owner,
+            access,
+        )? {
+            Ok(())
+        } else {
+            Err(IllegalAccessError {
+                from,
+                member_owner,
+                access,
+            }
+            .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        data::{class_files::ClassFiles, class_names::ClassNames, classes::Classes},
+        package::Packages,
+        test_util::{EmptyLoader, TestClass},
+    };
+
+    use super::{AccessCheckError, MemberAccess};
+
+    #[test]
+    fn test_can_access() {
+        let mut class_names = ClassNames::new();
+        let mut class_files = ClassFiles::new(EmptyLoader);
+        let mut classes = Classes::new();
+        let mut packages = Packages::default();
+
+        let object = "java/lang/Object";
+        let mut insert = |this: &str, super_name: Option<&str>| {
+            TestClass::new(this, super_name).insert(&mut class_names, &mut class_files)
+        };
+        insert(object, None);
+        let owner = insert("a/Owner", Some(object));
+        let peer = insert("a/Peer", Some(object));
+        let child = insert("b/Child", Some("a/Owner"));
+        let other = insert("b/Other", Some(object));
+
+        let mut can_access = |from, access| {
+            classes
+                .can_access(
+                    &mut class_names,
+                    &mut class_files,
+                    &mut packages,
+                    from,
+                    owner,
+                    access,
+                )
+                .unwrap()
+        };
+
+        for from in [owner, peer, child, other] {
+            assert!(can_access(from, MemberAccess::Public));
+        }
+
+        assert!(can_access(owner, MemberAccess::Private));
+        assert!(!can_access(peer, MemberAccess::Private));
+
+        assert!(can_access(peer, MemberAccess::Package));
+        assert!(!can_access(child, MemberAccess::Package));
+
+        assert!(can_access(peer, MemberAccess::Protected));
+        assert!(can_access(child, MemberAccess::Protected));
+        assert!(!can_access(other, MemberAccess::Protected));
+
+        let err = classes
+            .check_access(
+                &mut class_names,
+                &mut class_files,
+                &mut packages,
+                other,
+                owner,
+                MemberAccess::Package,
+            )
+            .unwrap_err();
+        let AccessCheckError::IllegalAccess(err) = err else {
+            panic!("Expected an illegal access error, got {:?}", err);
+        };
+        assert_eq!(err.from, other);
+        assert_eq!(err.access, MemberAccess::Package);
+    }
+}
@@ -4,30 +4,55 @@ use crate::{class::ClassFileData, id::ClassId, BadIdError};
 
 use super::class_names::ClassNames;
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum LoadClassFileError {
     /// The path given was empty
+    #[error("empty class path")]
     EmptyPath,
     /// The file didn't exist with the relative path
+    #[error("class file {} does not exist", .0.display())]
     NonexistentFile(PathBuf),
     /// The class didn't exist
+    #[error("class does not exist")]
     Nonexistent,
     /// There was an error in reading the file
-    ReadError(std::io::Error),
+    #[error("failed to read class file")]
+    ReadError(#[source] std::io::Error),
+    /// The data did not start with the class file magic number, so it is not a class file
+    #[error("not a class file, it did not start with 0xCAFEBABE")]
+    NotAClassFile,
     /// There was an error in parsing the class file
+    #[error("failed to parse class file: {0}")]
     ClassFileParseError(String),
     /// There was a bad class file id
+    #[error(transparent)]
     BadId(BadIdError),
-    OpaqueError(Box<dyn Error>),
+    /// The class file's version is newer than what we support
+    #[error(
+        "unsupported class file version {major}.{minor}, the maximum supported version is \
+         {max_major}.{max_minor}"
+    )]
+    UnsupportedClassVersion {
+        major: u16,
+        minor: u16,
+        max_major: u16,
+        max_minor: u16,
+    },
+    /// This is `Send`, so that loading can be done on another thread
+    #[error(transparent)]
+    OpaqueError(Box<dyn Error + Send + Sync>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum LoadResourceError {
     /// The resource did not exist
+    #[error("resource does not exist")]
     Nonexistent,
-    ReadError(std::io::Error),
+    #[error("failed to read resource")]
+    ReadError(#[source] std::io::Error),
+    #[error(transparent)]
     OpaqueError(Box<dyn Error>),
 }
 
@@ -1,17 +1,33 @@
 use std::{collections::HashMap, hash::BuildHasherDefault};
 
+use classfile_parser::ClassFileVersion;
+use smallvec::SmallVec;
+
 use crate::{
-    class::ClassFileData,
+    class::{ClassFileData, SIGNATURE_POLYMORPHIC_CLASSES},
+    class_writer::ClassEditError,
     id::ClassId,
     util::{self},
-    StepError,
+    ClassCircularityError, StepError,
 };
 
 use super::class_file_loader::{ClassFileLoader, LoadClassFileError};
 use super::class_names::ClassNames;
+use super::classes::Classes;
+
+/// The newest class file version that is loaded by default, which is Java 17's, since it is the
+/// newest version whose features ([`crate::class::ClassFileFeature`]) are supported.
+/// Preview class files of that version have a higher minor version, and so are not loaded.
+pub const DEFAULT_MAX_SUPPORTED_VERSION: ClassFileVersion = ClassFileVersion {
+    major: 61,
+    minor: 0,
+};
 
 pub struct ClassFiles {
     pub loader: Box<dyn ClassFileLoader + 'static>,
+    /// Class files with a version above this fail to load with
+    /// [`LoadClassFileError::UnsupportedClassVersion`]
+    pub max_supported_version: ClassFileVersion,
     map: HashMap<
         ClassId,
         ClassFileData,
@@ -24,6 +40,7 @@ impl ClassFiles {
     pub fn new(loader: impl ClassFileLoader + 'static) -> ClassFiles {
         ClassFiles {
             loader: Box::new(loader),
+            max_supported_version: DEFAULT_MAX_SUPPORTED_VERSION,
             map: HashMap::with_hasher(BuildHasherDefault::default()),
         }
     }
@@ -60,6 +77,52 @@ impl ClassFiles {
         }
     }
 
+    /// Rename the loaded class file, moving it to the id of its new name
+    /// This must be done before the class is derived, since the derived class and anything that
+    /// was derived from it would still refer to the old id, and so it is an error if it already
+    /// is in `classes`.
+    /// See [`ClassFileData::set_this_class_name`]
+    pub fn rename(
+        &mut self,
+        classes: &Classes,
+        class_names: &mut ClassNames,
+        class_id: ClassId,
+        name: &str,
+    ) -> Result<ClassId, ClassEditError> {
+        if classes.contains_key(&class_id) {
+            return Err(ClassEditError::AlreadyDerived(class_id));
+        }
+
+        let new_id = class_names.gcid_from_bytes(&cesu8::to_java_cesu8(name));
+        if new_id != class_id && self.map.contains_key(&new_id) {
+            return Err(ClassEditError::AlreadyLoaded(new_id));
+        }
+
+        let mut class_file = self
+            .map
+            .remove(&class_id)
+            .ok_or(ClassEditError::NotLoaded(class_id))?;
+        let result = class_file.set_this_class_name(class_names, name);
+        // If it failed then the class file was left unchanged
+        self.map.insert(class_file.id(), class_file);
+        result
+    }
+
+    fn check_version(&self, class_file: &ClassFileData) -> Result<(), LoadClassFileError> {
+        let max = self.max_supported_version;
+        let version = (class_file.major_version(), class_file.minor_version());
+        if version > (max.major, max.minor) {
+            return Err(LoadClassFileError::UnsupportedClassVersion {
+                major: class_file.major_version(),
+                minor: class_file.minor_version(),
+                max_major: max.major,
+                max_minor: max.minor,
+            });
+        }
+
+        Ok(())
+    }
+
     /// This is primarily for the JVM impl to load classes from user input
     pub fn load_by_class_path_slice<T: AsRef<str>>(
         &mut self,
@@ -81,6 +144,7 @@ impl ClassFiles {
             .loader
             .load_class_file_by_id(class_names, class_file_id)?;
         if let Some(class_file) = class_file {
+            self.check_version(&class_file)?;
             self.set_at(class_file_id, class_file);
         }
 
@@ -93,14 +157,18 @@ impl ClassFiles {
         class_names: &mut ClassNames,
         class_file_id: ClassId,
     ) -> Result<(), LoadClassFileError> {
+        trace_span!("load_class_file", class = class_names.tpath(class_file_id));
         if self.contains_key(&class_file_id) {
+            trace_event!("class file cache hit");
             return Ok(());
         }
+        trace_event!("class file cache miss");
 
         let class_file = self
             .loader
             .load_class_file_by_id(class_names, class_file_id)?;
         if let Some(class_file) = class_file {
+            self.check_version(&class_file)?;
             // If it has a class file, store it,
             // If it doesn't, whatever
             self.set_at(class_file_id, class_file);
@@ -108,11 +176,131 @@ impl ClassFiles {
 
         Ok(())
     }
+
+    /// Determine the host of the nest that the class is in
+    /// A class's claim to be in a nest is only accepted if the host is in the same package and
+    /// lists the class as a member. Otherwise, including if the host fails to load, the class is
+    /// the host of its own nest.
+    pub fn nest_host_of(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_id: ClassId,
+    ) -> Result<ClassId, LoadClassFileError> {
+        self.load_by_class_path_id(class_names, class_id)?;
+        let host_id = match self.get(&class_id) {
+            Some(class_file) => class_file.nest_host(class_names),
+            // Things without class files, like arrays, are not in a nest
+            None => None,
+        };
+        let host_id = match host_id {
+            Some(host_id) if host_id != class_id => host_id,
+            _ => return Ok(class_id),
+        };
+
+        let (class_name, _) = class_names
+            .name_from_gcid(class_id)
+            .map_err(LoadClassFileError::BadId)?;
+        let (host_name, _) = class_names
+            .name_from_gcid(host_id)
+            .map_err(LoadClassFileError::BadId)?;
+        if util::access_path_initial_part(class_name.get())
+            != util::access_path_initial_part(host_name.get())
+        {
+            return Ok(class_id);
+        }
+
+        if self.load_by_class_path_id(class_names, host_id).is_err() {
+            return Ok(class_id);
+        }
+
+        let is_member = self
+            .get(&host_id)
+            .and_then(|host| host.nest_members(class_names))
+            .map_or(false, |members| members.contains(&class_id));
+        Ok(if is_member { host_id } else { class_id })
+    }
+
+    /// Check if the two classes are in the same nest, which lets them access each other's
+    /// private members
+    pub fn is_nestmate_of(
+        &mut self,
+        class_names: &mut ClassNames,
+        a: ClassId,
+        b: ClassId,
+    ) -> Result<bool, LoadClassFileError> {
+        if a == b {
+            return Ok(true);
+        }
+
+        Ok(self.nest_host_of(class_names, a)? == self.nest_host_of(class_names, b)?)
+    }
+
+    /// Check if `sub` is allowed to directly extend or implement `sealed_super`
+    /// This is always true if `sealed_super` is not sealed. Otherwise, `sub` must be listed in its
+    /// permitted subclasses and, since we don't model modules, be in the same package.
+    /// A sealed class with a malformed `PermittedSubclasses` attribute permits nothing.
+    pub fn may_extend(
+        &mut self,
+        class_names: &mut ClassNames,
+        sub: ClassId,
+        sealed_super: ClassId,
+    ) -> Result<bool, LoadClassFileError> {
+        self.load_by_class_path_id(class_names, sealed_super)?;
+        let permitted = match self.get(&sealed_super) {
+            Some(super_file) if super_file.is_sealed() => {
+                super_file.permitted_subclasses(class_names)
+            }
+            _ => return Ok(true),
+        };
+
+        if !permitted.map_or(false, |permitted| permitted.contains(&sub)) {
+            return Ok(false);
+        }
+
+        let (sub_name, _) = class_names
+            .name_from_gcid(sub)
+            .map_err(LoadClassFileError::BadId)?;
+        let (super_name, _) = class_names
+            .name_from_gcid(sealed_super)
+            .map_err(LoadClassFileError::BadId)?;
+        Ok(util::access_path_initial_part(sub_name.get())
+            == util::access_path_initial_part(super_name.get()))
+    }
+
+    /// Check if `owner` declares a signature polymorphic method with the given name, like
+    /// `java/lang/invoke/MethodHandle.invokeExact`
+    /// See [`ClassFileData::is_signature_polymorphic`]
+    pub fn is_signature_polymorphic(
+        &mut self,
+        class_names: &mut ClassNames,
+        owner: ClassId,
+        name: &str,
+    ) -> Result<bool, LoadClassFileError> {
+        // Avoid loading the class file for the vast majority of classes
+        let (owner_name, _) = class_names
+            .name_from_gcid(owner)
+            .map_err(LoadClassFileError::BadId)?;
+        if !SIGNATURE_POLYMORPHIC_CLASSES.contains(&owner_name.get()) {
+            return Ok(false);
+        }
+
+        self.load_by_class_path_id(class_names, owner)?;
+        let class_file = self.get(&owner).ok_or(LoadClassFileError::Nonexistent)?;
+        let is_signature_polymorphic =
+            class_file
+                .load_method_info_opt_iter_with_index()
+                .any(|(index, method)| {
+                    class_file.get_text_b(method.name_index) == Some(name.as_bytes())
+                        && class_file.is_signature_polymorphic(index)
+                });
+        Ok(is_signature_polymorphic)
+    }
 }
 impl std::fmt::Debug for ClassFiles {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ClassFiles")
             .field("loader", &"(unprintable)")
+            .field("max_supported_version", &self.max_supported_version)
             .field("map", &self.map)
             .finish()
     }
@@ -128,6 +316,9 @@ pub fn load_super_class_files_iter(class_file_id: ClassId) -> SuperClassFileIter
 pub struct SuperClassFileIterator {
     topmost: Option<Result<ClassId, StepError>>,
     had_error: bool,
+    /// The classes that have been walked so far, so that a circular hierarchy from a malformed
+    /// class file is reported rather than looping forever
+    visited: SmallVec<[ClassId; 8]>,
 }
 impl SuperClassFileIterator {
     /// Construct the iterator, doing basic processing
@@ -135,6 +326,7 @@ impl SuperClassFileIterator {
         SuperClassFileIterator {
             topmost: Some(Ok(base_class_id)),
             had_error: false,
+            visited: SmallVec::new(),
         }
     }
 
@@ -165,6 +357,11 @@ impl SuperClassFileIterator {
             None => return None,
         };
 
+        if let Err(err) = ClassCircularityError::visit(&mut self.visited, topmost) {
+            self.had_error = true;
+            return Some(Err(err.into()));
+        }
+
         // Load the class file by the id
         if let Err(err) = class_files.load_by_class_path_id(class_names, topmost) {
             self.had_error = true;
@@ -179,7 +376,10 @@ impl SuperClassFileIterator {
                 return Some(Err(StepError::BadId(err)));
             }
         };
-        if has_class_file {
+        if class_names.is_object(topmost) {
+            // Object is the root, so there is nothing further up the chain
+            Some(Ok(topmost))
+        } else if has_class_file {
             // We just loaded it
             let class_file = class_files.get(&topmost).unwrap();
 
@@ -202,3 +402,163 @@ impl SuperClassFileIterator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use classfile_parser::ClassFileVersion;
+
+    use crate::{
+        class_writer::ClassEditError,
+        data::{class_file_loader::LoadClassFileError, class_names::ClassNames, classes::Classes},
+        package::Packages,
+        test_util::{test_class, EmptyLoader, MemoryLoader, TestClass},
+        StepError,
+    };
+
+    use super::{load_super_class_files_iter, ClassFiles};
+
+    #[test]
+    fn test_circular_super_classes() {
+        let mut class_names = ClassNames::new();
+        let mut class_files = ClassFiles::new(EmptyLoader);
+
+        let a_id = TestClass::new("a/A", Some("a/B")).insert(&mut class_names, &mut class_files);
+        let b_id = TestClass::new("a/B", Some("a/A")).insert(&mut class_names, &mut class_files);
+
+        let mut iter = load_super_class_files_iter(a_id);
+        let mut walked = Vec::new();
+        let err = loop {
+            match iter.next_item(&mut class_names, &mut class_files) {
+                Some(Ok(id)) => walked.push(id),
+                Some(Err(err)) => break err,
+                None => panic!("Expected the circular hierarchy to be an error"),
+            }
+        };
+        assert_eq!(walked, [a_id, b_id]);
+        let StepError::ClassCircularity(err) = err else {
+            panic!("Expected a class circularity error, got {:?}", err);
+        };
+        assert_eq!(err.classes, [a_id, b_id]);
+        assert!(iter.next_item(&mut class_names, &mut class_files).is_none());
+    }
+
+    #[test]
+    fn test_signature_polymorphic() {
+        const POLYMORPHIC: u16 = 0x0001 | 0x0010 | 0x0080 | 0x0100;
+        const DESCRIPTOR: &str = "([Ljava/lang/Object;)Ljava/lang/Object;";
+        let handle = |name| {
+            TestClass::new(name, Some("java/lang/Object"))
+                .access(0x0421)
+                .method(POLYMORPHIC, "invokeExact", DESCRIPTOR)
+                // Not native
+                .method(0x0081, "invokeWithArguments", DESCRIPTOR)
+        };
+
+        let mut class_names = ClassNames::new();
+        let mut class_files = ClassFiles::new(EmptyLoader);
+        let handle_id =
+            handle("java/lang/invoke/MethodHandle").insert(&mut class_names, &mut class_files);
+        let other_id = handle("a/MethodHandle").insert(&mut class_names, &mut class_files);
+        let mut is_polymorphic = |owner, name| {
+            class_files
+                .is_signature_polymorphic(&mut class_names, owner, name)
+                .unwrap()
+        };
+
+        assert!(is_polymorphic(handle_id, "invokeExact"));
+        assert!(!is_polymorphic(handle_id, "invokeWithArguments"));
+        assert!(!is_polymorphic(handle_id, "invoke"));
+        // Only the methods of `MethodHandle` and `VarHandle` are signature polymorphic
+        assert!(!is_polymorphic(other_id, "invokeExact"));
+
+        // Other classes aren't loaded to check, so one that doesn't exist isn't an error
+        let missing_id = class_names.gcid_from_bytes(b"a/Missing");
+        assert!(!class_files
+            .is_signature_polymorphic(&mut class_names, missing_id, "invokeExact")
+            .unwrap());
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut class_names = ClassNames::new();
+        let mut class_files = ClassFiles::new(EmptyLoader);
+        let mut classes = Classes::new();
+        let mut packages = Packages::default();
+
+        let id = TestClass::new("a/A", Some("java/lang/Object"))
+            .insert(&mut class_names, &mut class_files);
+        let taken_id = TestClass::new("a/Taken", Some("java/lang/Object"))
+            .insert(&mut class_names, &mut class_files);
+
+        let err = class_files
+            .rename(&classes, &mut class_names, id, "a/Taken")
+            .unwrap_err();
+        assert!(matches!(err, ClassEditError::AlreadyLoaded(err_id) if err_id == taken_id));
+
+        let new_id = class_files
+            .rename(&classes, &mut class_names, id, "a/Generated")
+            .unwrap();
+        assert!(!class_files.contains_key(&id));
+        assert_eq!(class_files.get(&new_id).unwrap().id(), new_id);
+
+        classes
+            .load_class(&mut class_names, &mut class_files, &mut packages, new_id)
+            .unwrap();
+        let err = class_files
+            .rename(&classes, &mut class_names, new_id, "a/Other")
+            .unwrap_err();
+        assert!(matches!(err, ClassEditError::AlreadyDerived(err_id) if err_id == new_id));
+        assert!(class_files.contains_key(&new_id));
+    }
+
+    #[test]
+    fn test_unsupported_class_version() {
+        let mut current = test_class("a/Current");
+        current.major_version = 61;
+        let mut preview = test_class("a/Preview");
+        preview.major_version = 61;
+        preview.minor_version = 0xFFFF;
+        let mut newer = test_class("a/Newer");
+        newer.major_version = 62;
+
+        let mut class_names = ClassNames::new();
+        let mut class_files = ClassFiles::new(MemoryLoader::new(&[
+            ("a/Current", &current),
+            ("a/Preview", &preview),
+            ("a/Newer", &newer),
+        ]));
+        let current_id = class_names.gcid_from_bytes(b"a/Current");
+        let preview_id = class_names.gcid_from_bytes(b"a/Preview");
+        let newer_id = class_names.gcid_from_bytes(b"a/Newer");
+
+        class_files
+            .load_by_class_path_id(&mut class_names, current_id)
+            .unwrap();
+        for (id, major, minor) in [(preview_id, 61, 0xFFFF), (newer_id, 62, 0)] {
+            let err = class_files
+                .load_by_class_path_id(&mut class_names, id)
+                .unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    LoadClassFileError::UnsupportedClassVersion {
+                        major: err_major,
+                        minor: err_minor,
+                        max_major: 61,
+                        max_minor: 0,
+                    } if err_major == major && err_minor == minor
+                ),
+                "{err:?}"
+            );
+            assert!(class_files.get(&id).is_none());
+        }
+
+        class_files.max_supported_version = ClassFileVersion {
+            major: 62,
+            minor: 0,
+        };
+        class_files
+            .load_by_class_path_id(&mut class_names, newer_id)
+            .unwrap();
+    }
+}
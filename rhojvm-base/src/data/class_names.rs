@@ -2,10 +2,12 @@ use std::borrow::Cow;
 use std::hash::{Hash, Hasher};
 use std::num::NonZeroUsize;
 use std::sync::atomic::{self, AtomicU32};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use indexmap::{Equivalent, IndexMap};
 
 use crate::{
+    class::ArrayComponentType,
     code::{method::DescriptorTypeBasic, types::PrimitiveType},
     id::{self, ClassId},
     util::{self},
@@ -15,6 +17,8 @@ use crate::{
 #[derive(Debug, Clone)]
 pub(crate) enum InternalKind {
     Array,
+    /// The pseudo-class for a primitive type or void, like `int.class`
+    Primitive,
 }
 impl InternalKind {
     fn from_slice<T: AsRef<str>>(class_path: &[T]) -> Option<InternalKind> {
@@ -31,6 +35,8 @@ impl InternalKind {
     fn from_bytes(class_path: &[u8]) -> Option<InternalKind> {
         if id::is_array_class_bytes(class_path) {
             Some(InternalKind::Array)
+        } else if class_path.starts_with(&[PRIMITIVE_CLASS_PREFIX]) {
+            Some(InternalKind::Primitive)
         } else {
             None
         }
@@ -43,6 +49,8 @@ impl InternalKind {
     fn from_str(class_path: &str) -> Option<InternalKind> {
         if id::is_array_class(class_path) {
             Some(InternalKind::Array)
+        } else if class_path.as_bytes().starts_with(&[PRIMITIVE_CLASS_PREFIX]) {
+            Some(InternalKind::Primitive)
         } else {
             None
         }
@@ -53,6 +61,25 @@ impl InternalKind {
     }
 }
 
+/// The names of the primitive pseudo-classes start with this, which is not allowed in any class
+/// name, so that they can never collide with a real class
+pub const PRIMITIVE_CLASS_PREFIX: u8 = b';';
+
+/// The first id of the primitive pseudo-classes, which are reserved right after Object
+const FIRST_PRIMITIVE_ID: u32 = 2;
+/// The primitive pseudo-classes in the order that their ids are reserved
+const PRIMITIVE_CLASSES: [(PrimitiveType, &[u8]); 8] = [
+    (PrimitiveType::Boolean, b";boolean"),
+    (PrimitiveType::Char, b";char"),
+    (PrimitiveType::Byte, b";byte"),
+    (PrimitiveType::Short, b";short"),
+    (PrimitiveType::Int, b";int"),
+    (PrimitiveType::Long, b";long"),
+    (PrimitiveType::Float, b";float"),
+    (PrimitiveType::Double, b";double"),
+];
+const VOID_CLASS_NAME: &[u8] = b";void";
+
 /// An insert into [`ClassNames`] that is trusted, aka it has all the right values
 /// and is computed to be inserted when we have issues getting borrowing right.
 /// The variants are private
@@ -233,6 +260,11 @@ pub struct ClassNameInfo {
     id: ClassId,
 }
 impl ClassNameInfo {
+    #[must_use]
+    pub fn id(&self) -> ClassId {
+        self.id
+    }
+
     #[must_use]
     pub fn has_class_file(&self) -> bool {
         if let Some(kind) = &self.kind {
@@ -246,26 +278,123 @@ impl ClassNameInfo {
     pub fn is_array(&self) -> bool {
         matches!(self.kind, Some(InternalKind::Array))
     }
+
+    #[must_use]
+    /// Whether this is the pseudo-class of a primitive type or of void
+    pub fn is_primitive(&self) -> bool {
+        matches!(self.kind, Some(InternalKind::Primitive))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NameError {
+    #[error("class name was empty")]
+    Empty,
+    #[error("class name has an empty segment at byte {index}")]
+    EmptySegment { index: usize },
+    #[error("class name has an invalid character {byte:?} at byte {index}")]
+    InvalidCharacter { index: usize, byte: char },
+    #[error("array class name has {0} dimensions, but at most 255 are allowed")]
+    TooManyDimensions(usize),
+    #[error("array class name does not have a valid component type")]
+    InvalidArrayComponent,
+}
+
+/// Check that the name is a valid internal class name, like `java/lang/String`, or a valid array
+/// class name, like `[[I` or `[Ljava/lang/String;`.
+/// This catches passing a binary name (`java.lang.String`) where an internal name is expected.
+pub fn validate_internal_name(name: &[u8]) -> Result<(), NameError> {
+    match name.first() {
+        None => Err(NameError::Empty),
+        Some(b'[') => validate_array_name(name),
+        Some(_) => validate_class_name(name, 0),
+    }
+}
+
+/// Validate a non-array class name, with `offset` being where it starts in the full name, for
+/// errors
+fn validate_class_name(name: &[u8], offset: usize) -> Result<(), NameError> {
+    let mut segment_start = 0;
+    for (i, &byte) in name.iter().enumerate() {
+        match byte {
+            b'/' => {
+                if i == segment_start {
+                    return Err(NameError::EmptySegment { index: offset + i });
+                }
+                segment_start = i + 1;
+            }
+            b'.' | b';' | b'[' => {
+                return Err(NameError::InvalidCharacter {
+                    index: offset + i,
+                    byte: char::from(byte),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    if segment_start == name.len() {
+        return Err(NameError::EmptySegment {
+            index: offset + name.len(),
+        });
+    }
+
+    Ok(())
+}
+
+fn validate_array_name(name: &[u8]) -> Result<(), NameError> {
+    let dimensions = name.iter().take_while(|x| **x == b'[').count();
+    if dimensions > 255 {
+        return Err(NameError::TooManyDimensions(dimensions));
+    }
+
+    let component = &name[dimensions..];
+    match component {
+        [b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z'] => Ok(()),
+        [b'L', class_name @ .., b';'] if !class_name.is_empty() => {
+            validate_class_name(class_name, dimensions + 1)
+        }
+        _ => Err(NameError::InvalidArrayComponent),
+    }
 }
 
 #[derive(Debug)]
 pub struct ClassNames {
     next_id: AtomicU32,
     names: IndexMap<RawClassName, ClassNameInfo>,
+    /// Whether names given to the registration functions are checked to be valid
+    strict: bool,
 }
 impl ClassNames {
     #[must_use]
     pub fn new() -> Self {
+        // TODO: We could probably choose a better and more accurate default
+        // For a basic program, it might fit under this limit
+        Self::with_capacity(32)
+    }
+
+    #[must_use]
+    /// Create with space for `capacity` class names without reallocating
+    /// There is one entry per class, including array classes, so a rough guideline is the number
+    /// of classes that you expect to load. The JDK's standard library is tens of thousands.
+    pub fn with_capacity(capacity: usize) -> Self {
         let mut class_names = ClassNames {
-            next_id: AtomicU32::new(0),
-            // TODO: We could probably choose a better and more accurate default
-            // For a basic program, it might fit under this limit
-            names: IndexMap::with_capacity(32),
+            // Ids start at 1, since 0 is never a valid id
+            next_id: AtomicU32::new(1),
+            names: IndexMap::with_capacity(capacity),
+            strict: false,
         };
 
-        // Reserve the first id, 0, so it is always for Object
+        // Reserve the first id, 1, so it is always for Object
         class_names.gcid_from_bytes(b"java/lang/Object");
 
+        // Reserve the ids after that for the primitive pseudo-classes, so that they can be
+        // computed without a lookup
+        for (_, name) in PRIMITIVE_CLASSES {
+            class_names.gcid_from_bytes(name);
+        }
+        class_names.gcid_from_bytes(VOID_CLASS_NAME);
+
         class_names
     }
 
@@ -276,10 +405,101 @@ impl ClassNames {
         ClassId::new_unchecked(self.next_id.fetch_add(1, atomic::Ordering::Relaxed))
     }
 
+    #[must_use]
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Set whether this is in strict mode.
+    /// In strict mode, registering a new malformed name through functions like
+    /// [`ClassNames::gcid_from_bytes`] panics, rather than silently registering a bad entry. This
+    /// is meant for catching mistakes, such as using binary names where internal names are
+    /// expected. See [`ClassNames::try_gcid_from_bytes`] for handling untrusted names.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Panic if we're in strict mode and the name is invalid
+    fn check_strict(&self, class_path: &[u8]) {
+        if self.strict {
+            if let Err(err) = validate_internal_name(class_path) {
+                panic!(
+                    "Registered malformed class name {:?}: {}",
+                    util::Cesu8Str(class_path),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Reserve space for at least `additional` more class names
+    pub fn reserve(&mut self, additional: usize) {
+        self.names.reserve(additional);
+    }
+
+    #[must_use]
+    /// The number of registered class names
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Iterate over all of the registered class names, in the order that they were registered
+    pub fn iter(&self) -> impl Iterator<Item = (ClassId, RawClassNameSlice<'_>, &ClassNameInfo)> {
+        self.names
+            .iter()
+            .map(|(name, info)| (info.id, name.as_slice(), info))
+    }
+
     /// Get the id of `b"java/lang/Object"`. Cached.
     #[must_use]
     pub fn object_id(&self) -> ClassId {
-        ClassId::new_unchecked(0)
+        ClassId::new_unchecked(1)
+    }
+
+    /// Get the id of the pseudo-class for the primitive type, like `int.class`
+    /// The unsigned types are treated as their signed equivalents.
+    #[must_use]
+    pub fn primitive_id(&self, prim: PrimitiveType) -> ClassId {
+        // Matches the order of `PRIMITIVE_CLASSES`
+        let index = match prim {
+            PrimitiveType::Boolean => 0,
+            PrimitiveType::Char => 1,
+            PrimitiveType::Byte | PrimitiveType::UnsignedByte => 2,
+            PrimitiveType::Short | PrimitiveType::UnsignedShort => 3,
+            PrimitiveType::Int => 4,
+            PrimitiveType::Long => 5,
+            PrimitiveType::Float => 6,
+            PrimitiveType::Double => 7,
+        };
+        ClassId::new_unchecked(FIRST_PRIMITIVE_ID + index)
+    }
+
+    /// Get the id of the pseudo-class for void, `void.class`
+    #[must_use]
+    pub fn void_id(&self) -> ClassId {
+        // It is reserved directly after the eight primitives
+        ClassId::new_unchecked(FIRST_PRIMITIVE_ID + 8)
+    }
+
+    /// Get the primitive type that the id is the pseudo-class of
+    /// Returns `None` for `void`, since it is not a primitive type.
+    #[must_use]
+    pub fn as_primitive(&self, id: ClassId) -> Option<PrimitiveType> {
+        let index = id.get().checked_sub(FIRST_PRIMITIVE_ID)?;
+        PRIMITIVE_CLASSES
+            .get(usize::try_from(index).ok()?)
+            .map(|(prim, _)| *prim)
+    }
+
+    /// Check if the given id is for `java/lang/Object`, the root of every class hierarchy
+    #[must_use]
+    pub fn is_object(&self, id: ClassId) -> bool {
+        id == self.object_id()
     }
 
     /// Check if the given id is for an array
@@ -303,6 +523,17 @@ impl ClassNames {
             })
     }
 
+    /// Get the id for the name, registering it if it is new, but only if it is a valid internal
+    /// name or array class name. This always validates, regardless of strict mode.
+    pub fn try_gcid_from_bytes(&mut self, class_path: &[u8]) -> Result<ClassId, NameError> {
+        if let Some(entry) = self.names.get(&RawClassNameSlice(class_path)) {
+            return Ok(entry.id);
+        }
+
+        validate_internal_name(class_path)?;
+        Ok(self.gcid_from_bytes(class_path))
+    }
+
     pub fn gcid_from_bytes(&mut self, class_path: &[u8]) -> ClassId {
         let class_path = RawClassNameSlice(class_path);
         let kind = InternalKind::from_raw_class_name(class_path);
@@ -311,6 +542,7 @@ impl ClassNames {
             return entry.id;
         }
 
+        self.check_strict(class_path.get());
         let id = self.get_new_id();
         self.names
             .insert(class_path.to_owned(), ClassNameInfo { kind, id });
@@ -325,6 +557,7 @@ impl ClassNames {
             return entry.id;
         }
 
+        self.check_strict(class_path.get());
         let id = self.get_new_id();
         self.names.insert(class_path, ClassNameInfo { kind, id });
         id
@@ -338,6 +571,7 @@ impl ClassNames {
             return entry.id;
         }
 
+        self.check_strict(&class_path);
         let id = self.get_new_id();
         self.names.insert(
             RawClassName(class_path.into_owned()),
@@ -357,12 +591,24 @@ impl ClassNames {
             return entry.id;
         }
 
+        let class_path = class_path.into_raw_class_name();
+        self.check_strict(class_path.get());
         let id = self.get_new_id();
-        self.names
-            .insert(class_path.into_raw_class_name(), ClassNameInfo { kind, id });
+        self.names.insert(class_path, ClassNameInfo { kind, id });
         id
     }
 
+    /// Get the id of the array class with the given component type, registering it if needed
+    /// This keeps the name and the id in sync, unlike constructing the id separately.
+    pub fn get_or_create_array_class(
+        &mut self,
+        component: ArrayComponentType,
+    ) -> Result<ClassId, BadIdError> {
+        let mut class_path = vec![b'['];
+        class_path.extend(component.to_desc_string(self)?);
+        Ok(self.gcid_from_vec(class_path))
+    }
+
     pub fn gcid_from_array_of_primitives(&mut self, prim: PrimitiveType) -> ClassId {
         let prefix = prim.as_desc_prefix();
         let class_path = [b"[", prefix];
@@ -515,6 +761,28 @@ impl ClassNames {
         }
     }
 
+    #[must_use]
+    /// Estimate the memory used by the registry
+    /// This is not exact, since it does not know the allocator's overhead or the exact layout of
+    /// the map, but it is a reasonable estimate for judging the footprint of loading many classes.
+    pub fn memory_usage(&self) -> ClassNamesMemoryStats {
+        let name_bytes = self.names.keys().map(|name| name.0.capacity()).sum();
+
+        let capacity = self.names.capacity();
+        // Each entry stores the key, the value, and the hash
+        let entry_size = std::mem::size_of::<(RawClassName, ClassNameInfo, u64)>();
+        // The hash table stores an index into the entries and a control byte for each slot, and
+        // keeps some slots free so that it doesn't get too full
+        let slot_size = std::mem::size_of::<usize>() + 1;
+        let slots = capacity.saturating_mul(8) / 7;
+
+        ClassNamesMemoryStats {
+            name_bytes,
+            id_to_name_bytes: capacity * entry_size,
+            name_to_id_bytes: slots * slot_size,
+        }
+    }
+
     /// Get the information in a nice representation for logging
     /// The output of this function is not guaranteed
     #[must_use]
@@ -529,8 +797,240 @@ impl ClassNames {
     }
 }
 
+/// An estimate of the memory used by a [`ClassNames`], in bytes
+/// See [`ClassNames::memory_usage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassNamesMemoryStats {
+    /// The allocated capacity of the name strings themselves
+    pub name_bytes: usize,
+    /// The entries, in order of registration, that are searched to go from an id to its name
+    pub id_to_name_bytes: usize,
+    /// The hash index used to go from a name to its id
+    pub name_to_id_bytes: usize,
+}
+impl ClassNamesMemoryStats {
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.name_bytes + self.id_to_name_bytes + self.name_to_id_bytes
+    }
+}
+
+/// The state of a [`ClassNames`] instance, for storing it and restoring it later
+/// Restoring it gives back the same ids for the same names, so serialized [`ClassId`]s stay valid.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ClassNamesSnapshot {
+    next_id: u32,
+    /// The names in order of insertion, with their ids
+    names: Vec<(Vec<u8>, ClassId)>,
+}
+
+#[cfg(feature = "serde")]
+impl ClassNames {
+    #[must_use]
+    pub fn snapshot(&self) -> ClassNamesSnapshot {
+        ClassNamesSnapshot {
+            next_id: self.next_id.load(atomic::Ordering::Relaxed),
+            names: self
+                .names
+                .iter()
+                .map(|(name, info)| (name.get().to_vec(), info.id))
+                .collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn from_snapshot(snapshot: ClassNamesSnapshot) -> ClassNames {
+        let mut names = IndexMap::with_capacity(snapshot.names.len());
+        for (name, id) in snapshot.names {
+            let name = RawClassName(name);
+            let kind = InternalKind::from_raw_class_name(name.as_slice());
+            names.insert(name, ClassNameInfo { kind, id });
+        }
+
+        ClassNames {
+            next_id: AtomicU32::new(snapshot.next_id),
+            names,
+            strict: false,
+        }
+    }
+}
+
 impl Default for ClassNames {
     fn default() -> Self {
         Self::new()
     }
 }
+
+/// A [`ClassNames`] which can be shared between threads, so that classes can be loaded
+/// concurrently
+/// Lookups of already registered names only take a read lock. Registering a new name takes the
+/// write lock and checks again, so two threads registering the same name get the same id.
+#[derive(Debug, Default)]
+pub struct SyncClassNames {
+    inner: RwLock<ClassNames>,
+}
+impl SyncClassNames {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::from(ClassNames::new())
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> ClassNames {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Lock the registry for reading, such as for iterating over it
+    /// The names are only ever added to, so a panic while holding the lock can't leave them in
+    /// an inconsistent state, and so poisoning is ignored.
+    pub fn read(&self) -> RwLockReadGuard<'_, ClassNames> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Lock the registry for writing
+    pub fn write(&self) -> RwLockWriteGuard<'_, ClassNames> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    #[must_use]
+    pub fn object_id(&self) -> ClassId {
+        self.read().object_id()
+    }
+
+    pub fn is_array(&self, id: ClassId) -> Result<bool, BadIdError> {
+        self.read().is_array(id)
+    }
+
+    /// Get the name and class info for a given id
+    /// These are cloned, since they can't be borrowed past the lock.
+    pub fn name_from_gcid(&self, id: ClassId) -> Result<(RawClassName, ClassNameInfo), BadIdError> {
+        self.read()
+            .name_from_gcid(id)
+            .map(|(name, info)| (name.to_owned(), info.clone()))
+    }
+
+    /// Get the id of an already registered name, without registering it
+    #[must_use]
+    pub fn get_gcid(&self, class_path: &[u8]) -> Option<ClassId> {
+        self.read()
+            .names
+            .get(&RawClassNameSlice(class_path))
+            .map(|info| info.id)
+    }
+
+    pub fn gcid_from_bytes(&self, class_path: &[u8]) -> ClassId {
+        if let Some(id) = self.get_gcid(class_path) {
+            return id;
+        }
+
+        // This checks for the name again, in case another thread registered it in between
+        self.write().gcid_from_bytes(class_path)
+    }
+
+    pub fn gcid_from_str(&self, class_path: &str) -> ClassId {
+        self.gcid_from_bytes(class_path.as_bytes())
+    }
+
+    /// See [`ClassNames::try_gcid_from_bytes`]
+    pub fn try_gcid_from_bytes(&self, class_path: &[u8]) -> Result<ClassId, NameError> {
+        if let Some(id) = self.get_gcid(class_path) {
+            return Ok(id);
+        }
+
+        self.write().try_gcid_from_bytes(class_path)
+    }
+
+    pub fn gcid_from_array_of_primitives(&self, prim: PrimitiveType) -> ClassId {
+        self.write().gcid_from_array_of_primitives(prim)
+    }
+
+    pub fn get_or_create_array_class(
+        &self,
+        component: ArrayComponentType,
+    ) -> Result<ClassId, BadIdError> {
+        self.write().get_or_create_array_class(component)
+    }
+}
+impl From<ClassNames> for SyncClassNames {
+    fn from(class_names: ClassNames) -> Self {
+        SyncClassNames {
+            inner: RwLock::new(class_names),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{ClassNames, SyncClassNames};
+
+    #[test]
+    fn test_memory_usage() {
+        let mut class_names = ClassNames::new();
+        let before = class_names.memory_usage();
+        assert!(before.name_bytes >= b"java/lang/Object".len());
+
+        for i in 0..100 {
+            class_names.gcid_from_vec(format!("a/b/Class{}", i).into_bytes());
+        }
+        let after = class_names.memory_usage();
+        assert!(after.name_bytes > before.name_bytes);
+        assert!(after.id_to_name_bytes > before.id_to_name_bytes);
+        assert!(after.name_to_id_bytes > before.name_to_id_bytes);
+        assert_eq!(
+            after.total(),
+            after.name_bytes + after.id_to_name_bytes + after.name_to_id_bytes
+        );
+    }
+
+    #[test]
+    fn test_sync_class_names() {
+        let class_names = Arc::new(SyncClassNames::new());
+        let object_id = class_names.object_id();
+        assert_eq!(class_names.gcid_from_str("java/lang/Object"), object_id);
+        let initial_len = class_names.len();
+
+        let names = ["java/lang/String", "java/util/List", "[I", "a/b/C"];
+        let threads = (0..4)
+            .map(|_| {
+                let class_names = Arc::clone(&class_names);
+                std::thread::spawn(move || {
+                    names
+                        .iter()
+                        .map(|name| class_names.gcid_from_str(name))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let ids = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>();
+
+        // Every thread got the same id for each name, and each name only got one id
+        assert!(ids.iter().all(|x| *x == ids[0]));
+        for (name, id) in names.iter().zip(&ids[0]) {
+            assert_eq!(class_names.get_gcid(name.as_bytes()), Some(*id));
+            assert_eq!(
+                class_names.name_from_gcid(*id).unwrap().0.get(),
+                name.as_bytes()
+            );
+        }
+        assert_eq!(class_names.len(), initial_len + names.len());
+        assert!(class_names.is_array(ids[0][2]).unwrap());
+    }
+}
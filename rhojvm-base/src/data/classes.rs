@@ -3,20 +3,22 @@ use std::{collections::HashMap, hash::BuildHasherDefault, num::NonZeroUsize};
 use classfile_parser::{
     constant_info::{ClassConstant, Utf8Constant},
     constant_pool::ConstantPoolIndexRaw,
+    method_info::MethodAccessFlags,
     ClassAccessFlags,
 };
 use smallvec::SmallVec;
 
 use crate::{
     class::{ArrayClass, ArrayComponentType, Class, ClassFileIndexError, ClassVariant},
+    class_writer::{ClassEditError, CodeParts},
     code::{
         method::{DescriptorType, DescriptorTypeBasic},
         types::PrimitiveType,
     },
-    id::ClassId,
+    id::{ClassId, MethodId, MethodIndex},
     package::Packages,
     util::{self},
-    BadIdError, StepError,
+    BadIdError, ClassCircularityError, StepError,
 };
 
 use super::{
@@ -25,17 +27,37 @@ use super::{
     class_names::ClassNames,
 };
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum LoadClassError {
+    #[error(transparent)]
     BadId(BadIdError),
-    LoadClassFile(LoadClassFileError),
-    ClassFileIndex(ClassFileIndexError),
+    #[error("failed to load class file")]
+    LoadClassFile(#[source] LoadClassFileError),
+    #[error("class file index error")]
+    ClassFileIndex(#[source] ClassFileIndexError),
     /// An invalid index into the constant pool for an interface
+    #[error("invalid interface index: {}", .0.0)]
     BadInterfaceIndex(ConstantPoolIndexRaw<ClassConstant>),
     /// An invalid index for an interface's name into the constant pool
+    #[error("invalid interface name index: {}", .0.0)]
     BadInterfaceNameIndex(ConstantPoolIndexRaw<Utf8Constant>),
 }
+impl LoadClassError {
+    /// Convert an error from resolving an interface's class constant into the more specific
+    /// interface errors
+    fn from_interface_index_error(err: ClassFileIndexError) -> LoadClassError {
+        match err {
+            ClassFileIndexError::InvalidClassIndex(index) => {
+                LoadClassError::BadInterfaceIndex(index)
+            }
+            ClassFileIndexError::InvalidClassNameIndex(index) => {
+                LoadClassError::BadInterfaceNameIndex(index)
+            }
+            err => LoadClassError::ClassFileIndex(err),
+        }
+    }
+}
 impl From<ClassFileIndexError> for LoadClassError {
     fn from(err: ClassFileIndexError) -> Self {
         Self::ClassFileIndex(err)
@@ -67,6 +89,30 @@ impl Classes {
         self.map.len()
     }
 
+    /// Add a method to the class file of the class, keeping the method count of the class in sync
+    /// if it has already been loaded
+    /// See [`crate::class::ClassFileData::add_method`]
+    pub fn add_method(
+        &mut self,
+        class_files: &mut ClassFiles,
+        class_id: ClassId,
+        name: &str,
+        descriptor: &str,
+        access: MethodAccessFlags,
+        code: Option<&CodeParts>,
+    ) -> Result<MethodIndex, ClassEditError> {
+        let class_file = class_files
+            .get_mut(&class_id)
+            .ok_or(ClassEditError::NotLoaded(class_id))?;
+        let index = class_file.add_method(name, descriptor, access, code)?;
+
+        if let Some(ClassVariant::Class(class)) = self.map.get_mut(&class_id) {
+            class.len_method_idx = class_file.methods_len();
+        }
+
+        Ok(index)
+    }
+
     #[must_use]
     pub fn is_empty(&self) -> bool {
         self.map.is_empty()
@@ -82,6 +128,23 @@ impl Classes {
         self.map.get(key)
     }
 
+    #[must_use]
+    /// Check that the method id refers to a method index that is in range for its class.
+    /// Returns `false` if the class is not loaded, or if it is an array class, since those have no
+    /// methods by index. [`MethodId::ArrayClone`] is always valid.
+    pub fn is_valid_method_id(&self, method_id: MethodId) -> bool {
+        match method_id {
+            MethodId::Exact(method_id) => {
+                let (class_id, method_index) = method_id.decompose();
+                self.get(&class_id)
+                    .and_then(ClassVariant::as_class)
+                    .and_then(|class| class.method_id(method_index))
+                    .is_some()
+            }
+            MethodId::ArrayClone => true,
+        }
+    }
+
     #[must_use]
     pub fn get_mut(&mut self, key: &ClassId) -> Option<&mut ClassVariant> {
         self.map.get_mut(key)
@@ -94,6 +157,66 @@ impl Classes {
         }
     }
 
+    /// Iterate over the given class and then its super classes, up to `java/lang/Object`
+    /// This only walks classes which are already loaded, stopping after the first id whose class
+    /// is not loaded. If you need to load them as you go, use [`load_super_classes_iter`].
+    /// Arrays yield `java/lang/Object` as their only super class.
+    /// If the hierarchy is circular then it stops before the first class that would be repeated.
+    pub fn iter_super_chain(&self, class_id: ClassId) -> impl Iterator<Item = ClassId> + '_ {
+        let mut visited: SmallVec<[ClassId; 8]> = SmallVec::new();
+        std::iter::successors(Some(class_id), move |id| {
+            visited.push(*id);
+            self.get(id)
+                .and_then(ClassVariant::super_id)
+                .filter(|super_id| !visited.contains(super_id))
+        })
+    }
+
+    /// Load the given class and all of its super classes, returning their ids in order from the
+    /// class itself up to `java/lang/Object`.
+    pub fn load_super_chain(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_files: &mut ClassFiles,
+        packages: &mut Packages,
+        class_id: ClassId,
+    ) -> Result<SmallVec<[ClassId; 8]>, StepError> {
+        let mut chain = SmallVec::new();
+        let mut iter = load_super_classes_iter(class_id);
+        while let Some(id) = iter.next_item(class_names, class_files, self, packages) {
+            chain.push(id?);
+        }
+
+        Ok(chain)
+    }
+
+    /// Find the nearest class that both `a` and `b` are, or extend.
+    /// Interfaces are not considered, and so if the only shared ancestor is `java/lang/Object` then
+    /// that is what is returned. This matches the merging behavior of the verifier.
+    pub fn common_superclass(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_files: &mut ClassFiles,
+        packages: &mut Packages,
+        a: ClassId,
+        b: ClassId,
+    ) -> Result<ClassId, StepError> {
+        if a == b {
+            return Ok(a);
+        }
+
+        let a_chain = self.load_super_chain(class_names, class_files, packages, a)?;
+        let b_chain = self.load_super_chain(class_names, class_files, packages, b)?;
+
+        // The chains include the classes themselves, so this also handles the case where one
+        // is an ancestor of the other.
+        Ok(a_chain
+            .iter()
+            .find(|id| b_chain.contains(id))
+            .copied()
+            .unwrap_or_else(|| class_names.object_id()))
+    }
+
     // FIXME: This doesn't force any verification
     /// The given array class must have valid and correct fields!
     pub fn register_array_class(&mut self, array_class: ArrayClass) {
@@ -107,10 +230,13 @@ impl Classes {
         packages: &mut Packages,
         class_file_id: ClassId,
     ) -> Result<(), StepError> {
+        trace_span!("load_class", class = class_names.tpath(class_file_id));
         if self.contains_key(&class_file_id) {
             // It was already loaded
+            trace_event!("class cache hit");
             return Ok(());
         }
+        trace_event!("class cache miss");
 
         let (_, class_info) = class_names
             .name_from_gcid(class_file_id)
@@ -148,6 +274,7 @@ impl Classes {
             package,
             class_file.access_flags(),
             class_file.methods_len(),
+            class_file.fields_len(),
         );
 
         self.set_at(class_file_id, ClassVariant::Class(class));
@@ -181,13 +308,13 @@ impl Classes {
             let class = self.get(&class_id).unwrap();
             (class.package(), class.access_flags())
         };
-        let array = ArrayClass {
+        let array = ArrayClass::new(
             id,
-            super_class: class_names.object_id(),
             component_type,
+            class_names.object_id(),
             access_flags,
             package,
-        };
+        );
         self.register_array_class(array);
         Ok(id)
     }
@@ -206,7 +333,7 @@ impl Classes {
             return Ok(array_id);
         }
 
-        let array = ArrayClass::new_unchecked(
+        let array = ArrayClass::new(
             array_id,
             component_type,
             class_names.object_id(),
@@ -242,7 +369,7 @@ impl Classes {
                 prim.into()
             };
 
-        let array = ArrayClass::new_unchecked(
+        let array = ArrayClass::new(
             array_id,
             component_type,
             class_names.object_id(),
@@ -298,13 +425,13 @@ impl Classes {
                 component.as_array_component_type()
             };
 
-        let array = ArrayClass {
-            id: array_id,
-            super_class: class_names.object_id(),
+        let array = ArrayClass::new(
+            array_id,
             component_type,
+            class_names.object_id(),
             access_flags,
             package,
-        };
+        );
         self.register_array_class(array);
 
         Ok(array_id)
@@ -416,7 +543,9 @@ impl Classes {
         // still existed
         // Load the class file, because we need the super id
         let mut current_class_id = class_id;
+        let mut visited = SmallVec::new();
         loop {
+            ClassCircularityError::visit(&mut visited, current_class_id)?;
             class_files.load_by_class_path_id(class_names, current_class_id)?;
             let class_file = class_files.get(&current_class_id).unwrap();
 
@@ -437,6 +566,88 @@ impl Classes {
         Ok(false)
     }
 
+    /// Whether `sub` is `sup` or extends it.
+    /// This does not consider interfaces, see [`Classes::is_assignable_from`] for that.
+    pub fn is_subclass_of(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_files: &mut ClassFiles,
+        packages: &mut Packages,
+        sub: ClassId,
+        sup: ClassId,
+    ) -> Result<bool, StepError> {
+        if sub == sup {
+            return Ok(true);
+        }
+
+        self.is_super_class(class_names, class_files, packages, sub, sup)
+    }
+
+    /// Whether an instance of `source` can be stored in a location of type `target`.
+    /// This is the check behind `checkcast` and `instanceof`, and so it considers super classes,
+    /// implemented interfaces, and array covariance.
+    pub fn is_assignable_from(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_files: &mut ClassFiles,
+        packages: &mut Packages,
+        target: ClassId,
+        source: ClassId,
+    ) -> Result<bool, StepError> {
+        if target == source {
+            return Ok(true);
+        }
+
+        let target_is_array = class_names.is_array(target).map_err(StepError::BadId)?;
+        let source_is_array = class_names.is_array(source).map_err(StepError::BadId)?;
+
+        if !source_is_array {
+            // A non-array can never be assigned to an array
+            if target_is_array {
+                return Ok(false);
+            }
+
+            return Ok(
+                self.is_super_class(class_names, class_files, packages, source, target)?
+                    || self.implements_interface(class_names, class_files, source, target)?,
+            );
+        }
+
+        // Any array is assignable to Object and to the interfaces that all arrays implement
+        if class_names.is_object(target)
+            || self.implements_interface(class_names, class_files, source, target)?
+        {
+            return Ok(true);
+        }
+
+        if !target_is_array {
+            return Ok(false);
+        }
+
+        let source_elem = self
+            .get_array_class(class_names, class_files, packages, source)?
+            .map(ArrayClass::component_type);
+        let target_elem = self
+            .get_array_class(class_names, class_files, packages, target)?
+            .map(ArrayClass::component_type);
+        match (source_elem, target_elem) {
+            // [A is assignable to [B if A is assignable to B
+            (
+                Some(ArrayComponentType::Class(source_elem)),
+                Some(ArrayComponentType::Class(target_elem)),
+            ) => self.is_assignable_from(
+                class_names,
+                class_files,
+                packages,
+                target_elem,
+                source_elem,
+            ),
+            // Arrays of primitives are only assignable to arrays of the exact same type
+            (Some(source_elem), Some(target_elem)) => Ok(source_elem == target_elem),
+            _ => Ok(false),
+        }
+    }
+
     pub fn implements_interface(
         &self,
         class_names: &mut ClassNames,
@@ -458,8 +669,10 @@ impl Classes {
         }
 
         let mut current_class_id = Some(class_id);
+        let mut visited = SmallVec::new();
 
         while let Some(current_id) = current_class_id {
+            ClassCircularityError::visit(&mut visited, current_id)?;
             let interfaces = {
                 class_files.load_by_class_path_id(class_names, current_id)?;
                 let class_file = class_files.get(&current_id).unwrap();
@@ -472,14 +685,9 @@ impl Classes {
 
                 // Check all the topmost indices first
                 for interface_index in interfaces.iter().copied() {
-                    let interface_constant = class_file
-                        .get_t(interface_index)
-                        .ok_or(LoadClassError::BadInterfaceIndex(interface_index))?;
-                    let interface_name =
-                        class_file.get_text_b(interface_constant.name_index).ok_or(
-                            LoadClassError::BadInterfaceNameIndex(interface_constant.name_index),
-                        )?;
-                    let interface_id = class_names.gcid_from_bytes(interface_name);
+                    let interface_id = class_file
+                        .class_id_from_constant(interface_index, class_names)
+                        .map_err(LoadClassError::from_interface_index_error)?;
 
                     if interface_id == impl_interface_id {
                         return Ok(true);
@@ -501,13 +709,9 @@ impl Classes {
                 class_files.load_by_class_path_id(class_names, current_id)?;
                 let class_file = class_files.get(&current_id).unwrap();
 
-                let interface_constant = class_file
-                    .get_t(interface_index)
-                    .ok_or(LoadClassError::BadInterfaceIndex(interface_index))?;
-                let interface_name = class_file.get_text_b(interface_constant.name_index).ok_or(
-                    LoadClassError::BadInterfaceNameIndex(interface_constant.name_index),
-                )?;
-                let interface_id = class_names.gcid_from_bytes(interface_name);
+                let interface_id = class_file
+                    .class_id_from_constant(interface_index, class_names)
+                    .map_err(LoadClassError::from_interface_index_error)?;
 
                 if self.implements_interface(
                     class_names,
@@ -690,11 +894,31 @@ pub fn does_extend_class(
     classes: &Classes,
     class_id: ClassId,
     desired_super_class_id: ClassId,
+) -> Result<bool, StepError> {
+    does_extend_class_visited(
+        class_names,
+        class_files,
+        classes,
+        class_id,
+        desired_super_class_id,
+        &mut SmallVec::new(),
+    )
+}
+
+fn does_extend_class_visited(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &Classes,
+    class_id: ClassId,
+    desired_super_class_id: ClassId,
+    visited: &mut SmallVec<[ClassId; 8]>,
 ) -> Result<bool, StepError> {
     if class_id == desired_super_class_id {
         return Ok(true);
     }
 
+    ClassCircularityError::visit(visited, class_id)?;
+
     let super_class_id = if let Some(class) = classes.get(&class_id) {
         class.super_id()
     } else if let Some(class_file) = class_files.get(&class_id) {
@@ -721,12 +945,13 @@ pub fn does_extend_class(
         } else {
             // Crawl further up the tree to see if it extends it
             // Trees should be relatively small so doing recursion probably doesn't matter
-            does_extend_class(
+            does_extend_class_visited(
                 class_names,
                 class_files,
                 classes,
                 super_class_id,
                 desired_super_class_id,
+                visited,
             )
         }
     } else {
@@ -734,3 +959,33 @@ pub fn does_extend_class(
         Ok(false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        data::{class_files::ClassFiles, class_names::ClassNames},
+        package::Packages,
+        test_util::{EmptyLoader, TestClass},
+    };
+
+    use super::Classes;
+
+    #[test]
+    fn test_iter_circular_super_chain() {
+        let mut class_names = ClassNames::new();
+        let mut class_files = ClassFiles::new(EmptyLoader);
+        let mut classes = Classes::new();
+        let mut packages = Packages::default();
+
+        let a = TestClass::new("a/A", Some("a/B")).insert(&mut class_names, &mut class_files);
+        let b = TestClass::new("a/B", Some("a/A")).insert(&mut class_names, &mut class_files);
+        for id in [a, b] {
+            classes
+                .load_class(&mut class_names, &mut class_files, &mut packages, id)
+                .unwrap();
+        }
+
+        assert_eq!(classes.iter_super_chain(a).collect::<Vec<_>>(), [a, b]);
+        assert_eq!(classes.iter_super_chain(b).collect::<Vec<_>>(), [b, a]);
+    }
+}
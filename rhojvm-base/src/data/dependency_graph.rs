@@ -0,0 +1,268 @@
+//! The graph of which classes reference which other classes, for loading them ahead of time
+
+use classfile_parser::constant_info::ConstantInfo;
+use indexmap::IndexMap;
+
+use crate::{class::ClassFileData, id::ClassId};
+
+use super::{
+    class_file_loader::LoadClassFileError, class_files::ClassFiles, class_names::ClassNames,
+};
+
+/// The classes reachable from some roots, with the classes that each of them references
+/// See [`ClassFiles::dependency_graph`].
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    /// The classes that each class references, in the order that they were discovered
+    /// Array classes are replaced by their innermost component class, and arrays of primitives
+    /// are not included.
+    edges: IndexMap<ClassId, Vec<ClassId>>,
+    /// Classes that were referenced but which could not be found
+    missing: Vec<ClassId>,
+}
+impl DependencyGraph {
+    #[must_use]
+    /// The number of classes in the graph, including the missing ones
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    #[must_use]
+    pub fn contains(&self, id: ClassId) -> bool {
+        self.edges.contains_key(&id)
+    }
+
+    #[must_use]
+    /// Get the classes that the class references directly
+    pub fn dependencies(&self, id: ClassId) -> Option<&[ClassId]> {
+        self.edges.get(&id).map(Vec::as_slice)
+    }
+
+    /// Iterate over every class in the graph, in the order that they were discovered
+    pub fn classes(&self) -> impl Iterator<Item = ClassId> + '_ {
+        self.edges.keys().copied()
+    }
+
+    #[must_use]
+    /// The classes that were referenced but whose class file could not be found
+    pub fn missing(&self) -> &[ClassId] {
+        &self.missing
+    }
+
+    #[must_use]
+    /// Group the classes into strongly connected components, ordered so that each component comes
+    /// after every component that it depends on.
+    /// Classes that reference each other, directly or indirectly, end up in the same component,
+    /// see [`DependencyGraph::cycles`].
+    pub fn strongly_connected_components(&self) -> Vec<Vec<ClassId>> {
+        // This is Tarjan's algorithm, which happens to produce the components in reverse
+        // topological order. It uses an explicit stack, since the graph can be deep.
+        let count = self.edges.len();
+        let mut indices: Vec<Option<usize>> = vec![None; count];
+        let mut low_links = vec![0; count];
+        let mut on_stack = vec![false; count];
+        let mut stack = Vec::new();
+        let mut next_index = 0;
+        let mut components = Vec::new();
+
+        for start in 0..count {
+            if indices[start].is_some() {
+                continue;
+            }
+
+            indices[start] = Some(next_index);
+            low_links[start] = next_index;
+            next_index += 1;
+            stack.push(start);
+            on_stack[start] = true;
+
+            // The nodes being visited, and the position in their edges to continue from
+            let mut calls = vec![(start, 0)];
+            while let Some(&(node, edge)) = calls.last() {
+                let edges = &self.edges[node];
+                if let Some(target) = edges.get(edge) {
+                    calls.last_mut().unwrap().1 += 1;
+                    let target = self.edges.get_index_of(target).unwrap();
+                    match indices[target] {
+                        None => {
+                            indices[target] = Some(next_index);
+                            low_links[target] = next_index;
+                            next_index += 1;
+                            stack.push(target);
+                            on_stack[target] = true;
+                            calls.push((target, 0));
+                        }
+                        Some(target_index) if on_stack[target] => {
+                            low_links[node] = low_links[node].min(target_index);
+                        }
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+
+                calls.pop();
+                if let Some(&(parent, _)) = calls.last() {
+                    low_links[parent] = low_links[parent].min(low_links[node]);
+                }
+
+                if Some(low_links[node]) == indices[node] {
+                    let mut component = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        component.push(*self.edges.get_index(member).unwrap().0);
+                        if member == node {
+                            break;
+                        }
+                    }
+                    components.push(component);
+                }
+            }
+        }
+
+        components
+    }
+
+    /// Iterate over the classes so that, apart from cycles, each class comes after the classes
+    /// that it depends on
+    pub fn topological_order(&self) -> impl Iterator<Item = ClassId> {
+        self.strongly_connected_components().into_iter().flatten()
+    }
+
+    #[must_use]
+    /// Get the groups of classes which depend on each other in a cycle
+    /// These are common in Java, since a class and its nested classes typically reference each
+    /// other, but they determine which classes have to be loaded together.
+    pub fn cycles(&self) -> Vec<Vec<ClassId>> {
+        self.strongly_connected_components()
+            .into_iter()
+            .filter(|component| match component.as_slice() {
+                [id] => self.edges[id].contains(id),
+                _ => true,
+            })
+            .collect()
+    }
+}
+
+impl ClassFiles {
+    /// Build the graph of classes reachable from the roots through their super class,
+    /// interfaces, and the `CONSTANT_Class` entries of their constant pool, loading their class
+    /// files as they are discovered.
+    /// Classes which can't be found are recorded in [`DependencyGraph::missing`] rather than
+    /// being an error, so that the rest of the graph can still be built.
+    pub fn dependency_graph(
+        &mut self,
+        class_names: &mut ClassNames,
+        roots: &[ClassId],
+    ) -> Result<DependencyGraph, LoadClassFileError> {
+        let mut graph = DependencyGraph::default();
+        let mut queue = roots.to_vec();
+        while let Some(class_id) = queue.pop() {
+            if graph.edges.contains_key(&class_id) {
+                continue;
+            }
+
+            match self.load_by_class_path_id(class_names, class_id) {
+                Ok(()) => {}
+                Err(LoadClassFileError::Nonexistent | LoadClassFileError::NonexistentFile(_)) => {
+                    graph.missing.push(class_id);
+                    graph.edges.insert(class_id, Vec::new());
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+
+            let dependencies = match self.get(&class_id) {
+                Some(class_file) => referenced_classes(class_file, class_names),
+                // Things without a class file, like the primitive pseudo-classes, don't
+                // reference anything
+                None => Vec::new(),
+            };
+            queue.extend(
+                dependencies
+                    .iter()
+                    .copied()
+                    .filter(|id| !graph.edges.contains_key(id)),
+            );
+            graph.edges.insert(class_id, dependencies);
+        }
+
+        Ok(graph)
+    }
+}
+
+/// Get the classes that the class file references, without duplicates or itself
+fn referenced_classes(class_file: &ClassFileData, class_names: &mut ClassNames) -> Vec<ClassId> {
+    let mut ids = Vec::new();
+    for (_, info) in class_file.constants() {
+        let ConstantInfo::Class(class) = info else {
+            continue;
+        };
+        let Some(name) = class_file.get_text_b(class.name_index) else {
+            continue;
+        };
+
+        // Depend on the component of arrays, since the array classes themselves are created
+        // rather than loaded
+        let name = if name.first() == Some(&b'[') {
+            let component = name
+                .iter()
+                .position(|x| *x != b'[')
+                .map_or(name, |i| &name[i..]);
+            match component
+                .strip_prefix(b"L")
+                .and_then(|x| x.strip_suffix(b";"))
+            {
+                Some(component) => component,
+                // An array of primitives
+                None => continue,
+            }
+        } else {
+            name
+        };
+
+        let id = class_names.gcid_from_bytes(name);
+        if id != class_file.id() && !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::id::ClassId;
+
+    use super::DependencyGraph;
+
+    #[test]
+    fn test_topological_order() {
+        let [a, b, c, d] = [1, 2, 3, 4].map(ClassId::new_unchecked);
+        // a -> b, b <-> c, c -> d
+        let graph = DependencyGraph {
+            edges: [(a, vec![b]), (b, vec![c]), (c, vec![b, d]), (d, vec![])]
+                .into_iter()
+                .collect(),
+            missing: Vec::new(),
+        };
+
+        let order = graph.topological_order().collect::<Vec<_>>();
+        let position = |id| order.iter().position(|x| *x == id).unwrap();
+        assert_eq!(order.len(), 4);
+        assert!(position(d) < position(c));
+        assert!(position(d) < position(b));
+        assert!(position(b) < position(a));
+        assert!(position(c) < position(a));
+
+        let cycles = graph.cycles();
+        assert_eq!(cycles.len(), 1);
+        let mut cycle = cycles[0].clone();
+        cycle.sort_by_key(|id| id.get());
+        assert_eq!(cycle, [b, c]);
+    }
+}
@@ -0,0 +1,410 @@
+//! The layout of the fields of a class in memory
+
+use classfile_parser::{field_info::FieldAccessFlags, LoadError};
+use indexmap::IndexMap;
+
+use crate::{
+    class::{ClassFileData, ConstantValueKind},
+    code::{
+        method::{DescriptorParseError, DescriptorType, DescriptorTypeBasic},
+        types,
+    },
+    id::{ClassId, FieldId, FieldIndex},
+    util::StaticMemorySizeU16,
+    StepError,
+};
+
+use super::{
+    class_files::{load_super_class_files_iter, ClassFiles},
+    class_names::ClassNames,
+};
+
+/// The size of a field which holds a reference, such as to an object or an array
+pub const REFERENCE_SIZE: u16 = 8;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayoutError {
+    #[error("failed to load the class or its super classes")]
+    Step(#[source] StepError),
+    #[error("failed to load field {index} of {class_id:?}: {err:?}")]
+    FieldLoad {
+        class_id: ClassId,
+        index: FieldIndex,
+        err: LoadError,
+    },
+    #[error("invalid descriptor index for field {index} of {class_id:?}")]
+    InvalidDescriptorIndex {
+        class_id: ClassId,
+        index: FieldIndex,
+    },
+    #[error("invalid descriptor for field {index} of {class_id:?}")]
+    InvalidDescriptor {
+        class_id: ClassId,
+        index: FieldIndex,
+        #[source]
+        err: DescriptorParseError,
+    },
+}
+impl From<StepError> for LayoutError {
+    fn from(err: StepError) -> Self {
+        Self::Step(err)
+    }
+}
+
+/// Where a field is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldLayout {
+    /// The offset in bytes from the start of the storage
+    pub offset: u32,
+    /// The size in bytes, which is also its alignment
+    pub size: u16,
+}
+
+/// The layout of the non-static fields of instances of a class, including those inherited from
+/// its super classes
+/// The fields of a super class are laid out before, and in the same place as, those of its
+/// subclasses, so an instance can be treated as an instance of its super class.
+#[derive(Debug, Clone, Default)]
+pub struct InstanceLayout {
+    /// The fields in order of their offset
+    fields: IndexMap<FieldId, FieldLayout>,
+    /// The total size in bytes, which is a multiple of the alignment
+    size: u32,
+    /// The largest alignment of any of the fields
+    alignment: u16,
+}
+impl InstanceLayout {
+    #[must_use]
+    pub fn get(&self, field_id: FieldId) -> Option<FieldLayout> {
+        self.fields.get(&field_id).copied()
+    }
+
+    #[must_use]
+    pub fn offset(&self, field_id: FieldId) -> Option<u32> {
+        self.get(field_id).map(|field| field.offset)
+    }
+
+    /// Iterate over the fields in order of their offset
+    pub fn iter(&self) -> impl Iterator<Item = (FieldId, FieldLayout)> + '_ {
+        self.fields.iter().map(|(id, layout)| (*id, *layout))
+    }
+
+    #[must_use]
+    /// The number of fields, including inherited fields
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    #[must_use]
+    /// The total size of the fields in bytes, including padding
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    #[must_use]
+    pub fn alignment(&self) -> u16 {
+        self.alignment
+    }
+}
+
+/// A static field of a class, see [`StaticLayout`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaticField {
+    pub layout: FieldLayout,
+    /// The value from the field's `ConstantValue` attribute, which it is set to before the class
+    /// is initialized
+    pub initial_value: Option<ConstantValueKind<'static>>,
+}
+
+/// The layout of the storage for the static fields of a single class
+/// Unlike [`InstanceLayout`], this does not include the fields of super classes, since a static
+/// field is only stored once, in the class that declares it.
+#[derive(Debug, Clone, Default)]
+pub struct StaticLayout {
+    /// The fields in order of their offset
+    fields: IndexMap<FieldId, StaticField>,
+    size: u32,
+    alignment: u16,
+}
+impl StaticLayout {
+    #[must_use]
+    pub fn get(&self, field_id: FieldId) -> Option<&StaticField> {
+        self.fields.get(&field_id)
+    }
+
+    #[must_use]
+    pub fn offset(&self, field_id: FieldId) -> Option<u32> {
+        self.get(field_id).map(|field| field.layout.offset)
+    }
+
+    /// Iterate over the fields in order of their offset
+    pub fn iter(&self) -> impl Iterator<Item = (FieldId, &StaticField)> + '_ {
+        self.fields.iter().map(|(id, field)| (*id, field))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    #[must_use]
+    /// The total size of the fields in bytes, including padding
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    #[must_use]
+    pub fn alignment(&self) -> u16 {
+        self.alignment
+    }
+}
+
+impl ClassFiles {
+    /// Compute the layout of the non-static fields of instances of the class
+    /// Each class's own fields are ordered from largest to smallest, which avoids most padding,
+    /// and then placed after the fields of its super class.
+    pub fn instance_layout(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_id: ClassId,
+    ) -> Result<InstanceLayout, LayoutError> {
+        let mut chain = Vec::new();
+        let mut iter = load_super_class_files_iter(class_id);
+        while let Some(id) = iter.next_item(class_names, self) {
+            chain.push(id?);
+        }
+
+        let mut layout = InstanceLayout {
+            alignment: 1,
+            ..InstanceLayout::default()
+        };
+        // Lay out from the root down, so that super classes have their fields first
+        for id in chain.into_iter().rev() {
+            // Things without class files, like arrays, have no fields of their own
+            let Some(class_file) = self.get(&id) else {
+                continue;
+            };
+
+            let fields = sized_fields(class_file, class_names, |flags| {
+                !flags.contains(FieldAccessFlags::STATIC)
+            })?;
+            for (index, size) in fields {
+                let offset = align_up(layout.size, size);
+                layout.size = offset + u32::from(size);
+                layout.alignment = layout.alignment.max(size);
+                layout.fields.insert(
+                    FieldId::unchecked_compose(id, index),
+                    FieldLayout { offset, size },
+                );
+            }
+        }
+        layout.size = align_up(layout.size, layout.alignment);
+
+        Ok(layout)
+    }
+
+    /// Compute the layout of the storage for the static fields declared by the class
+    /// The fields are ordered from largest to smallest, like [`ClassFiles::instance_layout`].
+    pub fn static_layout(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_id: ClassId,
+    ) -> Result<StaticLayout, LayoutError> {
+        self.load_by_class_path_id(class_names, class_id)
+            .map_err(StepError::from)?;
+
+        let mut layout = StaticLayout {
+            alignment: 1,
+            ..StaticLayout::default()
+        };
+        // Things without class files, like arrays, have no static fields
+        let Some(class_file) = self.get(&class_id) else {
+            return Ok(layout);
+        };
+
+        let fields = sized_fields(class_file, class_names, |flags| {
+            flags.contains(FieldAccessFlags::STATIC)
+        })?;
+        for (index, size) in fields {
+            let offset = align_up(layout.size, size);
+            layout.size = offset + u32::from(size);
+            layout.alignment = layout.alignment.max(size);
+            let initial_value = class_file
+                .field_constant_value(index)
+                .map(ConstantValueKind::into_owned);
+            layout.fields.insert(
+                FieldId::unchecked_compose(class_id, index),
+                StaticField {
+                    layout: FieldLayout { offset, size },
+                    initial_value,
+                },
+            );
+        }
+        layout.size = align_up(layout.size, layout.alignment);
+
+        Ok(layout)
+    }
+}
+
+fn align_up(offset: u32, alignment: u16) -> u32 {
+    offset.next_multiple_of(u32::from(alignment))
+}
+
+/// Get the size in bytes of a value of the field's type
+#[must_use]
+pub fn field_size(typ: DescriptorType) -> u16 {
+    match typ {
+        DescriptorType::Basic(basic) => match basic {
+            DescriptorTypeBasic::Byte => types::Byte::MEMORY_SIZE_U16,
+            DescriptorTypeBasic::Boolean => types::Boolean::MEMORY_SIZE_U16,
+            DescriptorTypeBasic::Char => types::Char::MEMORY_SIZE_U16,
+            DescriptorTypeBasic::Short => types::Short::MEMORY_SIZE_U16,
+            DescriptorTypeBasic::Int => types::Int::MEMORY_SIZE_U16,
+            DescriptorTypeBasic::Float => types::Float::MEMORY_SIZE_U16,
+            DescriptorTypeBasic::Long => types::Long::MEMORY_SIZE_U16,
+            DescriptorTypeBasic::Double => types::Double::MEMORY_SIZE_U16,
+            DescriptorTypeBasic::Class(_) => REFERENCE_SIZE,
+        },
+        DescriptorType::Array { .. } => REFERENCE_SIZE,
+    }
+}
+
+/// Get the index and size of each of the class's fields which match the filter, ordered from
+/// largest to smallest. Fields of the same size keep the order that they were declared in.
+fn sized_fields(
+    class_file: &ClassFileData,
+    class_names: &mut ClassNames,
+    filter: impl Fn(FieldAccessFlags) -> bool,
+) -> Result<Vec<(FieldIndex, u16)>, LayoutError> {
+    let class_id = class_file.id();
+    let mut fields = Vec::new();
+    for (index, field) in (0..).zip(class_file.fields()) {
+        let field = field.map_err(|err| LayoutError::FieldLoad {
+            class_id,
+            index,
+            err,
+        })?;
+        if !filter(field.access_flags) {
+            continue;
+        }
+
+        let descriptor = class_file
+            .get_text_b(field.descriptor_index)
+            .ok_or(LayoutError::InvalidDescriptorIndex { class_id, index })?;
+        let typ = DescriptorType::parse_field(descriptor, class_names).map_err(|err| {
+            LayoutError::InvalidDescriptor {
+                class_id,
+                index,
+                err,
+            }
+        })?;
+        fields.push((index, field_size(typ)));
+    }
+
+    fields.sort_by(|(_, a), (_, b)| b.cmp(a));
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        data::{class_files::ClassFiles, class_names::ClassNames},
+        id::{ClassId, FieldId},
+        test_util::{EmptyLoader, TestClass, ACC_STATIC},
+    };
+
+    use super::{FieldLayout, REFERENCE_SIZE};
+
+    fn insert(
+        class_files: &mut ClassFiles,
+        class_names: &mut ClassNames,
+        this: &str,
+        super_name: Option<&str>,
+        fields: &[(u16, &str, &str)],
+    ) -> ClassId {
+        fields
+            .iter()
+            .fold(
+                TestClass::new(this, super_name),
+                |class, (flags, name, desc)| class.field(*flags, name, desc),
+            )
+            .insert(class_names, class_files)
+    }
+
+    #[test]
+    fn test_instance_layout() {
+        let mut class_names = ClassNames::new();
+        let mut class_files = ClassFiles::new(EmptyLoader);
+        let object = "java/lang/Object";
+        insert(&mut class_files, &mut class_names, object, None, &[]);
+        let parent = insert(
+            &mut class_files,
+            &mut class_names,
+            "a/Parent",
+            Some(object),
+            &[(0, "flag", "Z"), (ACC_STATIC, "count", "J")],
+        );
+        let child = insert(
+            &mut class_files,
+            &mut class_names,
+            "a/Child",
+            Some("a/Parent"),
+            &[(0, "c", "C"), (0, "l", "J"), (0, "s", "Ljava/lang/String;")],
+        );
+
+        let layout = class_files
+            .instance_layout(&mut class_names, child)
+            .unwrap();
+        // The static field is not included
+        assert_eq!(layout.len(), 4);
+        let field = |id, index| layout.get(FieldId::unchecked_compose(id, index)).unwrap();
+        assert_eq!(field(parent, 0), FieldLayout { offset: 0, size: 1 });
+        // The child's fields come after, sorted by size and aligned
+        assert_eq!(field(child, 1), FieldLayout { offset: 8, size: 8 });
+        assert_eq!(
+            field(child, 2),
+            FieldLayout {
+                offset: 16,
+                size: REFERENCE_SIZE
+            }
+        );
+        assert_eq!(
+            field(child, 0),
+            FieldLayout {
+                offset: 16 + u32::from(REFERENCE_SIZE),
+                size: 2
+            }
+        );
+        assert_eq!(layout.alignment(), 8);
+        assert_eq!(layout.size(), 32);
+
+        let parent_layout = class_files
+            .instance_layout(&mut class_names, parent)
+            .unwrap();
+        assert_eq!(parent_layout.len(), 1);
+        assert_eq!(parent_layout.size(), 1);
+
+        let static_layout = class_files.static_layout(&mut class_names, parent).unwrap();
+        assert_eq!(static_layout.len(), 1);
+        let count = static_layout
+            .get(FieldId::unchecked_compose(parent, 1))
+            .unwrap();
+        assert_eq!(count.layout, FieldLayout { offset: 0, size: 8 });
+        assert_eq!(count.initial_value, None);
+        // Statics are not inherited
+        assert!(class_files
+            .static_layout(&mut class_names, child)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -27,71 +27,92 @@ use super::{
     classes::{load_descriptor_type, Classes},
 };
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum LoadMethodError {
     /// There was no method at that id
+    #[error("no method with id {id:?}")]
     NonexistentMethod { id: ExactMethodId },
     /// There was no method with that name
+    #[error("no method named {name:?} on class {class_id:?}")]
     NonexistentMethodName {
         class_id: ClassId,
         name: Cesu8String,
     },
     /// The index to the name of the method was invalid
+    #[error("invalid method name index: {}", index.0)]
     InvalidMethodNameIndex {
         index: ConstantPoolIndexRaw<Utf8Constant>,
     },
     /// The index to the descriptor of the method was invalid
+    #[error("invalid method descriptor index: {}", index.0)]
     InvalidDescriptorIndex {
         index: ConstantPoolIndexRaw<Utf8Constant>,
     },
     /// An error in parsing the method descriptor
+    #[error("invalid method descriptor: {0:?}")]
     MethodDescriptorError(classfile_parser::descriptor::method::MethodDescriptorError),
 }
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum LoadCodeError {
+    #[error("invalid code attribute")]
     InvalidCodeAttribute,
-    InstructionParse(InstructionParseError),
+    #[error("failed to parse instruction")]
+    InstructionParse(#[source] InstructionParseError),
     /// The method index was invalid, this could signify either a logical bug with this lib
     /// or the code using it.
+    #[error("bad method index")]
     BadMethodIndex,
 }
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum VerifyCodeExceptionError {
     /// The indices were from larger to greater, which is not allowed.
+    #[error("exception range start was not before its end")]
     InverseOrder,
     /// There was no code at the start index
+    #[error("no instruction at the exception range start")]
     InvalidStartIndex,
     /// There was no code at the end index
+    #[error("no instruction at the exception range end")]
     InvalidEndIndex,
     /// There was no code at the handler index
+    #[error("no instruction at the exception handler")]
     InvalidHandlerIndex,
     /// The const pool index for the catch type was invalid (zero is allowed)
+    #[error("invalid catch type index")]
     InvalidCatchTypeIndex,
     /// The const pool index for the class name was invalid
+    #[error("invalid catch type name index")]
     InvalidCatchTypeNameIndex,
     /// The catch type did not extend Throwable, which is required
+    #[error("catch type does not extend Throwable")]
     NonThrowableCatchType,
     /// The const pool index for the method that InvokeSpecial invokes
+    #[error("invalid invokespecial method index")]
     InvalidInvokeSpecialMethodIndex,
     /// The type of constant pool information at that position was unexpected
     /// This could theoretically mean a bug in the library
+    #[error("invokespecial index was not a method reference")]
     InvalidInvokeSpecialInfo,
     /// The const pool index for the name_and_type constantinfo of the method was invalid
+    #[error("invalid invokespecial name and type index")]
     InvalidInvokeSpecialMethodNameTypeIndex,
     /// The const pool index for name constantinfo of the method was invalid
+    #[error("invalid invokespecial method name index")]
     InvalidInvokeSpecialMethodNameIndex,
     /// There's illegal instructions used in the exception method / exception handler
+    #[error("illegal instructions in exception handler")]
     IllegalInstructions,
 }
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum VerifyMethodError {
+    #[error("incompatible visibility modifiers")]
     IncompatibleVisibilityModifiers,
 }
 
@@ -233,6 +254,9 @@ pub fn direct_load_method_from_index(
     Ok(method)
 }
 
+/// Find the method with the given name and descriptor
+/// A signature polymorphic method is found by its name alone, since calls to it use the
+/// descriptor of the call site rather than the one it was declared with.
 fn method_id_from_desc<'a>(
     class_names: &mut ClassNames,
     class_file: &'a ClassFileData,
@@ -253,9 +277,10 @@ fn method_id_from_desc<'a>(
             },
         )?;
 
-        if desc
-            .is_equal_to_descriptor(class_names, descriptor_text)
-            .map_err(LoadMethodError::MethodDescriptorError)?
+        if class_file.is_signature_polymorphic(method_index)
+            || desc
+                .is_equal_to_descriptor(class_names, descriptor_text)
+                .map_err(LoadMethodError::MethodDescriptorError)?
         {
             let method_id = ExactMethodId::unchecked_compose(class_file.id(), method_index);
 
@@ -1,5 +1,9 @@
+pub mod access;
 pub mod class_file_loader;
 pub mod class_files;
 pub mod class_names;
 pub mod classes;
-pub mod methods;
\ No newline at end of file
+pub mod dependency_graph;
+pub mod layout;
+pub mod methods;
+pub mod vtable;
\ No newline at end of file
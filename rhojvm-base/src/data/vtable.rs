@@ -0,0 +1,675 @@
+//! The virtual method table of a class, used for `invokevirtual` dispatch, and the selection of
+//! default methods from interfaces for `invokeinterface`
+
+use classfile_parser::method_info::MethodAccessFlags;
+use indexmap::IndexMap;
+
+use crate::{
+    class::{array_clone_descriptor, ARRAY_CLONE_NAME},
+    id::{ClassId, MethodId, MethodIndex},
+    util, StepError,
+};
+
+use super::{
+    class_files::{load_super_class_files_iter, ClassFiles},
+    class_names::ClassNames,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum VTableError {
+    #[error("failed to load the class or its super classes")]
+    Step(#[source] StepError),
+    #[error("failed to load method {index} of {class_id:?}")]
+    InvalidMethod {
+        class_id: ClassId,
+        index: MethodIndex,
+    },
+    /// A method overrides a method which is `final`
+    #[error("{method:?} overrides the final method {overridden:?}")]
+    OverridesFinal {
+        method: MethodId,
+        overridden: MethodId,
+    },
+}
+impl From<StepError> for VTableError {
+    fn from(err: StepError) -> Self {
+        Self::Step(err)
+    }
+}
+
+/// A single entry in a [`VTable`]
+#[derive(Debug, Clone)]
+pub struct VTableSlot {
+    name: Box<str>,
+    descriptor: Box<str>,
+    /// The method which should be run when this slot is invoked
+    method: MethodId,
+    /// The flags of `method`
+    flags: MethodAccessFlags,
+    /// The package of the class which declared `method`
+    package: Option<Box<[u8]>>,
+}
+impl VTableSlot {
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn descriptor(&self) -> &str {
+        &self.descriptor
+    }
+
+    #[must_use]
+    pub fn method(&self) -> MethodId {
+        self.method
+    }
+
+    /// Whether a method declared in a class in `package` may override the method in this slot
+    fn is_overridable_from(&self, package: Option<&[u8]>) -> bool {
+        if self
+            .flags
+            .intersects(MethodAccessFlags::PUBLIC | MethodAccessFlags::PROTECTED)
+        {
+            return true;
+        }
+
+        // Package-private methods can only be overridden from within the same package
+        self.package.as_deref() == package
+    }
+}
+
+/// The virtual methods of a class, each at a stable slot index
+/// The slots of a super class are a prefix of the slots of its subclasses, so a slot index
+/// resolved against a super class can be used to dispatch on any instance of a subclass.
+/// This only includes methods declared by classes, not default methods from interfaces.
+#[derive(Debug, Clone, Default)]
+pub struct VTable {
+    slots: Vec<VTableSlot>,
+}
+impl VTable {
+    #[must_use]
+    /// Get the method which should be run for the given slot
+    pub fn get(&self, slot: usize) -> Option<MethodId> {
+        self.slots.get(slot).map(VTableSlot::method)
+    }
+
+    #[must_use]
+    pub fn slot(&self, slot: usize) -> Option<&VTableSlot> {
+        self.slots.get(slot)
+    }
+
+    #[must_use]
+    /// Find the slot for the method with the given name and descriptor
+    /// There can be multiple slots with the same name and descriptor, if a package-private method
+    /// was not overridden by a method in another package. In that case this returns the most
+    /// derived, which is the last.
+    pub fn find(&self, name: &str, descriptor: &str) -> Option<usize> {
+        self.slots
+            .iter()
+            .rposition(|slot| &*slot.name == name && &*slot.descriptor == descriptor)
+    }
+
+    /// Iterate over the slots in order of their index
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &VTableSlot)> + '_ {
+        self.slots.iter().enumerate()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+/// The method selected by [`ClassFiles::resolve_interface_method`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodResolution {
+    /// A method declared by the receiver or one of its super classes
+    Class(MethodId),
+    /// A default method declared by an interface
+    Default(MethodId),
+}
+impl MethodResolution {
+    #[must_use]
+    pub fn method(self) -> MethodId {
+        match self {
+            MethodResolution::Class(method) | MethodResolution::Default(method) => method,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MethodResolutionError {
+    #[error("failed to load the class or its super types")]
+    Step(#[source] StepError),
+    #[error("failed to load method {index} of {class_id:?}")]
+    InvalidMethod {
+        class_id: ClassId,
+        index: MethodIndex,
+    },
+    /// The selected method is abstract, which would be an `AbstractMethodError`
+    #[error("{method:?} is abstract")]
+    Abstract { method: MethodId },
+    /// There are multiple maximally-specific default methods, none of which overrides the
+    /// others, which would be an `IncompatibleClassChangeError`
+    #[error("ambiguous default methods: {candidates:?}")]
+    Ambiguous { candidates: Vec<MethodId> },
+    /// Neither the class nor its interfaces declare the method
+    #[error("no such method")]
+    NotFound,
+}
+impl From<StepError> for MethodResolutionError {
+    fn from(err: StepError) -> Self {
+        Self::Step(err)
+    }
+}
+
+impl ClassFiles {
+    /// Build the virtual method table for the class
+    /// This walks from `java/lang/Object` down to the class, giving each virtual method
+    /// (non-static, non-private, and not an initializer) a slot. A method with the same name and
+    /// descriptor as an inherited slot overrides it, if it is allowed to by the access rules.
+    pub fn build_vtable(
+        &mut self,
+        class_names: &mut ClassNames,
+        class_id: ClassId,
+    ) -> Result<VTable, VTableError> {
+        let mut chain = Vec::new();
+        let mut iter = load_super_class_files_iter(class_id);
+        while let Some(id) = iter.next_item(class_names, self) {
+            chain.push(id?);
+        }
+
+        let mut vtable = VTable::default();
+        for id in chain.into_iter().rev() {
+            let (class_name, info) = class_names.name_from_gcid(id).map_err(StepError::BadId)?;
+            if info.is_array() {
+                // Arrays override `clone` with their own implementation
+                if let Some(slot) = vtable.find(ARRAY_CLONE_NAME, array_clone_descriptor()) {
+                    vtable.slots[slot].method = MethodId::ArrayClone;
+                }
+                continue;
+            }
+
+            let package = util::access_path_initial_part(class_name.get()).map(Box::<[u8]>::from);
+            let Some(class_file) = self.get(&id) else {
+                continue;
+            };
+
+            for index in 0..class_file.methods_len() {
+                let invalid = || VTableError::InvalidMethod {
+                    class_id: id,
+                    index,
+                };
+                let flags = class_file.method_flags(index).ok_or_else(invalid)?;
+                if flags.intersects(MethodAccessFlags::STATIC | MethodAccessFlags::PRIVATE) {
+                    continue;
+                }
+
+                let name = class_file.method_name(index).ok_or_else(invalid)?;
+                if name == "<init>" || name == "<clinit>" {
+                    continue;
+                }
+                let descriptor = class_file.method_descriptor(index).ok_or_else(invalid)?;
+                let method = MethodId::unchecked_compose(id, index);
+
+                let mut overrode = false;
+                for slot in &mut vtable.slots {
+                    if *slot.name != *name
+                        || *slot.descriptor != *descriptor
+                        || !slot.is_overridable_from(package.as_deref())
+                    {
+                        continue;
+                    }
+
+                    if slot.flags.contains(MethodAccessFlags::FINAL) {
+                        return Err(VTableError::OverridesFinal {
+                            method,
+                            overridden: slot.method,
+                        });
+                    }
+
+                    slot.method = method;
+                    slot.flags = flags;
+                    slot.package = package.clone();
+                    overrode = true;
+                }
+
+                if !overrode {
+                    vtable.slots.push(VTableSlot {
+                        name: Box::from(name),
+                        descriptor: Box::from(descriptor),
+                        method,
+                        flags,
+                        package: package.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(vtable)
+    }
+
+    /// Select the method which an `invokeinterface` (or `invokevirtual`) of the method with the
+    /// given name and descriptor on an instance of `receiver` should run
+    /// A method declared by the receiver or one of its super classes is preferred. Otherwise
+    /// this finds the maximally-specific default method from the interfaces that the receiver
+    /// transitively implements, which is an error if there is more than one.
+    pub fn resolve_interface_method(
+        &mut self,
+        class_names: &mut ClassNames,
+        receiver: ClassId,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<MethodResolution, MethodResolutionError> {
+        let mut chain = Vec::new();
+        let mut iter = load_super_class_files_iter(receiver);
+        while let Some(id) = iter.next_item(class_names, self) {
+            chain.push(id?);
+        }
+
+        for &id in &chain {
+            let Some(class_file) = self.get(&id) else {
+                let is_array = class_names.is_array(id).map_err(StepError::BadId)?;
+                if is_array && name == ARRAY_CLONE_NAME && descriptor == array_clone_descriptor() {
+                    return Ok(MethodResolution::Class(MethodId::ArrayClone));
+                }
+                continue;
+            };
+
+            let Some(index) = class_file.find_method(name, descriptor) else {
+                continue;
+            };
+            let flags =
+                class_file
+                    .method_flags(index)
+                    .ok_or(MethodResolutionError::InvalidMethod {
+                        class_id: id,
+                        index,
+                    })?;
+            if flags.intersects(MethodAccessFlags::STATIC | MethodAccessFlags::PRIVATE) {
+                continue;
+            }
+
+            let method = MethodId::unchecked_compose(id, index);
+            return if flags.contains(MethodAccessFlags::ABSTRACT) {
+                Err(MethodResolutionError::Abstract { method })
+            } else {
+                Ok(MethodResolution::Class(method))
+            };
+        }
+
+        // Collect every interface that is transitively implemented, along with the interfaces
+        // that each directly extends
+        let mut interfaces: IndexMap<ClassId, Vec<ClassId>> = IndexMap::new();
+        let mut pending = Vec::new();
+        for &id in &chain {
+            if let Some(class_file) = self.get(&id) {
+                pending.extend(
+                    class_file
+                        .interface_ids(class_names)
+                        .map_err(StepError::ClassFileIndex)?,
+                );
+            }
+        }
+        while let Some(id) = pending.pop() {
+            if interfaces.contains_key(&id) {
+                continue;
+            }
+
+            self.load_by_class_path_id(class_names, id)
+                .map_err(StepError::from)?;
+            let supers = match self.get(&id) {
+                Some(class_file) => class_file
+                    .interface_ids(class_names)
+                    .map_err(StepError::ClassFileIndex)?,
+                None => Vec::new(),
+            };
+            pending.extend(supers.iter().copied());
+            interfaces.insert(id, supers);
+        }
+
+        // The instance methods of the interfaces which match
+        let mut candidates = Vec::new();
+        for &id in interfaces.keys() {
+            let Some(class_file) = self.get(&id) else {
+                continue;
+            };
+            let Some(index) = class_file.find_method(name, descriptor) else {
+                continue;
+            };
+            let flags =
+                class_file
+                    .method_flags(index)
+                    .ok_or(MethodResolutionError::InvalidMethod {
+                        class_id: id,
+                        index,
+                    })?;
+            if !flags.intersects(MethodAccessFlags::STATIC | MethodAccessFlags::PRIVATE) {
+                candidates.push((id, index, flags));
+            }
+        }
+
+        // Only keep those which are not overridden by a method in a more specific interface
+        let maximally_specific = candidates
+            .iter()
+            .filter(|(id, _, _)| {
+                !candidates.iter().any(|(other, _, _)| {
+                    other != id && is_super_interface(&interfaces, *other, *id)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut defaults = maximally_specific
+            .iter()
+            .filter(|(_, _, flags)| !flags.contains(MethodAccessFlags::ABSTRACT))
+            .map(|(id, index, _)| MethodId::unchecked_compose(*id, *index))
+            .collect::<Vec<_>>();
+        match defaults.len() {
+            0 => match maximally_specific.first() {
+                Some((id, index, _)) => Err(MethodResolutionError::Abstract {
+                    method: MethodId::unchecked_compose(*id, *index),
+                }),
+                None => Err(MethodResolutionError::NotFound),
+            },
+            1 => Ok(MethodResolution::Default(defaults.remove(0))),
+            _ => Err(MethodResolutionError::Ambiguous {
+                candidates: defaults,
+            }),
+        }
+    }
+}
+
+/// Whether `sub` transitively extends `sup`, using the direct super interfaces in `interfaces`
+fn is_super_interface(
+    interfaces: &IndexMap<ClassId, Vec<ClassId>>,
+    sub: ClassId,
+    sup: ClassId,
+) -> bool {
+    let mut visited = Vec::new();
+    let mut pending = vec![sub];
+    while let Some(id) = pending.pop() {
+        if visited.contains(&id) {
+            continue;
+        }
+        visited.push(id);
+
+        let Some(supers) = interfaces.get(&id) else {
+            continue;
+        };
+        if supers.contains(&sup) {
+            return true;
+        }
+        pending.extend(supers.iter().copied());
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        data::{class_files::ClassFiles, class_names::ClassNames},
+        id::{ClassId, MethodId},
+        test_util::{
+            EmptyLoader, TestClass, ACC_ABSTRACT, ACC_FINAL, ACC_PRIVATE, ACC_PUBLIC, ACC_STATIC,
+        },
+    };
+
+    use super::{MethodResolution, MethodResolutionError, VTableError};
+
+    fn insert(
+        class_files: &mut ClassFiles,
+        class_names: &mut ClassNames,
+        this: &str,
+        super_name: Option<&str>,
+        methods: &[(u16, &str, &str)],
+    ) -> ClassId {
+        insert_implementing(class_files, class_names, this, super_name, &[], methods)
+    }
+
+    /// Insert an abstract class with the given methods, as `(access flags, name, descriptor)`
+    /// The methods have no code, which is fine since it is never loaded.
+    fn insert_implementing(
+        class_files: &mut ClassFiles,
+        class_names: &mut ClassNames,
+        this: &str,
+        super_name: Option<&str>,
+        interfaces: &[&str],
+        methods: &[(u16, &str, &str)],
+    ) -> ClassId {
+        let class = TestClass::new(this, super_name).access(0x0421);
+        let class = interfaces
+            .iter()
+            .fold(class, |class, interface| class.interface(interface));
+        methods
+            .iter()
+            .fold(class, |class, (flags, name, desc)| {
+                class.method(*flags, name, desc)
+            })
+            .insert(class_names, class_files)
+    }
+
+    #[test]
+    fn test_build_vtable() {
+        let mut class_names = ClassNames::new();
+        let mut class_files = ClassFiles::new(EmptyLoader);
+        let object = "java/lang/Object";
+        let object_id = insert(
+            &mut class_files,
+            &mut class_names,
+            object,
+            None,
+            &[
+                (0, "<init>", "()V"),
+                (ACC_PUBLIC, "hashCode", "()I"),
+                (ACC_PUBLIC, "clone", "()Ljava/lang/Object;"),
+                (ACC_STATIC, "registerNatives", "()V"),
+            ],
+        );
+        let parent = insert(
+            &mut class_files,
+            &mut class_names,
+            "a/Parent",
+            Some(object),
+            &[
+                (0, "run", "()V"),
+                (ACC_PRIVATE, "secret", "()V"),
+                (ACC_PUBLIC | ACC_FINAL, "done", "()Z"),
+            ],
+        );
+        let same_package = insert(
+            &mut class_files,
+            &mut class_names,
+            "a/Child",
+            Some("a/Parent"),
+            &[(ACC_PUBLIC, "hashCode", "()I"), (0, "run", "()V")],
+        );
+        let other_package = insert(
+            &mut class_files,
+            &mut class_names,
+            "b/Child",
+            Some("a/Parent"),
+            &[(0, "run", "()V"), (0, "secret", "()V")],
+        );
+
+        let vtable = class_files.build_vtable(&mut class_names, parent).unwrap();
+        assert_eq!(vtable.len(), 4);
+        let hash_code = vtable.find("hashCode", "()I").unwrap();
+        assert_eq!(
+            vtable.get(hash_code),
+            Some(MethodId::unchecked_compose(object_id, 1))
+        );
+        let run = vtable.find("run", "()V").unwrap();
+        assert!(vtable.find("secret", "()V").is_none());
+        assert!(vtable.find("<init>", "()V").is_none());
+
+        // Overriding in the same package replaces the inherited slots
+        let child = class_files
+            .build_vtable(&mut class_names, same_package)
+            .unwrap();
+        assert_eq!(child.len(), 4);
+        assert_eq!(
+            child.get(hash_code),
+            Some(MethodId::unchecked_compose(same_package, 0))
+        );
+        assert_eq!(
+            child.get(run),
+            Some(MethodId::unchecked_compose(same_package, 1))
+        );
+
+        // A package-private method can't be overridden from another package, so it gets a new
+        // slot. The private method is not inherited, so it also gets a new slot.
+        let child = class_files
+            .build_vtable(&mut class_names, other_package)
+            .unwrap();
+        assert_eq!(child.len(), 6);
+        assert_eq!(child.get(run), Some(MethodId::unchecked_compose(parent, 0)));
+        let new_run = child.find("run", "()V").unwrap();
+        assert_ne!(new_run, run);
+        assert_eq!(
+            child.get(new_run),
+            Some(MethodId::unchecked_compose(other_package, 0))
+        );
+
+        // Arrays use their own clone
+        let array =
+            class_names.gcid_from_array_of_primitives(crate::code::types::PrimitiveType::Int);
+        let array_vtable = class_files.build_vtable(&mut class_names, array).unwrap();
+        let clone = array_vtable.find("clone", "()Ljava/lang/Object;").unwrap();
+        assert_eq!(array_vtable.get(clone), Some(MethodId::ArrayClone));
+
+        insert(
+            &mut class_files,
+            &mut class_names,
+            "a/Bad",
+            Some("a/Parent"),
+            &[(ACC_PUBLIC, "done", "()Z")],
+        );
+        let bad = class_names.gcid_from_bytes(b"a/Bad");
+        assert!(matches!(
+            class_files.build_vtable(&mut class_names, bad),
+            Err(VTableError::OverridesFinal { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resolve_interface_method() {
+        let mut class_names = ClassNames::new();
+        let mut class_files = ClassFiles::new(EmptyLoader);
+        let object = "java/lang/Object";
+        let object_id = insert(
+            &mut class_files,
+            &mut class_names,
+            object,
+            None,
+            &[(ACC_PUBLIC, "hashCode", "()I")],
+        );
+        let run = (ACC_PUBLIC, "run", "()V");
+        let abstract_run = (ACC_PUBLIC | ACC_ABSTRACT, "run", "()V");
+        insert(
+            &mut class_files,
+            &mut class_names,
+            "a/A",
+            Some(object),
+            &[run],
+        );
+        let b = insert_implementing(
+            &mut class_files,
+            &mut class_names,
+            "a/B",
+            Some(object),
+            &["a/A"],
+            &[run],
+        );
+        let c = insert(
+            &mut class_files,
+            &mut class_names,
+            "a/C",
+            Some(object),
+            &[run],
+        );
+        insert_implementing(
+            &mut class_files,
+            &mut class_names,
+            "a/Reabstract",
+            Some(object),
+            &["a/A"],
+            &[abstract_run],
+        );
+
+        let mut resolve = |this: &str,
+                           super_name: &str,
+                           interfaces: &[&str],
+                           methods: &[(u16, &str, &str)],
+                           name: &str| {
+            let id = insert_implementing(
+                &mut class_files,
+                &mut class_names,
+                this,
+                Some(super_name),
+                interfaces,
+                methods,
+            );
+            let result = class_files.resolve_interface_method(&mut class_names, id, name, "()V");
+            (id, result)
+        };
+
+        // The default method of the most specific interface is selected
+        let (_, result) = resolve("a/X", object, &["a/A", "a/B"], &[], "run");
+        assert_eq!(
+            result.unwrap(),
+            MethodResolution::Default(MethodId::unchecked_compose(b, 0))
+        );
+
+        // Including when it comes from a super class's interfaces
+        let (_, result) = resolve("a/Y", "a/X", &[], &[], "run");
+        assert_eq!(
+            result.unwrap(),
+            MethodResolution::Default(MethodId::unchecked_compose(b, 0))
+        );
+
+        // A method in the class takes priority
+        let (z, result) = resolve("a/Z", "a/X", &[], &[run], "run");
+        assert_eq!(
+            result.unwrap(),
+            MethodResolution::Class(MethodId::unchecked_compose(z, 0))
+        );
+
+        // Two unrelated interfaces can't both provide it
+        let (_, result) = resolve("a/Both", object, &["a/B", "a/C"], &[], "run");
+        let Err(MethodResolutionError::Ambiguous { mut candidates }) = result else {
+            panic!("Expected an ambiguity error");
+        };
+        candidates.sort_by_key(|method| method.decompose().unwrap().0.get());
+        let mut expected = vec![
+            MethodId::unchecked_compose(b, 0),
+            MethodId::unchecked_compose(c, 0),
+        ];
+        expected.sort_by_key(|method| method.decompose().unwrap().0.get());
+        assert_eq!(candidates, expected);
+
+        // An abstract redeclaration in a subinterface hides the default method
+        let (_, result) = resolve("a/Abstract", object, &["a/Reabstract"], &[], "run");
+        assert!(matches!(
+            result,
+            Err(MethodResolutionError::Abstract { .. })
+        ));
+
+        let (_, result) = resolve("a/None", object, &["a/A"], &[], "walk");
+        assert!(matches!(result, Err(MethodResolutionError::NotFound)));
+
+        let x = class_names.gcid_from_bytes(b"a/X");
+        assert_eq!(
+            class_files
+                .resolve_interface_method(&mut class_names, x, "hashCode", "()I")
+                .unwrap(),
+            MethodResolution::Class(MethodId::unchecked_compose(object_id, 0))
+        );
+    }
+}
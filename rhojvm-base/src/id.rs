@@ -1,16 +1,52 @@
-use std::hash::{Hash, Hasher};
+use std::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+    num::NonZeroU32,
+};
 
+use crate::{
+    class::{array_clone_descriptor, ARRAY_CLONE_NAME},
+    data::{class_files::ClassFiles, class_names::ClassNames},
+    util::convert_classfile_text,
+};
+
+/// The id of a class, given out by a [`crate::data::class_names::ClassNames`] instance
+/// Zero is never a valid id, which lets `Option<ClassId>` be the same size as `ClassId`.
+/// The id is only meaningful with the `ClassNames` that it came from, so if it is serialized then
+/// that must be serialized as well.
 #[derive(Copy, Clone)]
-pub struct ClassId(u32);
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct ClassId(NonZeroU32);
 impl ClassId {
     #[must_use]
+    /// # Panics
+    /// If `id` is zero
     pub fn new_unchecked(id: u32) -> ClassId {
-        ClassId(id)
+        ClassId(NonZeroU32::new(id).expect("ClassId of zero"))
     }
 
     #[must_use]
     pub fn get(self) -> u32 {
-        self.0
+        self.0.get()
+    }
+
+    /// Display the class's name in its dotted form, like `java.lang.String`
+    pub fn display<'a>(&self, class_names: &'a ClassNames) -> impl Display + 'a {
+        let id = *self;
+        DisplayFn(
+            move |f: &mut std::fmt::Formatter<'_>| match class_names.name_from_gcid(id) {
+                Ok((name, info)) if info.is_primitive() => {
+                    // Skip the prefix that keeps it from colliding with real class names
+                    f.write_str(&convert_classfile_text(&name.get()[1..]))
+                }
+                Ok((name, _)) => f.write_str(&convert_classfile_text(name.get()).replace('/', ".")),
+                Err(_) => write!(f, "[Unknown {:?}]", id),
+            },
+        )
     }
 }
 
@@ -23,7 +59,7 @@ impl PartialEq for ClassId {
 impl Eq for ClassId {}
 impl Hash for ClassId {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        state.write_u32(self.0)
+        state.write_u32(self.0.get())
     }
 }
 #[cfg(feature = "implementation-cheaper-map-hashing")]
@@ -36,6 +72,11 @@ impl std::fmt::Debug for ClassId {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct PackageId(u32);
 impl PackageId {
     pub(crate) fn new_unchecked(id: u32) -> PackageId {
@@ -48,12 +89,15 @@ impl PackageId {
 pub type MethodIndex = u16;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExactMethodId {
     class_id: ClassId,
     method_index: MethodIndex,
 }
 impl ExactMethodId {
     #[must_use]
+    /// Create the id without checking that the class has a method at that index
+    /// See [`crate::class::Class::method_id`] for a checked version.
     pub fn unchecked_compose(class_id: ClassId, method_index: MethodIndex) -> Self {
         Self {
             class_id,
@@ -68,6 +112,7 @@ impl ExactMethodId {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MethodId {
     Exact(ExactMethodId),
     ArrayClone,
@@ -90,6 +135,40 @@ impl MethodId {
             MethodId::ArrayClone => None,
         }
     }
+
+    /// Display the method like `java.lang.String.equals(Ljava/lang/Object;)Z`
+    /// If the class file for the method is not loaded then its index is used instead of its name
+    /// and descriptor.
+    pub fn display<'a>(
+        &self,
+        class_names: &'a ClassNames,
+        class_files: &'a ClassFiles,
+    ) -> impl Display + 'a {
+        let id = *self;
+        DisplayFn(move |f: &mut std::fmt::Formatter<'_>| {
+            let (class_id, method_index) = match id {
+                MethodId::Exact(id) => id.decompose(),
+                MethodId::ArrayClone => {
+                    return write!(
+                        f,
+                        "<array>.{}{}",
+                        ARRAY_CLONE_NAME,
+                        array_clone_descriptor()
+                    )
+                }
+            };
+
+            write!(f, "{}.", class_id.display(class_names))?;
+            let class_file = class_files.get(&class_id);
+            let name = class_file.and_then(|x| x.method_name(method_index));
+            let descriptor = class_file.and_then(|x| x.method_descriptor(method_index));
+            if let (Some(name), Some(descriptor)) = (name, descriptor) {
+                write!(f, "{}{}", name, descriptor)
+            } else {
+                write!(f, "#{}", method_index)
+            }
+        })
+    }
 }
 impl From<ExactMethodId> for MethodId {
     fn from(v: ExactMethodId) -> Self {
@@ -97,6 +176,78 @@ impl From<ExactMethodId> for MethodId {
     }
 }
 
+/// This is an index into the fields
+/// This is not meaningful without a class
+pub type FieldIndex = u16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExactFieldId {
+    class_id: ClassId,
+    field_index: FieldIndex,
+}
+impl ExactFieldId {
+    #[must_use]
+    pub fn unchecked_compose(class_id: ClassId, field_index: FieldIndex) -> Self {
+        Self {
+            class_id,
+            field_index,
+        }
+    }
+
+    #[must_use]
+    pub fn decompose(self) -> (ClassId, FieldIndex) {
+        (self.class_id, self.field_index)
+    }
+}
+// We write this as a single integer so that it works with the cheaper hasher, which only allows
+// one write.
+impl Hash for ExactFieldId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64((u64::from(self.class_id.get()) << 16) | u64::from(self.field_index))
+    }
+}
+#[cfg(feature = "implementation-cheaper-map-hashing")]
+impl nohash_hasher::IsEnabled for ExactFieldId {}
+
+/// Mirrors [`MethodId`], though there are currently no special fields that are not from a class
+/// file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldId {
+    Exact(ExactFieldId),
+}
+impl FieldId {
+    #[must_use]
+    pub fn unchecked_compose(class_id: ClassId, field_index: FieldIndex) -> Self {
+        FieldId::Exact(ExactFieldId::unchecked_compose(class_id, field_index))
+    }
+
+    #[must_use]
+    pub fn decompose(self) -> Option<(ClassId, FieldIndex)> {
+        self.into_exact().map(ExactFieldId::decompose)
+    }
+
+    #[must_use]
+    pub fn into_exact(self) -> Option<ExactFieldId> {
+        match self {
+            FieldId::Exact(x) => Some(x),
+        }
+    }
+}
+impl Hash for FieldId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            FieldId::Exact(x) => x.hash(state),
+        }
+    }
+}
+#[cfg(feature = "implementation-cheaper-map-hashing")]
+impl nohash_hasher::IsEnabled for FieldId {}
+impl From<ExactFieldId> for FieldId {
+    fn from(v: ExactFieldId) -> Self {
+        FieldId::Exact(v)
+    }
+}
+
 pub(crate) fn is_array_class(first: &str) -> bool {
     first.starts_with('[')
 }
@@ -104,3 +255,11 @@ pub(crate) fn is_array_class(first: &str) -> bool {
 pub(crate) fn is_array_class_bytes(first: &[u8]) -> bool {
     first.starts_with(&[b'['])
 }
+
+/// Implements [`Display`] through the function, for the display adapters
+struct DisplayFn<F: Fn(&mut std::fmt::Formatter<'_>) -> std::fmt::Result>(F);
+impl<F: Fn(&mut std::fmt::Formatter<'_>) -> std::fmt::Result> Display for DisplayFn<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        (self.0)(f)
+    }
+}
@@ -32,47 +32,118 @@ use data::{
     methods::{LoadCodeError, LoadMethodError, VerifyCodeExceptionError, VerifyMethodError},
 };
 use id::ClassId;
+use smallvec::SmallVec;
 
+/// Enter a `tracing` span at the debug level for the rest of the current scope, if the
+/// `tracing-spans` feature is enabled. The fields are not evaluated otherwise.
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing-spans")]
+        let _span = tracing::debug_span!($($arg)*).entered();
+    };
+}
+
+/// Emit a `tracing` event at the trace level, if the `tracing-spans` feature is enabled
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing-spans")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+pub mod annotation;
 pub mod class;
+pub mod class_reader;
+pub mod class_writer;
 pub mod code;
+pub mod constant_pool_builder;
 pub mod data;
 pub mod id;
+pub mod names;
 pub mod package;
+pub mod signature;
+pub mod symbol;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 pub mod util;
 
 // Note: Currently all of these errors use non_exhaustive, but in the future that may be removed
 // on some if there is a belief that they are likely to be stable.
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("bad class id {:?}", .id)]
 pub struct BadIdError {
     pub id: ClassId,
 }
 
-#[derive(Debug)]
+/// A class is its own super class, directly or indirectly
+/// This mirrors the JVM's `ClassCircularityError`. It can only happen with crafted class files,
+/// since the compiler would not produce them.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("circular class hierarchy: {:?}", .classes)]
+pub struct ClassCircularityError {
+    /// The classes in the cycle, in the order that they were walked
+    pub classes: Vec<ClassId>,
+}
+impl ClassCircularityError {
+    /// Record that a walk up the super classes reached `id`, erroring if it was already reached
+    pub(crate) fn visit(
+        visited: &mut SmallVec<[ClassId; 8]>,
+        id: ClassId,
+    ) -> Result<(), ClassCircularityError> {
+        if let Some(start) = visited.iter().position(|x| *x == id) {
+            return Err(ClassCircularityError {
+                classes: visited[start..].to_vec(),
+            });
+        }
+
+        visited.push(id);
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum StepError {
+    #[error(transparent)]
     Custom(Box<dyn std::error::Error>),
-    LoadClassFile(LoadClassFileError),
-    LoadClass(LoadClassError),
-    LoadMethod(LoadMethodError),
-    VerifyMethod(VerifyMethodError),
-    LoadCode(LoadCodeError),
-    VerifyCodeException(VerifyCodeExceptionError),
-    StackMapError(StackMapError),
-    StackInfoError(StackInfoError),
+    #[error("failed to load class file")]
+    LoadClassFile(#[source] LoadClassFileError),
+    #[error("failed to load class")]
+    LoadClass(#[source] LoadClassError),
+    #[error("failed to load method")]
+    LoadMethod(#[source] LoadMethodError),
+    #[error("failed to verify method")]
+    VerifyMethod(#[source] VerifyMethodError),
+    #[error("failed to load code")]
+    LoadCode(#[source] LoadCodeError),
+    #[error("failed to verify code exception")]
+    VerifyCodeException(#[source] VerifyCodeExceptionError),
+    #[error("stack map error")]
+    StackMapError(#[source] StackMapError),
+    #[error("stack info error")]
+    StackInfoError(#[source] StackInfoError),
+    #[error("invalid descriptor type: {0:?}")]
     DescriptorTypeError(classfile_parser::descriptor::DescriptorTypeError),
     /// Some code loaded a value and then tried accessing it but it was missing.
     /// This might be a sign that it shouldn't assume that, or a sign of a bug elsewhere
     /// that caused it to not load but also not reporting an error.
+    #[error("expected {0} to be loaded")]
     MissingLoadedValue(&'static str),
     /// Expected a class that wasn't an array
+    #[error("expected a class that was not an array")]
     ExpectedNonArrayClass,
     /// There was a problem indexing into the class file
-    ClassFileIndex(ClassFileIndexError),
+    #[error("class file index error")]
+    ClassFileIndex(#[source] ClassFileIndexError),
     /// There was a bad class id that didn't have a name stored
+    #[error(transparent)]
     BadId(BadIdError),
     /// The type held by a descriptor was unexpected
+    #[error("unexpected descriptor type")]
     UnexpectedDescriptorType,
+    #[error(transparent)]
+    ClassCircularity(ClassCircularityError),
 }
 impl From<LoadClassFileError> for StepError {
     fn from(err: LoadClassFileError) -> Self {
@@ -114,6 +185,11 @@ impl From<StackInfoError> for StepError {
         Self::StackInfoError(err)
     }
 }
+impl From<ClassCircularityError> for StepError {
+    fn from(err: ClassCircularityError) -> Self {
+        Self::ClassCircularity(err)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
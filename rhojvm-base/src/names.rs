@@ -0,0 +1,108 @@
+//! Conversions between the different forms of class names.
+//!
+//! Class files use the internal form, `java/lang/String` and `[Ljava/lang/String;`, while
+//! reflection uses dotted forms. Which one is wanted depends on the method:
+//! - `Class.getName` and `Class.forName` use the binary name, which for arrays keeps the
+//!   descriptor form but dotted: `java.lang.String` and `[Ljava.lang.String;`. Use
+//!   [`internal_to_binary`] and [`binary_to_internal`] for these.
+//! - `Class.getCanonicalName` and `Class.getTypeName` write arrays with brackets:
+//!   `java.lang.String[]` and `int[][]`. Use [`internal_to_canonical`] for these.
+//!   NOTE: The canonical name of a nested class uses `.` rather than `$`, but that requires the
+//!   `InnerClasses` attribute to know, so this does not do it.
+
+use std::borrow::Cow;
+
+/// Convert an internal name (`java/lang/String`, `[Ljava/lang/String;`) into the binary name
+/// that `Class.getName` returns (`java.lang.String`, `[Ljava.lang.String;`).
+#[must_use]
+pub fn internal_to_binary(name: &str) -> Cow<str> {
+    if name.contains('/') {
+        Cow::Owned(name.replace('/', "."))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// Convert a binary name (`java.lang.String`, `[Ljava.lang.String;`), such as those given to
+/// `Class.forName`, into the internal name (`java/lang/String`, `[Ljava/lang/String;`).
+#[must_use]
+pub fn binary_to_internal(name: &str) -> Cow<str> {
+    if name.contains('.') {
+        Cow::Owned(name.replace('.', "/"))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// Convert an internal name into the form that `Class.getCanonicalName` uses, where arrays are
+/// written with brackets: `[[I` becomes `int[][]` and `[Ljava/lang/String;` becomes
+/// `java.lang.String[]`.
+/// Returns `None` if it is an array with an invalid component type.
+#[must_use]
+pub fn internal_to_canonical(name: &str) -> Option<Cow<str>> {
+    let dimensions = name.bytes().take_while(|x| *x == b'[').count();
+    if dimensions == 0 {
+        return Some(internal_to_binary(name));
+    }
+
+    let component = &name[dimensions..];
+    let component = match component {
+        "B" => Cow::Borrowed("byte"),
+        "C" => Cow::Borrowed("char"),
+        "D" => Cow::Borrowed("double"),
+        "F" => Cow::Borrowed("float"),
+        "I" => Cow::Borrowed("int"),
+        "J" => Cow::Borrowed("long"),
+        "S" => Cow::Borrowed("short"),
+        "Z" => Cow::Borrowed("boolean"),
+        _ => {
+            let class_name = component.strip_prefix('L')?.strip_suffix(';')?;
+            if class_name.is_empty() {
+                return None;
+            }
+            internal_to_binary(class_name)
+        }
+    };
+
+    let mut result = String::with_capacity(component.len() + dimensions * 2);
+    result.push_str(&component);
+    for _ in 0..dimensions {
+        result.push_str("[]");
+    }
+
+    Some(Cow::Owned(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{binary_to_internal, internal_to_binary, internal_to_canonical};
+
+    #[test]
+    fn test_name_conversion() {
+        assert_eq!(internal_to_binary("java/lang/String"), "java.lang.String");
+        assert_eq!(internal_to_binary("Main"), "Main");
+        assert_eq!(
+            internal_to_binary("[Ljava/lang/String;"),
+            "[Ljava.lang.String;"
+        );
+        assert_eq!(internal_to_binary("[[I"), "[[I");
+
+        assert_eq!(binary_to_internal("java.lang.String"), "java/lang/String");
+        assert_eq!(
+            binary_to_internal("[Ljava.lang.String;"),
+            "[Ljava/lang/String;"
+        );
+
+        assert_eq!(
+            internal_to_canonical("java/util/Map$Entry").as_deref(),
+            Some("java.util.Map$Entry")
+        );
+        assert_eq!(internal_to_canonical("[[I").as_deref(), Some("int[][]"));
+        assert_eq!(
+            internal_to_canonical("[Ljava/lang/String;").as_deref(),
+            Some("java.lang.String[]")
+        );
+        assert_eq!(internal_to_canonical("[Q").as_deref(), None);
+        assert_eq!(internal_to_canonical("[L;").as_deref(), None);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::id::PackageId;
+use std::borrow::Cow;
+
+use crate::{id::PackageId, util::convert_classfile_text};
 
 #[derive(Debug, Clone, Default)]
 pub struct Packages {
@@ -34,6 +36,23 @@ impl Packages {
         self.packages.iter_mut().find(|x| x.id() == id)
     }
 
+    #[must_use]
+    /// Get the name of the package, like `java/lang`
+    pub fn package_name(&self, id: PackageId) -> Option<Cow<'_, str>> {
+        self.get(id)
+            .map(|package| convert_classfile_text(package.name()))
+    }
+
+    #[must_use]
+    /// Get the package that contains this package, such as `java` for `java/lang`
+    /// Packages are only registered when they contain a class, so this returns `None` if the
+    /// parent has not been registered, as well as for topmost packages.
+    pub fn parent_package(&self, id: PackageId) -> Option<PackageId> {
+        let name = self.get(id)?.name();
+        let end = name.iter().rposition(|x| *x == b'/')?;
+        self.path_find(&name[..end]).map(Package::id)
+    }
+
     #[must_use]
     pub fn path_find(&self, name: &[u8]) -> Option<&Package> {
         self.packages.iter().find(|x| x.name == name)
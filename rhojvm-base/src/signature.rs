@@ -0,0 +1,501 @@
+//! Parsing of the `Signature` attribute, which holds the generic type information that is erased
+//! from descriptors.
+//! Ex: `List<String> get(int)` has the descriptor `(I)Ljava/util/List;` but the signature
+//! `(I)Ljava/util/List<Ljava/lang/String;>;`
+//! See the JVM spec section 4.7.9.1 for the grammar.
+
+use crate::code::types::PrimitiveType;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SignatureParseError {
+    /// The signature ended before it was complete
+    #[error("signature ended unexpectedly")]
+    UnexpectedEnd,
+    /// Expected something else at this byte
+    #[error("unexpected character {ch:?} at byte {index}")]
+    UnexpectedChar { index: usize, ch: char },
+    /// An identifier, like a class or type variable name, was empty
+    #[error("empty identifier at byte {index}")]
+    EmptyIdentifier { index: usize },
+    /// There was data remaining after a complete signature
+    #[error("trailing data at byte {index}")]
+    TrailingData { index: usize },
+}
+
+/// Any type, including primitives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JavaTypeSignature {
+    Primitive(PrimitiveType),
+    Reference(ReferenceTypeSignature),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReferenceTypeSignature {
+    Class(ClassTypeSignature),
+    /// A use of a type parameter, like the `T` in `List<T>`
+    TypeVariable(String),
+    /// An array with the given component type
+    Array(Box<JavaTypeSignature>),
+}
+
+/// A class type, possibly with type arguments on it and on its outer classes
+/// Ex: `java/util/Map<TK;TV;>.Entry<TK;TV;>` is in the package `java/util`, with the name
+/// `Map` and the suffix `Entry`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassTypeSignature {
+    /// The package in internal form, like `java/util`. Empty for the default package.
+    pub package: String,
+    pub class: SimpleClassTypeSignature,
+    /// The inner classes, in order from outermost to innermost
+    pub suffixes: Vec<SimpleClassTypeSignature>,
+}
+impl ClassTypeSignature {
+    #[must_use]
+    /// The internal name of the class that this refers to, with the type arguments erased
+    /// Ex: `java/util/Map$Entry`
+    pub fn internal_name(&self) -> String {
+        let mut name = self.package.clone();
+        if !name.is_empty() {
+            name.push('/');
+        }
+        name.push_str(&self.class.name);
+        for suffix in &self.suffixes {
+            name.push('$');
+            name.push_str(&suffix.name);
+        }
+
+        name
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleClassTypeSignature {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeArgument {
+    /// `*`, an unbounded wildcard, `?` in Java
+    Wildcard,
+    /// A specific type
+    Exact(ReferenceTypeSignature),
+    /// `+`, `? extends T` in Java
+    Extends(ReferenceTypeSignature),
+    /// `-`, `? super T` in Java
+    Super(ReferenceTypeSignature),
+}
+
+/// A declared type parameter, like `T extends Number & Comparable<T>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeParameter {
+    pub name: String,
+    /// The bound which is a class or type variable
+    /// This is `None` if the type parameter is only bounded by interfaces.
+    pub class_bound: Option<ReferenceTypeSignature>,
+    pub interface_bounds: Vec<ReferenceTypeSignature>,
+}
+
+/// The signature of a class, from its `Signature` attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub super_class: ClassTypeSignature,
+    pub interfaces: Vec<ClassTypeSignature>,
+}
+
+/// The signature of a method, from its `Signature` attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<JavaTypeSignature>,
+    /// `None` if it returns `void`
+    pub return_type: Option<JavaTypeSignature>,
+    /// The declared exceptions, which are either classes or type variables
+    pub throws: Vec<ReferenceTypeSignature>,
+}
+
+/// The signature of a field, from its `Signature` attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSignature(pub ReferenceTypeSignature);
+
+pub fn parse_class_signature(text: &str) -> Result<ClassSignature, SignatureParseError> {
+    let mut parser = Parser::new(text);
+    let type_parameters = parser.type_parameters()?;
+    let super_class = parser.class_type()?;
+    let mut interfaces = Vec::new();
+    while !parser.is_empty() {
+        interfaces.push(parser.class_type()?);
+    }
+
+    Ok(ClassSignature {
+        type_parameters,
+        super_class,
+        interfaces,
+    })
+}
+
+pub fn parse_method_signature(text: &str) -> Result<MethodSignature, SignatureParseError> {
+    let mut parser = Parser::new(text);
+    let type_parameters = parser.type_parameters()?;
+
+    parser.expect(b'(')?;
+    let mut parameters = Vec::new();
+    while parser.peek()? != b')' {
+        parameters.push(parser.java_type()?);
+    }
+    parser.expect(b')')?;
+
+    let return_type = if parser.peek()? == b'V' {
+        parser.pos += 1;
+        None
+    } else {
+        Some(parser.java_type()?)
+    };
+
+    let mut throws = Vec::new();
+    while !parser.is_empty() {
+        parser.expect(b'^')?;
+        let throw = match parser.peek()? {
+            b'T' => parser.type_variable()?,
+            _ => ReferenceTypeSignature::Class(parser.class_type()?),
+        };
+        throws.push(throw);
+    }
+
+    Ok(MethodSignature {
+        type_parameters,
+        parameters,
+        return_type,
+        throws,
+    })
+}
+
+pub fn parse_field_signature(text: &str) -> Result<FieldSignature, SignatureParseError> {
+    let mut parser = Parser::new(text);
+    let typ = parser.reference_type()?;
+    parser.finish()?;
+    Ok(FieldSignature(typ))
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Parser<'a> {
+        Parser { text, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.text.len()
+    }
+
+    fn peek(&self) -> Result<u8, SignatureParseError> {
+        self.text
+            .as_bytes()
+            .get(self.pos)
+            .copied()
+            .ok_or(SignatureParseError::UnexpectedEnd)
+    }
+
+    fn take(&mut self) -> Result<u8, SignatureParseError> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn unexpected(&self, index: usize) -> SignatureParseError {
+        let ch = self
+            .text
+            .get(index..)
+            .and_then(|rest| rest.chars().next())
+            .unwrap_or('\0');
+        SignatureParseError::UnexpectedChar { index, ch }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), SignatureParseError> {
+        let index = self.pos;
+        if self.take()? == expected {
+            Ok(())
+        } else {
+            Err(self.unexpected(index))
+        }
+    }
+
+    fn finish(&self) -> Result<(), SignatureParseError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(SignatureParseError::TrailingData { index: self.pos })
+        }
+    }
+
+    /// Parse an identifier, which continues until one of the characters that can't be in it
+    fn identifier(&mut self) -> Result<&'a str, SignatureParseError> {
+        let start = self.pos;
+        let len = self.text.as_bytes()[start..]
+            .iter()
+            .take_while(|x| !matches!(x, b'.' | b';' | b'[' | b'/' | b'<' | b'>' | b':'))
+            .count();
+        if len == 0 {
+            return if self.is_empty() {
+                Err(SignatureParseError::UnexpectedEnd)
+            } else {
+                Err(SignatureParseError::EmptyIdentifier { index: start })
+            };
+        }
+
+        self.pos += len;
+        Ok(&self.text[start..self.pos])
+    }
+
+    fn type_parameters(&mut self) -> Result<Vec<TypeParameter>, SignatureParseError> {
+        let mut type_parameters = Vec::new();
+        if self.is_empty() || self.peek()? != b'<' {
+            return Ok(type_parameters);
+        }
+
+        self.pos += 1;
+        loop {
+            let name = self.identifier()?.to_owned();
+
+            self.expect(b':')?;
+            // The class bound is optional, in which case the interface bounds follow directly
+            let class_bound = if self.peek()? == b':' {
+                None
+            } else {
+                Some(self.reference_type()?)
+            };
+
+            let mut interface_bounds = Vec::new();
+            while self.peek()? == b':' {
+                self.pos += 1;
+                interface_bounds.push(self.reference_type()?);
+            }
+
+            type_parameters.push(TypeParameter {
+                name,
+                class_bound,
+                interface_bounds,
+            });
+
+            if self.peek()? == b'>' {
+                self.pos += 1;
+                break;
+            }
+        }
+
+        Ok(type_parameters)
+    }
+
+    fn java_type(&mut self) -> Result<JavaTypeSignature, SignatureParseError> {
+        let prim = match self.peek()? {
+            b'B' => PrimitiveType::Byte,
+            b'C' => PrimitiveType::Char,
+            b'D' => PrimitiveType::Double,
+            b'F' => PrimitiveType::Float,
+            b'I' => PrimitiveType::Int,
+            b'J' => PrimitiveType::Long,
+            b'S' => PrimitiveType::Short,
+            b'Z' => PrimitiveType::Boolean,
+            _ => return self.reference_type().map(JavaTypeSignature::Reference),
+        };
+
+        self.pos += 1;
+        Ok(JavaTypeSignature::Primitive(prim))
+    }
+
+    fn reference_type(&mut self) -> Result<ReferenceTypeSignature, SignatureParseError> {
+        match self.peek()? {
+            b'L' => self.class_type().map(ReferenceTypeSignature::Class),
+            b'T' => self.type_variable(),
+            b'[' => {
+                self.pos += 1;
+                let component = self.java_type()?;
+                Ok(ReferenceTypeSignature::Array(Box::new(component)))
+            }
+            _ => Err(self.unexpected(self.pos)),
+        }
+    }
+
+    fn type_variable(&mut self) -> Result<ReferenceTypeSignature, SignatureParseError> {
+        self.expect(b'T')?;
+        let name = self.identifier()?.to_owned();
+        self.expect(b';')?;
+        Ok(ReferenceTypeSignature::TypeVariable(name))
+    }
+
+    fn class_type(&mut self) -> Result<ClassTypeSignature, SignatureParseError> {
+        self.expect(b'L')?;
+
+        // The package segments are each followed by a `/`, and the last identifier is the class
+        let mut package = String::new();
+        let mut name = self.identifier()?;
+        while self.peek()? == b'/' {
+            self.pos += 1;
+            if !package.is_empty() {
+                package.push('/');
+            }
+            package.push_str(name);
+            name = self.identifier()?;
+        }
+
+        let class = SimpleClassTypeSignature {
+            name: name.to_owned(),
+            type_arguments: self.type_arguments()?,
+        };
+
+        let mut suffixes = Vec::new();
+        while self.peek()? == b'.' {
+            self.pos += 1;
+            suffixes.push(SimpleClassTypeSignature {
+                name: self.identifier()?.to_owned(),
+                type_arguments: self.type_arguments()?,
+            });
+        }
+
+        self.expect(b';')?;
+
+        Ok(ClassTypeSignature {
+            package,
+            class,
+            suffixes,
+        })
+    }
+
+    fn type_arguments(&mut self) -> Result<Vec<TypeArgument>, SignatureParseError> {
+        let mut type_arguments = Vec::new();
+        if self.peek()? != b'<' {
+            return Ok(type_arguments);
+        }
+
+        self.pos += 1;
+        loop {
+            let argument = match self.peek()? {
+                b'*' => {
+                    self.pos += 1;
+                    TypeArgument::Wildcard
+                }
+                b'+' => {
+                    self.pos += 1;
+                    TypeArgument::Extends(self.reference_type()?)
+                }
+                b'-' => {
+                    self.pos += 1;
+                    TypeArgument::Super(self.reference_type()?)
+                }
+                _ => TypeArgument::Exact(self.reference_type()?),
+            };
+            type_arguments.push(argument);
+
+            if self.peek()? == b'>' {
+                self.pos += 1;
+                break;
+            }
+        }
+
+        Ok(type_arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code::types::PrimitiveType;
+
+    use super::{
+        parse_class_signature, parse_field_signature, parse_method_signature, JavaTypeSignature,
+        ReferenceTypeSignature, SignatureParseError, TypeArgument,
+    };
+
+    #[test]
+    fn test_class_signature() {
+        // class Foo<K extends Comparable<K>, V> extends AbstractMap<K, V> implements Cloneable
+        let sig = parse_class_signature(
+            "<K::Ljava/lang/Comparable<TK;>;V:Ljava/lang/Object;>Ljava/util/AbstractMap<TK;TV;>;Ljava/lang/Cloneable;",
+        )
+        .unwrap();
+
+        assert_eq!(sig.type_parameters.len(), 2);
+        assert_eq!(sig.type_parameters[0].name, "K");
+        assert_eq!(sig.type_parameters[0].class_bound, None);
+        assert_eq!(sig.type_parameters[0].interface_bounds.len(), 1);
+        assert_eq!(sig.type_parameters[1].name, "V");
+        assert!(sig.type_parameters[1].class_bound.is_some());
+
+        assert_eq!(sig.super_class.internal_name(), "java/util/AbstractMap");
+        assert_eq!(
+            sig.super_class.class.type_arguments,
+            vec![
+                TypeArgument::Exact(ReferenceTypeSignature::TypeVariable("K".to_owned())),
+                TypeArgument::Exact(ReferenceTypeSignature::TypeVariable("V".to_owned())),
+            ]
+        );
+        assert_eq!(sig.interfaces.len(), 1);
+        assert_eq!(sig.interfaces[0].internal_name(), "java/lang/Cloneable");
+    }
+
+    #[test]
+    fn test_method_signature() {
+        // <T extends Throwable> void run(List<? extends T>[], int) throws T, IOException
+        let sig = parse_method_signature(
+            "<T:Ljava/lang/Throwable;>([Ljava/util/List<+TT;>;I)V^TT;^Ljava/io/IOException;",
+        )
+        .unwrap();
+
+        assert_eq!(sig.type_parameters.len(), 1);
+        assert_eq!(sig.parameters.len(), 2);
+        assert!(matches!(
+            &sig.parameters[0],
+            JavaTypeSignature::Reference(ReferenceTypeSignature::Array(_))
+        ));
+        assert_eq!(
+            sig.parameters[1],
+            JavaTypeSignature::Primitive(PrimitiveType::Int)
+        );
+        assert_eq!(sig.return_type, None);
+        assert_eq!(sig.throws.len(), 2);
+        assert_eq!(
+            sig.throws[0],
+            ReferenceTypeSignature::TypeVariable("T".to_owned())
+        );
+
+        let sig =
+            parse_method_signature("()Ljava/util/Map<*-Ljava/lang/Integer;>.Entry<TK;>;").unwrap();
+        let Some(JavaTypeSignature::Reference(ReferenceTypeSignature::Class(ret))) =
+            sig.return_type
+        else {
+            panic!("Expected a class return type");
+        };
+        assert_eq!(ret.internal_name(), "java/util/Map$Entry");
+        assert_eq!(ret.class.type_arguments[0], TypeArgument::Wildcard);
+        assert_eq!(ret.suffixes[0].type_arguments.len(), 1);
+    }
+
+    #[test]
+    fn test_field_signature() {
+        let sig = parse_field_signature("LFoo<[[I>;").unwrap();
+        let ReferenceTypeSignature::Class(class) = sig.0 else {
+            panic!("Expected a class type");
+        };
+        assert_eq!(class.package, "");
+        assert_eq!(class.internal_name(), "Foo");
+
+        assert_eq!(
+            parse_field_signature("I"),
+            Err(SignatureParseError::UnexpectedChar { index: 0, ch: 'I' })
+        );
+        assert_eq!(
+            parse_field_signature("Ljava/util/List<"),
+            Err(SignatureParseError::UnexpectedEnd)
+        );
+        assert_eq!(
+            parse_field_signature("TT;;"),
+            Err(SignatureParseError::TrailingData { index: 3 })
+        );
+        assert_eq!(
+            parse_field_signature("Ljava//List;"),
+            Err(SignatureParseError::EmptyIdentifier { index: 6 })
+        );
+    }
+}
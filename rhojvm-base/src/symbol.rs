@@ -0,0 +1,89 @@
+//! Interning of the text in class files
+//! The same strings, like `()V`, `<init>`, and `java/lang/Object`, appear in almost every class
+//! file. Interning them into [`Symbols`] stores each only once and makes comparing them a cheap
+//! id comparison.
+//! This is optional, see [`crate::class::ClassFileData::get_symbol`].
+
+use indexmap::IndexSet;
+
+/// An interned string, which is only meaningful with the [`Symbols`] that it came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Symbol(u32);
+impl Symbol {
+    #[must_use]
+    pub fn get(self) -> u32 {
+        self.0
+    }
+
+    fn from_index(index: usize) -> Symbol {
+        Symbol(u32::try_from(index).expect("Interned more than u32::MAX strings"))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Symbols {
+    strings: IndexSet<Box<str>>,
+}
+impl Symbols {
+    #[must_use]
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    /// Get the symbol for the text, interning it if it is new
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(index) = self.strings.get_index_of(text) {
+            return Symbol::from_index(index);
+        }
+
+        let (index, _) = self.strings.insert_full(Box::from(text));
+        Symbol::from_index(index)
+    }
+
+    #[must_use]
+    /// Get the symbol for the text, if it has been interned
+    pub fn get(&self, text: &str) -> Option<Symbol> {
+        self.strings.get_index_of(text).map(Symbol::from_index)
+    }
+
+    #[must_use]
+    /// Get the text of the symbol
+    /// Panics if the symbol came from a different [`Symbols`] instance and is out of range.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+
+    #[must_use]
+    /// The number of interned strings
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Symbols;
+
+    #[test]
+    fn test_intern() {
+        let mut symbols = Symbols::new();
+        assert!(symbols.is_empty());
+
+        let init = symbols.intern("<init>");
+        let void_desc = symbols.intern("()V");
+        assert_ne!(init, void_desc);
+        assert_eq!(symbols.intern("<init>"), init);
+        assert_eq!(symbols.get("()V"), Some(void_desc));
+        assert_eq!(symbols.get("<clinit>"), None);
+        assert_eq!(symbols.len(), 2);
+
+        assert_eq!(symbols.resolve(init), "<init>");
+        assert_eq!(symbols.resolve(void_desc), "()V");
+    }
+}
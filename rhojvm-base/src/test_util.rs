@@ -0,0 +1,366 @@
+//! Helpers shared by the tests, for building class files without having to write out their bytes
+//! and loading them from memory
+//! This is available to the tests of other crates through the `test-util` feature.
+
+use std::collections::HashMap;
+
+use classfile_parser::method_info::MethodAccessFlags;
+
+use crate::{
+    class::ClassFileData,
+    class_writer::{AttributeParts, ClassFileParts, CodeParts, MemberParts},
+    code::{
+        builder::{CodeBuilder, CodeBuilderError},
+        CodeInfo,
+    },
+    data::{
+        class_file_loader::{ClassFileLoader, LoadClassFileError, LoadResourceError, Resource},
+        class_files::ClassFiles,
+        class_names::ClassNames,
+        classes::Classes,
+        methods::Methods,
+    },
+    id::{ClassId, ExactMethodId, MethodIndex},
+    package::Packages,
+};
+
+pub const ACC_PUBLIC: u16 = 0x0001;
+pub const ACC_PRIVATE: u16 = 0x0002;
+pub const ACC_PROTECTED: u16 = 0x0004;
+pub const ACC_STATIC: u16 = 0x0008;
+pub const ACC_FINAL: u16 = 0x0010;
+pub const ACC_ABSTRACT: u16 = 0x0400;
+
+#[must_use]
+/// Encode the values as big-endian `u16`s, which most attribute data is made of
+pub fn u16s(values: &[u16]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+/// A loader with no classes, so that only the class files inserted directly are available
+pub struct EmptyLoader;
+impl ClassFileLoader for EmptyLoader {
+    fn load_class_file_by_id(
+        &mut self,
+        _class_names: &ClassNames,
+        _class_file_id: ClassId,
+    ) -> Result<Option<ClassFileData>, LoadClassFileError> {
+        Err(LoadClassFileError::Nonexistent)
+    }
+
+    fn load_resource(&mut self, _resource_name: &str) -> Result<Resource, LoadResourceError> {
+        Err(LoadResourceError::Nonexistent)
+    }
+
+    fn has_resource(&mut self, _resource_name: &str) -> bool {
+        false
+    }
+}
+
+#[must_use]
+/// The data of a `Code` attribute with no exception handlers, excluding its name index and length
+pub fn code_attribute(
+    max_stack: u16,
+    max_locals: u16,
+    code: &[u8],
+    attributes: &[AttributeParts],
+) -> Vec<u8> {
+    CodeParts {
+        max_stack,
+        max_locals,
+        code: code.to_vec(),
+        exception_table: Vec::new(),
+        attributes: attributes.to_vec(),
+    }
+    .write()
+    .unwrap()
+}
+
+/// A class file being built for a test
+/// Members are given as their raw access flags, and methods added through this have no code.
+#[derive(Clone)]
+pub struct TestClass {
+    name: String,
+    pub parts: ClassFileParts,
+}
+impl TestClass {
+    #[must_use]
+    pub fn new(this: &str, super_name: Option<&str>) -> TestClass {
+        TestClass {
+            name: this.to_owned(),
+            parts: ClassFileParts::new(this, super_name).unwrap(),
+        }
+    }
+
+    #[must_use]
+    pub fn access(mut self, access_flags: u16) -> TestClass {
+        self.parts.access_flags = access_flags;
+        self
+    }
+
+    #[must_use]
+    pub fn interface(mut self, name: &str) -> TestClass {
+        let index = self.parts.constant_pool.add_class(name).unwrap();
+        self.parts.interfaces.push(index);
+        self
+    }
+
+    #[must_use]
+    pub fn field(mut self, access_flags: u16, name: &str, descriptor: &str) -> TestClass {
+        let field = self.member(access_flags, name, descriptor);
+        self.parts.fields.push(field);
+        self
+    }
+
+    #[must_use]
+    pub fn method(mut self, access_flags: u16, name: &str, descriptor: &str) -> TestClass {
+        let method = self.member(access_flags, name, descriptor);
+        self.parts.methods.push(method);
+        self
+    }
+
+    #[must_use]
+    /// Add a class attribute with the given data, excluding its name index and length
+    pub fn attribute(mut self, name: &str, data: Vec<u8>) -> TestClass {
+        let name_index = self.parts.constant_pool.add_utf8(name).unwrap();
+        self.parts
+            .attributes
+            .push(AttributeParts { name_index, data });
+        self
+    }
+
+    #[must_use]
+    /// Add an attribute to the field that was added last
+    pub fn field_attribute(mut self, name: &str, data: Vec<u8>) -> TestClass {
+        let name_index = self.parts.constant_pool.add_utf8(name).unwrap();
+        let field = self.parts.fields.last_mut().expect("No field to add to");
+        field.attributes.push(AttributeParts { name_index, data });
+        self
+    }
+
+    #[must_use]
+    /// Add an attribute to the method that was added last
+    pub fn method_attribute(mut self, name: &str, data: Vec<u8>) -> TestClass {
+        let name_index = self.parts.constant_pool.add_utf8(name).unwrap();
+        let method = self.parts.methods.last_mut().expect("No method to add to");
+        method.attributes.push(AttributeParts { name_index, data });
+        self
+    }
+
+    fn member(&mut self, access_flags: u16, name: &str, descriptor: &str) -> MemberParts {
+        let pool = &mut self.parts.constant_pool;
+        MemberParts {
+            access_flags,
+            name_index: pool.add_utf8(name).unwrap(),
+            descriptor_index: pool.add_utf8(descriptor).unwrap(),
+            attributes: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn bytes(&self) -> Vec<u8> {
+        self.parts.write().unwrap()
+    }
+
+    #[must_use]
+    pub fn build(&self, id: ClassId) -> ClassFileData {
+        ClassFileData::from_bytes(id, &self.bytes()).unwrap()
+    }
+
+    /// Add the class file to `class_files`, under the id of its name
+    pub fn insert(&self, class_names: &mut ClassNames, class_files: &mut ClassFiles) -> ClassId {
+        let id = class_names.gcid_from_bytes(self.name.as_bytes());
+        class_files.set_at(id, self.build(id));
+        id
+    }
+}
+
+/// A loader for class files which are kept in memory, by their name
+pub struct MemoryLoader {
+    classes: HashMap<Vec<u8>, Vec<u8>>,
+}
+impl MemoryLoader {
+    #[must_use]
+    /// A loader for `java/lang/Object`, which only has an empty constructor, and each of the
+    /// classes
+    pub fn new(classes: &[(&str, &ClassFileParts)]) -> MemoryLoader {
+        // return
+        let object = TestClass::new("java/lang/Object", None)
+            .method(ACC_PUBLIC, "<init>", "()V")
+            .method_attribute("Code", code_attribute(0, 1, &[0xB1], &[]));
+        let mut files = HashMap::new();
+        files.insert(b"java/lang/Object".to_vec(), object.bytes());
+        for (name, parts) in classes {
+            files.insert(name.as_bytes().to_vec(), parts.write().unwrap());
+        }
+
+        MemoryLoader { classes: files }
+    }
+}
+impl ClassFileLoader for MemoryLoader {
+    fn load_class_file_by_id(
+        &mut self,
+        class_names: &ClassNames,
+        class_file_id: ClassId,
+    ) -> Result<Option<ClassFileData>, LoadClassFileError> {
+        let (class_name, class_info) = class_names
+            .name_from_gcid(class_file_id)
+            .map_err(LoadClassFileError::BadId)?;
+        if !class_info.has_class_file() {
+            return Ok(None);
+        }
+
+        let bytes = self
+            .classes
+            .get(class_name.get())
+            .ok_or(LoadClassFileError::Nonexistent)?;
+        ClassFileData::from_bytes(class_file_id, bytes).map(Some)
+    }
+
+    fn load_resource(&mut self, _resource_name: &str) -> Result<Resource, LoadResourceError> {
+        Err(LoadResourceError::Nonexistent)
+    }
+
+    fn has_resource(&mut self, _resource_name: &str) -> bool {
+        false
+    }
+}
+
+#[must_use]
+/// A class named `name` which extends `java/lang/Object`
+pub fn test_class(name: &str) -> ClassFileParts {
+    ClassFileParts::new(name, Some("java/lang/Object")).unwrap()
+}
+
+/// Build the code of a method with the descriptor, with constants being added to the class's
+/// constant pool
+pub fn build_code(
+    parts: &mut ClassFileParts,
+    descriptor: &str,
+    is_static: bool,
+    build: impl FnOnce(&mut CodeBuilder) -> Result<(), CodeBuilderError>,
+) -> CodeParts {
+    let mut code = CodeBuilder::new(&mut parts.constant_pool, descriptor, is_static).unwrap();
+    build(&mut code).unwrap();
+    code.build().unwrap()
+}
+
+/// Build the code of a static method with the descriptor
+pub fn static_code(
+    parts: &mut ClassFileParts,
+    descriptor: &str,
+    build: impl FnOnce(&mut CodeBuilder) -> Result<(), CodeBuilderError>,
+) -> CodeParts {
+    build_code(parts, descriptor, true, build)
+}
+
+/// Add a `StackMapTable` to the code, with `frames` being the encoded entries
+pub fn add_stack_map_table(
+    parts: &mut ClassFileParts,
+    code: &mut CodeParts,
+    frame_count: u16,
+    frames: &[u8],
+) {
+    let mut data = frame_count.to_be_bytes().to_vec();
+    data.extend(frames);
+    code.attributes.push(AttributeParts {
+        name_index: parts.constant_pool.add_utf8("StackMapTable").unwrap(),
+        data,
+    });
+}
+
+/// Add a public static method named `test` with the code, returning its index
+pub fn add_test_method(
+    parts: &mut ClassFileParts,
+    descriptor: &str,
+    code: &CodeParts,
+) -> MethodIndex {
+    parts
+        .add_method(
+            "test",
+            descriptor,
+            MethodAccessFlags::PUBLIC | MethodAccessFlags::STATIC,
+            Some(code),
+        )
+        .unwrap()
+}
+
+/// Add a method with code built by `build`, returning its index
+pub fn add_method(
+    parts: &mut ClassFileParts,
+    access: MethodAccessFlags,
+    name: &str,
+    descriptor: &str,
+    build: impl FnOnce(&mut CodeBuilder) -> Result<(), CodeBuilderError>,
+) -> MethodIndex {
+    let is_static = access.contains(MethodAccessFlags::STATIC);
+    let code = build_code(parts, descriptor, is_static, build);
+    parts
+        .add_method(name, descriptor, access, Some(&code))
+        .unwrap()
+}
+
+/// Add a static method with code built by `build`, returning its index
+pub fn add_static_method(
+    parts: &mut ClassFileParts,
+    access: MethodAccessFlags,
+    name: &str,
+    descriptor: &str,
+    build: impl FnOnce(&mut CodeBuilder) -> Result<(), CodeBuilderError>,
+) -> MethodIndex {
+    add_method(
+        parts,
+        access | MethodAccessFlags::STATIC,
+        name,
+        descriptor,
+        build,
+    )
+}
+
+/// The state for loading classes, which can load `java/lang/Object` and the classes that it was
+/// made with
+pub struct TestEnv {
+    pub class_names: ClassNames,
+    pub class_files: ClassFiles,
+    pub classes: Classes,
+    pub packages: Packages,
+    pub methods: Methods,
+}
+impl TestEnv {
+    #[must_use]
+    pub fn new(classes: &[(&str, &ClassFileParts)]) -> TestEnv {
+        TestEnv {
+            class_names: ClassNames::default(),
+            class_files: ClassFiles::new(MemoryLoader::new(classes)),
+            classes: Classes::default(),
+            packages: Packages::default(),
+            methods: Methods::default(),
+        }
+    }
+
+    /// Load the class file, and the code of its method at `method_index`
+    pub fn load_code(
+        &mut self,
+        name: &str,
+        method_index: MethodIndex,
+    ) -> (ClassFileData, CodeInfo) {
+        let class_id = self.class_names.gcid_from_bytes(name.as_bytes());
+        self.class_files
+            .load_by_class_path_id(&mut self.class_names, class_id)
+            .unwrap();
+        let class_file = self.class_files.get(&class_id).unwrap().clone();
+
+        self.methods
+            .load_method_from_index(&mut self.class_names, &class_file, method_index)
+            .unwrap();
+        let method_id = ExactMethodId::unchecked_compose(class_id, method_index);
+        let method = self.methods.get_mut(&method_id).unwrap();
+        method.load_code(&mut self.class_files).unwrap();
+        let code = method.code().unwrap().clone();
+
+        (class_file, code)
+    }
+}
@@ -20,11 +20,29 @@ impl<'a> std::fmt::Debug for Cesu8Str<'a> {
     }
 }
 
+/// Decode the 'modified UTF-8' that Java class files use for their strings into Rust's utf8.
+/// This differs from standard UTF-8 in that NUL is encoded as two bytes and supplementary
+/// characters are encoded as a pair of surrogates, each taking three bytes.
+/// Returns `None` if the bytes are not valid modified UTF-8.
+/// This only allocates if the text contains NUL or supplementary characters.
+#[must_use]
+pub fn decode_modified_utf8(bytes: &[u8]) -> Option<std::borrow::Cow<str>> {
+    // Without any NUL bytes or four-byte sequences, valid UTF-8 is encoded identically in both,
+    // which covers almost every string in a class file
+    if !bytes.iter().any(|&b| b == 0 || b >= 0xF0) {
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            return Some(std::borrow::Cow::Borrowed(text));
+        }
+    }
+
+    cesu8::from_java_cesu8(bytes).ok()
+}
+
 /// Tries converting cesu8-java-style strings into Rust's utf8 strings
 /// This tries to avoid allocating but may not be able to avoid it
 #[must_use]
 pub fn convert_classfile_text(bytes: &[u8]) -> std::borrow::Cow<str> {
-    cesu8::from_java_cesu8(bytes).unwrap_or_else(|_| String::from_utf8_lossy(bytes))
+    decode_modified_utf8(bytes).unwrap_or_else(|| String::from_utf8_lossy(bytes))
 }
 
 /// Note: This will work fine for path to a class as well
@@ -155,3 +173,36 @@ impl<T> HashWrapperTrait<T> for HashWrapper {
     #[cfg(not(feature = "implementation-cheaper-map-hashing"))]
     type HashMapHasher = std::collections::hash_map::RandomState;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::decode_modified_utf8;
+
+    #[test]
+    fn test_decode_modified_utf8() {
+        assert!(matches!(
+            decode_modified_utf8(b"java/lang/Object"),
+            Some(Cow::Borrowed("java/lang/Object"))
+        ));
+        assert!(matches!(
+            decode_modified_utf8("h\u{e9}llo".as_bytes()),
+            Some(Cow::Borrowed("h\u{e9}llo"))
+        ));
+
+        // NUL is encoded as two bytes
+        assert_eq!(
+            decode_modified_utf8(&[b'a', 0xC0, 0x80, b'b']).as_deref(),
+            Some("a\0b")
+        );
+        // Supplementary characters are encoded as a surrogate pair
+        assert_eq!(
+            decode_modified_utf8(&[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]).as_deref(),
+            Some("\u{1F600}")
+        );
+
+        // Supplementary characters as a standard four-byte sequence are not valid
+        assert_eq!(decode_modified_utf8("\u{1F600}".as_bytes()), None);
+    }
+}
@@ -0,0 +1,212 @@
+//! Loading class files without blocking the executor of an async runtime
+//! The bytes are read with `tokio::fs`, or on a blocking thread for jars, and the class file is
+//! validated and parsed on a blocking thread as well. The parsed class file is only made into a
+//! [`ClassFileData`], which shares its data through an `Rc`, once it is back on the calling task.
+
+use std::path::{Path, PathBuf};
+
+use rhojvm_base::{
+    class::{ClassFileData, ClassFileSource, ParsedClassFile},
+    data::{class_file_loader::LoadClassFileError, class_names::SyncClassNames},
+    util::access_path_iter,
+};
+
+use crate::{
+    class_path::DirectoryClassSource, class_path_iter_to_relative_path,
+    class_path_iter_to_relative_path_string, jar_loader::JarClassFileLoader, ClassDirectories,
+};
+
+/// Read the file, returning `Ok(None)` if it does not exist
+async fn read_optional(path: &Path) -> Result<Option<Vec<u8>>, LoadClassFileError> {
+    match tokio::fs::read(path).await {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(LoadClassFileError::ReadError(err)),
+    }
+}
+
+/// Run `f` on a blocking thread
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, LoadClassFileError> + Send + 'static,
+) -> Result<T, LoadClassFileError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| LoadClassFileError::ReadError(err.into()))?
+}
+
+async fn parse(
+    class_names: &SyncClassNames,
+    internal_name: &str,
+    source: ClassFileSource,
+    data: Vec<u8>,
+) -> Result<ClassFileData, LoadClassFileError> {
+    let parsed = blocking(move || ParsedClassFile::parse(data)).await?;
+    let class_file_id = class_names.gcid_from_str(internal_name);
+    Ok(parsed.into_class_file_data(class_file_id, source))
+}
+
+impl DirectoryClassSource {
+    /// Read the bytes of the class with the given internal name, like `java/lang/String`
+    /// Returns `Ok(None)` if this source does not have that class.
+    pub async fn read_class_bytes_async(
+        &self,
+        internal_name: &str,
+    ) -> Result<Option<Vec<u8>>, LoadClassFileError> {
+        read_optional(&self.path_for(internal_name)).await
+    }
+
+    /// Load the class with the given internal name, like `java/lang/String`
+    pub async fn load_class(
+        &self,
+        class_names: &SyncClassNames,
+        internal_name: &str,
+    ) -> Result<ClassFileData, LoadClassFileError> {
+        let path = self.path_for(internal_name);
+        let data = read_optional(&path)
+            .await?
+            .ok_or_else(|| LoadClassFileError::NonexistentFile(path.clone()))?;
+
+        parse(
+            class_names,
+            internal_name,
+            ClassFileSource::File(path),
+            data,
+        )
+        .await
+    }
+}
+
+impl ClassDirectories {
+    /// Load the class with the given internal name, like `java/lang/String`, from the first
+    /// directory which has it
+    pub async fn load_class(
+        &self,
+        class_names: &SyncClassNames,
+        internal_name: &str,
+    ) -> Result<ClassFileData, LoadClassFileError> {
+        let rel_path = class_path_iter_to_relative_path(access_path_iter(internal_name));
+        for class_dir in &self.directories {
+            let full_path = class_dir.join(&rel_path);
+            if let Some(data) = read_optional(&full_path).await? {
+                let source = ClassFileSource::File(full_path);
+                return parse(class_names, internal_name, source, data).await;
+            }
+        }
+
+        Err(LoadClassFileError::NonexistentFile(rel_path))
+    }
+}
+
+/// Load the class with the given internal name, like `java/lang/String`, from the jar at the path
+/// The jar is opened for each call, since reading it is done on a blocking thread, so a
+/// [`JarClassFileLoader`] is better for loading many classes from the same jar.
+pub async fn load_jar_class(
+    class_names: &SyncClassNames,
+    jar_path: PathBuf,
+    internal_name: &str,
+) -> Result<ClassFileData, LoadClassFileError> {
+    let source = ClassFileSource::Jar {
+        jar_path: jar_path.clone(),
+        entry: class_path_iter_to_relative_path_string(access_path_iter(internal_name)),
+    };
+    let name = internal_name.to_owned();
+    let parsed = blocking(move || {
+        let mut loader =
+            JarClassFileLoader::new(jar_path).map_err(LoadClassFileError::ReadError)?;
+        let data = loader
+            .read_class_bytes(&name)?
+            .ok_or(LoadClassFileError::Nonexistent)?;
+        ParsedClassFile::parse(data)
+    })
+    .await?;
+
+    let class_file_id = class_names.gcid_from_str(internal_name);
+    Ok(parsed.into_class_file_data(class_file_id, source))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, future::Future, io::Write};
+
+    use rhojvm_base::{
+        class::ClassFileSource,
+        data::{class_file_loader::LoadClassFileError, class_names::SyncClassNames},
+    };
+
+    use crate::{class_path::DirectoryClassSource, test_util::TempDir, ClassDirectories};
+
+    use super::load_jar_class;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// The bytes of a class file for an empty class with the given name
+    fn class_bytes(name: &str) -> Vec<u8> {
+        let mut data = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52, 0, 5];
+        for (index, text) in [(1, name), (3, "java/lang/Object")] {
+            data.push(1);
+            data.extend(u16::try_from(text.len()).unwrap().to_be_bytes());
+            data.extend(text.as_bytes());
+            data.extend([7, 0, index]);
+        }
+        // access flags, this class, super class, and no interfaces, fields, methods or attributes
+        data.extend([0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn test_load_directory_class() {
+        let dir = TempDir::new("async-directory");
+        let path = dir.write("a/b/C.class", &class_bytes("a/b/C"));
+        dir.write("a/Bad.class", b"not a class file");
+        let source = DirectoryClassSource(dir.path().to_owned());
+        let class_names = SyncClassNames::new();
+
+        let class_file = block_on(source.load_class(&class_names, "a/b/C")).unwrap();
+        assert_eq!(class_file.id(), class_names.gcid_from_str("a/b/C"));
+        assert_eq!(class_file.source(), &ClassFileSource::File(path));
+
+        let err = block_on(source.load_class(&class_names, "a/Bad")).unwrap_err();
+        assert!(matches!(err, LoadClassFileError::NotAClassFile), "{err:?}");
+        let err = block_on(source.load_class(&class_names, "a/Missing")).unwrap_err();
+        assert!(
+            matches!(err, LoadClassFileError::NonexistentFile(_)),
+            "{err:?}"
+        );
+
+        let mut directories = ClassDirectories::default();
+        directories.add(dir.path()).unwrap();
+        let class_file = block_on(directories.load_class(&class_names, "a/b/C")).unwrap();
+        assert_eq!(class_file.id(), class_names.gcid_from_str("a/b/C"));
+    }
+
+    #[test]
+    fn test_load_jar_class() {
+        let dir = TempDir::new("async-jar");
+        let jar_path = dir.path().join("test.jar");
+        let mut jar = zip::ZipWriter::new(File::create(&jar_path).unwrap());
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        jar.start_file("a/b/C.class", options).unwrap();
+        jar.write_all(&class_bytes("a/b/C")).unwrap();
+        jar.finish().unwrap();
+        let class_names = SyncClassNames::new();
+
+        let class_file = block_on(load_jar_class(&class_names, jar_path.clone(), "a/b/C")).unwrap();
+        assert_eq!(class_file.id(), class_names.gcid_from_str("a/b/C"));
+        assert_eq!(
+            class_file.source(),
+            &ClassFileSource::Jar {
+                jar_path: jar_path.clone(),
+                entry: "a/b/C.class".to_owned(),
+            }
+        );
+
+        let err = block_on(load_jar_class(&class_names, jar_path, "a/Missing")).unwrap_err();
+        assert!(matches!(err, LoadClassFileError::Nonexistent), "{err:?}");
+    }
+}
@@ -0,0 +1,309 @@
+use std::{fs::File, io::Read, path::PathBuf, rc::Rc};
+
+use rhojvm_base::{
+    class::{ClassFileData, ClassFileSource},
+    data::{
+        class_file_loader::{ClassFileLoader, LoadClassFileError, LoadResourceError, Resource},
+        class_names::ClassNames,
+    },
+    id::ClassId,
+    util::{access_path_iter, convert_classfile_text},
+};
+
+use crate::{class_path_iter_to_relative_path, jar_loader::JarClassFileLoader};
+
+/// A single entry on the class path that can provide the bytes of class files
+pub trait ClassSource {
+    /// Read the bytes of the class with the given internal name, like `java/lang/String`
+    /// Returns `Ok(None)` if this source does not have that class.
+    fn read_class_bytes(
+        &mut self,
+        internal_name: &str,
+    ) -> Result<Option<Vec<u8>>, LoadClassFileError>;
+
+    /// Check whether this source has the class with the given internal name, without reading it
+    /// if that can be avoided
+    fn has_class(&mut self, internal_name: &str) -> Result<bool, LoadClassFileError> {
+        Ok(self.read_class_bytes(internal_name)?.is_some())
+    }
+
+    /// Describe where the class with the given internal name would be loaded from
+    fn class_file_source(&self, internal_name: &str) -> ClassFileSource;
+
+    /// Read the resource with the given name
+    /// Returns `Ok(None)` if this source does not have that resource.
+    fn read_resource_bytes(
+        &mut self,
+        _resource_name: &str,
+    ) -> Result<Option<Vec<u8>>, LoadResourceError> {
+        Ok(None)
+    }
+}
+
+/// A class source that maps `a/b/C` to `a/b/C.class` under some root directory
+#[derive(Debug, Clone)]
+pub struct DirectoryClassSource(pub PathBuf);
+impl DirectoryClassSource {
+    pub(crate) fn path_for(&self, internal_name: &str) -> PathBuf {
+        let mut path = self.0.clone();
+        path.push(class_path_iter_to_relative_path(access_path_iter(
+            internal_name,
+        )));
+        path
+    }
+}
+impl ClassSource for DirectoryClassSource {
+    fn read_class_bytes(
+        &mut self,
+        internal_name: &str,
+    ) -> Result<Option<Vec<u8>>, LoadClassFileError> {
+        let mut file = match File::open(self.path_for(internal_name)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(LoadClassFileError::ReadError(err)),
+        };
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(LoadClassFileError::ReadError)?;
+
+        Ok(Some(data))
+    }
+
+    fn has_class(&mut self, internal_name: &str) -> Result<bool, LoadClassFileError> {
+        match std::fs::metadata(self.path_for(internal_name)) {
+            Ok(metadata) => Ok(metadata.is_file()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(LoadClassFileError::ReadError(err)),
+        }
+    }
+
+    fn class_file_source(&self, internal_name: &str) -> ClassFileSource {
+        ClassFileSource::File(self.path_for(internal_name))
+    }
+}
+
+impl ClassSource for JarClassFileLoader {
+    fn read_class_bytes(
+        &mut self,
+        internal_name: &str,
+    ) -> Result<Option<Vec<u8>>, LoadClassFileError> {
+        JarClassFileLoader::read_class_bytes(self, internal_name)
+    }
+
+    fn has_class(&mut self, internal_name: &str) -> Result<bool, LoadClassFileError> {
+        JarClassFileLoader::has_class(self, internal_name)
+    }
+
+    fn class_file_source(&self, internal_name: &str) -> ClassFileSource {
+        ClassFileSource::Jar {
+            jar_path: self.jar_path().to_owned(),
+            entry: format!("{}.class", internal_name),
+        }
+    }
+
+    fn read_resource_bytes(
+        &mut self,
+        resource_name: &str,
+    ) -> Result<Option<Vec<u8>>, LoadResourceError> {
+        match self.load_resource(resource_name) {
+            Ok(Resource::Buffer(data)) => Ok(Some(data)),
+            Err(LoadResourceError::Nonexistent) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// A list of class sources which are tried in order
+#[derive(Default)]
+pub struct ClassPath {
+    sources: Vec<Box<dyn ClassSource>>,
+}
+impl ClassPath {
+    #[must_use]
+    pub fn new() -> ClassPath {
+        ClassPath::default()
+    }
+
+    pub fn push(&mut self, source: impl ClassSource + 'static) {
+        self.sources.push(Box::new(source));
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Resolve the class from the sources, returning the index of the source it came from.
+    /// This is first-source-wins: the sources are tried in the order they were added, and a
+    /// class in an earlier source shadows any with the same name in later sources.
+    pub fn resolve(
+        &mut self,
+        internal_name: &str,
+    ) -> Result<Option<(usize, Vec<u8>)>, LoadClassFileError> {
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            if let Some(data) = source.read_class_bytes(internal_name)? {
+                return Ok(Some((i, data)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Find the indices of every source that can provide the class, in order.
+    /// If there is more than one, then the later ones are shadowed by the first, which is
+    /// typically a sign of a misconfigured class path.
+    /// This only checks that each source has the class, without reading it.
+    pub fn find_all(&mut self, internal_name: &str) -> Result<Vec<usize>, LoadClassFileError> {
+        let mut found = Vec::new();
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            if source.has_class(internal_name)? {
+                found.push(i);
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Read the class from the first source which has it
+    pub fn read_class_bytes(
+        &mut self,
+        internal_name: &str,
+    ) -> Result<Option<(ClassFileSource, Vec<u8>)>, LoadClassFileError> {
+        Ok(self
+            .resolve(internal_name)?
+            .map(|(i, data)| (self.sources[i].class_file_source(internal_name), data)))
+    }
+}
+impl std::fmt::Debug for ClassPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClassPath")
+            .field("sources", &self.sources.len())
+            .finish()
+    }
+}
+
+impl ClassFileLoader for ClassPath {
+    fn load_class_file_by_id(
+        &mut self,
+        class_names: &ClassNames,
+        class_file_id: ClassId,
+    ) -> Result<Option<ClassFileData>, LoadClassFileError> {
+        let (class_name, class_info) = class_names
+            .name_from_gcid(class_file_id)
+            .map_err(LoadClassFileError::BadId)?;
+
+        // It doesn't have a class file at all, so whatever
+        if !class_info.has_class_file() {
+            return Ok(None);
+        }
+
+        let internal_name = convert_classfile_text(class_name.get());
+        let (source, data) = self
+            .read_class_bytes(&internal_name)?
+            .ok_or(LoadClassFileError::Nonexistent)?;
+
+        ClassFileData::parse(class_file_id, source, Rc::from(data)).map(Some)
+    }
+
+    fn load_resource(&mut self, resource_name: &str) -> Result<Resource, LoadResourceError> {
+        for source in &mut self.sources {
+            if let Some(data) = source.read_resource_bytes(resource_name)? {
+                return Ok(Resource::Buffer(data));
+            }
+        }
+
+        Err(LoadResourceError::Nonexistent)
+    }
+
+    fn has_resource(&mut self, resource_name: &str) -> bool {
+        self.load_resource(resource_name).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rhojvm_base::class::ClassFileSource;
+
+    use crate::test_util::TempDir;
+
+    use super::{ClassPath, ClassSource, DirectoryClassSource};
+
+    #[test]
+    fn test_directory_source() {
+        let dir = TempDir::new("directory-source");
+        let path = dir.write("a/b/C.class", b"C");
+        let mut source = DirectoryClassSource(dir.path().to_owned());
+
+        assert_eq!(
+            source.read_class_bytes("a/b/C").unwrap(),
+            Some(b"C".to_vec())
+        );
+        assert_eq!(source.read_class_bytes("a/b/D").unwrap(), None);
+        assert_eq!(source.read_class_bytes("a/C").unwrap(), None);
+        assert_eq!(
+            source.class_file_source("a/b/C"),
+            ClassFileSource::File(path)
+        );
+    }
+
+    #[test]
+    fn test_class_path_order() {
+        let first = TempDir::new("class-path-order-first");
+        let second = TempDir::new("class-path-order-second");
+        let a_path = first.write("a/A.class", b"first A");
+        second.write("a/A.class", b"second A");
+        let b_path = second.write("a/B.class", b"second B");
+
+        let mut class_path = ClassPath::new();
+        class_path.push(DirectoryClassSource(first.path().to_owned()));
+        class_path.push(DirectoryClassSource(second.path().to_owned()));
+        assert_eq!(class_path.len(), 2);
+
+        // The first source shadows the second
+        assert_eq!(
+            class_path.read_class_bytes("a/A").unwrap(),
+            Some((ClassFileSource::File(a_path), b"first A".to_vec()))
+        );
+        assert_eq!(
+            class_path.read_class_bytes("a/B").unwrap(),
+            Some((ClassFileSource::File(b_path), b"second B".to_vec()))
+        );
+        assert_eq!(class_path.read_class_bytes("a/C").unwrap(), None);
+    }
+
+    #[test]
+    fn test_find_all() {
+        let first = TempDir::new("find-all-first");
+        let second = TempDir::new("find-all-second");
+        let third = TempDir::new("find-all-third");
+        first.write("a/A.class", b"first A");
+        third.write("a/A.class", b"third A");
+        second.write("a/B.class", b"second B");
+        // A directory with the name of the class file isn't the class
+        std::fs::create_dir_all(first.path().join("a/B.class")).unwrap();
+
+        let mut class_path = ClassPath::new();
+        for dir in [&first, &second, &third] {
+            class_path.push(DirectoryClassSource(dir.path().to_owned()));
+        }
+
+        assert_eq!(class_path.find_all("a/A").unwrap(), vec![0, 2]);
+        assert_eq!(class_path.find_all("a/B").unwrap(), vec![1]);
+        assert_eq!(class_path.find_all("a/C").unwrap(), Vec::<usize>::new());
+        assert_eq!(
+            class_path.resolve("a/A").unwrap(),
+            Some((0, b"first A".to_vec()))
+        );
+        assert_eq!(
+            class_path.resolve("a/B").unwrap(),
+            Some((1, b"second B".to_vec()))
+        );
+        assert_eq!(class_path.resolve("a/C").unwrap(), None);
+    }
+}
@@ -1,8 +1,7 @@
 use std::{fs::File, io::Read, path::PathBuf, rc::Rc};
 
-use classfile_parser::{class_parser_opt, parser::ParseData};
 use rhojvm_base::{
-    class::ClassFileData,
+    class::{ClassFileData, ClassFileSource},
     data::{
         class_file_loader::{ClassFileLoader, LoadClassFileError, LoadResourceError, Resource},
         class_names::ClassNames,
@@ -14,21 +13,27 @@ use zip::ZipArchive;
 
 use crate::class_path_iter_to_relative_path_string;
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum LoadManifestError {
     /// An error in getting it from the zip
     /// This might be an error in decoding it or it might just not exist.
-    Zip(zip::result::ZipError),
+    #[error("failed to get the manifest from the jar")]
+    Zip(#[source] zip::result::ZipError),
     /// An error while reading the file out
-    Read(std::io::Error),
+    #[error("failed to read the manifest")]
+    Read(#[source] std::io::Error),
     /// The manifest was not a file
+    #[error("the manifest was not a file")]
     NotFile,
+    #[error("failed to parse the manifest: {0:?}")]
     Parse(kv_parser::KeyValueParseError),
 }
 
 const MANIFEST_PATH: &str = "META-INF/MANIFEST.MF";
 
 /// A class file loader specifically for loading classes from a given jar file
+/// The archive is opened once, and its central directory is read and indexed at that point, so
+/// looking up entries does not require rescanning the archive.
 #[derive(Debug)]
 pub struct JarClassFileLoader {
     jar_path: PathBuf,
@@ -42,6 +47,46 @@ impl JarClassFileLoader {
         Ok(JarClassFileLoader { jar_path, archive })
     }
 
+    #[must_use]
+    pub fn jar_path(&self) -> &std::path::Path {
+        &self.jar_path
+    }
+
+    /// Read the bytes of the class with the given internal name, like `java/lang/String`
+    /// Returns `Ok(None)` if there is no such class within the jar.
+    pub fn read_class_bytes(
+        &mut self,
+        internal_name: &str,
+    ) -> Result<Option<Vec<u8>>, LoadClassFileError> {
+        let path = class_path_iter_to_relative_path_string(access_path_iter(internal_name));
+        self.read_entry_bytes(&path)
+    }
+
+    /// Check whether the jar has the class with the given internal name, without reading it
+    pub fn has_class(&mut self, internal_name: &str) -> Result<bool, LoadClassFileError> {
+        let path = class_path_iter_to_relative_path_string(access_path_iter(internal_name));
+        match self.archive.by_name(&path) {
+            Ok(file) => Ok(file.is_file()),
+            Err(zip::result::ZipError::FileNotFound) => Ok(false),
+            Err(err) => Err(LoadClassFileError::OpaqueError(err.into())),
+        }
+    }
+
+    fn read_entry_bytes(&mut self, path: &str) -> Result<Option<Vec<u8>>, LoadClassFileError> {
+        let mut file = match self.archive.by_name(path) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(LoadClassFileError::OpaqueError(err.into())),
+        };
+
+        // Read the data out from the file
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(LoadClassFileError::ReadError)?;
+
+        Ok(Some(data))
+    }
+
     pub fn load_manifest(&mut self) -> Result<kv_parser::KeyValueData, LoadManifestError> {
         let mut manifest_file = self
             .archive
@@ -88,25 +133,17 @@ impl ClassFileLoader for JarClassFileLoader {
 
         let path = class_path_iter_to_relative_path_string(path);
 
-        let mut file = self
-            .archive
-            .by_name(&path)
-            .map_err(|x| LoadClassFileError::OpaqueError(x.into()))?;
-
-        // Read the data out from the file
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)
-            .map_err(LoadClassFileError::ReadError)?;
+        let data = self
+            .read_entry_bytes(&path)?
+            .ok_or(LoadClassFileError::Nonexistent)?;
         // Rc it, since class file data gets it
         let data = Rc::from(data);
 
-        // TODO: better errors
-        let (rem_data, class_file) = class_parser_opt(ParseData::new(&data))
-            .map_err(|x| format!("{:?}", x))
-            .map_err(LoadClassFileError::ClassFileParseError)?;
-        debug_assert!(rem_data.is_empty());
-
-        Ok(Some(ClassFileData::new(class_file_id, data, class_file)))
+        let source = ClassFileSource::Jar {
+            jar_path: self.jar_path.clone(),
+            entry: path,
+        };
+        ClassFileData::parse(class_file_id, source, data).map(Some)
     }
 
     fn load_resource(&mut self, resource_name: &str) -> Result<Resource, LoadResourceError> {
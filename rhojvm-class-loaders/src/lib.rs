@@ -5,9 +5,8 @@ use std::{
     rc::Rc,
 };
 
-use classfile_parser::class_parser_opt;
 use rhojvm_base::{
-    class::ClassFileData,
+    class::{ClassFileData, ClassFileSource},
     data::{
         class_file_loader::{ClassFileLoader, LoadClassFileError, LoadResourceError, Resource},
         class_names::ClassNames,
@@ -15,7 +14,12 @@ use rhojvm_base::{
     id::ClassId,
     util::{access_path_iter, convert_classfile_text},
 };
+#[cfg(feature = "async")]
+pub mod async_loader;
+pub mod class_path;
 pub mod jar_loader;
+#[cfg(test)]
+mod test_util;
 pub mod util;
 
 #[derive(Debug, Default, Clone)]
@@ -47,22 +51,13 @@ impl ClassDirectories {
         class_file_id: ClassId,
         rel_path: PathBuf,
     ) -> Result<ClassFileData, LoadClassFileError> {
-        use classfile_parser::parser::ParseData;
-
-        if let Some((_, mut file)) = self.load_class_file_with_rel_path(&rel_path) {
+        if let Some((full_path, mut file)) = self.load_class_file_with_rel_path(&rel_path) {
             let mut data = Vec::new();
             file.read_to_end(&mut data)
                 .map_err(LoadClassFileError::ReadError)?;
             let data = Rc::from(data);
 
-            // TODO: Better errors
-            let (rem_data, class_file) = class_parser_opt(ParseData::new(&data))
-                .map_err(|x| format!("{:?}", x))
-                .map_err(LoadClassFileError::ClassFileParseError)?;
-            // TODO: Don't assert
-            debug_assert!(rem_data.is_empty());
-
-            Ok(ClassFileData::new(class_file_id, data, class_file))
+            ClassFileData::parse(class_file_id, ClassFileSource::File(full_path), data)
         } else {
             Err(LoadClassFileError::NonexistentFile(rel_path))
         }
@@ -0,0 +1,37 @@
+//! Helpers shared by the tests, for laying out class path entries on disk
+
+use std::path::{Path, PathBuf};
+
+/// A directory under the system's temporary directory, which is removed when dropped
+pub(crate) struct TempDir(PathBuf);
+impl TempDir {
+    /// Create an empty directory. The `name` must be unique among the tests, since they run in
+    /// parallel.
+    pub(crate) fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!(
+            "rhojvm-class-loaders-{}-{name}",
+            std::process::id()
+        ));
+        // Clear out anything left behind by an earlier run that didn't finish
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Write the file at the path relative to the directory, creating its parent directories
+    pub(crate) fn write(&self, rel_path: &str, data: &[u8]) -> PathBuf {
+        let path = self.0.join(rel_path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+}
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
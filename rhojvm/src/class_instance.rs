@@ -2,7 +2,10 @@ use std::{collections::HashMap, hash::Hash, thread::ThreadId};
 
 use classfile_parser::field_info::FieldAccessFlags;
 use either::Either;
-use rhojvm_base::{id::ClassId, util::MemorySize};
+use rhojvm_base::{
+    id::{ClassId, ExactFieldId},
+    util::MemorySize,
+};
 
 use crate::{
     gc::{GcRef, GcValueMarker},
@@ -104,8 +107,10 @@ impl Instance {
     /// for its fields.
     pub(crate) fn fields(
         &self,
-    ) -> Either<impl Iterator<Item = (FieldId, &Field)>, impl Iterator<Item = (FieldId, &Field)>>
-    {
+    ) -> Either<
+        impl Iterator<Item = (ExactFieldId, &Field)>,
+        impl Iterator<Item = (ExactFieldId, &Field)>,
+    > {
         match self {
             Instance::StaticClass(x) => Either::Left(x.fields.iter()),
             Instance::Reference(x) => Either::Right(x.fields()),
@@ -135,7 +140,7 @@ pub enum ReferenceInstance {
 }
 impl ReferenceInstance {
     /// Note that this does not peek upwards into the static class for its fields
-    pub(crate) fn fields(&self) -> impl Iterator<Item = (FieldId, &Field)> {
+    pub(crate) fn fields(&self) -> impl Iterator<Item = (ExactFieldId, &Field)> {
         match self {
             ReferenceInstance::Class(x) => x.fields.iter(),
             ReferenceInstance::StaticForm(x) => x.inner.fields.iter(),
@@ -428,68 +433,33 @@ impl MemorySize for ReferenceArrayInstance {
 }
 impl GcValueMarker for ReferenceArrayInstance {}
 
-/// Will not be [`u16::MAX`]
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
-pub struct FieldIndex(u16);
-impl FieldIndex {
-    #[must_use]
-    pub(crate) fn new_unchecked(v: u16) -> FieldIndex {
-        debug_assert_ne!(v, u16::MAX);
-        FieldIndex(v)
-    }
-
-    #[must_use]
-    pub(crate) fn get(self) -> u16 {
-        self.0
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct FieldId {
-    class_id: ClassId,
-    field_index: FieldIndex,
-}
-impl FieldId {
-    #[must_use]
-    pub fn unchecked_compose(class_id: ClassId, field_index: FieldIndex) -> Self {
-        Self {
-            class_id,
-            field_index,
-        }
-    }
-
-    #[must_use]
-    pub fn decompose(self) -> (ClassId, FieldIndex) {
-        (self.class_id, self.field_index)
-    }
-}
 #[derive(Default, Debug, Clone)]
 pub struct Fields {
     /// Stores the id of the class and its index
     /// because a class can have a field name 'a' and extend a class with a field named 'a'
     /// and they are different fields.
-    fields: HashMap<FieldId, Field>,
+    fields: HashMap<ExactFieldId, Field>,
 }
 impl Fields {
     #[must_use]
-    pub fn get(&self, id: FieldId) -> Option<&Field> {
+    pub fn get(&self, id: ExactFieldId) -> Option<&Field> {
         self.fields.get(&id)
     }
 
     #[must_use]
-    pub fn get_mut(&mut self, id: FieldId) -> Option<&mut Field> {
+    pub fn get_mut(&mut self, id: ExactFieldId) -> Option<&mut Field> {
         self.fields.get_mut(&id)
     }
 
-    pub fn insert(&mut self, id: FieldId, field: Field) {
+    pub fn insert(&mut self, id: ExactFieldId, field: Field) {
         self.fields.insert(id, field);
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (FieldId, &Field)> {
+    pub fn iter(&self) -> impl Iterator<Item = (ExactFieldId, &Field)> {
         self.fields.iter().map(|x| (*x.0, x.1))
     }
 
-    pub fn iter_mut(&mut self) -> impl Iterator<Item = (FieldId, &mut Field)> {
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (ExactFieldId, &mut Field)> {
         self.fields.iter_mut().map(|x| (*x.0, x.1))
     }
 }
@@ -1,5 +1,6 @@
 use classfile_parser::{constant_info::ConstantInfo, method_info::MethodAccessFlags};
 use rhojvm_base::{
+    class::ARRAY_CLONE_NAME,
     code::{
         method::{DescriptorType, DescriptorTypeBasic, MethodDescriptor},
         op::{InvokeDynamic, InvokeInterface, InvokeSpecial, InvokeStatic, InvokeVirtual},
@@ -21,7 +22,7 @@ use crate::{
     eval::{eval_method, EvalError, EvalMethodValue, Frame, Locals},
     initialize_class, map_interface_index_small_vec_to_ids, resolve_derive,
     rv::{RuntimeValue, RuntimeValuePrimitive},
-    util::CallStackEntry,
+    util::{CallStackEntry, Env},
     GeneralError, State,
 };
 
@@ -158,6 +159,33 @@ fn grab_runtime_value_from_stack_for_function(
     })
 }
 
+/// Check that the method that a reference in the class `from` resolved to can be accessed from
+/// that class
+fn check_method_access(
+    env: &mut Env,
+    from: ClassId,
+    method_id: ExactMethodId,
+) -> Result<(), GeneralError> {
+    let (owner_id, _) = method_id.decompose();
+    let access_flags = env
+        .methods
+        .get(&method_id)
+        .ok_or(StepError::MissingLoadedValue(
+            "check_method_access : method",
+        ))?
+        .access_flags();
+    env.classes.check_access(
+        &mut env.class_names,
+        &mut env.class_files,
+        &mut env.packages,
+        from,
+        owner_id,
+        access_flags,
+    )?;
+
+    Ok(())
+}
+
 // TODO: We need a general function for resolving a method
 fn find_static_method(
     class_names: &mut ClassNames,
@@ -171,50 +199,80 @@ fn find_static_method(
     let (_, target_info) = class_names
         .name_from_gcid(target_id)
         .map_err(StepError::BadId)?;
-    let target_has_class_file = target_info.has_class_file();
-
-    if target_has_class_file {
-        // TODO: This might be too lenient
-        let mut current_check_id = target_id;
-        loop {
-            let method_id = methods.load_method_from_desc(
-                class_names,
-                class_files,
-                current_check_id,
-                name,
-                descriptor,
-            );
-            match method_id {
-                Ok(method_id) => return Ok(method_id),
-                Err(StepError::LoadMethod(LoadMethodError::NonexistentMethodName { .. })) => {
-                    // Continue to the super class instance
-                    // We assume the class is already loaded
-                    let super_id = classes
-                        .get(&current_check_id)
-                        .ok_or(GeneralError::MissingLoadedClass(current_check_id))?
-                        .super_id();
-                    if let Some(super_id) = super_id {
-                        current_check_id = super_id;
-                        continue;
-                    }
-                    // Break out of the loop since we've checked all the way up the chain
-                    break;
-                }
-                // TODO: Or should we just log the error and skip past it?
-                Err(err) => return Err(err.into()),
-            }
-        }
-    } else {
+    if !target_info.has_class_file() {
         panic!("InvokeStatic when entry did not have class file");
     }
 
-    Err(
+    resolve_class_method(
+        class_names,
+        class_files,
+        classes,
+        methods,
+        target_id,
+        name,
+        descriptor,
+    )?
+    .ok_or_else(|| {
         StepError::LoadMethod(LoadMethodError::NonexistentMethodName {
             class_id: target_id,
             name: Cesu8String(name.to_owned()),
         })
-        .into(),
-    )
+        .into()
+    })
+}
+
+/// Resolve the method that a reference through `target_id` refers to, by looking in the class
+/// and then its super classes.
+/// This is the method that access is checked against, which is not necessarily the method that
+/// is invoked, since that may be an override of it.
+/// Returns `None` if the method was not found, or if `target_id` has no class file.
+// TODO: This should look in the superinterfaces as well
+fn resolve_class_method(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    methods: &mut Methods,
+    target_id: ClassId,
+    name: &[u8],
+    descriptor: &MethodDescriptor,
+) -> Result<Option<ExactMethodId>, GeneralError> {
+    let (_, target_info) = class_names
+        .name_from_gcid(target_id)
+        .map_err(StepError::BadId)?;
+    if !target_info.has_class_file() {
+        return Ok(None);
+    }
+
+    // TODO: This might be too lenient
+    let mut current_check_id = target_id;
+    loop {
+        let method_id = methods.load_method_from_desc(
+            class_names,
+            class_files,
+            current_check_id,
+            name,
+            descriptor,
+        );
+        match method_id {
+            Ok(method_id) => return Ok(Some(method_id)),
+            Err(StepError::LoadMethod(LoadMethodError::NonexistentMethodName { .. })) => {
+                // Continue to the super class instance
+                // We assume the class is already loaded
+                let super_id = classes
+                    .get(&current_check_id)
+                    .ok_or(GeneralError::MissingLoadedClass(current_check_id))?
+                    .super_id();
+                if let Some(super_id) = super_id {
+                    current_check_id = super_id;
+                    continue;
+                }
+                // We've checked all the way up the chain
+                return Ok(None);
+            }
+            // TODO: Or should we just log the error and skip past it?
+            Err(err) => return Err(err.into()),
+        }
+    }
 }
 
 impl RunInstContinue for InvokeStatic {
@@ -304,6 +362,7 @@ impl RunInstContinue for InvokeStatic {
             &method_name,
             &method_descriptor,
         )?;
+        check_method_access(env, class_id, target_method_id)?;
 
         let mut locals = Locals::default();
         for parameter in method_descriptor.parameters().iter().rev() {
@@ -416,6 +475,21 @@ impl RunInstContinue for InvokeInterface {
 
         initialize_class(env, target_interface_id)?;
 
+        // Access is checked on the method that the reference resolves to, rather than the one
+        // selected for the instance, since an override can be less accessible from here
+        let resolved_method_id = resolve_class_method(
+            &mut env.class_names,
+            &mut env.class_files,
+            &mut env.classes,
+            &mut env.methods,
+            target_interface_id,
+            &method_name,
+            &method_descriptor,
+        )?;
+        if let Some(resolved_method_id) = resolved_method_id {
+            check_method_access(env, class_id, resolved_method_id)?;
+        }
+
         let mut locals = Locals::default();
         for parameter in method_descriptor.parameters().iter().rev() {
             let value = grab_runtime_value_from_stack_for_function(
@@ -458,8 +532,6 @@ impl RunInstContinue for InvokeInterface {
             &method_descriptor,
         )?;
 
-        // TODO: Check if the method is accessible?
-
         let call_frame = Frame::new_locals(locals);
 
         let cstack_entry = CallStackEntry {
@@ -572,6 +644,7 @@ impl RunInstContinue for InvokeSpecial {
             &method_name,
             &method_descriptor,
         )?;
+        check_method_access(env, class_id, target_method_id)?;
 
         let mut locals = Locals::default();
 
@@ -745,7 +818,7 @@ pub fn find_virtual_method(
             .load_method_from_desc(class_names, class_files, base_id, name, descriptor)?
             .into())
     } else if instance_is_array {
-        if name == b"clone" {
+        if name == ARRAY_CLONE_NAME.as_bytes() {
             Ok(MethodId::ArrayClone)
         } else {
             Err(
@@ -843,6 +916,21 @@ impl RunInstContinue for InvokeVirtual {
         // TODO: Some of these errors should be exceptions
         initialize_class(env, target_class_id)?;
 
+        // As with invokeinterface, access is checked on the resolved method rather than the
+        // selected one
+        let resolved_method_id = resolve_class_method(
+            &mut env.class_names,
+            &mut env.class_files,
+            &mut env.classes,
+            &mut env.methods,
+            target_class_id,
+            &method_name,
+            &method_descriptor,
+        )?;
+        if let Some(resolved_method_id) = resolved_method_id {
+            check_method_access(env, class_id, resolved_method_id)?;
+        }
+
         // We have to make the locals before getting the target method id since we need the instance
 
         let mut locals = Locals::default();
@@ -888,8 +976,6 @@ impl RunInstContinue for InvokeVirtual {
             &method_descriptor,
         )?;
 
-        // TODO: Check if the method is accessible?
-
         let call_frame = Frame::new_locals(locals);
 
         let cstack_entry = CallStackEntry {
@@ -13,7 +13,7 @@ use rhojvm_base::{
         types::JavaChar,
     },
     data::classes::load_super_classes_iter,
-    id::ClassId,
+    id::{ClassId, ExactFieldId, FieldIndex},
     StepError,
 };
 use smallvec::SmallVec;
@@ -21,8 +21,8 @@ use usize_cast::IntoUsize;
 
 use crate::{
     class_instance::{
-        ClassInstance, Field, FieldAccess, FieldId, FieldIndex, Fields, PrimitiveArrayInstance,
-        ReferenceArrayInstance, ReferenceInstance, ThreadInstance,
+        ClassInstance, Field, FieldAccess, Fields, PrimitiveArrayInstance, ReferenceArrayInstance,
+        ReferenceInstance, ThreadInstance,
     },
     eval::EvalError,
     gc::GcRef,
@@ -51,8 +51,8 @@ pub(crate) fn add_fields_for_class<F: Fn(&FieldInfoOpt) -> bool>(
         .load_field_values_iter()
         .collect::<SmallVec<[_; 8]>>();
     for (field_index, field_info) in field_iter.into_iter().enumerate() {
-        let field_index = FieldIndex::new_unchecked(field_index as u16);
-        let field_id = FieldId::unchecked_compose(class_id, field_index);
+        let field_index = field_index as FieldIndex;
+        let field_id = ExactFieldId::unchecked_compose(class_id, field_index);
 
         // Reget the class file
         let class_file = env
@@ -488,7 +488,7 @@ pub(crate) fn try_casting(
                 desired_class_id,
                 "Array could not be casted to interface which was not a super-interface",
             )?)
-        } else if desired_class_id == env.class_names.object_id() {
+        } else if env.class_names.is_object(desired_class_id) {
             Ok(CastResult::Success)
         } else {
             Ok(make_failure(
@@ -516,7 +516,7 @@ pub(crate) fn try_casting(
                     "Interface could not be casted to other interface which was not a super-interface",
                 )?)
             }
-        } else if desired_class_id == env.class_names.object_id() {
+        } else if env.class_names.is_object(desired_class_id) {
             Ok(CastResult::Success)
         } else {
             Ok(make_failure(
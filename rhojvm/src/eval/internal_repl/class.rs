@@ -9,6 +9,7 @@ use rhojvm_base::{
         types::JavaChar,
     },
     id::ClassId,
+    names::internal_to_binary,
     util::convert_classfile_text,
 };
 
@@ -198,8 +199,7 @@ pub(crate) extern "C" fn class_get_name(env: *mut Env<'_>, this: JObject) -> JSt
             // TODO: Don't use this
             let name = convert_classfile_text(name);
 
-            // Replace it with . since those names are meant to be separated by a .
-            Cow::Owned(name.replace('/', "."))
+            Cow::Owned(internal_to_binary(&name).into_owned())
         }
     };
 
@@ -303,7 +303,7 @@ pub(crate) extern "C" fn class_get_declared_field(
             *(field_internal_fields
                 .get_mut(field_index_field)
                 .unwrap()
-                .value_mut()) = RuntimeValuePrimitive::I16(f_field_index.get() as i16).into();
+                .value_mut()) = RuntimeValuePrimitive::I16(f_field_index as i16).into();
             *(field_internal_fields
                 .get_mut(flags_field)
                 .unwrap()
@@ -1,10 +1,14 @@
 use classfile_parser::descriptor::DescriptorType as DescriptorTypeCF;
 
-use rhojvm_base::{code::method::DescriptorType, id::ClassId, StepError};
+use rhojvm_base::{
+    code::method::DescriptorType,
+    id::{ClassId, FieldIndex},
+    StepError,
+};
 use smallvec::SmallVec;
 
 use crate::{
-    class_instance::{FieldIndex, Instance, ReferenceInstance},
+    class_instance::{Instance, ReferenceInstance},
     eval::{
         internal_repl::class::{
             BOOLEAN_NAME, BYTE_NAME, CHARACTER_NAME, DOUBLE_NAME, FLOAT_NAME, INTEGER_NAME,
@@ -78,7 +82,7 @@ pub(crate) extern "C" fn field_get_type(env: *mut Env<'_>, field: JObject) -> JC
         .value()
         .into_i16()
         .expect("field index field should be i16");
-    let field_index_val = FieldIndex::new_unchecked(field_index_val as u16);
+    let field_index_val = field_index_val as FieldIndex;
 
     let class_file = env
         .class_files
@@ -92,7 +96,7 @@ pub(crate) extern "C" fn field_get_type(env: *mut Env<'_>, field: JObject) -> JC
     let (field_info, _) = field_iter
         .into_iter()
         .enumerate()
-        .find(|x| x.0 == usize::from(field_index_val.get()))
+        .find(|x| x.0 == usize::from(field_index_val))
         .map(|x| x.1)
         .transpose()
         .ok()
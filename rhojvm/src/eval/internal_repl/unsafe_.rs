@@ -1,7 +1,7 @@
-use rhojvm_base::id::ClassId;
+use rhojvm_base::id::{ClassId, FieldIndex};
 
 use crate::{
-    class_instance::{ClassInstance, FieldIndex, Instance, ReferenceInstance},
+    class_instance::{ClassInstance, Instance, ReferenceInstance},
     jni::{JByte, JChar, JDouble, JFieldId, JFloat, JInt, JLong, JObject, JShort},
     memblock::MemoryBlockPtr,
     rv::{RuntimeValue, RuntimeValuePrimitive},
@@ -334,7 +334,7 @@ pub(crate) extern "C" fn unsafe_object_field_offset(
         .value()
         .into_i16()
         .expect("field index field should be i16");
-    let field_index_val = FieldIndex::new_unchecked(field_index_val as u16);
+    let field_index_val = field_index_val as FieldIndex;
 
     // Safety: Only the JVM should fill out the Field class and so the values should be valid
     let field_id = unsafe { JFieldId::new_unchecked(class_id_val, field_index_val) };
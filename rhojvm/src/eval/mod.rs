@@ -12,14 +12,14 @@ use rhojvm_base::{
         op::{Inst, Wide, WideInst},
         types::{Instruction, JavaChar, LocalVariableIndex},
     },
-    id::{ClassId, ExactMethodId, MethodId},
+    id::{ClassId, ExactFieldId, ExactMethodId, MethodId},
     map_inst,
     util::{convert_classfile_text, MemorySizeU16},
     StepError,
 };
 
 use crate::{
-    class_instance::{ClassInstance, FieldId, Instance},
+    class_instance::{ClassInstance, Instance},
     gc::GcRef,
     jni::{self, JBoolean, JByte, JChar, JDouble, JFloat, JInt, JLong, JObject, JShort},
     method::NativeMethod,
@@ -60,7 +60,7 @@ pub enum EvalError {
     /// Expected there to be a static class reference for the given class
     MissingStaticClassRef(ClassId),
     /// We expected the field to exist but it did not.
-    MissingField(FieldId),
+    MissingField(ExactFieldId),
 
     /// The index into the constant pool was invalid, either out of bounds or incorrect type
     /// Should have been caught in stack map verification
@@ -1030,6 +1030,15 @@ impl RunInst for Wide {
         // instructions
         match self.0 {
             WideInst::WideIntLoad(x) => RunInst::run(x, args),
+            WideInst::WideLongLoad(x) => RunInst::run(x, args),
+            WideInst::WideFloatLoad(x) => RunInst::run(x, args),
+            WideInst::WideDoubleLoad(x) => RunInst::run(x, args),
+            WideInst::WideALoad(x) => RunInst::run(x, args),
+            WideInst::WideIntStore(x) => RunInst::run(x, args),
+            WideInst::WideLongStore(x) => RunInst::run(x, args),
+            WideInst::WideFloatStore(x) => RunInst::run(x, args),
+            WideInst::WideDoubleStore(x) => RunInst::run(x, args),
+            WideInst::WideAStore(x) => RunInst::run(x, args),
             WideInst::WideIntIncrement(x) => RunInst::run(x, args),
         }
     }
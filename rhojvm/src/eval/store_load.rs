@@ -23,7 +23,8 @@ use rhojvm_base::{
             LongArrayStore, LongConst0, LongConst1, LongLoad, LongLoad0, LongLoad1, LongLoad2,
             LongLoad3, LongStore, LongStore0, LongStore1, LongStore2, LongStore3, Pop, Pop2,
             PushByte, PushShort, PutField, PutStaticField, ShortArrayLoad, ShortArrayStore,
-            WideIntLoad,
+            WideALoad, WideAStore, WideDoubleLoad, WideDoubleStore, WideFloatLoad, WideFloatStore,
+            WideIntLoad, WideIntStore, WideLongLoad, WideLongStore,
         },
         types::{JavaChar, LocalVariableIndex},
     },
@@ -33,15 +34,15 @@ use rhojvm_base::{
         classes::{load_super_classes_iter, Classes},
         methods::Methods,
     },
-    id::ClassId,
+    id::{ClassId, ExactFieldId},
     package::Packages,
 };
 use usize_cast::IntoUsize;
 
 use crate::{
     class_instance::{
-        ClassInstance, Field, FieldAccess, FieldId, FieldIndex, FieldType, ReferenceInstance,
-        StaticClassInstance, StaticFormInstance,
+        ClassInstance, Field, FieldAccess, FieldType, ReferenceInstance, StaticClassInstance,
+        StaticFormInstance,
     },
     eval::instances::make_fields,
     gc::GcRef,
@@ -56,7 +57,7 @@ use super::{
 };
 
 enum DestRes {
-    GcRef((GcRef<StaticClassInstance>, FieldId, FieldRefConstant)),
+    GcRef((GcRef<StaticClassInstance>, ExactFieldId, FieldRefConstant)),
     RunInstContinue(RunInstContinueValue),
 }
 fn get_field_dest(
@@ -124,7 +125,16 @@ fn get_field_dest(
         let dest_id = dest_id?;
         let field_id = find_field_with_name(&env.class_files, dest_id, field_name)?;
 
-        if let Some((field_id, _)) = field_id {
+        if let Some((field_id, field_info)) = field_id {
+            env.classes.check_access(
+                &mut env.class_names,
+                &mut env.class_files,
+                &mut env.packages,
+                class_id,
+                dest_id,
+                field_info.access_flags,
+            )?;
+
             // TODO: dest_ref isn't accurate! it should probably be the destination that the field
             // is actually on!
             return Ok(DestRes::GcRef((dest_ref, field_id, field)));
@@ -742,6 +752,15 @@ impl RunInstContinue for ALoad3 {
     }
 }
 
+impl RunInstContinue for WideALoad {
+    fn run(
+        self,
+        RunInstArgsC { frame, .. }: RunInstArgsC,
+    ) -> Result<RunInstContinueValue, GeneralError> {
+        aload_index(frame, self.index)
+    }
+}
+
 // === Reference Store ===
 
 fn astore_index(
@@ -798,6 +817,15 @@ impl RunInstContinue for AStore3 {
     }
 }
 
+impl RunInstContinue for WideAStore {
+    fn run(
+        self,
+        RunInstArgsC { frame, .. }: RunInstArgsC,
+    ) -> Result<RunInstContinueValue, GeneralError> {
+        astore_index(frame, self.index)
+    }
+}
+
 // === Dup Instructions ===
 
 impl RunInstContinue for Dup {
@@ -1064,6 +1092,15 @@ impl RunInstContinue for IntStore3 {
     }
 }
 
+impl RunInstContinue for WideIntStore {
+    fn run(
+        self,
+        RunInstArgsC { frame, .. }: RunInstArgsC,
+    ) -> Result<RunInstContinueValue, GeneralError> {
+        intstore_index(frame, self.index)
+    }
+}
+
 // === Float Load ===
 
 fn floatload_index(
@@ -1127,6 +1164,15 @@ impl RunInstContinue for FloatLoad3 {
     }
 }
 
+impl RunInstContinue for WideFloatLoad {
+    fn run(
+        self,
+        RunInstArgsC { frame, .. }: RunInstArgsC,
+    ) -> Result<RunInstContinueValue, GeneralError> {
+        floatload_index(frame, self.index)
+    }
+}
+
 // === Float Store ===
 
 fn floatstore_index(
@@ -1184,6 +1230,15 @@ impl RunInstContinue for FloatStore3 {
     }
 }
 
+impl RunInstContinue for WideFloatStore {
+    fn run(
+        self,
+        RunInstArgsC { frame, .. }: RunInstArgsC,
+    ) -> Result<RunInstContinueValue, GeneralError> {
+        floatstore_index(frame, self.index)
+    }
+}
+
 // === Float Const ===
 
 impl RunInstContinue for FloatConst0 {
@@ -1277,6 +1332,15 @@ impl RunInstContinue for LongLoad3 {
     }
 }
 
+impl RunInstContinue for WideLongLoad {
+    fn run(
+        self,
+        RunInstArgsC { frame, .. }: RunInstArgsC,
+    ) -> Result<RunInstContinueValue, GeneralError> {
+        longload_index(frame, self.index)
+    }
+}
+
 // === Long Store ===
 
 fn longstore_index(
@@ -1334,6 +1398,15 @@ impl RunInstContinue for LongStore3 {
     }
 }
 
+impl RunInstContinue for WideLongStore {
+    fn run(
+        self,
+        RunInstArgsC { frame, .. }: RunInstArgsC,
+    ) -> Result<RunInstContinueValue, GeneralError> {
+        longstore_index(frame, self.index)
+    }
+}
+
 // === Long Const ===
 
 impl RunInstContinue for LongConst0 {
@@ -1418,6 +1491,15 @@ impl RunInstContinue for DoubleLoad3 {
     }
 }
 
+impl RunInstContinue for WideDoubleLoad {
+    fn run(
+        self,
+        RunInstArgsC { frame, .. }: RunInstArgsC,
+    ) -> Result<RunInstContinueValue, GeneralError> {
+        doubleload_index(frame, self.index)
+    }
+}
+
 // === Double Store ===
 
 fn doublestore_index(
@@ -1475,6 +1557,15 @@ impl RunInstContinue for DoubleStore3 {
     }
 }
 
+impl RunInstContinue for WideDoubleStore {
+    fn run(
+        self,
+        RunInstArgsC { frame, .. }: RunInstArgsC,
+    ) -> Result<RunInstContinueValue, GeneralError> {
+        doublestore_index(frame, self.index)
+    }
+}
+
 // === Double Const ===
 
 impl RunInstContinue for DoubleConst0 {
@@ -12,12 +12,12 @@ use rhojvm_base::{
         method::{DescriptorType, DescriptorTypeBasic},
         types::JavaChar,
     },
-    id::{ClassId, ExactMethodId, MethodIndex},
+    id::{ClassId, ExactFieldId, ExactMethodId, FieldIndex, MethodIndex},
 };
 use usize_cast::{FromUsize, IntoUsize};
 
 use crate::{
-    class_instance::{FieldId, FieldIndex, Instance},
+    class_instance::Instance,
     const_assert,
     rv::{RuntimeType, RuntimeTypePrimitive, RuntimeValue, RuntimeValuePrimitive},
     util::Env,
@@ -85,7 +85,8 @@ impl JFieldId {
         let class_id_v = class_id.get();
         // These are incremented by 1 so that null is a value that can be represented as a field id
         let class_id_v: u64 = (class_id_v + 1).into();
-        let field_index_v: u64 = (field_index.get() + 1).into();
+        // A field index is never u16::MAX, since a class file can't have that many fields
+        let field_index_v: u64 = (field_index + 1).into();
 
         // [class_id + 1][field_index + 1][0000]
         let field_id = (class_id_v << 32) | (field_index_v << 16);
@@ -103,9 +104,9 @@ impl JFieldId {
     /// # Safety
     /// This should be a valid [`JFieldId`] handed out by `new_unchecked`
     /// It must be safe to forge pointers and expect to get the original integer back
-    pub(crate) unsafe fn into_field_id(self) -> Option<FieldId> {
+    pub(crate) unsafe fn into_field_id(self) -> Option<ExactFieldId> {
         if let Some((class_id, field_index)) = self.decompose() {
-            Some(FieldId::unchecked_compose(class_id, field_index))
+            Some(ExactFieldId::unchecked_compose(class_id, field_index))
         } else {
             None
         }
@@ -129,7 +130,6 @@ impl JFieldId {
         let field_index = field_index - 1;
 
         let class_id = ClassId::new_unchecked(class_id);
-        let field_index = FieldIndex::new_unchecked(field_index);
 
         Some((class_id, field_index))
     }
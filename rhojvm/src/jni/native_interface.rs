@@ -9,14 +9,14 @@ use rhojvm_base::{
         method::{DescriptorType, DescriptorTypeBasic, Method, MethodDescriptor},
         types::JavaChar,
     },
-    id::ClassId,
+    id::{ClassId, FieldIndex},
     util::convert_classfile_text,
 };
 use smallvec::SmallVec;
 use usize_cast::{IntoIsize, IntoUsize};
 
 use crate::{
-    class_instance::{FieldIndex, Instance, ReferenceInstance},
+    class_instance::{Instance, ReferenceInstance},
     eval::{eval_method, EvalError, EvalMethodValue, Frame, Locals, ValueException},
     jni::{self, OpaqueClassMethod},
     method::NativeMethod,
@@ -1222,7 +1222,7 @@ fn get_field_id_safe(
 
     // Note: GetFieldId can't be used to get the length field of an array
     for (field_index, field_data) in class_file.load_field_values_iter().enumerate() {
-        let field_index = FieldIndex::new_unchecked(field_index as u16);
+        let field_index = field_index as FieldIndex;
         let (field_info, _) = field_data.map_err(GeneralError::ClassFileLoad)?;
         let target_field_name = class_file.get_text_b(field_info.name_index).ok_or(
             EvalError::InvalidConstantPoolIndex(field_info.name_index.into_generic()),
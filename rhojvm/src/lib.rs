@@ -29,7 +29,7 @@ use std::{
 };
 
 use class_instance::{
-    ClassInstance, FieldId, Instance, StaticClassInstance, StaticFormInstance, ThreadInstance,
+    ClassInstance, Fields, Instance, StaticClassInstance, StaticFormInstance, ThreadInstance,
 };
 use classfile_parser::{
     constant_info::{ClassConstant, ConstantInfo},
@@ -53,17 +53,18 @@ use rhojvm_base::{
         types::PrimitiveType,
     },
     data::{
+        access::{AccessCheckError, IllegalAccessError},
         class_files::ClassFiles,
         class_names::ClassNames,
         classes::{load_super_classes_iter, Classes},
         methods::{init_method_overrides, load_method_descriptor_types, LoadMethodError, Methods},
     },
-    id::{ClassId, ExactMethodId, MethodId},
+    id::{ClassId, ExactFieldId, ExactMethodId, MethodId},
     package::Packages,
     StepError,
 };
 use smallvec::{smallvec, SmallVec};
-use stack_map_verifier::{StackMapVerificationLogging, VerifyStackMapGeneralError};
+use stack_map_verifier::{StackMapVerificationLogging, VerifyError, VerifyStackMapGeneralError};
 use util::{find_field_with_name, Env};
 
 use crate::eval::{eval_method, Frame, ValueException};
@@ -79,6 +80,8 @@ pub mod memblock;
 pub mod method;
 pub mod rv;
 pub mod string_intern;
+#[cfg(test)]
+mod test_util;
 pub mod util;
 
 pub const ENV_TRACING_LEVEL: &str = "RHO_LOG_LEVEL";
@@ -231,12 +234,55 @@ impl<T> From<BegunStatus<T>> for Status<T> {
     }
 }
 
+/// The initialization state of a class, as in JVMS 5.5
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum InitState {
+    #[default]
+    Uninitialized,
+    /// The thread is running the class's initialization
+    BeingInitialized(ThreadId),
+    Initialized,
+    /// Initialization was attempted but failed, and so the class can't be used
+    Erroneous,
+}
+impl InitState {
+    /// Whether a class in this state can move to the `new` state
+    /// A thread which is already initializing the class may request it again, since
+    /// initialization can recursively use the class, but other threads have to wait for it to
+    /// finish instead.
+    #[must_use]
+    pub fn can_transition_to(self, new: InitState) -> bool {
+        match (self, new) {
+            (InitState::Uninitialized, InitState::BeingInitialized(_))
+            | (InitState::BeingInitialized(_), InitState::Initialized | InitState::Erroneous) => {
+                true
+            }
+            (InitState::BeingInitialized(current), InitState::BeingInitialized(thread)) => {
+                current == thread
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A class was moved to an initialization state that it can't reach from its current state
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct IllegalInitTransition {
+    pub class_id: ClassId,
+    pub from: InitState,
+    pub to: InitState,
+}
+
 /// Information specific to each class
 #[derive(Debug, Default, Clone)]
 pub struct ClassInfo {
     pub created: Status,
     pub verified: Status,
-    pub initialized: Status<ValueException<GcRef<StaticClassInstance>>>,
+    /// Updated through [`State::transition_init`], so that only legal transitions are made
+    init_state: InitState,
+    /// The static instance of the class, created when its initialization begins
+    /// Whether it can be handed out is decided by the `init_state`.
+    static_ref: Option<GcRef<StaticClassInstance>>,
     /// Cached reference to Class<T> for this class
     pub class_ref: Option<GcRef<StaticFormInstance>>,
 }
@@ -279,16 +325,16 @@ pub struct State {
     string_class_id: Option<ClassId>,
 
     /// The field in java/lang/String that holds the `char[]` that is its content.
-    string_data_field: Option<FieldId>,
+    string_data_field: Option<ExactFieldId>,
 
     /// (classId, fieldIndex, flags) in rho/InternalField
-    internal_field_field_ids: Option<(FieldId, FieldId, FieldId)>,
+    internal_field_field_ids: Option<(ExactFieldId, ExactFieldId, ExactFieldId)>,
 
     /// internalField in java/lang/reflect/Field
-    field_internal_field_id: Option<FieldId>,
+    field_internal_field_id: Option<ExactFieldId>,
 
     /// name field in java/lang/Package
-    package_name_field_id: Option<FieldId>,
+    package_name_field_id: Option<ExactFieldId>,
 
     // Cached gcreferences to the Class<T> types for primitives
     pub(crate) void_static_form: Option<GcRef<StaticFormInstance>>,
@@ -352,6 +398,37 @@ impl State {
         &self.conf
     }
 
+    /// Get the initialization state of the class
+    #[must_use]
+    pub fn init_state(&self, id: ClassId) -> InitState {
+        self.classes_info
+            .get(id)
+            .map_or(InitState::Uninitialized, |info| info.init_state)
+    }
+
+    /// Move the class to the `new_state`, returning the state it was in before
+    /// Moving to [`InitState::BeingInitialized`] with the thread that is already initializing
+    /// the class leaves it unchanged, which the caller can detect from the returned state to
+    /// avoid initializing it again.
+    pub fn transition_init(
+        &mut self,
+        id: ClassId,
+        new_state: InitState,
+    ) -> Result<InitState, IllegalInitTransition> {
+        let info = self.classes_info.get_mut_init(id);
+        let current = info.init_state;
+        if !current.can_transition_to(new_state) {
+            return Err(IllegalInitTransition {
+                class_id: id,
+                from: current,
+                to: new_state,
+            });
+        }
+
+        info.init_state = new_state;
+        Ok(current)
+    }
+
     pub fn fill_native_exception(&mut self, exc: GcRef<ClassInstance>) {
         if self.native_exception.is_some() {
             tracing::warn!(
@@ -422,7 +499,7 @@ impl State {
         &mut self,
         class_files: &ClassFiles,
         class_id: ClassId,
-    ) -> Result<FieldId, GeneralError> {
+    ) -> Result<ExactFieldId, GeneralError> {
         if let Some(field) = self.string_data_field {
             return Ok(field);
         }
@@ -441,7 +518,7 @@ impl State {
         &mut self,
         class_files: &ClassFiles,
         class_id: ClassId,
-    ) -> Result<(FieldId, FieldId, FieldId), GeneralError> {
+    ) -> Result<(ExactFieldId, ExactFieldId, ExactFieldId), GeneralError> {
         if let Some(fields) = self.internal_field_field_ids {
             return Ok(fields);
         }
@@ -464,7 +541,7 @@ impl State {
         &mut self,
         class_files: &ClassFiles,
         class_id: ClassId,
-    ) -> Result<FieldId, GeneralError> {
+    ) -> Result<ExactFieldId, GeneralError> {
         if let Some(field) = self.field_internal_field_id {
             return Ok(field);
         }
@@ -483,7 +560,7 @@ impl State {
         &mut self,
         class_files: &ClassFiles,
         class_id: ClassId,
-    ) -> Result<FieldId, GeneralError> {
+    ) -> Result<ExactFieldId, GeneralError> {
         if let Some(field) = self.package_name_field_id {
             return Ok(field);
         }
@@ -501,6 +578,10 @@ pub(crate) struct ClassesInfo {
     info: HashMap<ClassId, ClassInfo>,
 }
 impl ClassesInfo {
+    pub fn get(&self, id: ClassId) -> Option<&ClassInfo> {
+        self.info.get(&id)
+    }
+
     pub fn get_mut_init(&mut self, id: ClassId) -> &mut ClassInfo {
         self.info.entry(id).or_default()
     }
@@ -526,12 +607,17 @@ pub enum GeneralError {
     /// This likely points to an internal error
     MissingLoadedMethod(MethodId),
     BadClassFileIndex(ConstantPoolIndexRaw<ConstantInfo>),
-    /// The class version of the file is not supported by this JVM
-    UnsupportedClassVersion,
     InvalidDescriptorType(DescriptorTypeError),
     UnparsedFieldType,
     /// We failed to convert a java string to a rust string
     StringConversionFailure(FromUtf16Error),
+    /// A class was moved to an initialization state it can't reach
+    /// This likely points to an internal error
+    IllegalInitTransition(IllegalInitTransition),
+    /// The class is erroneous, because an earlier attempt to initialize it failed, and so it
+    /// can't be used (JVMS 5.5 step 5)
+    /// TODO: This should be thrown as a `NoClassDefFoundError`
+    NoClassDefFound(ClassId),
 }
 
 impl From<StepError> for GeneralError {
@@ -554,6 +640,14 @@ impl From<ResolveError> for GeneralError {
         Self::Resolve(err)
     }
 }
+impl From<AccessCheckError> for GeneralError {
+    fn from(err: AccessCheckError) -> Self {
+        match err {
+            AccessCheckError::Step(err) => Self::Step(err),
+            AccessCheckError::IllegalAccess(err) => Self::Resolve(ResolveError::IllegalAccess(err)),
+        }
+    }
+}
 impl From<StackMapError> for GeneralError {
     fn from(err: StackMapError) -> Self {
         Self::Verification(VerificationError::StackMap(err))
@@ -564,6 +658,11 @@ impl From<VerifyStackMapGeneralError> for GeneralError {
         Self::Verification(VerificationError::VerifyStackMapGeneralError(err))
     }
 }
+impl From<VerifyError> for GeneralError {
+    fn from(err: VerifyError) -> Self {
+        Self::Verification(VerificationError::Method(err))
+    }
+}
 impl From<LoadLibraryError> for GeneralError {
     fn from(err: LoadLibraryError) -> Self {
         Self::LoadLibrary(err)
@@ -574,6 +673,11 @@ impl From<FindSymbolError> for GeneralError {
         Self::FindSymbol(err)
     }
 }
+impl From<IllegalInitTransition> for GeneralError {
+    fn from(err: IllegalInitTransition) -> Self {
+        Self::IllegalInitTransition(err)
+    }
+}
 
 #[derive(Debug)]
 pub enum VerificationError {
@@ -603,19 +707,18 @@ pub enum VerificationError {
         base_id: ClassId,
         super_id: Option<ClassId>,
     },
-    /// The class inherits from itself
-    CircularInheritance {
-        base_id: ClassId,
-    },
-    /// The method should have had Code but it did not
-    NoMethodCode {
-        method_id: ExactMethodId,
-    },
+    /// The code of a method failed to verify
+    Method(VerifyError),
 }
 
 #[derive(Debug)]
 pub enum ResolveError {
-    InaccessibleClass { from: ClassId, target: ClassId },
+    InaccessibleClass {
+        from: ClassId,
+        target: ClassId,
+    },
+    /// A field or method was not accessible from the class that referred to it
+    IllegalAccess(IllegalAccessError),
 }
 
 /// Initialize a class
@@ -628,9 +731,13 @@ pub fn initialize_class(
     env: &mut Env<'_>,
     class_id: ClassId,
 ) -> Result<BegunStatus<ValueException<GcRef<StaticClassInstance>>>, GeneralError> {
-    let info = env.state.classes_info.get_mut_init(class_id);
-    if let Some(initialize_begun) = info.initialized.into_begun() {
-        return Ok(initialize_begun);
+    match env.state.init_state(class_id) {
+        InitState::Initialized => {
+            let instance_ref = static_instance(&env.state, class_id)?;
+            return Ok(BegunStatus::Done(ValueException::Value(instance_ref)));
+        }
+        InitState::Erroneous => return Err(GeneralError::NoClassDefFound(class_id)),
+        InitState::Uninitialized | InitState::BeingInitialized(_) => {}
     }
 
     verify_class(
@@ -643,9 +750,70 @@ pub fn initialize_class(
         class_id,
     )?;
 
-    let class = env.classes.get(&class_id).unwrap();
+    let being_initialized = InitState::BeingInitialized(env.tdata.id);
+    let previous = env.state.transition_init(class_id, being_initialized)?;
+    if previous == being_initialized {
+        // This is a recursive request from within the class's own initialization, which is
+        // allowed to use the class before it is done
+        let instance_ref = static_instance(&env.state, class_id)?;
+        return Ok(BegunStatus::Started(ValueException::Value(instance_ref)));
+    }
+
+    // The static instance is created before anything else, and its fields filled in later, so
+    // that a recursive request has an instance to get
+    let instance_ref = env
+        .state
+        .gc
+        .alloc(StaticClassInstance::new(class_id, Fields::default()));
+    env.state.classes_info.get_mut_init(class_id).static_ref = Some(instance_ref);
+
+    match run_class_initialization(env, class_id, instance_ref) {
+        Ok(ValueException::Value(())) => {
+            env.state
+                .transition_init(class_id, InitState::Initialized)?;
+            Ok(BegunStatus::Done(ValueException::Value(instance_ref)))
+        }
+        Ok(ValueException::Exception(exc)) => {
+            env.state.transition_init(class_id, InitState::Erroneous)?;
+            Ok(BegunStatus::Done(ValueException::Exception(exc)))
+        }
+        Err(err) => {
+            // The class was left partially initialized, so later requests fail on it being
+            // erroneous rather than getting its instance
+            env.state.transition_init(class_id, InitState::Erroneous)?;
+            Err(err)
+        }
+    }
+}
+
+/// Get the static instance of a class whose initialization has begun
+fn static_instance(
+    state: &State,
+    class_id: ClassId,
+) -> Result<GcRef<StaticClassInstance>, GeneralError> {
+    state
+        .classes_info
+        .get(class_id)
+        .and_then(|info| info.static_ref)
+        .ok_or_else(|| StepError::MissingLoadedValue("initialize_class : static instance").into())
+}
+
+/// Initialize the super class, fill in the static fields of `instance_ref`, and run the static
+/// initializer of the class, which has already been moved to being initialized
+/// Returns the exception if any of them threw one.
+fn run_class_initialization(
+    env: &mut Env<'_>,
+    class_id: ClassId,
+    instance_ref: GcRef<StaticClassInstance>,
+) -> Result<ValueException<()>, GeneralError> {
+    let class = env
+        .classes
+        .get(&class_id)
+        .ok_or(GeneralError::MissingLoadedClass(class_id))?;
     if let Some(super_id) = class.super_id() {
-        initialize_class(env, super_id)?;
+        if let ValueException::Exception(exc) = initialize_class(env, super_id)?.into_value() {
+            return Ok(ValueException::Exception(exc));
+        }
     }
 
     let (_, cn_info) = env
@@ -662,21 +830,17 @@ pub fn initialize_class(
         field_info.access_flags.contains(FieldAccessFlags::STATIC)
     })? {
         Either::Left(fields) => fields,
-        Either::Right(exc) => {
-            let info = env.state.classes_info.get_mut_init(class_id);
-            info.initialized = Status::Done(ValueException::Exception(exc));
-            return Ok(BegunStatus::Done(ValueException::Exception(exc)));
-        }
+        Either::Right(exc) => return Ok(ValueException::Exception(exc)),
     };
 
-    let instance = StaticClassInstance::new(class_id, fields);
-    let instance_ref = env.state.gc.alloc(instance);
-
-    let info = env.state.classes_info.get_mut_init(class_id);
-    info.initialized = Status::Done(ValueException::Value(instance_ref));
+    let instance = env
+        .state
+        .gc
+        .deref_mut(instance_ref)
+        .ok_or_else(|| EvalError::InvalidGcRef(instance_ref.into_generic()))?;
+    instance.fields = fields;
 
     // TODO: This could potentially be gc'd, we could just store the id?
-    // TODO: Should this be done before or after we set initialized?
     if !is_array {
         let clinit_name = b"<clinit>";
         let clinit_desc = MethodDescriptor::new_empty();
@@ -694,11 +858,7 @@ pub fn initialize_class(
                     EvalMethodValue::Return(_) => {
                         tracing::warn!("<clinit> method returned a value");
                     }
-                    EvalMethodValue::Exception(exc) => {
-                        let info = env.state.classes_info.get_mut_init(class_id);
-                        info.initialized = Status::Done(ValueException::Exception(exc));
-                        return Ok(BegunStatus::Done(ValueException::Exception(exc)));
-                    }
+                    EvalMethodValue::Exception(exc) => return Ok(ValueException::Exception(exc)),
                 }
             }
             // Ignore it, if it doesn't exist
@@ -707,7 +867,7 @@ pub fn initialize_class(
         }
     }
 
-    Ok(BegunStatus::Done(ValueException::Value(instance_ref)))
+    Ok(ValueException::Value(()))
 }
 
 pub fn verify_from_entrypoint(
@@ -881,33 +1041,14 @@ fn derive_class(
 
     // TODO: Loader stuff?
 
-    // If it has a class file, then we want to check the version
-    if let Some(class_file) = class_files.get(&class_id) {
-        if let Some(version) = class_file.version() {
-            // Currently we don't support pre-jdk8 class files
-            // because we have yet to implement the type verifier that they require
-            if version.major <= 50 {
-                return Err(GeneralError::UnsupportedClassVersion);
-            }
-        }
-    }
-
     // TODO: Should this be moved after resolving the super class and before creating it?
-    // This checks for if the class inherits from itself
+    // This checks for if the class inherits from itself, which the iterator reports as a
+    // `ClassCircularity` error
     let mut super_iter = load_super_classes_iter(class_id);
-    // We skip the base class, since that is the class we just passed in and we already know what it
-    // is.
-    super_iter
-        .next_item(class_names, class_files, classes, packages)
-        .expect("Load Super Classes Iter should have at least one entry")?;
     while let Some(super_class_id) =
         super_iter.next_item(class_names, class_files, classes, packages)
     {
-        let super_class_id = super_class_id?;
-
-        if super_class_id == class_id {
-            return Err(VerificationError::CircularInheritance { base_id: class_id }.into());
-        }
+        super_class_id?;
     }
 
     let class = classes
@@ -936,7 +1077,7 @@ fn derive_class(
             }
             .into());
         }
-    } else if class_names.object_id() != class_id {
+    } else if !class_names.is_object(class_id) {
         // There was no super class and we were not the Object
         // TODO: Should we error on this? THe JVM docs technically don't.
     }
@@ -1160,10 +1301,6 @@ fn verify_type_safe_method(
     state: &mut State,
     method_id: ExactMethodId,
 ) -> Result<(), GeneralError> {
-    let (class_id, method_index) = method_id.decompose();
-    // It is generally cheaper to clone since they tend to load it as well..
-    let class_file = class_files.get(&class_id).unwrap().clone();
-    // let mut methods = Methods::default();
     methods.load_method_from_id(class_names, class_files, method_id)?;
     let method = methods.get(&method_id).unwrap();
     method
@@ -1181,31 +1318,403 @@ fn verify_type_safe_method(
         method_id,
     )?;
 
-    let method = methods.get_mut(&method_id).unwrap();
-    method.load_code(class_files)?;
-    let method_code = method.take_code_info();
-    if method_code.is_none() && method.should_have_code() {
-        // We should have code but there was no code!
-        return Err(VerificationError::NoMethodCode { method_id }.into());
+    stack_map_verifier::verify_method(
+        class_names,
+        class_files,
+        classes,
+        packages,
+        methods,
+        state.conf().stack_map_verification_logging.clone(),
+        method_id,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use classfile_parser::{field_info::FieldAccessFlags, method_info::MethodAccessFlags};
+    use rhojvm_base::{
+        class_writer::ClassFileParts,
+        code::builder::{CodeBuilder, CodeBuilderError},
+        data::access::{IllegalAccessError, MemberAccess},
+        test_util::{
+            add_static_method, code_attribute, test_class, TestClass, ACC_PRIVATE, ACC_PROTECTED,
+            ACC_PUBLIC, ACC_STATIC,
+        },
+        StepError,
+    };
+
+    use crate::{
+        eval::{eval_method, EvalMethodValue, Frame, ValueException},
+        rv::{RuntimeValue, RuntimeValuePrimitive},
+        test_util::{find_method, test_env},
+        util::Env,
+    };
+
+    use super::{initialize_class, BegunStatus, GeneralError, InitState, ResolveError};
+
+    #[test]
+    fn test_init_state_transitions() {
+        let thread = std::thread::current().id();
+        let other = std::thread::spawn(|| std::thread::current().id())
+            .join()
+            .unwrap();
+
+        let being = InitState::BeingInitialized(thread);
+        assert!(InitState::Uninitialized.can_transition_to(being));
+        assert!(!InitState::Uninitialized.can_transition_to(InitState::Initialized));
+
+        // The initializing thread can recursively request initialization, but others can't
+        assert!(being.can_transition_to(being));
+        assert!(!being.can_transition_to(InitState::BeingInitialized(other)));
+
+        assert!(being.can_transition_to(InitState::Initialized));
+        assert!(being.can_transition_to(InitState::Erroneous));
+
+        // Classes are only initialized once, and erroneous classes can't be retried
+        for state in [InitState::Initialized, InitState::Erroneous] {
+            assert!(!state.can_transition_to(being));
+            assert!(!state.can_transition_to(InitState::Initialized));
+            assert!(!state.can_transition_to(InitState::Uninitialized));
+        }
     }
 
-    if let Some(method_code) = method_code {
-        stack_map_verifier::verify_type_safe_method_stack_map(
-            class_names,
-            class_files,
-            classes,
-            packages,
-            methods,
-            state.conf().stack_map_verification_logging.clone(),
-            &class_file,
-            method_index,
-            &method_code,
-        )?;
+    const ALOAD_0: u8 = 0x2A;
+    const DUP: u8 = 0x59;
+    const IRETURN: u8 = 0xAC;
+    const RETURN: u8 = 0xB1;
+    const GETSTATIC: u8 = 0xB2;
+    const INVOKEVIRTUAL: u8 = 0xB6;
+    const INVOKESPECIAL: u8 = 0xB7;
+    const INVOKESTATIC: u8 = 0xB8;
+    const NEW: u8 = 0xBB;
+
+    /// An instruction which takes a constant pool index
+    fn with_index(opcode: u8, index: u16) -> [u8; 3] {
+        let [high, low] = index.to_be_bytes();
+        [opcode, high, low]
+    }
 
-        // Restore the method's code since we have not modified it
-        let method = methods.get_mut(&method_id).unwrap();
-        method.unchecked_insert_code(method_code);
+    /// Add a method whose code is the raw bytecode `code`
+    fn with_code(
+        class: TestClass,
+        access_flags: u16,
+        name: &str,
+        descriptor: &str,
+        max_stack: u16,
+        max_locals: u16,
+        code: &[u8],
+    ) -> TestClass {
+        class
+            .method(access_flags, name, descriptor)
+            .method_attribute("Code", code_attribute(max_stack, max_locals, code, &[]))
     }
 
-    Ok(())
+    /// Add a public constructor which takes no arguments and only calls the same constructor of
+    /// `super_name`
+    fn with_constructor(mut class: TestClass, super_name: &str) -> TestClass {
+        let pool = &mut class.parts.constant_pool;
+        let super_init = pool.add_methodref(super_name, "<init>", "()V").unwrap();
+        let code = [
+            &[ALOAD_0][..],
+            &with_index(INVOKESPECIAL, super_init),
+            &[RETURN],
+        ]
+        .concat();
+        with_code(class, ACC_PUBLIC, "<init>", "()V", 1, 1, &code)
+    }
+
+    #[test]
+    fn test_illegal_private_access() {
+        let target = TestClass::new("a/Target", Some("java/lang/Object")).field(
+            ACC_PRIVATE | ACC_STATIC,
+            "hidden",
+            "I",
+        );
+        let target = with_code(
+            target,
+            ACC_PRIVATE | ACC_STATIC,
+            "secret",
+            "()V",
+            0,
+            0,
+            &[RETURN],
+        );
+        let target = with_code(
+            target,
+            ACC_PUBLIC | ACC_STATIC,
+            "open",
+            "()V",
+            0,
+            0,
+            &[RETURN],
+        );
+
+        let mut caller = TestClass::new("a/Caller", Some("java/lang/Object"));
+        for name in ["open", "secret"] {
+            let pool = &mut caller.parts.constant_pool;
+            let method = pool.add_methodref("a/Target", name, "()V").unwrap();
+            let code = [&with_index(INVOKESTATIC, method)[..], &[RETURN]].concat();
+            caller = with_code(caller, ACC_PUBLIC | ACC_STATIC, name, "()V", 0, 0, &code);
+        }
+        let pool = &mut caller.parts.constant_pool;
+        let field = pool.add_fieldref("a/Target", "hidden", "I").unwrap();
+        let code = [&with_index(GETSTATIC, field)[..], &[IRETURN]].concat();
+        let caller = with_code(
+            caller,
+            ACC_PUBLIC | ACC_STATIC,
+            "hidden",
+            "()I",
+            1,
+            0,
+            &code,
+        );
+
+        let mut env = test_env(&[("a/Target", &target.parts), ("a/Caller", &caller.parts)]);
+        let caller_id = env.class_names.gcid_from_bytes(b"a/Caller");
+        let target_id = env.class_names.gcid_from_bytes(b"a/Target");
+        initialize_class(&mut env, caller_id).unwrap();
+
+        let open = find_method(&mut env, caller_id, "open", "()V");
+        let res = eval_method(&mut env, open.into(), Frame::default()).unwrap();
+        assert!(matches!(res, EvalMethodValue::ReturnVoid));
+
+        for (name, descriptor) in [("secret", "()V"), ("hidden", "()I")] {
+            let method = find_method(&mut env, caller_id, name, descriptor);
+            let err = eval_method(&mut env, method.into(), Frame::default()).unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    GeneralError::Resolve(ResolveError::IllegalAccess(IllegalAccessError {
+                        from,
+                        member_owner,
+                        access: MemberAccess::Private,
+                    })) if from == caller_id && member_owner == target_id
+                ),
+                "{name}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_protected_override_access() {
+        let parent = TestClass::new("a/Parent", Some("java/lang/Object"));
+        let parent = with_constructor(parent, "java/lang/Object");
+        let parent = with_code(parent, ACC_PROTECTED, "foo", "()V", 0, 1, &[RETURN]);
+
+        // The override is in another package, so only its subclasses could call it directly
+        let child = TestClass::new("b/Child", Some("a/Parent"));
+        let child = with_constructor(child, "a/Parent");
+        let child = with_code(child, ACC_PROTECTED, "foo", "()V", 0, 1, &[RETURN]);
+
+        // `a/Other` can call `foo` through `a/Parent` since they share a package, even though
+        // that selects the override in `b/Child`
+        let mut other = TestClass::new("a/Other", Some("java/lang/Object"));
+        let pool = &mut other.parts.constant_pool;
+        let child_class = pool.add_class("b/Child").unwrap();
+        let init = pool.add_methodref("b/Child", "<init>", "()V").unwrap();
+        let foo = pool.add_methodref("a/Parent", "foo", "()V").unwrap();
+        let code = [
+            &with_index(NEW, child_class)[..],
+            &[DUP],
+            &with_index(INVOKESPECIAL, init),
+            &with_index(INVOKEVIRTUAL, foo),
+            &[RETURN],
+        ]
+        .concat();
+        let other = with_code(other, ACC_PUBLIC | ACC_STATIC, "call", "()V", 2, 0, &code);
+
+        let mut env = test_env(&[
+            ("a/Parent", &parent.parts),
+            ("b/Child", &child.parts),
+            ("a/Other", &other.parts),
+        ]);
+        let other_id = env.class_names.gcid_from_bytes(b"a/Other");
+        initialize_class(&mut env, other_id).unwrap();
+
+        let call = find_method(&mut env, other_id, "call", "()V");
+        let res = eval_method(&mut env, call.into(), Frame::default()).unwrap();
+        assert!(matches!(res, EvalMethodValue::ReturnVoid));
+    }
+
+    #[test]
+    fn test_initialize_old_class() {
+        // Version 49 class files have no stack map tables, so the verifier has to infer the frame
+        // at the join after the branch
+        let mut old = test_class("a/Old");
+        old.major_version = 49;
+        old.add_field("flag", "I", FieldAccessFlags::STATIC)
+            .unwrap();
+        add_static_method(
+            &mut old,
+            MethodAccessFlags::empty(),
+            "<clinit>",
+            "()V",
+            |code| {
+                let flag = code.pool().add_fieldref("a/Old", "flag", "I")?;
+                let end = code.new_label();
+                code.iconst(0)?;
+                code.ifne(end)?;
+                code.iconst(1)?;
+                code.putstatic(flag)?;
+                code.place_label(end)?;
+                code.return_()
+            },
+        );
+
+        let mut env = test_env(&[("a/Old", &old)]);
+        let old_id = env.class_names.gcid_from_bytes(b"a/Old");
+        let res = initialize_class(&mut env, old_id).unwrap();
+        assert!(matches!(res, BegunStatus::Done(ValueException::Value(_))));
+    }
+
+    #[test]
+    fn test_circular_inheritance() {
+        let a = ClassFileParts::new("a/A", Some("a/B")).unwrap();
+        let b = ClassFileParts::new("a/B", Some("a/A")).unwrap();
+
+        let mut env = test_env(&[("a/A", &a), ("a/B", &b)]);
+        let a_id = env.class_names.gcid_from_bytes(b"a/A");
+        let err = initialize_class(&mut env, a_id).unwrap_err();
+        assert!(
+            matches!(err, GeneralError::Step(StepError::ClassCircularity(_))),
+            "{err:?}"
+        );
+    }
+
+    /// A class whose static initializer is built by `build`
+    fn class_with_clinit(
+        name: &str,
+        super_name: &str,
+        build: impl FnOnce(&mut CodeBuilder) -> Result<(), CodeBuilderError>,
+    ) -> ClassFileParts {
+        let mut parts = ClassFileParts::new(name, Some(super_name)).unwrap();
+        add_static_method(
+            &mut parts,
+            MethodAccessFlags::empty(),
+            "<clinit>",
+            "()V",
+            build,
+        );
+        parts
+    }
+
+    fn initialize(env: &mut Env, name: &str) -> Result<ValueException<()>, GeneralError> {
+        let class_id = env.class_names.gcid_from_bytes(name.as_bytes());
+        match initialize_class(env, class_id)? {
+            BegunStatus::Done(ValueException::Value(_)) => Ok(ValueException::Value(())),
+            BegunStatus::Done(ValueException::Exception(exc)) => Ok(ValueException::Exception(exc)),
+            BegunStatus::Started(_) => panic!("{name} was left being initialized"),
+        }
+    }
+
+    fn init_state(env: &mut Env, name: &str) -> InitState {
+        let class_id = env.class_names.gcid_from_bytes(name.as_bytes());
+        env.state.init_state(class_id)
+    }
+
+    #[test]
+    fn test_initialize_super_exception() {
+        let throwable = test_class("java/lang/Throwable");
+        let failure = ClassFileParts::new("a/Failure", Some("java/lang/Throwable")).unwrap();
+        let base = class_with_clinit("a/Base", "java/lang/Object", |code| {
+            let failure = code.pool().add_class("a/Failure")?;
+            code.new_object(failure)?;
+            code.athrow()
+        });
+        let sub = class_with_clinit("a/Sub", "a/Base", |code| code.return_());
+
+        let mut env = test_env(&[
+            ("java/lang/Throwable", &throwable),
+            ("a/Failure", &failure),
+            ("a/Base", &base),
+            ("a/Sub", &sub),
+        ]);
+
+        // The exception from the super class's initializer is the subclass's as well, and
+        // neither of them can be used afterwards
+        let res = initialize(&mut env, "a/Sub").unwrap();
+        assert!(matches!(res, ValueException::Exception(_)));
+        assert_eq!(init_state(&mut env, "a/Base"), InitState::Erroneous);
+        assert_eq!(init_state(&mut env, "a/Sub"), InitState::Erroneous);
+
+        // Later requests don't run the initializers again
+        let err = initialize(&mut env, "a/Sub").unwrap_err();
+        assert!(matches!(err, GeneralError::NoClassDefFound(_)), "{err:?}");
+        let err = initialize(&mut env, "a/Base").unwrap_err();
+        assert!(matches!(err, GeneralError::NoClassDefFound(_)), "{err:?}");
+    }
+
+    #[test]
+    fn test_initialize_error() {
+        let broken = class_with_clinit("a/Broken", "java/lang/Object", |code| {
+            let method = code.pool().add_methodref("a/Broken", "missing", "()V")?;
+            code.invokestatic(method)?;
+            code.return_()
+        });
+
+        let mut env = test_env(&[("a/Broken", &broken)]);
+        initialize(&mut env, "a/Broken").unwrap_err();
+        assert_eq!(init_state(&mut env, "a/Broken"), InitState::Erroneous);
+
+        // The class was only partially initialized, so it isn't handed out to later requests
+        let broken_id = env.class_names.gcid_from_bytes(b"a/Broken");
+        let err = initialize(&mut env, "a/Broken").unwrap_err();
+        assert!(
+            matches!(err, GeneralError::NoClassDefFound(id) if id == broken_id),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_initialize_recursive() {
+        let mut counter = class_with_clinit("a/Counter", "java/lang/Object", |code| {
+            let method = code.pool().add_methodref("a/Counter", "bump", "()V")?;
+            code.invokestatic(method)?;
+            code.return_()
+        });
+        counter
+            .add_field("count", "I", FieldAccessFlags::STATIC)
+            .unwrap();
+        // Using the class's own field from its initializer requests its initialization again
+        add_static_method(
+            &mut counter,
+            MethodAccessFlags::PUBLIC,
+            "bump",
+            "()V",
+            |code| {
+                let field = code.pool().add_fieldref("a/Counter", "count", "I")?;
+                code.getstatic(field)?;
+                code.iconst(1)?;
+                code.iadd()?;
+                code.putstatic(field)?;
+                code.return_()
+            },
+        );
+        add_static_method(
+            &mut counter,
+            MethodAccessFlags::PUBLIC,
+            "get",
+            "()I",
+            |code| {
+                let field = code.pool().add_fieldref("a/Counter", "count", "I")?;
+                code.getstatic(field)?;
+                code.ireturn()
+            },
+        );
+
+        let mut env = test_env(&[("a/Counter", &counter)]);
+        let res = initialize(&mut env, "a/Counter").unwrap();
+        assert!(matches!(res, ValueException::Value(())));
+        assert_eq!(init_state(&mut env, "a/Counter"), InitState::Initialized);
+
+        let counter_id = env.class_names.gcid_from_bytes(b"a/Counter");
+        let get = find_method(&mut env, counter_id, "get", "()I");
+        let res = eval_method(&mut env, get.into(), Frame::default()).unwrap();
+        assert!(matches!(
+            res,
+            EvalMethodValue::Return(RuntimeValue::Primitive(RuntimeValuePrimitive::I32(1)))
+        ));
+    }
 }
@@ -0,0 +1,57 @@
+//! Helpers shared by the tests, for running code from class files built in memory
+//! See `rhojvm_base::test_util` for building the class files.
+
+use rhojvm_base::{
+    class_writer::ClassFileParts,
+    code::method::MethodDescriptor,
+    id::{ClassId, ExactMethodId},
+    test_util::TestEnv,
+};
+
+use crate::{
+    jni::native_interface::NativeInterface, string_intern::StringInterner, util::Env, State,
+    StateConfig, ThreadData,
+};
+
+/// An environment which can load `java/lang/Object` and each of the classes, by their name
+pub(crate) fn test_env(classes: &[(&str, &ClassFileParts)]) -> Env<'static> {
+    let TestEnv {
+        class_names,
+        class_files,
+        classes,
+        packages,
+        methods,
+    } = TestEnv::new(classes);
+
+    Env::new(
+        Box::leak(Box::new(NativeInterface::new_typical())),
+        class_names,
+        class_files,
+        classes,
+        packages,
+        methods,
+        State::new(StateConfig::new()),
+        ThreadData::new(std::thread::current().id()),
+        StringInterner::default(),
+    )
+}
+
+/// Find the method of the class by its name and descriptor, loading its class file
+pub(crate) fn find_method(
+    env: &mut Env,
+    class_id: ClassId,
+    name: &str,
+    descriptor: &str,
+) -> ExactMethodId {
+    let descriptor =
+        MethodDescriptor::from_text(descriptor.as_bytes(), &mut env.class_names).unwrap();
+    env.methods
+        .load_method_from_desc(
+            &mut env.class_names,
+            &mut env.class_files,
+            class_id,
+            name.as_bytes(),
+            &descriptor,
+        )
+        .unwrap()
+}
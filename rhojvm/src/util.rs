@@ -11,16 +11,17 @@ use rhojvm_base::{
         types::{JavaChar, PrimitiveType},
     },
     data::{class_files::ClassFiles, class_names::ClassNames, classes::Classes, methods::Methods},
-    id::{ClassId, MethodId},
+    id::{ClassId, ExactFieldId, FieldIndex, MethodId},
     package::Packages,
     util::MemorySize,
 };
+use stack_map_verifier::VerifyError;
 use sysinfo::{RefreshKind, SystemExt};
 
 use crate::{
     class_instance::{
-        ClassInstance, FieldId, FieldIndex, Instance, PrimitiveArrayInstance, ReferenceInstance,
-        StaticClassInstance, StaticFormInstance,
+        ClassInstance, Instance, PrimitiveArrayInstance, ReferenceInstance, StaticClassInstance,
+        StaticFormInstance,
     },
     eval::{
         eval_method, instances::make_fields, EvalError, EvalMethodValue, Frame, Locals,
@@ -156,6 +157,25 @@ impl<'i> Env<'i> {
 
         Some(gc_ref)
     }
+
+    /// Verify the type safety of the method's code against its `StackMapTable`
+    /// This only verifies the code, not the rest of the class that the method is in.
+    /// The `clone` method of arrays has no code to verify.
+    pub fn verify_method(&mut self, method: MethodId) -> Result<(), VerifyError> {
+        let MethodId::Exact(method_id) = method else {
+            return Ok(());
+        };
+
+        stack_map_verifier::verify_method(
+            &mut self.class_names,
+            &mut self.class_files,
+            &mut self.classes,
+            &mut self.packages,
+            &mut self.methods,
+            self.state.conf().stack_map_verification_logging.clone(),
+            method_id,
+        )
+    }
 }
 
 // TODO: A JavaString is obviously not exactly equivalent to a Rust string..
@@ -301,18 +321,21 @@ pub(crate) fn find_field_with_name(
     class_files: &ClassFiles,
     class_id: ClassId,
     target_name: &[u8],
-) -> Result<Option<(FieldId, FieldInfoOpt)>, GeneralError> {
+) -> Result<Option<(ExactFieldId, FieldInfoOpt)>, GeneralError> {
     let class_file = class_files
         .get(&class_id)
         .ok_or(GeneralError::MissingLoadedClassFile(class_id))?;
     for (i, field_data) in class_file.load_field_values_iter().enumerate() {
-        let i = FieldIndex::new_unchecked(i as u16);
+        let i = i as FieldIndex;
         let (field_info, _) = field_data.map_err(GeneralError::ClassFileLoad)?;
         let field_name = class_file.get_text_b(field_info.name_index).ok_or(
             EvalError::InvalidConstantPoolIndex(field_info.name_index.into_generic()),
         )?;
         if field_name == target_name {
-            return Ok(Some((FieldId::unchecked_compose(class_id, i), field_info)));
+            return Ok(Some((
+                ExactFieldId::unchecked_compose(class_id, i),
+                field_info,
+            )));
         }
     }
 
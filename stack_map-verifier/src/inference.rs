@@ -0,0 +1,723 @@
+//! Inferring the types at each instruction of a method without a stack map table, which class
+//! files from before Java 6 don't have.
+//! This is the older type inference verifier: the types coming into each instruction are
+//! repeatedly propagated along the control flow until nothing changes, and where control flow
+//! joins the types are merged into a type which can hold both.
+
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
+
+use classfile_parser::attribute_info::InstructionIndex;
+use rhojvm_base::{
+    class::{ArrayClass, ArrayComponentType, ClassFileData},
+    code::{stack_map::StackMapFrame, CodeInfo},
+    data::{class_files::ClassFiles, class_names::ClassNames, classes::Classes, methods::Methods},
+    id::{ClassId, ExactMethodId, MethodIndex},
+    package::Packages,
+    util::MemorySizeU16,
+    StepError,
+};
+use smallvec::SmallVec;
+
+use crate::{
+    handler_catch_id, run_instruction,
+    types::{ComplexFrameType, FrameType, InstTypes},
+    Frame, Local, StackMapVerificationLogging, VerifyError, VerifyStackMapError,
+    VerifyStackMapGeneralError,
+};
+
+/// The types coming into each reachable instruction of a method, inferred from its code
+#[derive(Debug, Clone)]
+pub struct InferredFrames {
+    frames: BTreeMap<u16, Frame>,
+}
+impl InferredFrames {
+    /// Whether the instruction at `idx` can be reached, and so has a frame
+    #[must_use]
+    pub fn has_frame_at(&self, idx: InstructionIndex) -> bool {
+        self.frames.contains_key(&idx.0)
+    }
+
+    /// The instructions which can be reached, in order
+    pub fn reachable(&self) -> impl Iterator<Item = InstructionIndex> + '_ {
+        self.frames.keys().copied().map(InstructionIndex)
+    }
+
+    /// The number of stack entries coming into the instruction at `idx`, where `long` and
+    /// `double` count as a single entry
+    #[must_use]
+    pub fn stack_len_at(&self, idx: InstructionIndex) -> Option<usize> {
+        self.frames.get(&idx.0).map(|frame| frame.stack.len())
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Whether the method's code has no stack map table and is old enough that it is allowed to
+/// not have one, and so its frames have to be inferred.
+/// Version 50 class files may still fall back to inference, as the JVMS allows.
+#[must_use]
+pub fn needs_inference(class_file: &ClassFileData, method_code: &CodeInfo) -> bool {
+    let has_stack_map = method_code.attributes().iter().any(|attr| {
+        class_file.get_text_b(attr.attribute_name_index) == Some(b"StackMapTable".as_slice())
+    });
+    !has_stack_map && class_file.version().map_or(false, |x| x.major <= 50)
+}
+
+/// Compute the frames of a method by type inference, rather than reading them from a stack map
+/// table, which also verifies the type safety of each reachable instruction.
+/// The same requirements on the arguments as [`crate::verify_type_safe_method_stack_map`]
+/// apply.
+///
+/// Where control flow joins, references are merged into their nearest common superclass, which
+/// ignores interfaces, so like the JVM's own inference verifier a merged type might only be
+/// known to be `java/lang/Object`. Arrays of references are merged by their component types.
+pub fn compute_frames(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    methods: &mut Methods,
+    conf: StackMapVerificationLogging,
+    class_file: &ClassFileData,
+    method_index: MethodIndex,
+    method_code: &CodeInfo,
+) -> Result<InferredFrames, VerifyError> {
+    let method_id = ExactMethodId::unchecked_compose(class_file.id(), method_index);
+
+    let initial_frame = initial_frame(
+        class_names,
+        class_files,
+        methods,
+        &conf,
+        class_file,
+        method_index,
+        method_code,
+    )
+    .map_err(|err| VerifyError::new(method_id, None, err))?;
+
+    let mut frames = BTreeMap::new();
+    frames.insert(0, initial_frame);
+
+    // The instructions whose incoming types have changed since they were last checked
+    let mut changed = BTreeSet::new();
+    changed.insert(0);
+
+    let mut inst_types = InstTypes::new();
+    while let Some(pc) = changed.pop_first() {
+        let idx = InstructionIndex(pc);
+        infer_instruction(
+            class_names,
+            class_files,
+            classes,
+            packages,
+            &conf,
+            class_file,
+            method_id,
+            method_code,
+            &mut frames,
+            &mut changed,
+            &mut inst_types,
+            idx,
+        )
+        .map_err(|err| VerifyError::new(method_id, Some(idx), err))?;
+    }
+
+    Ok(InferredFrames { frames })
+}
+
+/// Build the frame at the start of the method from its descriptor
+fn initial_frame(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    methods: &mut Methods,
+    conf: &StackMapVerificationLogging,
+    class_file: &ClassFileData,
+    method_index: MethodIndex,
+    method_code: &CodeInfo,
+) -> Result<Frame, VerifyStackMapGeneralError> {
+    let class_id = class_file.id();
+    let method_id = ExactMethodId::unchecked_compose(class_id, method_index);
+
+    methods.load_method_from_index(class_names, class_file, method_index)?;
+    let method = methods
+        .get_mut(&method_id)
+        .ok_or(StepError::MissingLoadedValue("initial_frame : method"))?;
+    method.load_code(class_files)?;
+    let method = &*method;
+
+    if conf.log_method_name {
+        tracing::info!(
+            "! Inferring {} :: {}{}",
+            class_names.tpath(class_id),
+            class_file
+                .get_text_t(method.name_index())
+                .unwrap_or_else(|| std::borrow::Cow::Owned("[BadMethodNameIndex]".to_owned())),
+            method.descriptor().as_pretty_string(class_names),
+        );
+    }
+
+    let frame = StackMapFrame::initial(class_names, class_file, method)
+        .map_err(VerifyStackMapGeneralError::StackMapError)?;
+    Frame::from_stack_map_frame(class_names, class_file, method_code, &frame)
+}
+
+/// Check the instruction at `idx` with the types coming into it, and merge the types it leaves
+/// into each instruction that it can continue on to
+fn infer_instruction(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    conf: &StackMapVerificationLogging,
+    class_file: &ClassFileData,
+    method_id: ExactMethodId,
+    method_code: &CodeInfo,
+    frames: &mut BTreeMap<u16, Frame>,
+    changed: &mut BTreeSet<u16>,
+    inst_types: &mut InstTypes,
+    idx: InstructionIndex,
+) -> Result<(), VerifyStackMapGeneralError> {
+    let inst = method_code
+        .instructions()
+        .get_instruction_at(idx)
+        .ok_or(VerifyStackMapError::InvalidBranchOffset)?;
+    let mut act_frame = frames
+        .get(&idx.0)
+        .cloned()
+        .ok_or(StepError::MissingLoadedValue("infer_instruction : frame"))?;
+
+    if conf.log_instruction {
+        tracing::info!(
+            "# ({}) {}",
+            idx.0,
+            inst.as_pretty_string(class_names, class_file)
+        );
+    }
+
+    // If the instruction throws, then the handlers are reached with the locals from before it
+    for handler in method_code.exception_table() {
+        if idx < handler.start_pc || idx >= handler.end_pc {
+            continue;
+        }
+
+        let catch_id = handler_catch_id(class_names, class_file, handler)?;
+        let mut stack = SmallVec::new();
+        stack.push(ComplexFrameType::ReferenceClass(catch_id).into());
+        let handler_state = Frame {
+            at: handler.handler_pc,
+            stack,
+            locals: act_frame.locals.clone(),
+        };
+        merge_into(
+            class_names,
+            class_files,
+            classes,
+            packages,
+            method_code,
+            frames,
+            changed,
+            handler.handler_pc,
+            handler_state,
+        )?;
+    }
+
+    run_instruction(
+        class_names,
+        class_files,
+        classes,
+        packages,
+        conf,
+        class_file,
+        method_id,
+        &mut act_frame,
+        inst_types,
+        inst,
+    )?;
+
+    let stack_size = act_frame.stack_slot_count();
+    if stack_size > usize::from(method_code.max_stack()) {
+        return Err(VerifyStackMapError::MaxStackExceeded {
+            max_stack: method_code.max_stack(),
+            got: stack_size,
+        }
+        .into());
+    }
+
+    let targets = inst
+        .branch_targets(idx)
+        .ok_or(VerifyStackMapError::InvalidBranchOffset)?;
+    for target in targets {
+        merge_into(
+            class_names,
+            class_files,
+            classes,
+            packages,
+            method_code,
+            frames,
+            changed,
+            target,
+            act_frame.clone(),
+        )?;
+    }
+
+    if inst.can_fall_through() {
+        let next = idx
+            .0
+            .checked_add(inst.memory_size_u16())
+            .map(InstructionIndex)
+            .filter(|next| method_code.instructions().has_instruction_at(*next))
+            .ok_or(VerifyStackMapError::FallsOffEndOfCode)?;
+        merge_into(
+            class_names,
+            class_files,
+            classes,
+            packages,
+            method_code,
+            frames,
+            changed,
+            next,
+            act_frame,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Merge the types reaching `target` into the frame already there, marking `target` to be
+/// checked again if its frame changed
+fn merge_into(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    method_code: &CodeInfo,
+    frames: &mut BTreeMap<u16, Frame>,
+    changed: &mut BTreeSet<u16>,
+    target: InstructionIndex,
+    mut incoming: Frame,
+) -> Result<(), VerifyStackMapGeneralError> {
+    if !method_code.instructions().has_instruction_at(target) {
+        return Err(VerifyStackMapError::InvalidBranchOffset.into());
+    }
+
+    incoming.at = target;
+    match frames.entry(target.0) {
+        Entry::Vacant(entry) => {
+            entry.insert(incoming);
+            changed.insert(target.0);
+        }
+        Entry::Occupied(mut entry) => {
+            if merge_frame(
+                class_names,
+                class_files,
+                classes,
+                packages,
+                entry.get_mut(),
+                &incoming,
+            )? {
+                changed.insert(target.0);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Merge `incoming` into `existing`, returning whether `existing` changed
+fn merge_frame(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    existing: &mut Frame,
+    incoming: &Frame,
+) -> Result<bool, VerifyStackMapGeneralError> {
+    let target = existing.at;
+    if existing.stack.len() != incoming.stack.len() {
+        return Err(VerifyStackMapError::FrameStackSizeMismatch {
+            target,
+            expected: existing.stack.len(),
+            got: incoming.stack.len(),
+        }
+        .into());
+    }
+
+    let mut did_change = false;
+    for (index, (current, got)) in existing
+        .stack
+        .iter_mut()
+        .zip(incoming.stack.iter())
+        .enumerate()
+    {
+        let merged = merge_type(class_names, class_files, classes, packages, current, got)?
+            .ok_or_else(|| VerifyStackMapError::FrameStackTypeMismatch {
+                target,
+                index,
+                expected: current.clone(),
+                got: got.clone(),
+            })?;
+        if merged != *current {
+            *current = merged;
+            did_change = true;
+        }
+    }
+
+    for (current, got) in existing
+        .locals
+        .locals
+        .iter_mut()
+        .zip(incoming.locals.locals.iter())
+    {
+        let merged = match (&*current, got) {
+            (Local::FrameType(current_type), Local::FrameType(got_type)) => {
+                // A local which holds different types on different paths can't be used
+                merge_type(
+                    class_names,
+                    class_files,
+                    classes,
+                    packages,
+                    current_type,
+                    got_type,
+                )?
+                .map_or(Local::Unfilled, Local::FrameType)
+            }
+            (Local::Top, Local::Top) => Local::Top,
+            _ => Local::Unfilled,
+        };
+        if merged != *current {
+            *current = merged;
+            did_change = true;
+        }
+    }
+
+    Ok(did_change)
+}
+
+/// Find a type that can hold both `a` and `b`, if there is one
+fn merge_type(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    a: &FrameType,
+    b: &FrameType,
+) -> Result<Option<FrameType>, StepError> {
+    Ok(match (a, b) {
+        (FrameType::Primitive(a_prim), FrameType::Primitive(b_prim)) => {
+            a_prim.is_same_type_on_stack(b_prim).then(|| a.clone())
+        }
+        (FrameType::Complex(a_complex), FrameType::Complex(b_complex)) => {
+            match (a_complex, b_complex) {
+                (
+                    ComplexFrameType::ReferenceClass(a_id),
+                    ComplexFrameType::ReferenceClass(b_id),
+                ) => {
+                    let id =
+                        merge_class(class_names, class_files, classes, packages, *a_id, *b_id)?;
+                    Some(ComplexFrameType::ReferenceClass(id).into())
+                }
+                (ComplexFrameType::ReferenceNull, ComplexFrameType::ReferenceNull)
+                | (ComplexFrameType::ReferenceClass(_), ComplexFrameType::ReferenceNull) => {
+                    Some(a.clone())
+                }
+                (ComplexFrameType::ReferenceNull, ComplexFrameType::ReferenceClass(_)) => {
+                    Some(b.clone())
+                }
+                (
+                    ComplexFrameType::UninitializedReferenceClass(a_id),
+                    ComplexFrameType::UninitializedReferenceClass(b_id),
+                ) => (a_id == b_id).then(|| a.clone()),
+                // Uninitialized objects can't be merged with anything else, since we would lose
+                // track of whether they were initialized
+                (ComplexFrameType::UninitializedReferenceClass(_), _)
+                | (_, ComplexFrameType::UninitializedReferenceClass(_)) => None,
+            }
+        }
+        (FrameType::Primitive(_), FrameType::Complex(_))
+        | (FrameType::Complex(_), FrameType::Primitive(_)) => None,
+    })
+}
+
+/// Find the class that both `a` and `b` are assignable to.
+/// Arrays of references are merged by their component types, so that `String[]` and `Integer[]`
+/// become `Object[]`, while arrays of differing primitives can only be an `Object`.
+fn merge_class(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    a: ClassId,
+    b: ClassId,
+) -> Result<ClassId, StepError> {
+    if a == b {
+        return Ok(a);
+    }
+
+    let a_component = classes
+        .get_array_class(class_names, class_files, packages, a)?
+        .map(ArrayClass::component_type);
+    let b_component = classes
+        .get_array_class(class_names, class_files, packages, b)?
+        .map(ArrayClass::component_type);
+    match (a_component, b_component) {
+        (Some(ArrayComponentType::Class(a_elem)), Some(ArrayComponentType::Class(b_elem))) => {
+            let elem = merge_class(class_names, class_files, classes, packages, a_elem, b_elem)?;
+            class_names
+                .get_or_create_array_class(ArrayComponentType::Class(elem))
+                .map_err(StepError::BadId)
+        }
+        (Some(_), Some(_)) => Ok(class_names.object_id()),
+        _ => classes.common_superclass(class_names, class_files, packages, a, b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use classfile_parser::attribute_info::InstructionIndex;
+    use rhojvm_base::{
+        class_writer::ClassFileParts,
+        code::{op::IntConst1, types::PrimitiveType},
+        id::MethodIndex,
+        test_util::{add_test_method, static_code, test_class, TestEnv},
+    };
+
+    use crate::{
+        types::{ComplexFrameType, FrameType},
+        Local, StackMapVerificationLogging, VerifyError, VerifyStackMapError,
+        VerifyStackMapGeneralError,
+    };
+
+    use super::{compute_frames, InferredFrames};
+
+    /// Infer the frames of the method of `a/Test`
+    fn infer(env: &mut TestEnv, method_index: MethodIndex) -> Result<InferredFrames, VerifyError> {
+        let (class_file, code) = env.load_code("a/Test", method_index);
+        compute_frames(
+            &mut env.class_names,
+            &mut env.class_files,
+            &mut env.classes,
+            &mut env.packages,
+            &mut env.methods,
+            StackMapVerificationLogging::default(),
+            &class_file,
+            method_index,
+            &code,
+        )
+    }
+
+    /// `a/Test`, at a version from before stack map tables were required
+    fn old_test_class() -> ClassFileParts {
+        let mut parts = test_class("a/Test");
+        parts.major_version = 50;
+        parts
+    }
+
+    fn at(offset: usize) -> u16 {
+        u16::try_from(offset).unwrap()
+    }
+
+    #[test]
+    fn test_infer_straight_line() {
+        let mut parts = old_test_class();
+        let code = static_code(&mut parts, "(II)I", |code| {
+            code.iload(0)?;
+            code.iload(1)?;
+            code.iadd()?;
+            code.ireturn()
+        });
+        let method_index = add_test_method(&mut parts, "(II)I", &code);
+
+        let mut env = TestEnv::new(&[("a/Test", &parts)]);
+        let frames = infer(&mut env, method_index).unwrap();
+        let reachable = frames.reachable().map(|idx| idx.0).collect::<Vec<_>>();
+        assert_eq!(reachable, [0, 1, 2, 3]);
+        let stack_lens = frames
+            .reachable()
+            .map(|idx| frames.stack_len_at(idx).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(stack_lens, [0, 1, 2, 1]);
+    }
+
+    #[test]
+    fn test_infer_loop() {
+        let mut parts = old_test_class();
+        let mut loop_start = 0;
+        let mut end_start = 0;
+        // for (int i = 0; i < n; i++) {}
+        let code = static_code(&mut parts, "(I)V", |code| {
+            let top = code.new_label();
+            let end = code.new_label();
+            code.iconst(0)?;
+            code.istore(1)?;
+            loop_start = code.offset();
+            code.place_label(top)?;
+            code.iload(1)?;
+            code.iload(0)?;
+            code.if_icmpge(end)?;
+            code.iinc(1, 1)?;
+            code.goto(top)?;
+            end_start = code.offset();
+            code.place_label(end)?;
+            code.return_()
+        });
+        let method_index = add_test_method(&mut parts, "(I)V", &code);
+
+        let mut env = TestEnv::new(&[("a/Test", &parts)]);
+        let frames = infer(&mut env, method_index).unwrap();
+        assert_eq!(frames.len(), 8);
+        assert_eq!(
+            frames.stack_len_at(InstructionIndex(at(end_start))),
+            Some(0)
+        );
+
+        // The counter is still known to be an int after going around the loop
+        let int = Local::FrameType(FrameType::Primitive(PrimitiveType::Int));
+        let top_frame = &frames.frames[&at(loop_start)];
+        assert_eq!(top_frame.locals.locals[..2], [int.clone(), int]);
+    }
+
+    /// Infer the type on the stack where `if (n == 0)` casting null to `a` joins with the other
+    /// branch casting null to `b`
+    fn merge_join(a: &str, b: &str) -> (TestEnv, FrameType) {
+        let mut parts = old_test_class();
+        let mut join_start = 0;
+        let code = static_code(&mut parts, "(I)V", |code| {
+            let other = code.new_label();
+            let join = code.new_label();
+            let a = code.pool().add_class(a)?;
+            let b = code.pool().add_class(b)?;
+            code.iload(0)?;
+            code.ifeq(other)?;
+            code.aconst_null()?;
+            code.checkcast(a)?;
+            code.goto(join)?;
+            code.place_label(other)?;
+            code.aconst_null()?;
+            code.checkcast(b)?;
+            join_start = code.offset();
+            code.place_label(join)?;
+            code.astore(1)?;
+            code.return_()
+        });
+        let method_index = add_test_method(&mut parts, "(I)V", &code);
+
+        let string = test_class("java/lang/String");
+        let integer = test_class("java/lang/Integer");
+        let mut env = TestEnv::new(&[
+            ("a/Test", &parts),
+            ("java/lang/String", &string),
+            ("java/lang/Integer", &integer),
+        ]);
+        let frames = infer(&mut env, method_index).unwrap();
+        let merged = frames.frames[&at(join_start)].stack[0].clone();
+        (env, merged)
+    }
+
+    #[test]
+    fn test_infer_reference_merge() {
+        let cases = [
+            ("java/lang/String", "java/lang/Integer", "java/lang/Object"),
+            ("java/lang/String", "java/lang/String", "java/lang/String"),
+            (
+                "[Ljava/lang/String;",
+                "[Ljava/lang/Integer;",
+                "[Ljava/lang/Object;",
+            ),
+            (
+                "[[Ljava/lang/String;",
+                "[[Ljava/lang/Integer;",
+                "[[Ljava/lang/Object;",
+            ),
+            (
+                "[Ljava/lang/String;",
+                "[[Ljava/lang/String;",
+                "[Ljava/lang/Object;",
+            ),
+            // Arrays of primitives have nothing more specific in common
+            ("[I", "[J", "java/lang/Object"),
+            ("[I", "[Ljava/lang/String;", "java/lang/Object"),
+            ("[I", "java/lang/String", "java/lang/Object"),
+        ];
+        for (a, b, expected) in cases {
+            let (mut env, merged) = merge_join(a, b);
+            let expected = env.class_names.gcid_from_bytes(expected.as_bytes());
+            assert_eq!(
+                merged,
+                FrameType::Complex(ComplexFrameType::ReferenceClass(expected)),
+                "merging {a} with {b}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_infer_stack_depth_mismatch() {
+        let mut parts = old_test_class();
+        let code = static_code(&mut parts, "()V", |code| {
+            let end = code.new_label();
+            code.iconst(0)?;
+            code.ifeq(end)?;
+            // Push an int which the builder doesn't know about, so that the join has an extra
+            // entry on one path
+            code.op(IntConst1::OPCODE, 0, 0)?;
+            code.place_label(end)?;
+            code.return_()
+        });
+        let method_index = add_test_method(&mut parts, "()V", &code);
+
+        let mut env = TestEnv::new(&[("a/Test", &parts)]);
+        let err = infer(&mut env, method_index).unwrap_err();
+        assert!(
+            matches!(
+                err.error,
+                VerifyStackMapGeneralError::VerifyStackMapError(
+                    VerifyStackMapError::FrameStackSizeMismatch {
+                        expected: 0,
+                        got: 1,
+                        ..
+                    }
+                )
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_infer_uninitialized_merge() {
+        let mut parts = old_test_class();
+        let code = static_code(&mut parts, "(I)V", |code| {
+            let join = code.new_label();
+            let object = code.pool().add_class("java/lang/Object")?;
+            code.new_object(object)?;
+            code.iload(0)?;
+            code.ifeq(join)?;
+            code.pop()?;
+            code.aconst_null()?;
+            code.place_label(join)?;
+            code.pop()?;
+            code.return_()
+        });
+        let method_index = add_test_method(&mut parts, "(I)V", &code);
+
+        // The uninitialized object can't be merged with null, since whether it was initialized
+        // would be lost
+        let mut env = TestEnv::new(&[("a/Test", &parts)]);
+        let err = infer(&mut env, method_index).unwrap_err();
+        assert!(
+            matches!(
+                err.error,
+                VerifyStackMapGeneralError::VerifyStackMapError(
+                    VerifyStackMapError::FrameStackTypeMismatch { index: 0, .. }
+                )
+            ),
+            "{err:?}"
+        );
+    }
+}
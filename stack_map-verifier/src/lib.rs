@@ -10,15 +10,17 @@
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::too_many_lines)]
 
+use std::collections::BTreeMap;
+
 use classfile_parser::constant_info::ConstantInfo;
 
 use classfile_parser::{
-    attribute_info::InstructionIndex,
+    attribute_info::{ExceptionEntry, InstructionIndex},
     constant_info::{ClassConstant, FieldRefConstant, NameAndTypeConstant, Utf8Constant},
     constant_pool::ConstantPoolIndexRaw,
 };
 use rhojvm_base::code::op::InstMapFunc;
-use rhojvm_base::code::stack_map::{StackMapError, StackMapFramesProcessor};
+use rhojvm_base::code::stack_map::{StackMapError, StackMapFrame, StackMapFramesProcessor};
 use rhojvm_base::code::types::{
     Category, Instruction, LocalVariableInType, LocalVariableIndex, LocalVariableType, LocalsIn,
     LocalsOutAt, StackSizes,
@@ -45,6 +47,7 @@ use types::{ComplexFrameType, FrameType};
 
 use crate::types::InstTypes;
 
+pub mod inference;
 mod types;
 
 #[derive(Debug)]
@@ -200,6 +203,66 @@ pub enum VerifyStackMapError {
     BadConstantIndex(ConstantPoolIndexRaw<ConstantInfo>),
     /// The type at the index was not an accepted constant info
     BadConstantType,
+    /// A stack map frame was at an offset which is not the start of an instruction
+    FrameNotAtInstruction { at: InstructionIndex },
+    /// The instruction can't be reached from the previous instruction, such as one after a
+    /// `goto`, and so it needs a stack map frame
+    MissingFrameAfterUnconditionalBranch,
+    /// The instruction branches to an instruction which has no stack map frame
+    MissingFrameAtBranchTarget { target: InstructionIndex },
+    /// The exception handler for the instruction has no stack map frame
+    MissingFrameAtHandler { handler: InstructionIndex },
+    /// The instruction branches outside of the code, or into the middle of an instruction
+    InvalidBranchOffset,
+    /// The stack had a different number of entries than the frame at the target
+    FrameStackSizeMismatch {
+        target: InstructionIndex,
+        expected: usize,
+        got: usize,
+    },
+    /// An entry on the stack was not assignable to the type in the frame at the target
+    FrameStackTypeMismatch {
+        target: InstructionIndex,
+        index: usize,
+        expected: FrameType,
+        got: FrameType,
+    },
+    /// A local variable was not assignable to the type in the frame at the target
+    FrameLocalTypeMismatch {
+        target: InstructionIndex,
+        index: usize,
+        expected: Local,
+        got: Local,
+    },
+    /// The stack grew beyond the `max_stack` of the code
+    MaxStackExceeded { max_stack: u16, got: usize },
+    /// The last instruction can continue on to the next, but there is no next instruction
+    FallsOffEndOfCode,
+    /// The method should have code but it did not
+    MissingCode,
+}
+
+/// An error from verifying a method, along with where it happened
+#[derive(Debug)]
+pub struct VerifyError {
+    pub method_id: ExactMethodId,
+    /// The instruction that was being checked, `None` if it failed before checking any
+    /// instructions, such as when loading the stack map frames
+    pub pc: Option<InstructionIndex>,
+    pub error: VerifyStackMapGeneralError,
+}
+impl VerifyError {
+    fn new(
+        method_id: ExactMethodId,
+        pc: Option<InstructionIndex>,
+        error: impl Into<VerifyStackMapGeneralError>,
+    ) -> VerifyError {
+        VerifyError {
+            method_id,
+            pc,
+            error: error.into(),
+        }
+    }
 }
 
 /// Settings for logging in the stack map verification.
@@ -222,7 +285,7 @@ pub struct StackMapVerificationLogging {
 }
 
 /// Variants of this enumeration are unstable and should not be relied upon.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Local {
     /// It has not yet received a type but can hold one
     Unfilled,
@@ -250,7 +313,7 @@ impl Local {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Locals {
     locals: SmallVec<[Local; 16]>,
 }
@@ -439,13 +502,62 @@ impl Locals {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Frame {
     at: InstructionIndex,
     stack: SmallVec<[FrameType; 20]>,
     locals: Locals,
 }
 impl Frame {
+    /// Convert a frame from the stack map, with all of the allowed locals filled in
+    fn from_stack_map_frame(
+        class_names: &mut ClassNames,
+        class_file: &ClassFileData,
+        code: &CodeInfo,
+        frame: &StackMapFrame,
+    ) -> Result<Frame, VerifyStackMapGeneralError> {
+        let inst = code
+            .instructions()
+            .get_instruction_at(frame.at)
+            .ok_or(VerifyStackMapError::FrameNotAtInstruction { at: frame.at })?;
+
+        let mut result = Frame {
+            at: frame.at,
+            stack: SmallVec::new(),
+            locals: Locals::default(),
+        };
+        FrameType::from_stack_map_types(
+            class_names,
+            class_file,
+            code,
+            &frame.stack,
+            &mut result.stack,
+        )?;
+        result
+            .locals
+            .ingest_stack_map_types(class_names, class_file, code, &frame.locals)?;
+        if result.locals.len() > usize::from(code.max_locals()) {
+            return Err(VerifyStackMapError::ReceivedFrameTooManyLocals {
+                inst_name: inst.name(),
+                inst_index: frame.at,
+            }
+            .into());
+        }
+
+        // Fill in the rest of the allowed locals with `None`
+        result.locals.resize_to(usize::from(code.max_locals()));
+
+        Ok(result)
+    }
+
+    /// The number of slots that the stack takes up, where `long` and `double` take up two
+    fn stack_slot_count(&self) -> usize {
+        self.stack
+            .iter()
+            .map(|entry| if entry.is_category_1() { 1 } else { 2 })
+            .sum()
+    }
+
     fn stack_sizes(&self) -> StackSizes {
         let mut res = [None; 4];
         for (i, entry) in self.stack.iter().rev().take(res.len()).enumerate() {
@@ -468,7 +580,13 @@ impl Frame {
 ///   You may note that it wouldn't be possible to load the method from `methods` and pass it in
 ///   Due to the design of the code, you will possibly have to clone it.
 ///   If there is no code, then the code is verified (assuming that it shouldn't have code)
-/// # Panics
+///
+/// Each instruction is checked against the types that the previous instructions left, and
+/// wherever control flow joins, which is at branch targets, exception handlers, and after
+/// instructions that don't fall through, there must be a stack map frame that the incoming
+/// types are assignable to.
+/// Old class files without a stack map table instead have their frames inferred, with
+/// [`inference::compute_frames`].
 pub fn verify_type_safe_method_stack_map(
     class_names: &mut ClassNames,
     class_files: &mut ClassFiles,
@@ -479,9 +597,168 @@ pub fn verify_type_safe_method_stack_map(
     class_file: &ClassFileData,
     method_index: MethodIndex,
     method_code: &CodeInfo,
-) -> Result<(), VerifyStackMapGeneralError> {
+) -> Result<(), VerifyError> {
     let _span = tracing::span!(tracing::Level::TRACE, "stackmap verification").entered();
 
+    if inference::needs_inference(class_file, method_code) {
+        // Inferring the frames checks each instruction, so there's nothing more to do
+        inference::compute_frames(
+            class_names,
+            class_files,
+            classes,
+            packages,
+            methods,
+            conf,
+            class_file,
+            method_index,
+            method_code,
+        )?;
+        return Ok(());
+    }
+
+    let class_id = class_file.id();
+    let method_id = ExactMethodId::unchecked_compose(class_id, method_index);
+
+    // The acting frame, which is used to keep track of what is active, and thus do the checking
+    // if an instruction requries an int at the top of the stack and it isn't there, then that's
+    // an error
+    // It starts out as the initial frame, which is built from the method's descriptor.
+    let (mut act_frame, frames) = collect_frames(
+        class_names,
+        class_files,
+        methods,
+        &conf,
+        class_file,
+        method_index,
+        method_code,
+    )
+    .map_err(|err| VerifyError::new(method_id, None, err))?;
+
+    // We don't bother doing the somewhat odd merging of stack map and code that the JVM
+    // documentation does, since it seems pointless.
+
+    // Note: This checking is theoretically not the best type checking that we could do with the
+    // information extractable from stack maps and instruction behavior, but it is the proper way
+    // of doing JVM stack map frame verification, and thus should verify anything that the official
+    // JVM verifies.
+
+    // The types that have been resolved for a single instruction
+    let mut inst_types = InstTypes::new();
+
+    // Whether the previous instruction can continue on to the current one, which is true for
+    // the first instruction since it is reached with the initial frame
+    let mut falls_through = true;
+
+    // Iterate over all instructions, performing type checking of each instruction with the given
+    // stack frame.
+    // Transformations of the type sthat the instructions have is done, because they encode more
+    // information than the main code uses.
+    for (idx, inst) in method_code.instructions().iter() {
+        check_instruction_at(
+            class_names,
+            class_files,
+            classes,
+            packages,
+            &conf,
+            class_file,
+            method_id,
+            method_code,
+            &frames,
+            &mut act_frame,
+            &mut inst_types,
+            falls_through,
+            *idx,
+            inst,
+        )
+        .map_err(|err| VerifyError::new(method_id, Some(*idx), err))?;
+
+        falls_through = inst.can_fall_through();
+    }
+
+    if falls_through {
+        // Execution would continue past the end of the code
+        let last = method_code.instructions().last().map(|(idx, _)| *idx);
+        return Err(VerifyError::new(
+            method_id,
+            last,
+            VerifyStackMapError::FallsOffEndOfCode,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Verify the type safety of the method's code, loading the method and its class file if they
+/// are not already loaded
+/// Methods which should not have code, like abstract and native methods, are trivially valid.
+pub fn verify_method(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    methods: &mut Methods,
+    conf: StackMapVerificationLogging,
+    method_id: ExactMethodId,
+) -> Result<(), VerifyError> {
+    let (class_id, method_index) = method_id.decompose();
+    let at_start = |err: VerifyStackMapGeneralError| VerifyError::new(method_id, None, err);
+
+    methods
+        .load_method_from_id(class_names, class_files, method_id)
+        .map_err(|err| at_start(err.into()))?;
+    // It is generally cheaper to clone since they tend to load it as well..
+    let class_file = class_files
+        .get(&class_id)
+        .ok_or(StepError::MissingLoadedValue("verify_method : class_file"))
+        .map_err(|err| at_start(err.into()))?
+        .clone();
+
+    let method = methods
+        .get_mut(&method_id)
+        .ok_or(StepError::MissingLoadedValue("verify_method : method"))
+        .map_err(|err| at_start(err.into()))?;
+    method
+        .load_code(class_files)
+        .map_err(|err| at_start(err.into()))?;
+    let Some(method_code) = method.take_code_info() else {
+        if method.should_have_code() {
+            return Err(at_start(VerifyStackMapError::MissingCode.into()));
+        }
+
+        return Ok(());
+    };
+
+    let result = verify_type_safe_method_stack_map(
+        class_names,
+        class_files,
+        classes,
+        packages,
+        methods,
+        conf,
+        &class_file,
+        method_index,
+        &method_code,
+    );
+
+    // Restore the method's code since we have not modified it
+    if let Some(method) = methods.get_mut(&method_id) {
+        method.unchecked_insert_code(method_code);
+    }
+
+    result
+}
+
+/// Parse all of the stack map frames of the method, returning the initial frame and the rest of
+/// the frames by the instruction that they are at
+fn collect_frames(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    methods: &mut Methods,
+    conf: &StackMapVerificationLogging,
+    class_file: &ClassFileData,
+    method_index: MethodIndex,
+    method_code: &CodeInfo,
+) -> Result<(Frame, BTreeMap<u16, Frame>), VerifyStackMapGeneralError> {
     let class_id = class_file.id();
     let method_id = ExactMethodId::unchecked_compose(class_id, method_index);
 
@@ -510,149 +787,321 @@ pub fn verify_type_safe_method_stack_map(
         StackMapFramesProcessor::new(class_names, class_file, method, method_code)
             .map_err(VerifyStackMapGeneralError::StackMapError)?;
 
-    // TODO: Verify max stack size from code
-    // TODO: Verify max stack size from state
-    // TODO: If we are verifying max stack size usage above, then it would be nice if the stack map
-    // frame parsing let us do some checks for each iteration of it, so that we could produce
-    // an error without parsing everything.
+    let mut initial_frame = None;
+    let mut frames = BTreeMap::new();
+    while let Some(frame) = stack_frames
+        .next_frame(class_names, class_file)
+        .map_err(VerifyStackMapGeneralError::StackMapError)?
+    {
+        let frame = Frame::from_stack_map_frame(class_names, class_file, method_code, frame)?;
+        if initial_frame.is_none() {
+            // The first frame is always the initial frame, and there may be a frame in the table
+            // at the first instruction as well, which the initial frame is checked against
+            initial_frame = Some(frame);
+        } else {
+            frames.insert(frame.at.0, frame);
+        }
+    }
 
-    // We don't bother doing the somewhat odd merging of stack map and code that the JVM
-    // documentation does, since it seems pointless.
+    let initial_frame =
+        initial_frame.expect("The stack map frames processor always produces the initial frame");
+    Ok((initial_frame, frames))
+}
 
-    // Note: This checking is theoretically not the best type checking that we could do with the
-    // information extractable from stack maps and instruction behavior, but it is the proper way
-    // of doing JVM stack map frame verification, and thus should verify anything that the official
-    // JVM verifies.
+/// Check a single instruction, with `act_frame` being the types after the previous instruction
+fn check_instruction_at(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    conf: &StackMapVerificationLogging,
+    class_file: &ClassFileData,
+    method_id: ExactMethodId,
+    method_code: &CodeInfo,
+    frames: &BTreeMap<u16, Frame>,
+    act_frame: &mut Frame,
+    inst_types: &mut InstTypes,
+    falls_through: bool,
+    idx: InstructionIndex,
+    inst: &Inst,
+) -> Result<(), VerifyStackMapGeneralError> {
+    if conf.log_instruction {
+        tracing::info!(
+            "# ({}) {}",
+            idx.0,
+            inst.as_pretty_string(class_names, class_file)
+        );
+    }
 
-    // The acting frame, which is used to keep track of what is active, and thus do the checking
-    // if an instruction requries an int at the top of the stack and it isn't there, then that's
-    // an error
-    let mut act_frame = Frame {
-        stack: SmallVec::new(),
-        locals: Locals::default(),
-        at: InstructionIndex(0),
-    };
+    // Update the current frame if there is an injected one
+    // The frame given by the JVM takes precedence over our frame, but if we can reach it from the
+    // previous instruction then what we have must be assignable to it.
+    if let Some(frame) = frames.get(&idx.0) {
+        if conf.log_received_frame {
+            tracing::info!("\t Received Frame: {:#?}", frame);
+        }
 
-    // The types that have been resolved for a single instruction
-    let mut inst_types = InstTypes::new();
+        if falls_through {
+            check_frame_assignable(
+                class_names,
+                class_files,
+                classes,
+                packages,
+                act_frame,
+                frame,
+                idx,
+            )?;
+        }
+        act_frame.clone_from(frame);
+    } else if !falls_through {
+        return Err(VerifyStackMapError::MissingFrameAfterUnconditionalBranch.into());
+    }
 
-    // Iterate over all instructions, performing type checking of each instruction with the given
-    // stack frame.
-    // Transformations of the type sthat the instructions have is done, because they encode more
-    // information than the main code uses.
-    for (idx, inst) in method_code.instructions().iter() {
-        struct Data<'cn, 'cf, 'c, 'p, 'cfd, 'af, 'it> {
-            class_names: &'cn mut ClassNames,
-            class_files: &'cf mut ClassFiles,
-            classes: &'c mut Classes,
-            packages: &'p mut Packages,
-            class_file: &'cfd ClassFileData,
-            conf: StackMapVerificationLogging,
-            method_id: ExactMethodId,
-            act_frame: &'af mut Frame,
-            inst_types: &'it mut InstTypes,
+    check_exception_handlers(
+        class_names,
+        class_files,
+        classes,
+        packages,
+        class_file,
+        method_code,
+        frames,
+        act_frame,
+        idx,
+    )?;
+
+    run_instruction(
+        class_names,
+        class_files,
+        classes,
+        packages,
+        conf,
+        class_file,
+        method_id,
+        act_frame,
+        inst_types,
+        inst,
+    )?;
+
+    let stack_size = act_frame.stack_slot_count();
+    if stack_size > usize::from(method_code.max_stack()) {
+        return Err(VerifyStackMapError::MaxStackExceeded {
+            max_stack: method_code.max_stack(),
+            got: stack_size,
         }
-        impl<'cn, 'cf, 'c, 'p, 'cfd, 'af, 'it> InstMapFunc<'_> for Data<'cn, 'cf, 'c, 'p, 'cfd, 'af, 'it> {
-            type Output = Result<(), VerifyStackMapGeneralError>;
-
-            fn call(self, inst: &impl Instruction) -> Self::Output {
-                check_instruction(
-                    self.class_names,
-                    self.class_files,
-                    self.classes,
-                    self.packages,
-                    self.class_file,
-                    self.conf,
-                    self.method_id,
-                    self.act_frame,
-                    self.inst_types,
-                    inst,
-                )
-            }
+        .into());
+    }
+
+    let targets = inst
+        .branch_targets(idx)
+        .ok_or(VerifyStackMapError::InvalidBranchOffset)?;
+    for target in targets {
+        let frame = frames
+            .get(&target.0)
+            .ok_or(VerifyStackMapError::MissingFrameAtBranchTarget { target })?;
+        check_frame_assignable(
+            class_names,
+            class_files,
+            classes,
+            packages,
+            act_frame,
+            frame,
+            target,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Apply the effects of the instruction to `act_frame`, checking that it has the types that the
+/// instruction needs
+fn run_instruction(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    conf: &StackMapVerificationLogging,
+    class_file: &ClassFileData,
+    method_id: ExactMethodId,
+    act_frame: &mut Frame,
+    inst_types: &mut InstTypes,
+    inst: &Inst,
+) -> Result<(), VerifyStackMapGeneralError> {
+    struct Data<'cn, 'cf, 'c, 'p, 'cfd, 'af, 'it> {
+        class_names: &'cn mut ClassNames,
+        class_files: &'cf mut ClassFiles,
+        classes: &'c mut Classes,
+        packages: &'p mut Packages,
+        class_file: &'cfd ClassFileData,
+        conf: StackMapVerificationLogging,
+        method_id: ExactMethodId,
+        act_frame: &'af mut Frame,
+        inst_types: &'it mut InstTypes,
+    }
+    impl<'cn, 'cf, 'c, 'p, 'cfd, 'af, 'it> InstMapFunc<'_> for Data<'cn, 'cf, 'c, 'p, 'cfd, 'af, 'it> {
+        type Output = Result<(), VerifyStackMapGeneralError>;
+
+        fn call(self, inst: &impl Instruction) -> Self::Output {
+            check_instruction(
+                self.class_names,
+                self.class_files,
+                self.classes,
+                self.packages,
+                self.class_file,
+                self.conf,
+                self.method_id,
+                self.act_frame,
+                self.inst_types,
+                inst,
+            )
         }
+    }
 
-        if conf.log_instruction {
-            tracing::info!(
-                "# ({}) {}",
-                idx.0,
-                inst.as_pretty_string(class_names, class_file)
-            );
+    // This maps the data to the generic version of check_instruction so that Rust
+    // can optimize each variant, since many can have statically known sizes and more
+    inst.map(Data {
+        class_names,
+        class_files,
+        classes,
+        packages,
+        class_file,
+        conf: conf.clone(),
+        method_id,
+        act_frame,
+        inst_types,
+    })
+}
+
+/// Check that, if the instruction at `idx` throws, the handlers which cover it can be jumped to
+/// with the current locals and the exception as the only value on the stack
+fn check_exception_handlers(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    class_file: &ClassFileData,
+    method_code: &CodeInfo,
+    frames: &BTreeMap<u16, Frame>,
+    act_frame: &Frame,
+    idx: InstructionIndex,
+) -> Result<(), VerifyStackMapGeneralError> {
+    for handler in method_code.exception_table() {
+        if idx < handler.start_pc || idx >= handler.end_pc {
+            continue;
         }
 
-        // Update the current frame if there is an injected one
-        check_frame(
-            class_names,
-            class_file,
-            &conf,
-            method_code,
-            &mut stack_frames,
-            &mut act_frame,
-            *idx,
-            inst.name(),
+        let catch_id = handler_catch_id(class_names, class_file, handler)?;
+
+        let frame = frames.get(&handler.handler_pc.0).ok_or(
+            VerifyStackMapError::MissingFrameAtHandler {
+                handler: handler.handler_pc,
+            },
         )?;
 
-        // Check the instruction
-        // This maps the data to the generic version of check_instruction so that Rust
-        // can optimize each variant, since many can have statically known sizes and more
-        inst.map(Data {
+        let mut stack = SmallVec::new();
+        stack.push(ComplexFrameType::ReferenceClass(catch_id).into());
+        let handler_state = Frame {
+            at: idx,
+            stack,
+            locals: act_frame.locals.clone(),
+        };
+        check_frame_assignable(
             class_names,
             class_files,
             classes,
             packages,
-            class_file,
-            conf: conf.clone(),
-            method_id,
-            act_frame: &mut act_frame,
-            inst_types: &mut inst_types,
-        })?;
+            &handler_state,
+            frame,
+            handler.handler_pc,
+        )?;
     }
 
     Ok(())
 }
 
-fn check_frame(
+/// The class of the exceptions that the handler catches, which is `java/lang/Throwable` if it
+/// catches everything
+fn handler_catch_id(
     class_names: &mut ClassNames,
     class_file: &ClassFileData,
-    conf: &StackMapVerificationLogging,
-    code: &CodeInfo,
-    stack_frames: &mut StackMapFramesProcessor,
-    act_frame: &mut Frame,
-    idx: InstructionIndex,
-    inst_name: &'static str,
-) -> Result<(), VerifyStackMapGeneralError> {
-    if stack_frames.has_next_frame_at(idx) {
-        let frame = stack_frames
-            .next_frame(class_names, class_file)
-            .map_err(VerifyStackMapGeneralError::StackMapError)?
-            .expect("has_next_frame_at was expected to not be incorrect about there being a frame");
+    handler: &ExceptionEntry,
+) -> Result<ClassId, VerifyStackMapError> {
+    if handler.catch_type.is_zero() {
+        return Ok(class_names.gcid_from_bytes(b"java/lang/Throwable"));
+    }
 
-        // The frame given by the JVM takes precedence over our frame
-        if conf.log_received_frame {
-            tracing::info!("\t Received Frame: {:#?}", frame);
+    let catch_type = class_file
+        .get_t(handler.catch_type)
+        .ok_or(VerifyStackMapError::BadClassIndex(handler.catch_type))?;
+    let catch_name = class_file.get_text_b(catch_type.name_index).ok_or(
+        VerifyStackMapError::BadClassNameIndex(catch_type.name_index),
+    )?;
+    Ok(class_names.gcid_from_bytes(catch_name))
+}
+
+/// Check that the types in `from` can be used where the frame `to` is expected, such as when
+/// branching to `target` which has the frame `to`
+fn check_frame_assignable(
+    class_names: &mut ClassNames,
+    class_files: &mut ClassFiles,
+    classes: &mut Classes,
+    packages: &mut Packages,
+    from: &Frame,
+    to: &Frame,
+    target: InstructionIndex,
+) -> Result<(), VerifyStackMapGeneralError> {
+    if from.stack.len() != to.stack.len() {
+        return Err(VerifyStackMapError::FrameStackSizeMismatch {
+            target,
+            expected: to.stack.len(),
+            got: from.stack.len(),
         }
+        .into());
+    }
 
-        act_frame.stack.clear();
-        FrameType::from_stack_map_types(
+    for (index, (expected, got)) in to.stack.iter().zip(from.stack.iter()).enumerate() {
+        if !expected.is_stack_same_of_frame_type(
+            classes,
             class_names,
-            class_file,
-            code,
-            &frame.stack,
-            &mut act_frame.stack,
-        )?;
-        act_frame
-            .locals
-            .ingest_stack_map_types(class_names, class_file, code, &frame.locals)?;
-        if act_frame.locals.len() > usize::from(code.max_locals()) {
-            return Err(VerifyStackMapError::ReceivedFrameTooManyLocals {
-                inst_name,
-                inst_index: idx,
+            class_files,
+            packages,
+            got,
+        )? {
+            return Err(VerifyStackMapError::FrameStackTypeMismatch {
+                target,
+                index,
+                expected: expected.clone(),
+                got: got.clone(),
             }
             .into());
         }
+    }
 
-        // Fill in the rest of the allowed locals with `None`
-        act_frame.locals.resize_to(usize::from(code.max_locals()));
-        act_frame.at = frame.at;
+    for (index, expected) in to.locals.locals.iter().enumerate() {
+        let Local::FrameType(expected_type) = expected else {
+            // Anything can be put where the frame doesn't have a type
+            continue;
+        };
+
+        let got = from.locals.locals.get(index);
+        let is_assignable = match got {
+            Some(Local::FrameType(got_type)) => expected_type.is_stack_same_of_frame_type(
+                classes,
+                class_names,
+                class_files,
+                packages,
+                got_type,
+            )?,
+            Some(Local::Unfilled | Local::Top) | None => false,
+        };
+        if !is_assignable {
+            return Err(VerifyStackMapError::FrameLocalTypeMismatch {
+                target,
+                index,
+                expected: expected.clone(),
+                got: got.cloned().unwrap_or(Local::Unfilled),
+            }
+            .into());
+        }
     }
 
     Ok(())
@@ -1106,3 +1555,303 @@ fn check_instruction<T: Instruction>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use classfile_parser::{attribute_info::InstructionIndex, method_info::MethodAccessFlags};
+    use rhojvm_base::{
+        class_writer::{ClassFileParts, CodeParts},
+        id::{ExactMethodId, MethodIndex},
+        test_util::{add_stack_map_table, add_test_method, static_code, test_class, TestEnv},
+    };
+
+    use crate::{
+        verify_method, verify_type_safe_method_stack_map, StackMapVerificationLogging, VerifyError,
+        VerifyStackMapError, VerifyStackMapGeneralError,
+    };
+
+    // Verification types and frame types of the entries of a `StackMapTable`
+    const ITEM_INTEGER: u8 = 1;
+    const ITEM_OBJECT: u8 = 7;
+    const SAME_LOCALS_1_STACK_ITEM: u8 = 64;
+
+    /// The environment for `a/Test`, along with `java/lang/Throwable` for exception handlers
+    fn test_env(parts: &ClassFileParts) -> TestEnv {
+        let throwable = test_class("java/lang/Throwable");
+        TestEnv::new(&[("a/Test", parts), ("java/lang/Throwable", &throwable)])
+    }
+
+    /// Verify the method of `a/Test`
+    fn verify(parts: &ClassFileParts, method_index: MethodIndex) -> Result<(), VerifyError> {
+        let mut env = test_env(parts);
+        let (class_file, code) = env.load_code("a/Test", method_index);
+        verify_type_safe_method_stack_map(
+            &mut env.class_names,
+            &mut env.class_files,
+            &mut env.classes,
+            &mut env.packages,
+            &mut env.methods,
+            StackMapVerificationLogging::default(),
+            &class_file,
+            method_index,
+            &code,
+        )
+    }
+
+    fn verify_by_id(parts: &ClassFileParts, method_index: MethodIndex) -> Result<(), VerifyError> {
+        let mut env = test_env(parts);
+        let class_id = env.class_names.gcid_from_bytes(b"a/Test");
+        verify_method(
+            &mut env.class_names,
+            &mut env.class_files,
+            &mut env.classes,
+            &mut env.packages,
+            &mut env.methods,
+            StackMapVerificationLogging::default(),
+            ExactMethodId::unchecked_compose(class_id, method_index),
+        )
+    }
+
+    fn assert_fails_with(
+        result: Result<(), VerifyError>,
+        pc: Option<u16>,
+        check: impl FnOnce(&VerifyStackMapError) -> bool,
+    ) {
+        let err = result.unwrap_err();
+        assert_eq!(err.pc, pc.map(InstructionIndex), "{err:?}");
+        let VerifyStackMapGeneralError::VerifyStackMapError(inner) = &err.error else {
+            panic!("Expected a verification error, got {err:?}");
+        };
+        assert!(check(inner), "{err:?}");
+    }
+
+    fn at(offset: usize) -> u8 {
+        u8::try_from(offset).unwrap()
+    }
+
+    #[test]
+    fn test_verify_branches() {
+        let mut parts = test_class("a/Test");
+        let mut other_start = 0;
+        // return n == 0 ? 0 : 1;
+        let mut code = static_code(&mut parts, "(I)I", |code| {
+            let other = code.new_label();
+            code.iload(0)?;
+            code.ifeq(other)?;
+            code.iconst(1)?;
+            code.ireturn()?;
+            other_start = code.offset();
+            code.place_label(other)?;
+            code.iconst(0)?;
+            code.ireturn()
+        });
+        // A same_frame, which has the same locals as the initial frame and an empty stack
+        add_stack_map_table(&mut parts, &mut code, 1, &[at(other_start)]);
+        let method_index = add_test_method(&mut parts, "(I)I", &code);
+
+        verify(&parts, method_index).unwrap();
+        verify_by_id(&parts, method_index).unwrap();
+    }
+
+    #[test]
+    fn test_verify_method_without_code() {
+        let mut parts = test_class("a/Test");
+        let abstract_index = parts
+            .add_method(
+                "abstract",
+                "()V",
+                MethodAccessFlags::PUBLIC | MethodAccessFlags::ABSTRACT,
+                None,
+            )
+            .unwrap();
+        let missing_index = parts
+            .add_method("missing", "()V", MethodAccessFlags::PUBLIC, None)
+            .unwrap();
+
+        verify_by_id(&parts, abstract_index).unwrap();
+        assert_fails_with(verify_by_id(&parts, missing_index), None, |err| {
+            matches!(err, VerifyStackMapError::MissingCode)
+        });
+    }
+
+    #[test]
+    fn test_verify_missing_frame_after_goto() {
+        let mut parts = test_class("a/Test");
+        let mut dead_start = 0;
+        let mut end_start = 0;
+        let mut code = static_code(&mut parts, "()V", |code| {
+            let end = code.new_label();
+            code.goto(end)?;
+            dead_start = code.offset();
+            code.iconst(0)?;
+            code.pop()?;
+            end_start = code.offset();
+            code.place_label(end)?;
+            code.return_()
+        });
+        // Only the target of the goto has a frame, not the instruction after it
+        add_stack_map_table(&mut parts, &mut code, 1, &[at(end_start)]);
+        let method_index = add_test_method(&mut parts, "()V", &code);
+
+        assert_fails_with(
+            verify(&parts, method_index),
+            Some(u16::from(at(dead_start))),
+            |err| {
+                matches!(
+                    err,
+                    VerifyStackMapError::MissingFrameAfterUnconditionalBranch
+                )
+            },
+        );
+    }
+
+    #[test]
+    fn test_verify_branch_target_mismatch() {
+        let mut parts = test_class("a/Test");
+        let mut branch_start = 0;
+        let mut end_start = 0;
+        let mut code = static_code(&mut parts, "(I)V", |code| {
+            let end = code.new_label();
+            code.fconst(0.0)?;
+            code.iload(0)?;
+            branch_start = code.offset();
+            code.ifeq(end)?;
+            end_start = code.offset();
+            code.place_label(end)?;
+            code.pop()?;
+            code.return_()
+        });
+        // The frame claims that there is an int on the stack, but it is a float
+        add_stack_map_table(
+            &mut parts,
+            &mut code,
+            1,
+            &[SAME_LOCALS_1_STACK_ITEM + at(end_start), ITEM_INTEGER],
+        );
+        let method_index = add_test_method(&mut parts, "(I)V", &code);
+
+        let end = InstructionIndex(u16::from(at(end_start)));
+        assert_fails_with(
+            verify(&parts, method_index),
+            Some(u16::from(at(branch_start))),
+            |err| {
+                matches!(
+                    err,
+                    VerifyStackMapError::FrameStackTypeMismatch { target, index: 0, .. }
+                        if *target == end
+                )
+            },
+        );
+    }
+
+    /// Build code which throws null within a handler for any exception, returning the code and
+    /// the offset of the handler
+    fn throwing_code(parts: &mut ClassFileParts) -> (CodeParts, u8) {
+        let mut handler_start = 0;
+        let code = static_code(parts, "()V", |code| {
+            let start = code.new_label();
+            let end = code.new_label();
+            let handler = code.new_label();
+            code.try_catch(start, end, handler, None)?;
+            code.place_label(start)?;
+            code.aconst_null()?;
+            code.athrow()?;
+            handler_start = code.offset();
+            code.place_label(handler)?;
+            code.place_label(end)?;
+            code.pop()?;
+            code.return_()
+        });
+        (code, at(handler_start))
+    }
+
+    #[test]
+    fn test_verify_handler_frames() {
+        // The handler is reached with the exception on the stack
+        let mut parts = test_class("a/Test");
+        let (mut code, handler) = throwing_code(&mut parts);
+        let [throwable_high, throwable_low] = parts
+            .constant_pool
+            .add_class("java/lang/Throwable")
+            .unwrap()
+            .to_be_bytes();
+        add_stack_map_table(
+            &mut parts,
+            &mut code,
+            1,
+            &[
+                SAME_LOCALS_1_STACK_ITEM + handler,
+                ITEM_OBJECT,
+                throwable_high,
+                throwable_low,
+            ],
+        );
+        let method_index = add_test_method(&mut parts, "()V", &code);
+        verify(&parts, method_index).unwrap();
+
+        // The handler has no frame
+        let mut parts = test_class("a/Test");
+        let (code, handler) = throwing_code(&mut parts);
+        let method_index = add_test_method(&mut parts, "()V", &code);
+        let handler = InstructionIndex(u16::from(handler));
+        assert_fails_with(verify(&parts, method_index), Some(0), |err| {
+            matches!(
+                err,
+                VerifyStackMapError::MissingFrameAtHandler { handler: got } if *got == handler
+            )
+        });
+
+        // The frame at the handler expects an int rather than the exception
+        let mut parts = test_class("a/Test");
+        let (mut code, handler) = throwing_code(&mut parts);
+        add_stack_map_table(
+            &mut parts,
+            &mut code,
+            1,
+            &[SAME_LOCALS_1_STACK_ITEM + handler, ITEM_INTEGER],
+        );
+        let method_index = add_test_method(&mut parts, "()V", &code);
+        assert_fails_with(verify(&parts, method_index), Some(0), |err| {
+            matches!(
+                err,
+                VerifyStackMapError::FrameStackTypeMismatch { index: 0, .. }
+            )
+        });
+    }
+
+    #[test]
+    fn test_verify_max_stack_exceeded() {
+        let mut parts = test_class("a/Test");
+        let mut code = static_code(&mut parts, "()V", |code| {
+            code.iconst(0)?;
+            code.pop()?;
+            code.return_()
+        });
+        code.max_stack = 0;
+        let method_index = add_test_method(&mut parts, "()V", &code);
+
+        assert_fails_with(verify(&parts, method_index), Some(0), |err| {
+            matches!(
+                err,
+                VerifyStackMapError::MaxStackExceeded {
+                    max_stack: 0,
+                    got: 1
+                }
+            )
+        });
+    }
+
+    #[test]
+    fn test_verify_falls_off_end_of_code() {
+        let mut parts = test_class("a/Test");
+        let code = static_code(&mut parts, "()V", |code| {
+            code.iconst(0)?;
+            code.pop()
+        });
+        let method_index = add_test_method(&mut parts, "()V", &code);
+
+        assert_fails_with(verify(&parts, method_index), Some(1), |err| {
+            matches!(err, VerifyStackMapError::FallsOffEndOfCode)
+        });
+    }
+}
@@ -43,7 +43,7 @@ impl InstTypes {
 /// A type for verifying frames
 /// This does not use [`StackMapType`] because there is no sensible way to
 /// convert something like a [`ComplexType::ReferenceAny`] into a specific type // without lookahead.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum FrameType {
     /// We simply use the [`PrimitiveType`] from opcodes, because they are the same.
     /// Technically, the stack represents several different types as integers, but we
@@ -606,7 +606,7 @@ impl From<ComplexFrameType> for FrameType {
 }
 // TODO: Should we be moving the idea of an array out? We could keep it around and just do the comparisons
 // between it and a normal referenceclass.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ComplexFrameType {
     /// A reference to a class of this id
     ReferenceClass(ClassId),